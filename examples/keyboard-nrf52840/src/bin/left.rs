@@ -2,13 +2,13 @@
 #![no_std]
 
 use embassy_executor::Spawner;
-use keyboard_nrf52840::{Central, DefaultState, KeyboardLeft, NUM_KEYS};
+use keyboard_nrf52840::{Central, DefaultState, KeyboardLeft, NUM_CENTRAL_KEYS, NUM_KEYS};
 use lokey::{Context, Device};
 use lokey_blink::Blink;
 use lokey_keyboard::action::{
     BleClearActive, BleNextProfile, BlePreviousProfile, Layer, NoOp, ToggleExternalTransport,
 };
-use lokey_keyboard::{Key, KeyOverride, KeyOverrideEntry, MatrixConfig, Scanner, layout};
+use lokey_keyboard::{Key, KeyOverride, KeyOverrideEntry, MatrixConfig, Scanner, split_layout};
 use lokey_layer::LayerId;
 use lokey_led_array::{BleAdvertisementHook, BleProfileHook, BootHook, LedArray};
 
@@ -18,14 +18,15 @@ fn key_override() -> KeyOverride<1> {
 
 #[lokey::device(message_override = key_override())]
 async fn main(context: Context<KeyboardLeft, Central, DefaultState>, _spawner: Spawner) {
-    let layout = layout!(
+    let layout = split_layout!(
+        central_keys = NUM_CENTRAL_KEYS;
         // Layer 0
         [
             Key::Z,
             BleClearActive,
             BleNextProfile,
             BlePreviousProfile,
-            ToggleExternalTransport(KeyboardLeft::DEFAULT_ADDRESS),
+            ToggleExternalTransport::new(KeyboardLeft::DEFAULT_ADDRESS),
             Key::A,
             Key::B,
             Key::C,
@@ -39,6 +40,7 @@ async fn main(context: Context<KeyboardLeft, Central, DefaultState>, _spawner: S
             NoOp,
             NoOp,
             NoOp,
+        ] | [
             Key::A,
             NoOp,
             NoOp,
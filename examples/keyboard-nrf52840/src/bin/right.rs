@@ -2,14 +2,16 @@
 #![no_std]
 
 use embassy_executor::Spawner;
-use keyboard_nrf52840::{DefaultState, KeyboardRight, NUM_KEYS, Peripheral};
+use keyboard_nrf52840::{
+    DefaultState, KeyboardRight, NUM_CENTRAL_KEYS, NUM_PERIPHERAL_KEYS, Peripheral,
+};
 use lokey::Context;
 use lokey_blink::Blink;
 use lokey_keyboard::{DirectPinsConfig, Scanner};
 
 #[lokey::device]
 async fn main(context: Context<KeyboardRight, Peripheral, DefaultState>, _spawner: Spawner) {
-    let scanner = Scanner::<DirectPinsConfig, NUM_KEYS>::new();
+    let scanner = Scanner::<DirectPinsConfig, NUM_PERIPHERAL_KEYS, NUM_CENTRAL_KEYS>::new();
 
     context.enable_all((scanner, Blink::new())).await;
 }
@@ -27,6 +27,8 @@ use lokey_nrf::Nrf;
 use panic_probe as _;
 
 pub const NUM_KEYS: usize = 36;
+pub const NUM_CENTRAL_KEYS: usize = 18;
+pub const NUM_PERIPHERAL_KEYS: usize = NUM_KEYS - NUM_CENTRAL_KEYS;
 pub type NumKeys = <typenum::Const<NUM_KEYS> as typenum::ToUInt>::Output;
 
 #[derive(Default, State)]
@@ -65,9 +67,7 @@ impl Transports<Nrf> for Central {
 
     fn internal_transport_config() -> <Self::InternalTransport as internal::Transport>::Config {
         internal::empty::TransportConfig
-        // internal::ble::TransportConfig::Central {
-        //     peripheral_addresses: &[KeyboardRight::DEFAULT_ADDRESS],
-        // }
+        // internal::ble::TransportConfig::central(&[KeyboardRight::DEFAULT_ADDRESS])
     }
 }
 
@@ -84,9 +84,7 @@ impl Transports<Nrf> for Peripheral {
 
     fn internal_transport_config() -> <Self::InternalTransport as internal::Transport>::Config {
         internal::empty::TransportConfig
-        // internal::ble::TransportConfig::Peripheral {
-        //     central_address: KeyboardLeft::DEFAULT_ADDRESS,
-        // }
+        // internal::ble::TransportConfig::peripheral(KeyboardLeft::DEFAULT_ADDRESS)
     }
 }
 
@@ -254,15 +252,20 @@ impl<S: AnyState> ComponentSupport<Blink, S> for KeyboardRight {
     }
 }
 
-impl<S: AnyState> ComponentSupport<Scanner<DirectPinsConfig, NUM_KEYS>, S> for KeyboardRight {
-    async fn enable<T>(component: Scanner<DirectPinsConfig, NUM_KEYS>, context: Context<Self, T, S>)
-    where
+impl<S: AnyState>
+    ComponentSupport<Scanner<DirectPinsConfig, NUM_PERIPHERAL_KEYS, NUM_CENTRAL_KEYS>, S>
+    for KeyboardRight
+{
+    async fn enable<T>(
+        component: Scanner<DirectPinsConfig, NUM_PERIPHERAL_KEYS, NUM_CENTRAL_KEYS>,
+        context: Context<Self, T, S>,
+    ) where
         T: Transports<Self::Mcu>,
     {
         let input_pins = unsafe {
             [Input::new(P1_11::steal().into::<AnyPin>(), Pull::Up).into_active_low_switch()]
         };
-        let scanner = DirectPins::new::<NUM_KEYS>(input_pins).continuous::<18>();
+        let scanner = DirectPins::new::<NUM_PERIPHERAL_KEYS>(input_pins).continuous::<0>();
 
         component.run(scanner, context.as_dyn()).await
     }
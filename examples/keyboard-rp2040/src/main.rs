@@ -110,6 +110,7 @@ async fn main(context: Context<KeyboardLeft, Central, DefaultState>, _spawner: S
         debounce_key_release: Debounce::Defer {
             duration: Duration::from_millis(30),
         },
+        ..Default::default()
     });
 
     context.enable_all((layout, scanner, Blink::new())).await;
@@ -104,6 +104,18 @@ pub fn tx_message_derive(item: TokenStream) -> TokenStream {
                     #( <#variant_types as ::lokey_ble::external::TxMessage>::service_uuids_128() ),*
                 )
             }
+
+            fn replaces(&self, previous: &Self) -> bool {
+                match (self, previous) {
+                    #(
+                        (#ident::#variant_names(v), #ident::#variant_names(previous)) => {
+                            ::lokey_ble::external::TxMessage::replaces(v, previous)
+                        }
+                    )*
+                    #[allow(unreachable_patterns)]
+                    _ => false,
+                }
+            }
         }
 
         struct #message_service_ident {
@@ -113,10 +125,11 @@ pub fn tx_message_derive(item: TokenStream) -> TokenStream {
         impl ::lokey_ble::external::InitMessageService for #message_service_ident {
             fn init<const ATT_MAX: usize>(
                 attribute_table: &mut ::lokey_ble::trouble_host::prelude::AttributeTable<'static, ::lokey_ble::embassy_sync::blocking_mutex::raw::NoopRawMutex, ATT_MAX>,
+                configs: ::lokey_ble::external::MessageConfigs,
             ) -> Self {
                 Self {
                     services: (
-                        #(<<#variant_types as ::lokey_ble::external::TxMessage>::MessageService as ::lokey_ble::external::InitMessageService>::init(attribute_table)),*
+                        #(<<#variant_types as ::lokey_ble::external::TxMessage>::MessageService as ::lokey_ble::external::InitMessageService>::init(attribute_table, configs)),*
                     ),
                 }
             }
@@ -132,6 +145,15 @@ pub fn tx_message_derive(item: TokenStream) -> TokenStream {
                     #(#ident::#variant_names(v) => self.services.#field_indices.send(v, connection).await),*
                 }
             }
+
+            fn handle_write<'stack, 'server>(
+                &self,
+                event: &::lokey_ble::trouble_host::gatt::WriteEvent<'stack, 'server, ::lokey_ble::trouble_host::prelude::DefaultPacketPool>,
+            ) {
+                #(
+                    <<#variant_types as ::lokey_ble::external::TxMessage>::MessageService as ::lokey_ble::external::TxMessageService<#variant_types>>::handle_write(&self.services.#field_indices, event);
+                )*
+            }
         }
     }
     .into()
@@ -1,16 +1,89 @@
+mod battery_service;
 mod message_service;
 mod transport;
 
+use core::future::Future;
 use embassy_time::Duration;
 use generic_array::{ArrayLength, GenericArray};
+pub use lokey::external::MessageConfigs;
 use lokey::external::NoMessage;
 use lokey::util::error;
-use lokey::{Address, external, internal};
+use lokey::{Address, DynContext, external, internal};
 #[cfg(feature = "macros")]
 pub use lokey_ble_macros::TxMessage;
 pub use message_service::{InitMessageService, RxMessageService, TxMessageService};
 pub use transport::Transport;
-use trouble_host::prelude::{BluetoothUuid16, appearance};
+use trouble_host::prelude::{AdStructure, BluetoothUuid16, appearance};
+
+/// Connection parameters that are requested from the central after connecting.
+///
+/// The parameters are only a request, the central decides which parameters are actually used and
+/// may reject or change them. Parameters that are `None` are requested with the values of the
+/// current connection, so they keep the values that were negotiated when connecting. If only one
+/// bound of the connection interval is specified, the other bound is clamped to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnectionParams {
+    pub min_connection_interval: Option<Duration>,
+    pub max_connection_interval: Option<Duration>,
+    /// The number of connection events that the device is allowed to skip.
+    ///
+    /// Higher values reduce the power consumption, but increase the latency.
+    pub max_latency: Option<u16>,
+    pub supervision_timeout: Option<Duration>,
+}
+
+impl ConnectionParams {
+    pub const fn new() -> Self {
+        Self {
+            min_connection_interval: None,
+            max_connection_interval: None,
+            max_latency: None,
+            supervision_timeout: None,
+        }
+    }
+
+    pub const fn min_connection_interval(mut self, value: Duration) -> Self {
+        self.min_connection_interval = Some(value);
+        self
+    }
+
+    pub const fn max_connection_interval(mut self, value: Duration) -> Self {
+        self.max_connection_interval = Some(value);
+        self
+    }
+
+    pub const fn max_latency(mut self, value: u16) -> Self {
+        self.max_latency = Some(value);
+        self
+    }
+
+    pub const fn supervision_timeout(mut self, value: Duration) -> Self {
+        self.supervision_timeout = Some(value);
+        self
+    }
+
+    fn is_empty(&self) -> bool {
+        self.min_connection_interval.is_none()
+            && self.max_connection_interval.is_none()
+            && self.max_latency.is_none()
+            && self.supervision_timeout.is_none()
+    }
+
+    /// Returns the parameters of `self`, with the parameters that are `None` taken from `other`.
+    fn or(self, other: Self) -> Self {
+        Self {
+            min_connection_interval: self
+                .min_connection_interval
+                .or(other.min_connection_interval),
+            max_connection_interval: self
+                .max_connection_interval
+                .or(other.max_connection_interval),
+            max_latency: self.max_latency.or(other.max_latency),
+            supervision_timeout: self.supervision_timeout.or(other.supervision_timeout),
+        }
+    }
+}
 
 pub struct TransportConfig {
     pub name: &'static str,
@@ -24,6 +97,43 @@ pub struct TransportConfig {
     pub appearance: &'static BluetoothUuid16,
     pub min_connection_interval: Option<Duration>,
     pub max_connection_interval: Option<Duration>,
+    /// Connection parameters for the individual profiles (indexed by the profile index).
+    ///
+    /// The parameters of a profile take precedence over `min_connection_interval` and
+    /// `max_connection_interval`. Profiles without an entry use only these fields.
+    pub profile_connection_params: &'static [ConnectionParams],
+    pub battery_service: bool,
+    /// Delay after startup before the transport starts advertising.
+    ///
+    /// This can be used to give other hardware (e.g. the key matrix or LEDs) time to settle
+    /// before the device becomes visible to hosts.
+    pub startup_delay: Duration,
+    /// Duration for which messages are collected before they are sent.
+    ///
+    /// Messages that arrive within this window after another message are sent together, and
+    /// consecutive messages that [replace](TxMessage::replaces) the previous message are merged
+    /// into a single notification. This reduces the BLE traffic when typing fast, at the cost of
+    /// adding the window to the latency of each message, so it is disabled by default (i.e. set
+    /// to zero).
+    pub coalesce_window: Duration,
+    /// Additional AD structures (e.g. manufacturer specific data) that are appended to the
+    /// advertisement data.
+    ///
+    /// The advertisement data, including the flags, the name and the service UUIDs that are
+    /// always advertised, must not exceed 31 bytes. If it does, the transport logs an error and
+    /// does not start advertising.
+    pub custom_ad_structures: &'static [AdStructure<'static>],
+    /// Only accept new bonds while the transport is in pairing mode.
+    ///
+    /// If this is enabled, hosts can only pair with a profile that has no bond yet after the
+    /// pairing mode was entered (e.g. with [`Message::EnterPairingMode`]). Hosts that connect to
+    /// a profile without a bond while the pairing mode is not active are disconnected. Hosts that
+    /// are already bonded with a profile can always reconnect.
+    pub require_pairing_mode: bool,
+    /// Duration after which the pairing mode is left if no new bond was created.
+    pub pairing_mode_timeout: Duration,
+    /// Configurations of the sent and received message types (e.g. of the keyboard reports).
+    pub message_configs: MessageConfigs,
 }
 
 impl Default for TransportConfig {
@@ -40,18 +150,43 @@ impl Default for TransportConfig {
             appearance: &appearance::UNKNOWN,
             min_connection_interval: None,
             max_connection_interval: None,
+            profile_connection_params: &[],
+            battery_service: false,
+            startup_delay: Duration::from_ticks(0),
+            coalesce_window: Duration::from_ticks(0),
+            custom_ad_structures: &[],
+            require_pairing_mode: false,
+            pairing_mode_timeout: Duration::from_secs(60),
+            message_configs: MessageConfigs::default(),
         }
     }
 }
 
 pub enum Message {
-    SelectProfile { index: u8 },
+    SelectProfile {
+        index: u8,
+    },
     SelectNextProfile,
     SelectPreviousProfile,
     DisconnectActive,
-    Clear { profile_index: u8 },
+    Clear {
+        profile_index: u8,
+    },
     ClearActive,
     ClearAll,
+    /// Accept new bonds until a host has paired or the pairing mode timed out.
+    ///
+    /// This only has an effect if [`TransportConfig::require_pairing_mode`] is enabled.
+    EnterPairingMode,
+    ExitPairingMode,
+    /// Selects the next profile that has a bond, skipping the profiles without a bond.
+    ///
+    /// The active profile stays selected if no other profile has a bond.
+    SelectNextBondedProfile,
+    /// Selects the previous profile that has a bond, skipping the profiles without a bond.
+    ///
+    /// The active profile stays selected if no other profile has a bond.
+    SelectPreviousBondedProfile,
 }
 
 impl internal::Message for Message {
@@ -71,6 +206,10 @@ impl internal::Message for Message {
             [4, profile_index] => Self::Clear { profile_index },
             [5, 0] => Self::ClearActive,
             [6, 0] => Self::ClearAll,
+            [7, 0] => Self::EnterPairingMode,
+            [8, 0] => Self::ExitPairingMode,
+            [9, 0] => Self::SelectNextBondedProfile,
+            [10, 0] => Self::SelectPreviousBondedProfile,
             _ => return None,
         };
         Some(message)
@@ -85,11 +224,135 @@ impl internal::Message for Message {
             Self::Clear { profile_index } => [4, *profile_index],
             Self::ClearActive => [5, 0],
             Self::ClearAll => [6, 0],
+            Self::EnterPairingMode => [7, 0],
+            Self::ExitPairingMode => [8, 0],
+            Self::SelectNextBondedProfile => [9, 0],
+            Self::SelectPreviousBondedProfile => [10, 0],
         }
         .into()
     }
 }
 
+/// Internal request for the [`ProfileStatus`] of the BLE transport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ProfileStatusRequest {
+    pub request_id: internal::RequestId,
+}
+
+impl internal::Message for ProfileStatusRequest {
+    type Size = typenum::U8;
+
+    const TAG: [u8; 4] = [0x94, 0x0e, 0x6b, 0x2f];
+
+    fn from_bytes(bytes: GenericArray<u8, Self::Size>) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        Some(Self {
+            request_id: internal::RequestId::from_bytes(bytes.into_array()),
+        })
+    }
+
+    fn to_bytes(&self) -> GenericArray<u8, Self::Size> {
+        self.request_id.to_bytes().into()
+    }
+}
+
+impl internal::Request for ProfileStatusRequest {
+    type Response = ProfileStatus;
+
+    fn request_id(&self) -> internal::RequestId {
+        self.request_id
+    }
+}
+
+/// The active profile, the number of profiles, the profiles with a bond and the active status of
+/// the BLE transport.
+///
+/// This is sent by the transport in response to a [`ProfileStatusRequest`]. Changes of the active
+/// profile are reported with [`Event::SwitchedProfile`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ProfileStatus {
+    pub request_id: internal::RequestId,
+    pub active_profile_index: u8,
+    pub num_profiles: u8,
+    /// Bit mask of the profiles that have a bond, where bit `n` is set if the profile with index
+    /// `n` has a bond.
+    pub bonded_profiles: u16,
+    /// Whether the transport is active (see [`external::Transport::is_active`]).
+    pub is_active: bool,
+}
+
+impl ProfileStatus {
+    /// Returns `true` if the profile with the specified index has a bond.
+    pub const fn has_bond(&self, profile_index: u8) -> bool {
+        profile_index < 16 && self.bonded_profiles & (1 << profile_index) != 0
+    }
+}
+
+impl internal::Message for ProfileStatus {
+    type Size = typenum::U13;
+
+    const TAG: [u8; 4] = [0x27, 0xd5, 0x81, 0xc3];
+
+    fn from_bytes(bytes: GenericArray<u8, Self::Size>) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        let [
+            r0,
+            r1,
+            r2,
+            r3,
+            r4,
+            r5,
+            r6,
+            r7,
+            active_profile_index,
+            num_profiles,
+            b0,
+            b1,
+            is_active,
+        ] = bytes.into_array::<13>();
+        Some(Self {
+            request_id: internal::RequestId::from_bytes([r0, r1, r2, r3, r4, r5, r6, r7]),
+            active_profile_index,
+            num_profiles,
+            bonded_profiles: u16::from_be_bytes([b0, b1]),
+            is_active: is_active != 0,
+        })
+    }
+
+    fn to_bytes(&self) -> GenericArray<u8, Self::Size> {
+        let [r0, r1, r2, r3, r4, r5, r6, r7] = self.request_id.to_bytes();
+        let [b0, b1] = self.bonded_profiles.to_be_bytes();
+        [
+            r0,
+            r1,
+            r2,
+            r3,
+            r4,
+            r5,
+            r6,
+            r7,
+            self.active_profile_index,
+            self.num_profiles,
+            b0,
+            b1,
+            self.is_active as u8,
+        ]
+        .into()
+    }
+}
+
+impl internal::Response for ProfileStatus {
+    fn request_id(&self) -> internal::RequestId {
+        self.request_id
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Event {
@@ -98,6 +361,7 @@ pub enum Event {
     Connected { device_address: Address },
     Disconnected { device_address: Address },
     SwitchedProfile { profile_index: u8, changed: bool },
+    PairingMode { active: bool },
 }
 
 impl internal::Message for Event {
@@ -128,6 +392,8 @@ impl internal::Message for Event {
                 profile_index,
                 changed: true,
             }),
+            [5, 0, 0, 0, 0, 0, 0] => Some(Self::PairingMode { active: false }),
+            [5, 1, 0, 0, 0, 0, 0] => Some(Self::PairingMode { active: true }),
             v => {
                 error!("invalid bytes {}", v);
                 None
@@ -154,6 +420,7 @@ impl internal::Message for Event {
                 profile_index,
                 changed,
             } => [4, *profile_index, *changed as u8, 0, 0, 0, 0],
+            Self::PairingMode { active } => [5, *active as u8, 0, 0, 0, 0, 0],
         }
         .into()
     }
@@ -170,6 +437,18 @@ pub trait TxMessage: external::Message + Sized {
 
     fn service_uuids_16() -> GenericArray<[u8; 2], Self::LenServiceUuids16>;
     fn service_uuids_128() -> GenericArray<[u8; 16], Self::LenServiceUuids128>;
+
+    /// Returns `true` if this message can be sent instead of the specified previous message
+    /// without losing information.
+    ///
+    /// This is used to merge messages that are queued at the same time (see
+    /// [`TransportConfig::coalesce_window`]). A queued message is only replaced if it replaced the
+    /// message before it as well, so e.g. a keyboard report that releases a key is never replaced
+    /// by a report that presses the key again. By default messages never replace each other.
+    fn replaces(&self, previous: &Self) -> bool {
+        let _ = previous;
+        false
+    }
 }
 
 pub trait RxMessage: external::Message + Sized {
@@ -220,3 +499,214 @@ impl RxMessage for NoMessage {
         [].into()
     }
 }
+
+/// Extension trait for controlling the BLE transport from a [`DynContext`].
+///
+/// The methods send the corresponding [`Message`] to the BLE transport through the internal
+/// channel, so the message type does not have to be used directly.
+pub trait BleControl {
+    /// Selects the profile with the specified index.
+    fn ble_select_profile(&self, index: u8) -> impl Future<Output = ()>;
+
+    /// Selects the next profile.
+    fn ble_next_profile(&self) -> impl Future<Output = ()>;
+
+    /// Selects the previous profile.
+    fn ble_previous_profile(&self) -> impl Future<Output = ()>;
+
+    /// Selects the next profile that has a bond.
+    fn ble_next_bonded_profile(&self) -> impl Future<Output = ()>;
+
+    /// Selects the previous profile that has a bond.
+    fn ble_previous_bonded_profile(&self) -> impl Future<Output = ()>;
+
+    /// Disconnects the host of the active profile.
+    fn ble_disconnect_active(&self) -> impl Future<Output = ()>;
+
+    /// Removes the bond of the profile with the specified index.
+    fn ble_clear_profile(&self, profile_index: u8) -> impl Future<Output = ()>;
+
+    /// Removes the bond of the active profile.
+    fn ble_clear_active_profile(&self) -> impl Future<Output = ()>;
+
+    /// Removes the bonds of all profiles.
+    fn ble_clear_all_profiles(&self) -> impl Future<Output = ()>;
+
+    /// Enters the pairing mode (see [`TransportConfig::require_pairing_mode`]).
+    fn ble_enter_pairing_mode(&self) -> impl Future<Output = ()>;
+
+    /// Exits the pairing mode (see [`TransportConfig::require_pairing_mode`]).
+    fn ble_exit_pairing_mode(&self) -> impl Future<Output = ()>;
+
+    /// Returns the active profile, the number of profiles and the profiles with a bond.
+    ///
+    /// The status is requested from the BLE transport through the internal channel. The returned
+    /// future never completes if no BLE transport answers the request (e.g. because the device
+    /// part does not use a BLE transport or the transport is not running yet). Wrap it in a timeout
+    /// (e.g. `embassy_time::with_timeout`) if the device might not have a BLE transport.
+    fn ble_profile_status(
+        &self,
+    ) -> impl Future<Output = Result<ProfileStatus, internal::MaximumReceiversReached>>;
+
+    /// Returns whether the BLE transport is active.
+    ///
+    /// The transport can be activated and deactivated with [`DynContext::set_external_active`] if
+    /// it is wrapped in a [toggle transport](lokey::external::toggle).
+    ///
+    /// Like [`ble_profile_status`](Self::ble_profile_status), the returned future never completes
+    /// if no BLE transport answers the request.
+    fn ble_is_active(
+        &self,
+    ) -> impl Future<Output = Result<bool, internal::MaximumReceiversReached>>;
+}
+
+impl BleControl for DynContext {
+    async fn ble_select_profile(&self, index: u8) {
+        self.internal_channel
+            .send(Message::SelectProfile { index })
+            .await;
+    }
+
+    async fn ble_next_profile(&self) {
+        self.internal_channel.send(Message::SelectNextProfile).await;
+    }
+
+    async fn ble_previous_profile(&self) {
+        self.internal_channel
+            .send(Message::SelectPreviousProfile)
+            .await;
+    }
+
+    async fn ble_next_bonded_profile(&self) {
+        self.internal_channel
+            .send(Message::SelectNextBondedProfile)
+            .await;
+    }
+
+    async fn ble_previous_bonded_profile(&self) {
+        self.internal_channel
+            .send(Message::SelectPreviousBondedProfile)
+            .await;
+    }
+
+    async fn ble_disconnect_active(&self) {
+        self.internal_channel.send(Message::DisconnectActive).await;
+    }
+
+    async fn ble_clear_profile(&self, profile_index: u8) {
+        self.internal_channel
+            .send(Message::Clear { profile_index })
+            .await;
+    }
+
+    async fn ble_clear_active_profile(&self) {
+        self.internal_channel.send(Message::ClearActive).await;
+    }
+
+    async fn ble_clear_all_profiles(&self) {
+        self.internal_channel.send(Message::ClearAll).await;
+    }
+
+    async fn ble_enter_pairing_mode(&self) {
+        self.internal_channel.send(Message::EnterPairingMode).await;
+    }
+
+    async fn ble_exit_pairing_mode(&self) {
+        self.internal_channel.send(Message::ExitPairingMode).await;
+    }
+
+    async fn ble_profile_status(&self) -> Result<ProfileStatus, internal::MaximumReceiversReached> {
+        self.internal_channel
+            .request(self.address, |request_id| ProfileStatusRequest {
+                request_id,
+            })
+            .await
+    }
+
+    async fn ble_is_active(&self) -> Result<bool, internal::MaximumReceiversReached> {
+        Ok(self.ble_profile_status().await?.is_active)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_futures::block_on;
+    use embassy_futures::select::{Either3, select3};
+    use lokey::storage::EmptyStorage;
+
+    /// Internal transport that only delivers messages locally.
+    struct LocalTransport;
+
+    impl internal::Transport for LocalTransport {
+        type Config = ();
+        type Mcu = ();
+
+        async fn create(_: Self::Config, _: &'static Self::Mcu, _: Address) -> Self {
+            Self
+        }
+
+        async fn run<Storage>(&self, _: &'static Storage)
+        where
+            Storage: lokey::storage::Storage,
+        {
+        }
+
+        async fn send(&self, _: &[u8]) {}
+
+        async fn receive(&self, _: &mut [u8]) -> usize {
+            core::future::pending().await
+        }
+    }
+
+    #[test]
+    fn profile_status_request_round_trip() {
+        let channel = internal::Channel::new(LocalTransport);
+        let channel_ref = &channel;
+        let serve = channel.serve(move |request: ProfileStatusRequest| async move {
+            // A response with the same counter for another device part must be ignored.
+            let other_request_id = internal::RequestId {
+                address: Address([2; 6]),
+                ..request.request_id
+            };
+            channel_ref
+                .send(ProfileStatus {
+                    request_id: other_request_id,
+                    active_profile_index: 0,
+                    num_profiles: 0,
+                    bonded_profiles: 0,
+                    is_active: true,
+                })
+                .await;
+            ProfileStatus {
+                request_id: request.request_id,
+                active_profile_index: 2,
+                num_profiles: 4,
+                bonded_profiles: 0b0101,
+                is_active: false,
+            }
+        });
+        let request = channel.request(Address([1; 6]), |request_id| ProfileStatusRequest {
+            request_id,
+        });
+        let response = match block_on(select3(channel.run(&EmptyStorage), serve, request)) {
+            Either3::Third(response) => response.unwrap(),
+            _ => panic!("request was not answered"),
+        };
+        assert_eq!(response.request_id.address, Address([1; 6]));
+        assert_eq!(response.active_profile_index, 2);
+        assert_eq!(response.num_profiles, 4);
+        assert!(response.has_bond(2));
+        assert!(!response.has_bond(1));
+        assert!(!response.is_active);
+    }
+
+    #[test]
+    fn request_ids_differ() {
+        let address = Address([1; 6]);
+        let first = internal::RequestId::new(address);
+        let second = internal::RequestId::new(address);
+        assert_ne!(first, second);
+        assert_eq!(internal::RequestId::from_bytes(first.to_bytes()), first);
+    }
+}
@@ -0,0 +1,10 @@
+#![allow(clippy::needless_borrows_for_generic_args)] // Produced by the macros from trouble_host
+
+use trouble_host::prelude::*;
+
+#[gatt_service(uuid = service::BATTERY)]
+pub struct BatteryService {
+    #[descriptor(uuid = descriptors::VALID_RANGE, read, value = [0, 100])]
+    #[characteristic(uuid = characteristic::BATTERY_LEVEL, read, notify, value = 100)]
+    pub level: u8,
+}
@@ -1,12 +1,17 @@
 use core::any::Any;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
-use lokey::external::{Message, NoMessage};
+use lokey::external::{Message, MessageConfigs, NoMessage};
 use trouble_host::gatt::{GattConnection, WriteEvent};
 use trouble_host::prelude::{AttributeTable, DefaultPacketPool};
 
 pub trait InitMessageService {
+    /// Initializes the message service.
+    ///
+    /// `configs` contains the message configurations of the transport config, from which the
+    /// message service can get its configuration.
     fn init<const ATT_MAX: usize>(
         attribute_table: &mut AttributeTable<'static, NoopRawMutex, ATT_MAX>,
+        configs: MessageConfigs,
     ) -> Self;
 }
 
@@ -16,6 +21,16 @@ pub trait TxMessageService<T: Message>: Any {
         message: T,
         connection: &GattConnection<'stack, 'server, DefaultPacketPool>,
     ) -> impl Future<Output = ()>;
+
+    /// Handles a write of the host to an attribute of the message service (e.g. an output report).
+    ///
+    /// This is called for every write event that is received on an encrypted connection.
+    fn handle_write<'stack, 'server>(
+        &self,
+        event: &WriteEvent<'stack, 'server, DefaultPacketPool>,
+    ) {
+        let _ = event;
+    }
 }
 
 pub trait RxMessageService<T: Message>: Any {
@@ -29,6 +44,7 @@ pub trait RxMessageService<T: Message>: Any {
 impl InitMessageService for () {
     fn init<'a, const ATT_MAX: usize>(
         _: &mut AttributeTable<'static, NoopRawMutex, ATT_MAX>,
+        _: MessageConfigs,
     ) -> Self {
     }
 }
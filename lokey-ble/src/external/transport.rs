@@ -1,19 +1,24 @@
-use super::{Event, Message, TransportConfig};
+use super::battery_service::BatteryService;
+use super::{
+    ConnectionParams, Event, Message, MessageConfigs, ProfileStatus, ProfileStatusRequest,
+    TransportConfig,
+};
 use crate::BleStack;
 use crate::external::{InitMessageService, RxMessageService, TxMessageService};
 use arrayvec::ArrayVec;
 use bt_hci::param::BdAddr;
 use core::num::NonZeroU8;
 use core::sync::atomic::Ordering;
-use embassy_futures::join::join5;
+use embassy_futures::join::{join, join3, join5};
 use embassy_futures::select::{Either, select};
 use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex};
 use embassy_sync::channel::Channel;
 use embassy_sync::mutex::Mutex;
 use embassy_sync::rwlock::RwLock;
 use embassy_sync::signal::Signal;
-use embassy_time::{Duration, Timer};
+use embassy_time::{Duration, Instant, Timer};
 use generic_array::GenericArray;
+use lokey::battery::{BatteryLevels, DeviceBatteryLevel};
 use lokey::util::{debug, error, info, unwrap, warn};
 use lokey::{Address, external, internal, storage};
 use portable_atomic::{AtomicBool, AtomicU8};
@@ -22,7 +27,7 @@ use trouble_host::gap::{GapConfig, PeripheralConfig};
 use trouble_host::gatt::{GattConnection, GattConnectionEvent, GattEvent};
 use trouble_host::prelude::{
     AdStructure, Advertisement, AdvertisementParameters, AttributeServer, AttributeTable,
-    BR_EDR_NOT_SUPPORTED, BluetoothUuid16, DefaultPacketPool, LE_GENERAL_DISCOVERABLE,
+    BR_EDR_NOT_SUPPORTED, BluetoothUuid16, ConnParams, DefaultPacketPool, LE_GENERAL_DISCOVERABLE,
     RequestedConnParams, SecurityLevel,
 };
 use trouble_host::{BleHostError, BondInformation, Identity, IdentityResolvingKey, LongTermKey};
@@ -30,6 +35,18 @@ use trouble_host::{BleHostError, BondInformation, Identity, IdentityResolvingKey
 // TODO: Don't hardcode maximum number of bond infos
 const MAX_NUM_BOND_INFOS: usize = 10;
 
+/// The maximum length of the advertisement data in bytes.
+const MAX_ADV_DATA_LEN: usize = 31;
+
+/// The maximum number of AD structures in the advertisement data.
+///
+/// Each AD structure takes up at least two bytes, so no more than this number of structures can
+/// fit into the advertisement data.
+const MAX_AD_STRUCTURES: usize = MAX_ADV_DATA_LEN / 2;
+
+/// The maximum number of messages that are collected within the coalesce window.
+const MAX_COALESCED_MESSAGES: usize = 8;
+
 static ACTIVE_SIGNAL: Signal<CriticalSectionRawMutex, bool> = Signal::new();
 static IS_ACTIVE: AtomicBool = AtomicBool::new(true);
 
@@ -43,6 +60,14 @@ pub struct Transport<Mcu: 'static, TxMessages, RxMessages, const CONN_MAX: usize
     internal_channel: internal::DynChannelRef<'static>,
     min_connection_interval: Option<Duration>,
     max_connection_interval: Option<Duration>,
+    profile_connection_params: &'static [ConnectionParams],
+    battery_service: bool,
+    startup_delay: Duration,
+    coalesce_window: Duration,
+    custom_ad_structures: &'static [AdStructure<'static>],
+    require_pairing_mode: bool,
+    pairing_mode_timeout: Duration,
+    message_configs: MessageConfigs,
 }
 
 impl<Mcu, TxMessage, RxMessage, const CONN_MAX: usize> external::Transport
@@ -76,6 +101,14 @@ where
             internal_channel: internal_channel.as_dyn_ref(),
             min_connection_interval: config.min_connection_interval,
             max_connection_interval: config.max_connection_interval,
+            profile_connection_params: config.profile_connection_params,
+            battery_service: config.battery_service,
+            startup_delay: config.startup_delay,
+            coalesce_window: config.coalesce_window,
+            custom_ad_structures: config.custom_ad_structures,
+            require_pairing_mode: config.require_pairing_mode,
+            pairing_mode_timeout: config.pairing_mode_timeout,
+            message_configs: config.message_configs,
         }
     }
 
@@ -97,8 +130,11 @@ where
             error!("Failed to set GAP config for BLE transport: {}", e);
         }
 
-        let tx_message_service = TxMessage::MessageService::init(&mut table);
-        let rx_message_service = RxMessage::MessageService::init(&mut table);
+        let tx_message_service = TxMessage::MessageService::init(&mut table, self.message_configs);
+        let rx_message_service = RxMessage::MessageService::init(&mut table, self.message_configs);
+        let battery_service = self
+            .battery_service
+            .then(|| BatteryService::new(&mut table));
 
         let server = AttributeServer::<
             '_,
@@ -129,14 +165,14 @@ where
         };
 
         let adv_params = AdvertisementParameters::default();
-        let mut adv_data = [0; 31];
+        let mut adv_data = [0; MAX_ADV_DATA_LEN];
 
         let adv_service_uuids_16_tx = TxMessage::service_uuids_16();
         let adv_service_uuids_128_tx = TxMessage::service_uuids_128();
         let adv_service_uuids_16_rx = RxMessage::service_uuids_16();
         let adv_service_uuids_128_rx = RxMessage::service_uuids_128();
 
-        let mut ad_structure_vec = ArrayVec::<AdStructure, 6>::new();
+        let mut ad_structure_vec = ArrayVec::<AdStructure, MAX_AD_STRUCTURES>::new();
         ad_structure_vec.extend([
             AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
             AdStructure::CompleteLocalName(self.name.as_bytes()),
@@ -154,11 +190,36 @@ where
             ad_structure_vec.push(AdStructure::ServiceUuids128(&adv_service_uuids_128_rx));
         }
 
-        let adv_data_len = unwrap!(AdStructure::encode_slice(&ad_structure_vec, &mut adv_data));
+        if ad_structure_vec
+            .try_extend_from_slice(self.custom_ad_structures)
+            .is_err()
+        {
+            error!(
+                "Too many custom AD structures for the BLE advertisement data (at most {} AD structures fit into {} bytes)",
+                MAX_AD_STRUCTURES, MAX_ADV_DATA_LEN
+            );
+            return;
+        }
+
+        let adv_data_len = match AdStructure::encode_slice(&ad_structure_vec, &mut adv_data) {
+            Ok(v) => v,
+            Err(e) => {
+                #[cfg(feature = "defmt")]
+                let e = defmt::Debug2Format(&e);
+                error!(
+                    "Failed to encode the BLE advertisement data (the name, service UUIDs and custom AD structures must fit into {} bytes): {}",
+                    MAX_ADV_DATA_LEN, e
+                );
+                return;
+            }
+        };
 
         // TODO: add services to scan data
         let scan_data = [0; 31];
 
+        // The bond info of each profile is stored with the profile index as the tag parameter. A
+        // profile whose entry is missing or can not be read is treated as unpaired, so it can be
+        // paired again and the entry is overwritten.
         let mut bond_infos = ArrayVec::<_, MAX_NUM_BOND_INFOS>::new();
         for i in 0..num_profiles.get() {
             match storage.fetch::<StoredBondInformation>(i).await {
@@ -185,7 +246,16 @@ where
         let cancel_activation_wait = Signal::<CriticalSectionRawMutex, ()>::new();
         let cancel_advertisement = Signal::<CriticalSectionRawMutex, ()>::new();
         let active_profile_index: AtomicU8 = AtomicU8::new(0);
+        let pairing_mode = AtomicBool::new(false);
+        let pairing_mode_signal = Signal::<CriticalSectionRawMutex, bool>::new();
         let advertise = async {
+            if self.startup_delay > Duration::from_ticks(0) {
+                debug!(
+                    "Waiting {}ms before starting BLE advertisement",
+                    self.startup_delay.as_millis()
+                );
+                Timer::after(self.startup_delay).await;
+            }
             loop {
                 *connection.write().await = None;
                 cancel_advertisement.reset();
@@ -205,7 +275,7 @@ where
                     }
                 }
 
-                let scannable = match &bond_infos.lock().await[profile_index as usize] {
+                let has_bond = match &bond_infos.lock().await[profile_index as usize] {
                     Some(bond_info) => {
                         #[cfg(feature = "defmt")]
                         debug!("Adding existing bond info: {}", bond_info);
@@ -214,13 +284,14 @@ where
                         if let Err(e) = ble_stack.add_bond_information(bond_info.0.clone()) {
                             error!("Failed to add bond info: {}", e);
                         }
-                        false
+                        true
                     }
                     None => {
                         debug!("No existing bond info found");
-                        true
+                        false
                     }
                 };
+                let scannable = !has_bond;
 
                 let adv = Advertisement::ConnectableScannableUndirected {
                     adv_data: &adv_data[..adv_data_len],
@@ -285,16 +356,33 @@ where
                     .await;
                 let device_address = Address(new_connection.peer_address().into_inner());
 
-                if self.min_connection_interval.is_some() || self.max_connection_interval.is_some()
-                {
-                    debug!("Updating connection parameters");
-                    let mut conn_params = RequestedConnParams::default();
-                    if let Some(v) = self.min_connection_interval {
-                        conn_params.min_connection_interval = v;
-                    }
-                    if let Some(v) = self.max_connection_interval {
-                        conn_params.max_connection_interval = v;
-                    }
+                if !accepts_connection(
+                    has_bond,
+                    self.require_pairing_mode,
+                    pairing_mode.load(Ordering::SeqCst),
+                ) {
+                    warn!(
+                        "Rejecting connection to profile without bond because pairing mode is not active, disconnecting..."
+                    );
+                    new_connection.disconnect();
+                    continue;
+                }
+
+                let default_params = ConnectionParams {
+                    min_connection_interval: self.min_connection_interval,
+                    max_connection_interval: self.max_connection_interval,
+                    ..ConnectionParams::new()
+                };
+                let params = match self.profile_connection_params.get(profile_index as usize) {
+                    Some(profile_params) => profile_params.or(default_params),
+                    None => default_params,
+                };
+                if !params.is_empty() {
+                    debug!(
+                        "Updating connection parameters for profile {}",
+                        profile_index
+                    );
+                    let conn_params = requested_conn_params(params, new_connection.params());
                     let result = new_connection
                         .update_connection_params(ble_stack, &conn_params)
                         .await;
@@ -368,7 +456,21 @@ where
                                         stored_bond_info.0.identity.bd_addr != bond.identity.bd_addr
                                     }
                                 }
-                                None => true,
+                                None => {
+                                    if self.require_pairing_mode
+                                        && !pairing_mode.load(Ordering::SeqCst)
+                                    {
+                                        warn!(
+                                            "Rejecting new bond because pairing mode is not active, disconnecting..."
+                                        );
+                                        connection.raw().disconnect();
+                                        self.internal_channel
+                                            .send(Event::Disconnected { device_address })
+                                            .await;
+                                        break;
+                                    }
+                                    true
+                                }
                             };
                             if store_new_bond_info {
                                 debug!("Writing bond info to flash");
@@ -386,6 +488,9 @@ where
                                 }
                                 bond_infos.lock().await[profile_index as usize] =
                                     Some(StoredBondInformation(bond));
+                                if pairing_mode.load(Ordering::SeqCst) {
+                                    pairing_mode_signal.signal(false);
+                                }
                             }
                         }
                         GattConnectionEvent::PairingFailed(error) => {
@@ -408,6 +513,9 @@ where
                                 .map(|v| v.encrypted())
                                 .unwrap_or(false)
                             {
+                                if let GattEvent::Write(write_event) = &event {
+                                    tx_message_service.handle_write(write_event);
+                                }
                                 if let GattEvent::Write(write_event) = &event
                                     && let Some(message) =
                                         rx_message_service.receive(write_event, connection).await
@@ -445,13 +553,38 @@ where
         };
 
         let handle_messages = async {
+            let mut messages = ArrayVec::<TxMessage, MAX_COALESCED_MESSAGES>::new();
+            let mut last_sent_message = None;
             loop {
-                let message = self.tx_channel.receive().await;
+                messages.push(self.tx_channel.receive().await);
+                if self.coalesce_window > Duration::from_ticks(0) {
+                    let deadline = Instant::now() + self.coalesce_window;
+                    while !messages.is_full() {
+                        let message =
+                            match select(self.tx_channel.receive(), Timer::at(deadline)).await {
+                                Either::First(v) => v,
+                                Either::Second(()) => break,
+                            };
+                        push_coalesced(
+                            &mut messages,
+                            last_sent_message.as_ref(),
+                            message,
+                            |message, previous| message.replaces(previous),
+                        );
+                    }
+                }
                 match &*connection.read().await {
                     Some(connection) => {
-                        tx_message_service.send(message, connection).await;
+                        last_sent_message = messages.last().cloned();
+                        for message in messages.drain(..) {
+                            tx_message_service.send(message, connection).await;
+                        }
+                    }
+                    None => {
+                        info!("Ignoring external message because BLE is disconnected");
+                        last_sent_message = None;
+                        messages.clear();
                     }
-                    None => info!("Ignoring external message because BLE is disconnected"),
                 }
             }
         };
@@ -526,6 +659,28 @@ where
                             })
                             .await;
                     }
+                    Message::SelectNextBondedProfile | Message::SelectPreviousBondedProfile => {
+                        let forward = matches!(message, Message::SelectNextBondedProfile);
+                        let active = active_profile_index.load(Ordering::SeqCst);
+                        let new_profile_index =
+                            find_bonded_profile(&bond_infos.lock().await, active, forward);
+                        let Some(new_profile_index) = new_profile_index else {
+                            info!("No other profile with a bond to switch to");
+                            continue;
+                        };
+                        info!("Switching to profile {}", new_profile_index);
+                        active_profile_index.store(new_profile_index, Ordering::SeqCst);
+                        if let Some(connection) = &*connection.read().await {
+                            connection.raw().disconnect();
+                        }
+                        cancel_advertisement.signal(());
+                        self.internal_channel
+                            .send(Event::SwitchedProfile {
+                                profile_index: new_profile_index,
+                                changed: true,
+                            })
+                            .await;
+                    }
                     Message::DisconnectActive => {
                         if let Some(connection) = &*connection.read().await {
                             connection.raw().disconnect();
@@ -585,6 +740,63 @@ where
                         }
                         cancel_advertisement.signal(());
                     }
+                    Message::EnterPairingMode => pairing_mode_signal.signal(true),
+                    Message::ExitPairingMode => pairing_mode_signal.signal(false),
+                }
+            }
+        };
+
+        let handle_profile_status_requests = async {
+            let bond_infos = &bond_infos;
+            let active_profile_index = &active_profile_index;
+            let result = self
+                .internal_channel
+                .serve(move |request: ProfileStatusRequest| async move {
+                    let bonded_profiles = bond_infos
+                        .lock()
+                        .await
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, bond_info)| bond_info.is_some())
+                        .fold(0, |mask, (i, _)| mask | (1 << i));
+                    ProfileStatus {
+                        request_id: request.request_id,
+                        active_profile_index: active_profile_index.load(Ordering::SeqCst),
+                        num_profiles: num_profiles.get(),
+                        bonded_profiles,
+                        is_active: IS_ACTIVE.load(Ordering::Acquire),
+                    }
+                })
+                .await;
+            let Err(e) = result;
+            error!("Failed to serve profile status requests: {}", e);
+        };
+
+        let handle_pairing_mode = async {
+            loop {
+                let mut active = pairing_mode_signal.wait().await;
+                while active {
+                    if !pairing_mode.swap(true, Ordering::SeqCst) {
+                        info!("Entering pairing mode");
+                        self.internal_channel
+                            .send(Event::PairingMode { active: true })
+                            .await;
+                    }
+                    active = match select(
+                        pairing_mode_signal.wait(),
+                        Timer::after(self.pairing_mode_timeout),
+                    )
+                    .await
+                    {
+                        Either::First(v) => v,
+                        Either::Second(()) => false,
+                    };
+                }
+                if pairing_mode.swap(false, Ordering::SeqCst) {
+                    info!("Exiting pairing mode");
+                    self.internal_channel
+                        .send(Event::PairingMode { active: false })
+                        .await;
                 }
             }
         };
@@ -600,12 +812,45 @@ where
             }
         };
 
-        join5(
-            run,
-            advertise,
-            handle_messages,
-            handle_internal_messages,
-            handle_activation,
+        let handle_battery_level = async {
+            let Some(battery_service) = &battery_service else {
+                return;
+            };
+            let mut receiver = unwrap!(self.internal_channel.receiver::<DeviceBatteryLevel>());
+            // The lowest battery level of all parts of a split keyboard is reported to the host.
+            let mut battery_levels = BatteryLevels::new();
+            loop {
+                if !battery_levels.update(receiver.next().await) {
+                    continue;
+                }
+                let Some(percentage) = battery_levels.lowest() else {
+                    continue;
+                };
+                debug!("Updating battery level to {}%", percentage);
+                if let Err(e) = battery_service.level.set(&server, &percentage) {
+                    error!("Failed to set battery level: {}", e);
+                }
+                if let Some(connection) = &*connection.read().await
+                    && let Err(e) = battery_service.level.notify(connection, &percentage).await
+                {
+                    error!("Failed to notify battery level: {}", e);
+                }
+            }
+        };
+
+        join(
+            join5(
+                run,
+                advertise,
+                handle_messages,
+                handle_internal_messages,
+                handle_activation,
+            ),
+            join3(
+                handle_battery_level,
+                handle_pairing_mode,
+                handle_profile_status_requests,
+            ),
         )
         .await;
     }
@@ -633,6 +878,88 @@ where
     }
 }
 
+/// Returns the index of the next (or previous, if `forward` is `false`) profile after the active
+/// profile that has a bond.
+///
+/// Returns `None` if no other profile has a bond.
+fn find_bonded_profile<T>(bond_infos: &[Option<T>], active: u8, forward: bool) -> Option<u8> {
+    let num_profiles = bond_infos.len();
+    (1..num_profiles)
+        .map(|offset| {
+            if forward {
+                (active as usize + offset) % num_profiles
+            } else {
+                (active as usize + num_profiles - offset) % num_profiles
+            }
+        })
+        .find(|i| bond_infos[*i].is_some())
+        .map(|i| i as u8)
+}
+
+/// Returns `true` if a host that connected to a profile is allowed to stay connected.
+///
+/// If the pairing mode is required, a profile without a bond only accepts connections while the
+/// pairing mode is active, so that no host can pair (with or without bonding) otherwise.
+fn accepts_connection(has_bond: bool, require_pairing_mode: bool, pairing_mode: bool) -> bool {
+    has_bond || !require_pairing_mode || pairing_mode
+}
+
+/// Builds the connection parameters to request, taking the parameters that are `None` from the
+/// parameters of the current connection.
+///
+/// If only one bound of the connection interval is specified, the other bound is clamped to it so
+/// that the requested interval range stays valid.
+fn requested_conn_params(params: ConnectionParams, current: ConnParams) -> RequestedConnParams {
+    let min_connection_interval = params.min_connection_interval.unwrap_or(
+        params
+            .max_connection_interval
+            .map_or(current.conn_interval, |max| max.min(current.conn_interval)),
+    );
+    let max_connection_interval = params
+        .max_connection_interval
+        .unwrap_or(current.conn_interval.max(min_connection_interval));
+    RequestedConnParams {
+        min_connection_interval,
+        max_connection_interval,
+        max_latency: params.max_latency.unwrap_or(current.peripheral_latency),
+        supervision_timeout: params
+            .supervision_timeout
+            .unwrap_or(current.supervision_timeout),
+        ..RequestedConnParams::default()
+    }
+}
+
+/// Adds the message to the queued messages, replacing the last queued message if `replaces`
+/// returns `true` for them.
+///
+/// The last queued message is only replaced if it replaced the message before it as well (or the
+/// last sent message, if it is the only queued message). Otherwise the last queued message could
+/// contain information that the new message does not contain, e.g. for a keyboard report that
+/// releases a key which the new report presses again, replacing it would hide the key release
+/// from the host.
+fn push_coalesced<M, const N: usize>(
+    messages: &mut ArrayVec<M, N>,
+    last_sent_message: Option<&M>,
+    message: M,
+    replaces: impl Fn(&M, &M) -> bool,
+) {
+    let predecessor = match messages.len() {
+        0 | 1 => last_sent_message,
+        len => Some(&messages[len - 2]),
+    };
+    let can_replace = match (messages.last(), predecessor) {
+        (Some(previous), Some(predecessor)) => {
+            replaces(&message, previous) && replaces(previous, predecessor)
+        }
+        _ => false,
+    };
+    if can_replace {
+        *unwrap!(messages.last_mut()) = message;
+    } else {
+        messages.push(message);
+    }
+}
+
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(transparent)]
@@ -717,6 +1044,116 @@ impl storage::Entry for StoredBondInformation {
 mod tests {
     use super::*;
 
+    #[test]
+    fn bonded_profile_cycling() {
+        let bond_infos = [Some(()), None, None, Some(()), None];
+        assert_eq!(find_bonded_profile(&bond_infos, 0, true), Some(3));
+        assert_eq!(find_bonded_profile(&bond_infos, 3, true), Some(0));
+        assert_eq!(find_bonded_profile(&bond_infos, 0, false), Some(3));
+        assert_eq!(find_bonded_profile(&bond_infos, 1, false), Some(0));
+        assert_eq!(find_bonded_profile(&[Some(()), None], 0, true), None);
+    }
+
+    #[test]
+    fn requested_conn_params_keep_current_values() {
+        let current = ConnParams {
+            conn_interval: Duration::from_millis(30),
+            peripheral_latency: 4,
+            supervision_timeout: Duration::from_secs(2),
+        };
+
+        let requested = requested_conn_params(ConnectionParams::new().max_latency(10), current);
+        assert_eq!(requested.min_connection_interval, Duration::from_millis(30));
+        assert_eq!(requested.max_connection_interval, Duration::from_millis(30));
+        assert_eq!(requested.max_latency, 10);
+        assert_eq!(requested.supervision_timeout, Duration::from_secs(2));
+
+        let requested = requested_conn_params(
+            ConnectionParams::new().max_connection_interval(Duration::from_millis(15)),
+            current,
+        );
+        assert_eq!(requested.min_connection_interval, Duration::from_millis(15));
+        assert_eq!(requested.max_connection_interval, Duration::from_millis(15));
+        assert_eq!(requested.max_latency, 4);
+
+        let requested = requested_conn_params(
+            ConnectionParams::new().min_connection_interval(Duration::from_millis(45)),
+            current,
+        );
+        assert_eq!(requested.min_connection_interval, Duration::from_millis(45));
+        assert_eq!(requested.max_connection_interval, Duration::from_millis(45));
+    }
+
+    #[test]
+    fn connection_acceptance() {
+        // Without the pairing mode requirement all connections are accepted
+        assert!(accepts_connection(false, false, false));
+        assert!(accepts_connection(true, false, false));
+        // Bonded hosts can always reconnect
+        assert!(accepts_connection(true, true, false));
+        assert!(accepts_connection(true, true, true));
+        // A profile without a bond only accepts connections in pairing mode
+        assert!(!accepts_connection(false, true, false));
+        assert!(accepts_connection(false, true, true));
+    }
+
+    /// Returns `true` if the message does not set any bit that is not set in the previous message.
+    fn is_subset(message: &u8, previous: &u8) -> bool {
+        previous & message == *message
+    }
+
+    fn coalesce(last_sent_message: Option<u8>, messages: &[u8]) -> ArrayVec<u8, 8> {
+        let mut queued = ArrayVec::new();
+        queued.push(messages[0]);
+        for message in &messages[1..] {
+            push_coalesced(&mut queued, last_sent_message.as_ref(), *message, is_subset);
+        }
+        queued
+    }
+
+    #[test]
+    fn coalescing_merges_releases() {
+        assert_eq!(
+            coalesce(Some(0b111), &[0b011, 0b001, 0b000]).as_slice(),
+            &[0b000]
+        );
+    }
+
+    #[test]
+    fn coalescing_keeps_presses() {
+        assert_eq!(
+            coalesce(Some(0b000), &[0b001, 0b011, 0b111]).as_slice(),
+            &[0b001, 0b011, 0b111]
+        );
+    }
+
+    #[test]
+    fn coalescing_keeps_release_before_press() {
+        // Press, release and press of the same key
+        assert_eq!(
+            coalesce(Some(0b000), &[0b001, 0b000, 0b001]).as_slice(),
+            &[0b001, 0b000, 0b001]
+        );
+        // The release of the first queued message is relative to the last sent message
+        assert_eq!(
+            coalesce(Some(0b001), &[0b000, 0b001]).as_slice(),
+            &[0b000, 0b001]
+        );
+        assert_eq!(
+            coalesce(Some(0b000), &[0b011, 0b001, 0b011]).as_slice(),
+            &[0b011, 0b001, 0b011]
+        );
+    }
+
+    #[test]
+    fn coalescing_without_last_sent_message() {
+        assert_eq!(coalesce(None, &[0b011, 0b001]).as_slice(), &[0b011, 0b001]);
+        assert_eq!(
+            coalesce(None, &[0b111, 0b011, 0b001]).as_slice(),
+            &[0b111, 0b001]
+        );
+    }
+
     #[test]
     fn bond_info_serialization1() {
         let bond_info = BondInformation::new(
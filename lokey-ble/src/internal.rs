@@ -7,7 +7,8 @@ use embassy_futures::select::{select, select3};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::Channel;
 use embassy_time::{Duration, TimeoutError, Timer, WithTimeout};
-use lokey::internal::MAX_MESSAGE_SIZE_WITH_TAG;
+use generic_array::GenericArray;
+use lokey::internal::{MAX_MESSAGE_SIZE_WITH_TAG, Message as _};
 use lokey::util::{debug, error, info, unwrap};
 use lokey::{Address, internal, storage};
 use trouble_host::gatt::{GattClient, GattConnectionEvent, GattEvent};
@@ -20,15 +21,213 @@ use trouble_host::types::gatt_traits::FromGattError;
 // TODO: Don't hardcode max number of peripherals
 const MAX_NUM_PERIPHERALS: usize = 10;
 
+/// Filter for the tags of the internal messages that are sent or received by the transport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MessageFilter {
+    /// All messages pass the filter.
+    All,
+    /// Only messages with one of the specified tags pass the filter.
+    Allow(&'static [[u8; 4]]),
+    /// Messages with one of the specified tags are filtered out.
+    Deny(&'static [[u8; 4]]),
+}
+
+impl MessageFilter {
+    /// Returns `true` if the serialized message (starting with its tag) passes the filter.
+    fn allows(&self, message_bytes: &[u8]) -> bool {
+        let Some(tag) = message_bytes.first_chunk::<4>() else {
+            return false;
+        };
+        match self {
+            Self::All => true,
+            Self::Allow(tags) => tags.contains(tag),
+            Self::Deny(tags) => !tags.contains(tag),
+        }
+    }
+}
+
 pub enum TransportConfig {
     Central {
         peripheral_addresses: &'static [Address],
+        max_reconnect_delay: Duration,
+        send_filter: MessageFilter,
+        receive_filter: MessageFilter,
     },
     Peripheral {
         central_address: Address,
+        max_reconnect_delay: Duration,
+        send_filter: MessageFilter,
+        receive_filter: MessageFilter,
     },
 }
 
+impl TransportConfig {
+    const DEFAULT_MAX_RECONNECT_DELAY: Duration = Duration::from_secs(16);
+
+    /// Creates a configuration for the central device that connects to the peripherals with the
+    /// specified addresses.
+    pub const fn central(peripheral_addresses: &'static [Address]) -> Self {
+        Self::Central {
+            peripheral_addresses,
+            max_reconnect_delay: Self::DEFAULT_MAX_RECONNECT_DELAY,
+            send_filter: MessageFilter::All,
+            receive_filter: MessageFilter::All,
+        }
+    }
+
+    /// Creates a configuration for a peripheral device that connects to the central with the
+    /// specified address.
+    pub const fn peripheral(central_address: Address) -> Self {
+        Self::Peripheral {
+            central_address,
+            max_reconnect_delay: Self::DEFAULT_MAX_RECONNECT_DELAY,
+            send_filter: MessageFilter::All,
+            receive_filter: MessageFilter::All,
+        }
+    }
+
+    /// Sets the maximum delay between two connection attempts.
+    ///
+    /// After a failed connection attempt the delay before the next attempt is doubled, until it
+    /// reaches this maximum. The delay is reset once a connection is established.
+    pub const fn max_reconnect_delay(mut self, value: Duration) -> Self {
+        match &mut self {
+            Self::Central {
+                max_reconnect_delay,
+                ..
+            }
+            | Self::Peripheral {
+                max_reconnect_delay,
+                ..
+            } => *max_reconnect_delay = value,
+        }
+        self
+    }
+
+    /// Sets which messages are sent to the other devices (defaults to [`MessageFilter::All`]).
+    ///
+    /// Messages that are only relevant for this device (e.g. requests for a component that only
+    /// runs on this device) can be filtered out to reduce the traffic between the devices.
+    pub const fn send_filter(mut self, value: MessageFilter) -> Self {
+        match &mut self {
+            Self::Central { send_filter, .. } | Self::Peripheral { send_filter, .. } => {
+                *send_filter = value
+            }
+        }
+        self
+    }
+
+    /// Sets which messages received from the other devices are passed to this device (defaults
+    /// to [`MessageFilter::All`]).
+    ///
+    /// This can be used to prevent a device from acting on messages that are meant for another
+    /// device (e.g. a peripheral that should ignore BLE profile changes of the central).
+    pub const fn receive_filter(mut self, value: MessageFilter) -> Self {
+        match &mut self {
+            Self::Central { receive_filter, .. } | Self::Peripheral { receive_filter, .. } => {
+                *receive_filter = value
+            }
+        }
+        self
+    }
+
+    const fn filters(&self) -> (MessageFilter, MessageFilter) {
+        match *self {
+            Self::Central {
+                send_filter,
+                receive_filter,
+                ..
+            }
+            | Self::Peripheral {
+                send_filter,
+                receive_filter,
+                ..
+            } => (send_filter, receive_filter),
+        }
+    }
+}
+
+/// Events of the internal BLE transport.
+///
+/// These events are only sent to the local device and are not forwarded to the other devices.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// A connection to the device with the specified address was established.
+    Connected { device_address: Address },
+    /// The connection to the device with the specified address was lost.
+    Disconnected { device_address: Address },
+}
+
+impl internal::Message for Event {
+    type Size = typenum::U7;
+
+    const TAG: [u8; 4] = [0x5d, 0x0b, 0xe3, 0x96];
+
+    fn from_bytes(bytes: GenericArray<u8, Self::Size>) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        match bytes.into_array::<7>() {
+            [0, address_bytes @ ..] => Some(Self::Connected {
+                device_address: Address(address_bytes),
+            }),
+            [1, address_bytes @ ..] => Some(Self::Disconnected {
+                device_address: Address(address_bytes),
+            }),
+            v => {
+                error!("invalid bytes {}", v);
+                None
+            }
+        }
+    }
+
+    fn to_bytes(&self) -> GenericArray<u8, Self::Size> {
+        let (tag_byte, address) = match self {
+            Self::Connected { device_address } => (0, device_address),
+            Self::Disconnected { device_address } => (1, device_address),
+        };
+        let mut bytes = [0; 7];
+        bytes[0] = tag_byte;
+        bytes[1..].copy_from_slice(&address.0);
+        bytes.into()
+    }
+}
+
+/// Publishes the specified event to the local device by passing it to the receiving end of this
+/// transport.
+async fn emit_event(event: Event) {
+    let message = Event::TAG.into_iter().chain(event.to_bytes()).collect();
+    RECV_CHANNEL.send(Message(message)).await;
+}
+
+struct Backoff {
+    delay: Duration,
+    max_delay: Duration,
+}
+
+impl Backoff {
+    const INITIAL_DELAY: Duration = Duration::from_millis(250);
+
+    fn new(max_delay: Duration) -> Self {
+        Self {
+            delay: Self::INITIAL_DELAY.min(max_delay),
+            max_delay,
+        }
+    }
+
+    async fn wait(&mut self) {
+        debug!("Retrying in {}ms", self.delay.as_millis());
+        Timer::after(self.delay).await;
+        self.delay = (self.delay * 2).min(self.max_delay);
+    }
+
+    fn reset(&mut self) {
+        self.delay = Self::INITIAL_DELAY.min(self.max_delay);
+    }
+}
+
 const SERVICE_UUID: Uuid = Uuid::Uuid128([
     0x2e, 0x51, 0x03, 0x5f, 0xd3, 0x9b, 0x41, 0xfe, 0x8b, 0x1b, 0x70, 0xa5, 0x3e, 0x58, 0xa9, 0x85,
 ]);
@@ -113,14 +312,22 @@ impl<Mcu: BleStack + 'static> internal::Transport for Transport<Mcu> {
         match self.config {
             TransportConfig::Central {
                 peripheral_addresses,
-            } => central(self.mcu, peripheral_addresses).await,
-            TransportConfig::Peripheral { central_address } => {
-                peripheral(self.mcu, central_address).await
-            }
+                max_reconnect_delay,
+                ..
+            } => central(self.mcu, peripheral_addresses, max_reconnect_delay).await,
+            TransportConfig::Peripheral {
+                central_address,
+                max_reconnect_delay,
+                ..
+            } => peripheral(self.mcu, central_address, max_reconnect_delay).await,
         }
     }
 
     async fn send(&self, message_bytes: &[u8]) {
+        let (send_filter, _) = self.config.filters();
+        if !send_filter.allows(message_bytes) {
+            return;
+        }
         if IS_CONNECTED.load(Ordering::Acquire) {
             match ArrayVec::try_from(message_bytes) {
                 Ok(array) => SEND_CHANNEL.send(Message(array)).await,
@@ -130,7 +337,14 @@ impl<Mcu: BleStack + 'static> internal::Transport for Transport<Mcu> {
     }
 
     async fn receive(&self, buf: &mut [u8]) -> usize {
-        let array = RECV_CHANNEL.receive().await.0;
+        let (_, receive_filter) = self.config.filters();
+        let array = loop {
+            let array = RECV_CHANNEL.receive().await.0;
+            // Events of this transport are emitted locally and are never filtered out.
+            if array.starts_with(&Event::TAG) || receive_filter.allows(&array) {
+                break array;
+            }
+        };
         if buf.len() < MAX_MESSAGE_SIZE_WITH_TAG {
             panic!("Provided buffer is smaller than configured max message size");
         }
@@ -142,7 +356,11 @@ impl<Mcu: BleStack + 'static> internal::Transport for Transport<Mcu> {
     }
 }
 
-async fn central<M: BleStack + 'static>(mcu: &'static M, peripheral_addresses: &'static [Address]) {
+async fn central<M: BleStack + 'static>(
+    mcu: &'static M,
+    peripheral_addresses: &'static [Address],
+    max_reconnect_delay: Duration,
+) {
     let ble_stack = mcu.ble_stack();
     let ble_host_central = mcu.ble_host_central();
     let ble_host_runner = mcu.ble_host_runner();
@@ -175,6 +393,7 @@ async fn central<M: BleStack + 'static>(mcu: &'static M, peripheral_addresses: &
     };
 
     let connect = async {
+        let mut backoff = Backoff::new(max_reconnect_delay);
         loop {
             debug!("Looking for BLE connection to peripheral");
             let connection = match ble_host_central.lock().await.connect(&config).await {
@@ -183,7 +402,7 @@ async fn central<M: BleStack + 'static>(mcu: &'static M, peripheral_addresses: &
                     #[cfg(feature = "defmt")]
                     let e = defmt::Debug2Format(&e);
                     error!("Failed to connect: {}", e);
-                    Timer::after_secs(1).await;
+                    backoff.wait().await;
                     continue;
                 }
             };
@@ -198,16 +417,20 @@ async fn central<M: BleStack + 'static>(mcu: &'static M, peripheral_addresses: &
                         #[cfg(feature = "defmt")]
                         let e = defmt::Debug2Format(&e);
                         error!("Failed to create GATT client: {}", e);
-                        Timer::after_secs(1).await;
+                        backoff.wait().await;
                         continue;
                     }
                     Err(TimeoutError) => {
                         error!("Reached timeout while creating GATT client");
+                        backoff.wait().await;
                         continue;
                     }
                 };
             info!("BLE connected to peripheral");
+            backoff.reset();
             IS_CONNECTED.store(true, Ordering::Release);
+            let device_address = Address(connection.peer_address().into_inner());
+            emit_event(Event::Connected { device_address }).await;
 
             let check_connection = async {
                 loop {
@@ -316,13 +539,18 @@ async fn central<M: BleStack + 'static>(mcu: &'static M, peripheral_addresses: &
             select3(check_connection, client_task, handle_messages()).await;
 
             IS_CONNECTED.store(false, Ordering::Release);
+            emit_event(Event::Disconnected { device_address }).await;
         }
     };
 
     join(run, connect).await;
 }
 
-async fn peripheral<M: BleStack + 'static>(mcu: &'static M, central_address: Address) {
+async fn peripheral<M: BleStack + 'static>(
+    mcu: &'static M,
+    central_address: Address,
+    max_reconnect_delay: Duration,
+) {
     let ble_host_peripheral = mcu.ble_host_peripheral();
     let ble_host_runner = mcu.ble_host_runner();
 
@@ -345,6 +573,7 @@ async fn peripheral<M: BleStack + 'static>(mcu: &'static M, central_address: Add
     };
 
     let connect = async {
+        let mut backoff = Backoff::new(max_reconnect_delay);
         loop {
             info!("Starting BLE advertisement");
             let advertiser = match ble_host_peripheral
@@ -358,7 +587,7 @@ async fn peripheral<M: BleStack + 'static>(mcu: &'static M, central_address: Add
                     #[cfg(feature = "defmt")]
                     let e = defmt::Debug2Format(&e);
                     error!("Failed to advertise: {}", e);
-                    Timer::after_secs(1).await;
+                    backoff.wait().await;
                     continue;
                 }
             };
@@ -366,7 +595,7 @@ async fn peripheral<M: BleStack + 'static>(mcu: &'static M, central_address: Add
                 Ok(v) => v,
                 Err(e) => {
                     error!("Failed to accept connection: {}", e);
-                    Timer::after_secs(1).await;
+                    backoff.wait().await;
                     continue;
                 }
             };
@@ -374,12 +603,15 @@ async fn peripheral<M: BleStack + 'static>(mcu: &'static M, central_address: Add
                 Ok(v) => v,
                 Err(e) => {
                     error!("Failed to add attribute server to connection: {}", e);
-                    Timer::after_secs(1).await;
+                    backoff.wait().await;
                     continue;
                 }
             };
             IS_CONNECTED.store(true, Ordering::Release);
             info!("BLE connected to central");
+            backoff.reset();
+            let device_address = Address(connection.raw().peer_address().into_inner());
+            emit_event(Event::Connected { device_address }).await;
 
             let receive = async {
                 loop {
@@ -446,6 +678,7 @@ async fn peripheral<M: BleStack + 'static>(mcu: &'static M, central_address: Add
             select(receive, send).await;
 
             IS_CONNECTED.store(false, Ordering::Release);
+            emit_event(Event::Disconnected { device_address }).await;
         }
     };
 
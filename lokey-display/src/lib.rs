@@ -0,0 +1,309 @@
+//! Component for showing the status of a device on a display.
+//!
+//! The [`Display`] component keeps track of the [`Status`] of the device by listening to the
+//! events on the internal channel and renders it to a [`DrawTarget`] whenever it changes.
+//!
+//! # Feature flags
+//!
+#![doc = document_features::document_features!(feature_label = r#"<span class="stab portability"><code>{feature}</code></span>"#)]
+//!
+
+#![no_std]
+#![cfg_attr(docsrs, feature(doc_cfg))]
+
+use embassy_futures::select::{Either5, select5};
+use embassy_time::{Duration, Timer};
+pub use embedded_graphics_core::draw_target::DrawTarget;
+use lokey::battery::BatteryLevel;
+use lokey::util::{error, unwrap};
+use lokey::{Component, DynContext};
+
+/// The status of the device that is passed to the [`Render`] implementation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct Status {
+    /// The last measured battery level in percent.
+    pub battery_level: Option<u8>,
+    /// The index of the active BLE profile.
+    ///
+    /// This is only updated if the `ble` feature is enabled.
+    pub ble_profile: Option<u8>,
+    /// Whether a host is connected via BLE.
+    ///
+    /// This is only updated if the `ble` feature is enabled.
+    pub ble_connected: bool,
+    /// Whether the device is advertising via BLE.
+    ///
+    /// This is only updated if the `ble` feature is enabled.
+    pub ble_advertising: bool,
+    /// The ID of the active layer.
+    ///
+    /// This is only updated if the `layer` feature is enabled. The layer is received from the
+    /// `LayerChangeNotifier` component of `lokey-layer`, which has to be enabled on the device
+    /// that has the `LayerManager`.
+    pub active_layer: Option<u8>,
+    /// Whether Caps Lock is enabled on the host.
+    ///
+    /// This is only updated if the `keyboard` feature is enabled. The state is read from the LED
+    /// output report of the host (see `HostLeds` of `lokey-keyboard`), so it is only known on the
+    /// device that is connected to the host.
+    pub caps_lock: bool,
+}
+
+/// A change of the device that may change the [`Status`].
+enum Update {
+    BatteryLevel(u8),
+    #[cfg(feature = "ble")]
+    Ble(lokey_ble::external::Event),
+    #[cfg_attr(not(any(feature = "layer", test)), allow(dead_code))]
+    ActiveLayer(u8),
+    #[cfg_attr(not(any(feature = "keyboard", test)), allow(dead_code))]
+    CapsLock(bool),
+    Refresh,
+}
+
+impl Status {
+    /// Applies the update to the status and returns `true` if the display has to be redrawn.
+    fn update(&mut self, update: Update) -> bool {
+        let previous = self.clone();
+        match update {
+            Update::BatteryLevel(percentage) => self.battery_level = Some(percentage),
+            #[cfg(feature = "ble")]
+            Update::Ble(event) => self.update_ble(event),
+            Update::ActiveLayer(layer) => self.active_layer = Some(layer),
+            Update::CapsLock(value) => self.caps_lock = value,
+            Update::Refresh => return true,
+        }
+        *self != previous
+    }
+
+    #[cfg(feature = "ble")]
+    fn update_ble(&mut self, event: lokey_ble::external::Event) {
+        use lokey_ble::external::Event;
+        match event {
+            Event::StartedAdvertising { .. } => self.ble_advertising = true,
+            Event::StoppedAdvertising { .. } => self.ble_advertising = false,
+            Event::Connected { .. } => {
+                self.ble_connected = true;
+                self.ble_advertising = false;
+            }
+            Event::Disconnected { .. } => self.ble_connected = false,
+            Event::SwitchedProfile { profile_index, .. } => self.ble_profile = Some(profile_index),
+            Event::PairingMode { .. } => {}
+        }
+    }
+}
+
+/// Trait for rendering the [`Status`] to a display.
+///
+/// This trait is implemented for all closures with the signature
+/// `FnMut(&Status, DynContext, &mut T) -> Result<(), T::Error>`. The [`DynContext`] can be used to
+/// access additional information from the application state.
+pub trait Render<T: DrawTarget> {
+    /// Renders the status to the specified draw target.
+    fn render(
+        &mut self,
+        status: &Status,
+        context: DynContext,
+        target: &mut T,
+    ) -> Result<(), T::Error>;
+}
+
+impl<T, F> Render<T> for F
+where
+    T: DrawTarget,
+    F: FnMut(&Status, DynContext, &mut T) -> Result<(), T::Error>,
+{
+    fn render(
+        &mut self,
+        status: &Status,
+        context: DynContext,
+        target: &mut T,
+    ) -> Result<(), T::Error> {
+        self(status, context, target)
+    }
+}
+
+/// Component that renders the status of the device to a display.
+///
+/// The display is redrawn whenever the [`Status`] changes. After rendering, the flush function
+/// that is passed to [`Display::run`] is called, which should write the buffer of displays that
+/// buffer the drawn content to the display (e.g. `|display| display.flush()`). Displays that
+/// draw directly can pass `|_| Ok(())`.
+///
+/// # Example
+///
+/// ```ignore
+/// use lokey::{AnyState, ComponentSupport, Context, DynContext, Transports};
+/// use lokey_display::{Display, Render, Status};
+///
+/// impl<S: AnyState, R: Render<MyDisplay>> ComponentSupport<Display<R>, S> for MyDevice {
+///     async fn enable<T>(component: Display<R>, context: Context<Self, T, S>)
+///     where
+///         T: Transports<Self::Mcu>,
+///     {
+///         let display = MyDisplay::new(/* ... */);
+///         component
+///             .run(display, |display| display.flush(), context.as_dyn())
+///             .await;
+///     }
+/// }
+///
+/// let display = Display::new(|status: &Status, _: DynContext, target: &mut MyDisplay| {
+///     // Draw the status with embedded-graphics...
+///     Ok(())
+/// });
+/// context.enable(display).await;
+/// ```
+pub struct Display<R> {
+    renderer: R,
+    refresh_interval: Option<Duration>,
+}
+
+impl<R> Component for Display<R> {}
+
+impl<R> Display<R> {
+    /// Creates a new [`Display`] component that uses the specified renderer.
+    pub const fn new(renderer: R) -> Self {
+        Self {
+            renderer,
+            refresh_interval: None,
+        }
+    }
+
+    /// Sets the interval in which the display is redrawn even if the status did not change.
+    ///
+    /// This is useful if the renderer shows information that is not part of the [`Status`]. By
+    /// default, the display is only redrawn when the status changes.
+    pub const fn refresh_interval(mut self, value: Duration) -> Self {
+        self.refresh_interval = Some(value);
+        self
+    }
+
+    /// Runs the display component.
+    ///
+    /// The `flush` function is called after every rendering of the status to write the drawn
+    /// content to the display.
+    pub async fn run<T, F>(mut self, mut target: T, mut flush: F, context: DynContext)
+    where
+        T: DrawTarget,
+        R: Render<T>,
+        F: FnMut(&mut T) -> Result<(), T::Error>,
+    {
+        let mut battery_receiver = unwrap!(context.internal_channel.receiver::<BatteryLevel>());
+        #[cfg(feature = "ble")]
+        let mut ble_receiver = unwrap!(
+            context
+                .internal_channel
+                .receiver::<lokey_ble::external::Event>()
+        );
+        #[cfg(feature = "layer")]
+        let mut layer_receiver = unwrap!(
+            context
+                .internal_channel
+                .receiver::<lokey_layer::LayerChanged>()
+        );
+
+        let mut status = Status::default();
+        #[cfg(feature = "keyboard")]
+        {
+            status.caps_lock = lokey_keyboard::HostLeds::current().caps_lock();
+        }
+        let mut redraw = true;
+        loop {
+            if redraw {
+                if self.renderer.render(&status, context, &mut target).is_err() {
+                    error!("Failed to render status to display");
+                } else if flush(&mut target).is_err() {
+                    error!("Failed to flush display");
+                }
+            }
+
+            let battery_level =
+                async { Update::BatteryLevel(battery_receiver.next().await.percentage) };
+            #[cfg(feature = "ble")]
+            let ble_event = async { Update::Ble(ble_receiver.next().await) };
+            #[cfg(not(feature = "ble"))]
+            let ble_event = core::future::pending();
+            #[cfg(feature = "layer")]
+            let layer_change = async { Update::ActiveLayer(layer_receiver.next().await.layer.0) };
+            #[cfg(not(feature = "layer"))]
+            let layer_change = core::future::pending();
+            #[cfg(feature = "keyboard")]
+            let host_leds_change = async {
+                Update::CapsLock(
+                    lokey_keyboard::HostLeds::wait_for_change()
+                        .await
+                        .caps_lock(),
+                )
+            };
+            #[cfg(not(feature = "keyboard"))]
+            let host_leds_change = core::future::pending();
+            let refresh = async {
+                match self.refresh_interval {
+                    Some(interval) => Timer::after(interval).await,
+                    None => core::future::pending().await,
+                }
+                Update::Refresh
+            };
+
+            let update = match select5(
+                battery_level,
+                ble_event,
+                layer_change,
+                host_leds_change,
+                refresh,
+            )
+            .await
+            {
+                Either5::First(v)
+                | Either5::Second(v)
+                | Either5::Third(v)
+                | Either5::Fourth(v)
+                | Either5::Fifth(v) => v,
+            };
+            redraw = status.update(update);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_updates() {
+        let mut status = Status::default();
+        assert!(status.update(Update::BatteryLevel(80)));
+        assert_eq!(status.battery_level, Some(80));
+        assert!(status.update(Update::ActiveLayer(2)));
+        assert_eq!(status.active_layer, Some(2));
+        assert!(status.update(Update::CapsLock(true)));
+        assert!(status.caps_lock);
+
+        // Updates that do not change the status do not redraw the display, except for refreshes
+        assert!(!status.update(Update::ActiveLayer(2)));
+        assert!(!status.update(Update::CapsLock(true)));
+        assert!(status.update(Update::Refresh));
+        assert!(status.update(Update::CapsLock(false)));
+        assert!(!status.caps_lock);
+    }
+
+    #[cfg(feature = "ble")]
+    #[test]
+    fn ble_status_updates() {
+        use lokey::Address;
+        use lokey_ble::external::Event;
+
+        let device_address = Address([0; 6]);
+        let mut status = Status::default();
+        assert!(status.update(Update::Ble(Event::StartedAdvertising { scannable: true })));
+        assert!(status.ble_advertising);
+        assert!(status.update(Update::Ble(Event::Connected { device_address })));
+        assert!(status.ble_connected);
+        assert!(!status.ble_advertising);
+        assert!(status.update(Update::Ble(Event::Disconnected { device_address })));
+        assert!(!status.ble_connected);
+    }
+}
@@ -1,6 +1,7 @@
 use proc_macro::TokenStream;
 use proc_macro_error::abort;
 use quote::{ToTokens, quote};
+use syn::parse::{Parse, ParseStream};
 use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
 use syn::{Ident, parse_macro_input};
@@ -45,6 +46,99 @@ pub fn layout(item: TokenStream) -> TokenStream {
     let arrays = parse_macro_input!(
         item with Punctuated::<syn::ExprArray, syn::token::Comma>::parse_terminated
     );
+    build_layout(arrays).into()
+}
+
+struct SplitLayoutInput {
+    central_keys: Option<syn::Expr>,
+    layers: Punctuated<syn::Expr, syn::token::Comma>,
+}
+
+impl Parse for SplitLayoutInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let central_keys = if input.peek(Ident) && input.peek2(syn::token::Eq) {
+            let ident = input.parse::<Ident>()?;
+            if ident != "central_keys" {
+                return Err(syn::Error::new(
+                    ident.span(),
+                    "Expected `central_keys = <expr>;`",
+                ));
+            }
+            input.parse::<syn::token::Eq>()?;
+            let expr = input.parse::<syn::Expr>()?;
+            input.parse::<syn::token::Semi>()?;
+            Some(expr)
+        } else {
+            None
+        };
+        let layers = Punctuated::parse_terminated(input)?;
+        Ok(Self {
+            central_keys,
+            layers,
+        })
+    }
+}
+
+fn split_layer(expr: syn::Expr) -> (syn::ExprArray, syn::ExprArray) {
+    match expr {
+        syn::Expr::Binary(syn::ExprBinary {
+            left,
+            op: syn::BinOp::BitOr(_),
+            right,
+            ..
+        }) => match (*left, *right) {
+            (syn::Expr::Array(left), syn::Expr::Array(right)) => (left, right),
+            (left, _) => abort!(
+                left.span(),
+                "Expected a layer in the form `[<central actions>] | [<peripheral actions>]`"
+            ),
+        },
+        expr => abort!(
+            expr.span(),
+            "Expected a layer in the form `[<central actions>] | [<peripheral actions>]`"
+        ),
+    }
+}
+
+pub fn split_layout(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as SplitLayoutInput);
+
+    let mut num_central_keys = None;
+    let mut arrays = Punctuated::<syn::ExprArray, syn::token::Comma>::new();
+    for layer in input.layers {
+        let (central, peripheral) = split_layer(layer);
+        match num_central_keys {
+            None => num_central_keys = Some(central.elems.len()),
+            Some(v) if v != central.elems.len() => abort!(
+                central.span(),
+                "All layers must have an equal amount of actions on the central half"
+            ),
+            Some(_) => {}
+        }
+        let mut array = central;
+        array.elems.extend(peripheral.elems);
+        arrays.push(array);
+    }
+    let num_central_keys = num_central_keys.unwrap_or(0);
+
+    let layout = build_layout(arrays);
+    let central_keys_check = input.central_keys.map(|central_keys| {
+        quote! {
+            const _: () = ::core::assert!(
+                (#central_keys) as usize == #num_central_keys,
+                "The central half of the layout does not match the specified number of central keys"
+            );
+        }
+    });
+
+    quote! {{
+        #central_keys_check
+        #layout
+    }}
+    .into()
+}
+
+fn build_layout(arrays: Punctuated<syn::ExprArray, syn::token::Comma>) -> proc_macro2::TokenStream {
     let layer_actions = layer_actions(arrays);
 
     let combined_actions = layer_actions
@@ -132,5 +226,4 @@ pub fn layout(item: TokenStream) -> TokenStream {
         #struct_impl
         ::lokey_keyboard::Layout::new(__LayoutActionContainer(#(#combined_actions,)*))
     }}
-    .into()
 }
@@ -8,3 +8,9 @@ use proc_macro_error::proc_macro_error;
 pub fn layout(item: TokenStream) -> TokenStream {
     layout::layout(item)
 }
+
+#[proc_macro_error]
+#[proc_macro]
+pub fn split_layout(item: TokenStream) -> TokenStream {
+    layout::split_layout(item)
+}
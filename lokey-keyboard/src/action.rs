@@ -1,18 +1,32 @@
-use crate::{Key, KeyboardReport, KeyboardReportState};
+use crate::caps_word::CapsWordState;
+use crate::capture::KeyCapture;
+use crate::combo::ComboEvent;
+use crate::defer::{self, KeyDeferral};
+use crate::unicode::hex_digits;
+use crate::{
+    ConsumerReportState, ConsumerUsage, HidLayout, HidLayoutState, HostLeds, Key, KeySet,
+    KeyStroke, KeyboardReport, KeyboardReportState, SystemControlReport, SystemControlUsage,
+    TappingTermState, UnicodeMode, UnicodeModeState, UsLayout,
+};
+use arrayvec::ArrayVec;
+use core::cell::Cell;
 use core::future::Future;
 use core::sync::atomic::Ordering;
 use derive_more::{Display, Error};
-use embassy_futures::select::{Either, select};
+use embassy_futures::select::{Either, Either3, select, select3};
+use embassy_sync::blocking_mutex;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::mutex::Mutex;
 use embassy_sync::signal::Signal;
-use embassy_time::{Duration, Timer};
+use embassy_time::{Duration, Instant, Timer};
+use enumset::enum_set;
 use generic_array::{ArrayLength, GenericArray};
 use lokey::external::toggle;
-use lokey::util::{error, unwrap, warn};
-use lokey::{Address, AnyState, Context, Device, Transports};
+use lokey::storage::Storage;
+use lokey::util::{debug, error, info, unwrap, warn};
+use lokey::{Address, AnyState, Context, Device, Mcu, Transports, internal};
 use lokey_layer::{LayerId, LayerManagerEntry, LayerManagerQuery};
-use portable_atomic::AtomicBool;
+use portable_atomic::{AtomicBool, AtomicU8};
 use seq_macro::seq;
 use typenum::Unsigned;
 
@@ -164,6 +178,28 @@ pub trait Action: Send + Sync + 'static {
         S: AnyState;
 }
 
+/// Actions that are stored in a `static` (e.g. because they can not be constructed in a const
+/// context) can be used by reference.
+impl<A: Action> Action for &'static A {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        (*self).on_press(context).await
+    }
+
+    async fn on_release<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        (*self).on_release(context).await
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct NoOp;
 
@@ -293,39 +329,65 @@ impl Action for Key {
     }
 }
 
-pub struct Layer {
-    pub layer: LayerId,
-    layer_manager_entry: Mutex<CriticalSectionRawMutex, Option<LayerManagerEntry>>,
+/// Action that presses a key, optionally repeating it while the key is held.
+///
+/// Without auto-repeat, this behaves the same as using the [`Key`] as the action directly.
+/// Auto-repeat is disabled by default, as hosts usually repeat held keys themselves.
+pub struct KeyCode {
+    key: Key,
+    auto_repeat: Option<(Duration, Duration)>,
+    released: Signal<CriticalSectionRawMutex, ()>,
 }
 
-impl Layer {
-    pub const fn new(layer: LayerId) -> Self {
+impl KeyCode {
+    pub const fn new(key: Key) -> Self {
         Self {
-            layer,
-            layer_manager_entry: Mutex::new(None),
+            key,
+            auto_repeat: None,
+            released: Signal::new(),
         }
     }
+
+    /// Enables auto-repeat (typematic) for devices whose host does not repeat held keys.
+    ///
+    /// Once the key was held for `delay`, it is released and pressed again every `interval` until
+    /// the key is released.
+    pub const fn auto_repeat(mut self, delay: Duration, interval: Duration) -> Self {
+        self.auto_repeat = Some((delay, interval));
+        self
+    }
 }
 
-impl Action for Layer {
+impl Action for KeyCode {
     async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
     where
         D: Device,
         T: Transports<D::Mcu>,
         S: AnyState,
     {
-        if let Some(entry) = self.layer_manager_entry.lock().await.take() {
-            warn!(
-                "on_press was called again without calling on_release first for layer {}",
-                self.layer.0
-            );
-            if let Some(layer_manager) = context.state.try_query::<LayerManagerQuery>() {
-                layer_manager.remove(entry);
-            }
+        self.released.reset();
+        self.key.on_press(context).await;
+        let Some((delay, interval)) = self.auto_repeat else {
+            return;
+        };
+        // The key is released by this function instead of `on_release` while auto-repeat is
+        // enabled, so that a repetition can not press the key again after it was released.
+        if let Either::Second(()) = select(Timer::after(delay), self.released.wait()).await {
+            self.key.on_release(context).await;
+            return;
         }
-        if let Some(layer_manager) = context.state.try_query::<LayerManagerQuery>() {
-            let entry = layer_manager.push(self.layer);
-            *self.layer_manager_entry.lock().await = Some(entry);
+        loop {
+            self.key.on_release(context).await;
+            // Keep the key released for a moment, so that the host does not miss the repetition
+            if let Either::Second(()) = select(Timer::after_millis(10), self.released.wait()).await
+            {
+                return;
+            }
+            self.key.on_press(context).await;
+            if let Either::Second(()) = select(Timer::after(interval), self.released.wait()).await {
+                self.key.on_release(context).await;
+                return;
+            }
         }
     }
 
@@ -335,47 +397,44 @@ impl Action for Layer {
         T: Transports<D::Mcu>,
         S: AnyState,
     {
-        if let Some(entry) = self.layer_manager_entry.lock().await.take()
-            && let Some(layer_manager) = context.state.try_query::<LayerManagerQuery>()
-        {
-            layer_manager.remove(entry);
+        if self.auto_repeat.is_some() {
+            self.released.signal(());
+        } else {
+            self.key.on_release(context).await;
         }
     }
 }
 
-pub struct PerLayer<A: ActionContainer> {
-    actions: A,
-    layer_ids: GenericArray<LayerId, A::NumChildren>,
-    active_action_index: Mutex<CriticalSectionRawMutex, Option<usize>>,
+/// Action that presses a consumer control key (e.g. a media key).
+///
+/// Any number of consumer control keys can be held at the same time. This action requires
+/// [`ConsumerReportState`] to be part of the state.
+pub struct ConsumerControl {
+    usage: ConsumerUsage,
 }
 
-impl<A: ActionContainer> PerLayer<A> {
-    pub const fn new(actions: A, layer_ids: GenericArray<LayerId, A::NumChildren>) -> Self {
-        Self {
-            actions,
-            layer_ids,
-            active_action_index: Mutex::new(None),
-        }
+impl ConsumerControl {
+    pub const fn new(usage: ConsumerUsage) -> Self {
+        Self { usage }
     }
 }
 
-impl<A: ActionContainer> Action for PerLayer<A> {
+impl Action for ConsumerControl {
     async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
     where
         D: Device,
         T: Transports<D::Mcu>,
         S: AnyState,
     {
-        if let Some(layer_manager) = context.state.try_query::<LayerManagerQuery>() {
-            let active_layer_id = layer_manager.active();
-            if let Some(index) = self
-                .layer_ids
-                .iter()
-                .position(|layer_id| *layer_id == active_layer_id)
-            {
-                *self.active_action_index.lock().await = Some(index);
-                unwrap!(self.actions.child_on_press(index, context).await);
-            }
+        let Some(report) = context.state.try_get::<ConsumerReportState>() else {
+            error!("ConsumerControl action requires ConsumerReportState");
+            return;
+        };
+        let consumer_report = report.modify_and_get(|consumer_report| {
+            consumer_report.usages.insert(self.usage);
+        });
+        if let Err(e) = context.external_channel.try_send(consumer_report).await {
+            error!("Failed to send consumer report: {:?}", e);
         }
     }
 
@@ -385,134 +444,85 @@ impl<A: ActionContainer> Action for PerLayer<A> {
         T: Transports<D::Mcu>,
         S: AnyState,
     {
-        if let Some(index) = *self.active_action_index.lock().await {
-            unwrap!(self.actions.child_on_release(index, context).await);
+        let Some(report) = context.state.try_get::<ConsumerReportState>() else {
+            error!("ConsumerControl action requires ConsumerReportState");
+            return;
+        };
+        let consumer_report = report.modify_and_get(|consumer_report| {
+            consumer_report.usages.remove(self.usage);
+        });
+        if let Err(e) = context.external_channel.try_send(consumer_report).await {
+            error!("Failed to send consumer report: {:?}", e);
         }
     }
 }
 
-pub struct Toggle<A> {
-    action: A,
-    active: AtomicBool,
+/// Action that presses a system control key (e.g. power down or sleep).
+///
+/// Only one system control key can be held at a time, pressing another system control key
+/// replaces the held one.
+pub struct SystemControl {
+    usage: SystemControlUsage,
 }
 
-impl<A: Action> Toggle<A> {
-    pub const fn new(action: A) -> Self {
-        Self {
-            action,
-            active: AtomicBool::new(false),
-        }
+impl SystemControl {
+    pub const fn new(usage: SystemControlUsage) -> Self {
+        Self { usage }
     }
 }
 
-impl<A: Action> Action for Toggle<A> {
+impl Action for SystemControl {
     async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
     where
         D: Device,
         T: Transports<D::Mcu>,
         S: AnyState,
     {
-        let active = self.active.load(Ordering::SeqCst);
-        if active {
-            self.action.on_release(context).await;
-        } else {
-            self.action.on_press(context).await;
+        let report = SystemControlReport {
+            usage: Some(self.usage),
+        };
+        if let Err(e) = context.external_channel.try_send(report).await {
+            error!("Failed to send system control report: {:?}", e);
         }
-        self.active.store(!active, Ordering::SeqCst);
     }
 
-    async fn on_release<D, T, S>(&self, _context: Context<D, T, S>)
+    async fn on_release<D, T, S>(&self, context: Context<D, T, S>)
     where
         D: Device,
         T: Transports<D::Mcu>,
         S: AnyState,
     {
+        let report = SystemControlReport::empty();
+        if let Err(e) = context.external_channel.try_send(report).await {
+            error!("Failed to send system control report: {:?}", e);
+        }
     }
 }
 
-pub struct Sticky<A> {
-    action: A,
-    timeout: Duration,
-    lazy: bool,
-    ignore_modifiers: bool,
-    is_held: AtomicBool,
-    was_released: AtomicBool,
+/// Action that presses multiple keys at once.
+///
+/// All keys (including modifiers) are added to the keyboard report in a single update, so the host
+/// receives the whole key combination in one report. This makes shortcuts register reliably, as the
+/// host never sees the regular keys without the modifiers.
+pub struct KeyCombo {
+    keys: &'static [Key],
 }
 
-impl<A: Action> Sticky<A> {
-    pub const fn new(action: A) -> Self {
-        Self {
-            action,
-            timeout: Duration::from_secs(1),
-            lazy: false,
-            ignore_modifiers: true,
-            is_held: AtomicBool::new(false),
-            was_released: AtomicBool::new(false),
-        }
-    }
-
-    pub const fn timeout(mut self, value: Duration) -> Self {
-        self.timeout = value;
-        self
-    }
-
-    pub const fn lazy(mut self, value: bool) -> Self {
-        self.lazy = value;
-        self
-    }
-
-    pub const fn ignore_modifiers(mut self, value: bool) -> Self {
-        self.ignore_modifiers = value;
-        self
+impl KeyCombo {
+    pub const fn new(keys: &'static [Key]) -> Self {
+        Self { keys }
     }
 }
 
-impl<A: Action> Action for Sticky<A> {
+impl Action for KeyCombo {
     async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
     where
         D: Device,
         T: Transports<D::Mcu>,
         S: AnyState,
     {
-        let previous_keyboard_report = match context.state.try_get::<KeyboardReportState>() {
-            Some(report) => report.get(),
-            None => {
-                error!("Sticky action requires KeyboardReportState");
-                return;
-            }
-        };
-        self.is_held.store(true, Ordering::SeqCst);
-        let mut observer = unwrap!(context.external_channel.try_observer::<KeyboardReport>());
-        if !self.lazy {
-            self.action.on_press(context).await;
-        }
-        let fut1 = async {
-            loop {
-                let keyboard_report = observer.next().await;
-                let new_keys = keyboard_report
-                    .keys
-                    .difference(previous_keyboard_report.keys);
-                if self.ignore_modifiers && new_keys.iter().all(|key| key.is_modifier()) {
-                    continue;
-                }
-                if self.lazy {
-                    self.action.on_press(context).await;
-                }
-                break;
-            }
-        };
-        let fut2 = Timer::after(self.timeout);
-        let was_pressed = match select(fut1, fut2).await {
-            Either::First(()) => true,
-            Either::Second(()) => !self.lazy,
-        };
-        if !was_pressed {
-            self.action.on_press(context).await;
-        }
-        if !self.is_held.load(Ordering::SeqCst) {
-            self.was_released.store(true, Ordering::SeqCst);
-            self.action.on_release(context).await;
-        }
+        let keys = self.keys.iter().copied().collect();
+        update_keys(context, "KeyCombo", |report| report.insert_all(keys)).await;
     }
 
     async fn on_release<D, T, S>(&self, context: Context<D, T, S>)
@@ -521,86 +531,313 @@ impl<A: Action> Action for Sticky<A> {
         T: Transports<D::Mcu>,
         S: AnyState,
     {
-        self.is_held.store(false, Ordering::SeqCst);
-        if !self.was_released.load(Ordering::SeqCst) {
-            self.action.on_release(context).await;
+        let keys = self.keys.iter().copied().collect();
+        update_keys(context, "KeyCombo", |report| report.remove_all(keys)).await;
+    }
+}
+
+/// Modifies the keys of the keyboard report in a single update and sends the report.
+async fn update_keys<D, T, S>(
+    context: Context<D, T, S>,
+    action_name: &'static str,
+    f: impl FnOnce(&mut KeySet),
+) where
+    D: Device,
+    T: Transports<D::Mcu>,
+    S: AnyState,
+{
+    let report = match context.state.try_get::<KeyboardReportState>() {
+        Some(report) => report,
+        None => {
+            error!("{} action requires KeyboardReportState", action_name);
+            return;
         }
+    };
+    let keyboard_report = report.modify_and_get(|keyboard_report| f(&mut keyboard_report.keys));
+    if let Err(e) = context.external_channel.try_send(keyboard_report).await {
+        error!("Failed to send keyboard report: {:?}", e);
     }
 }
 
-pub struct HoldTap<Hold, Tap> {
-    hold_action: Hold,
-    tap_action: Tap,
-    tapping_term: Duration,
-    activated_hold: AtomicBool,
-    activated_tap: Signal<CriticalSectionRawMutex, ()>,
+/// Returns the [`HidLayout`] of the host.
+///
+/// If the state does not contain a [`HidLayoutState`], the [`UsLayout`] is returned.
+fn hid_layout<D, T, S>(context: Context<D, T, S>) -> &'static dyn HidLayout
+where
+    D: Device,
+    T: Transports<D::Mcu>,
+    S: AnyState,
+{
+    match context.state.try_get::<HidLayoutState>() {
+        Some(state) => state.get(),
+        None => &UsLayout,
+    }
 }
 
-impl<Hold: Action, Tap: Action> HoldTap<Hold, Tap> {
-    pub const fn new(hold_action: Hold, tap_action: Tap) -> Self {
+/// Presses and releases the key of the key stroke together with its modifiers.
+///
+/// The modifiers are added to the same keyboard report as the key so that the host never receives
+/// the key without the correct modifier state.
+async fn tap_key<D, T, S>(
+    context: Context<D, T, S>,
+    report: &KeyboardReportState,
+    stroke: KeyStroke,
+    interval: Duration,
+) where
+    D: Device,
+    T: Transports<D::Mcu>,
+    S: AnyState,
+{
+    let mut added_modifiers = KeySet::new();
+    let keyboard_report = report.modify_and_get(|keyboard_report| {
+        for (modifier, required) in [(Key::LShift, stroke.shift), (Key::RAlt, stroke.alt_gr)] {
+            if required && !keyboard_report.keys.contains(modifier) {
+                keyboard_report.keys.insert(modifier);
+                added_modifiers.insert(modifier);
+            }
+        }
+        keyboard_report.keys.insert(stroke.key);
+    });
+    if let Err(e) = context.external_channel.try_send(keyboard_report).await {
+        error!("Failed to send keyboard report: {:?}", e);
+    }
+    Timer::after(interval).await;
+    let keyboard_report = report.modify_and_get(|keyboard_report| {
+        keyboard_report.keys.remove_all(added_modifiers);
+        keyboard_report.keys.remove(stroke.key);
+    });
+    if let Err(e) = context.external_channel.try_send(keyboard_report).await {
+        error!("Failed to send keyboard report: {:?}", e);
+    }
+    Timer::after(interval).await;
+}
+
+/// Action that types the specified text when the key is pressed.
+///
+/// Each character is typed by pressing and releasing the corresponding key of the [`HidLayout`]
+/// of the host (see [`HidLayoutState`], defaults to the [`UsLayout`]). The modifiers are pressed in
+/// the same report as the key, so that the case of the typed characters does not depend on the
+/// timing of the host. Characters that can not be typed with the layout (e.g. control characters)
+/// are skipped with a warning.
+pub struct SendString {
+    text: &'static str,
+    interval: Duration,
+}
+
+impl SendString {
+    pub const fn new(text: &'static str) -> Self {
         Self {
-            hold_action,
-            tap_action,
-            tapping_term: Duration::from_millis(200),
-            activated_hold: AtomicBool::new(false),
-            activated_tap: Signal::new(),
+            text,
+            interval: Duration::from_millis(10),
         }
     }
 
-    /// Sets how long a key must be pressed to trigger the hold action.
-    pub const fn tapping_term(mut self, value: Duration) -> Self {
-        self.tapping_term = value;
+    /// Sets the delay between sending two keyboard reports.
+    pub const fn interval(mut self, value: Duration) -> Self {
+        self.interval = value;
         self
     }
 }
 
-impl<Hold: Action, Tap: Action> Action for HoldTap<Hold, Tap> {
+impl Action for SendString {
     async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
     where
         D: Device,
         T: Transports<D::Mcu>,
         S: AnyState,
     {
-        self.activated_hold.store(false, Ordering::SeqCst);
-        self.activated_tap.reset();
-        if let Either::First(_) =
-            select(Timer::after(self.tapping_term), self.activated_tap.wait()).await
-        {
-            self.activated_hold.store(true, Ordering::SeqCst);
-            self.hold_action.on_press(context).await;
+        let report = match context.state.try_get::<KeyboardReportState>() {
+            Some(report) => report,
+            None => {
+                error!("SendString action requires KeyboardReportState");
+                return;
+            }
+        };
+        let layout = hid_layout(context);
+        for c in self.text.chars() {
+            let Some(stroke) = layout.key_stroke(c) else {
+                warn!("Skipping character that can not be typed: {}", c);
+                continue;
+            };
+            tap_key(context, report, stroke, self.interval).await;
         }
     }
 
-    async fn on_release<D, T, S>(&self, context: Context<D, T, S>)
+    async fn on_release<D, T, S>(&self, _: Context<D, T, S>)
     where
         D: Device,
         T: Transports<D::Mcu>,
         S: AnyState,
     {
-        if self.activated_hold.load(Ordering::SeqCst) {
-            self.hold_action.on_release(context).await;
-        } else {
-            self.activated_tap.signal(());
-            self.tap_action.on_press(context).await;
-            Timer::after_millis(10).await;
-            self.tap_action.on_release(context).await;
+    }
+}
+
+/// An entry of a compose table that maps a character to the compose sequence that produces it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ComposeEntry {
+    /// The character that is produced by the sequence.
+    pub character: char,
+    /// The ASCII characters that are typed after the compose key.
+    pub sequence: &'static str,
+}
+
+impl ComposeEntry {
+    pub const fn new(character: char, sequence: &'static str) -> Self {
+        Self {
+            character,
+            sequence,
         }
     }
 }
 
-pub struct ToggleExternalTransport(pub Address);
+/// Compose table with the default compose sequences of X11 for common accented characters and
+/// symbols.
+pub const DEFAULT_COMPOSE_TABLE: &[ComposeEntry] = &[
+    ComposeEntry::new('á', "'a"),
+    ComposeEntry::new('à', "`a"),
+    ComposeEntry::new('â', "^a"),
+    ComposeEntry::new('ä', "\"a"),
+    ComposeEntry::new('ã', "~a"),
+    ComposeEntry::new('å', "oa"),
+    ComposeEntry::new('æ', "ae"),
+    ComposeEntry::new('ç', ",c"),
+    ComposeEntry::new('é', "'e"),
+    ComposeEntry::new('è', "`e"),
+    ComposeEntry::new('ê', "^e"),
+    ComposeEntry::new('ë', "\"e"),
+    ComposeEntry::new('í', "'i"),
+    ComposeEntry::new('ì', "`i"),
+    ComposeEntry::new('î', "^i"),
+    ComposeEntry::new('ï', "\"i"),
+    ComposeEntry::new('ñ', "~n"),
+    ComposeEntry::new('ó', "'o"),
+    ComposeEntry::new('ò', "`o"),
+    ComposeEntry::new('ô', "^o"),
+    ComposeEntry::new('ö', "\"o"),
+    ComposeEntry::new('õ', "~o"),
+    ComposeEntry::new('ø', "/o"),
+    ComposeEntry::new('œ', "oe"),
+    ComposeEntry::new('ú', "'u"),
+    ComposeEntry::new('ù', "`u"),
+    ComposeEntry::new('û', "^u"),
+    ComposeEntry::new('ü', "\"u"),
+    ComposeEntry::new('ý', "'y"),
+    ComposeEntry::new('ÿ', "\"y"),
+    ComposeEntry::new('ß', "ss"),
+    ComposeEntry::new('Á', "'A"),
+    ComposeEntry::new('À', "`A"),
+    ComposeEntry::new('Â', "^A"),
+    ComposeEntry::new('Ä', "\"A"),
+    ComposeEntry::new('Ã', "~A"),
+    ComposeEntry::new('Å', "oA"),
+    ComposeEntry::new('Æ', "AE"),
+    ComposeEntry::new('Ç', ",C"),
+    ComposeEntry::new('É', "'E"),
+    ComposeEntry::new('È', "`E"),
+    ComposeEntry::new('Ê', "^E"),
+    ComposeEntry::new('Ë', "\"E"),
+    ComposeEntry::new('Í', "'I"),
+    ComposeEntry::new('Ì', "`I"),
+    ComposeEntry::new('Î', "^I"),
+    ComposeEntry::new('Ï', "\"I"),
+    ComposeEntry::new('Ñ', "~N"),
+    ComposeEntry::new('Ó', "'O"),
+    ComposeEntry::new('Ò', "`O"),
+    ComposeEntry::new('Ô', "^O"),
+    ComposeEntry::new('Ö', "\"O"),
+    ComposeEntry::new('Õ', "~O"),
+    ComposeEntry::new('Ø', "/O"),
+    ComposeEntry::new('Œ', "OE"),
+    ComposeEntry::new('Ú', "'U"),
+    ComposeEntry::new('Ù', "`U"),
+    ComposeEntry::new('Û', "^U"),
+    ComposeEntry::new('Ü', "\"U"),
+    ComposeEntry::new('Ý', "'Y"),
+    ComposeEntry::new('€', "=e"),
+    ComposeEntry::new('£', "-L"),
+    ComposeEntry::new('¡', "!!"),
+    ComposeEntry::new('¿', "??"),
+    ComposeEntry::new('°', "oo"),
+];
 
-impl Action for ToggleExternalTransport {
+/// Action that types the specified text by using the compose key of the host for characters that
+/// are not part of ASCII.
+///
+/// ASCII characters are typed directly with the [`HidLayout`] of the host (see
+/// [`HidLayoutState`]). For any other character the compose key is tapped and then the sequence of
+/// the matching entry from the compose table is typed. Characters that are neither ASCII nor part
+/// of the compose table are skipped.
+pub struct Compose {
+    text: &'static str,
+    compose_key: Key,
+    table: &'static [ComposeEntry],
+    interval: Duration,
+}
+
+impl Compose {
+    pub const fn new(text: &'static str) -> Self {
+        Self {
+            text,
+            compose_key: Key::RAlt,
+            table: DEFAULT_COMPOSE_TABLE,
+            interval: Duration::from_millis(10),
+        }
+    }
+
+    /// Sets the key that is configured as compose key on the host.
+    pub const fn compose_key(mut self, value: Key) -> Self {
+        self.compose_key = value;
+        self
+    }
+
+    /// Sets the table that maps characters to compose sequences.
+    pub const fn table(mut self, value: &'static [ComposeEntry]) -> Self {
+        self.table = value;
+        self
+    }
+
+    /// Sets the delay between sending two keyboard reports.
+    pub const fn interval(mut self, value: Duration) -> Self {
+        self.interval = value;
+        self
+    }
+}
+
+impl Action for Compose {
     async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
     where
         D: Device,
         T: Transports<D::Mcu>,
         S: AnyState,
     {
-        context
-            .internal_channel
-            .send(toggle::Message::Toggle(self.0))
-            .await;
+        let report = match context.state.try_get::<KeyboardReportState>() {
+            Some(report) => report,
+            None => {
+                error!("Compose action requires KeyboardReportState");
+                return;
+            }
+        };
+        let layout = hid_layout(context);
+        for c in self.text.chars() {
+            if c.is_ascii() {
+                match layout.key_stroke(c) {
+                    Some(stroke) => tap_key(context, report, stroke, self.interval).await,
+                    None => warn!("Skipping character that can not be typed: {}", c),
+                }
+                continue;
+            }
+            let Some(entry) = self.table.iter().find(|entry| entry.character == c) else {
+                warn!("Skipping character without compose sequence: {}", c as u32);
+                continue;
+            };
+            tap_key(context, report, self.compose_key.into(), self.interval).await;
+            for c in entry.sequence.chars() {
+                match layout.key_stroke(c) {
+                    Some(stroke) => tap_key(context, report, stroke, self.interval).await,
+                    None => warn!("Skipping character that can not be typed: {}", c),
+                }
+            }
+        }
     }
 
     async fn on_release<D, T, S>(&self, _: Context<D, T, S>)
@@ -612,10 +849,2405 @@ impl Action for ToggleExternalTransport {
     }
 }
 
-pub struct ActivateExternalTransport(pub Address);
+/// Action that types a Unicode character with the input method of the host.
+///
+/// The input method is selected by the [`UnicodeModeState`] (see [`UnicodeMode`] for the supported
+/// input methods), which can be changed at runtime with the [`SetUnicodeMode`] action. If the
+/// state does not contain a [`UnicodeModeState`], [`UnicodeMode::Linux`] is used.
+pub struct Unicode {
+    character: char,
+    interval: Duration,
+}
 
-impl Action for ActivateExternalTransport {
-    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+impl Unicode {
+    pub const fn new(character: char) -> Self {
+        Self {
+            character,
+            interval: Duration::from_millis(10),
+        }
+    }
+
+    /// Sets the delay between sending two keyboard reports.
+    pub const fn interval(mut self, value: Duration) -> Self {
+        self.interval = value;
+        self
+    }
+
+    async fn type_hex<D, T, S>(
+        &self,
+        context: Context<D, T, S>,
+        report: &KeyboardReportState,
+        value: u32,
+    ) where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let layout = hid_layout(context);
+        for c in hex_digits(value, 4) {
+            match layout.key_stroke(c.into()) {
+                Some(stroke) => tap_key(context, report, stroke, self.interval).await,
+                None => error!("Hexadecimal digit can not be typed: {}", c),
+            }
+        }
+    }
+
+    async fn set_modifier<D, T, S>(
+        &self,
+        context: Context<D, T, S>,
+        report: &KeyboardReportState,
+        modifier: Key,
+        pressed: bool,
+    ) where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let keyboard_report = report.modify_and_get(|keyboard_report| {
+            if pressed {
+                keyboard_report.keys.insert(modifier);
+            } else {
+                keyboard_report.keys.remove(modifier);
+            }
+        });
+        if let Err(e) = context.external_channel.try_send(keyboard_report).await {
+            error!("Failed to send keyboard report: {:?}", e);
+        }
+        Timer::after(self.interval).await;
+    }
+}
+
+impl Action for Unicode {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let report = match context.state.try_get::<KeyboardReportState>() {
+            Some(report) => report,
+            None => {
+                error!("Unicode action requires KeyboardReportState");
+                return;
+            }
+        };
+        let mode = context
+            .state
+            .try_get::<UnicodeModeState>()
+            .map_or(UnicodeMode::default(), |v| v.get());
+        match mode {
+            UnicodeMode::Linux => {
+                self.set_modifier(context, report, Key::LControl, true)
+                    .await;
+                tap_key(
+                    context,
+                    report,
+                    KeyStroke::new(Key::U).shift(),
+                    self.interval,
+                )
+                .await;
+                self.set_modifier(context, report, Key::LControl, false)
+                    .await;
+                self.type_hex(context, report, self.character as u32).await;
+                tap_key(context, report, Key::Space.into(), self.interval).await;
+            }
+            UnicodeMode::MacOs => {
+                self.set_modifier(context, report, Key::LAlt, true).await;
+                let mut code_units = [0; 2];
+                for code_unit in self.character.encode_utf16(&mut code_units) {
+                    self.type_hex(context, report, *code_unit as u32).await;
+                }
+                self.set_modifier(context, report, Key::LAlt, false).await;
+            }
+            UnicodeMode::WinCompose => {
+                tap_key(context, report, Key::RAlt.into(), self.interval).await;
+                tap_key(context, report, Key::U.into(), self.interval).await;
+                self.type_hex(context, report, self.character as u32).await;
+                tap_key(context, report, Key::Enter.into(), self.interval).await;
+            }
+        }
+    }
+
+    async fn on_release<D, T, S>(&self, _: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+    }
+}
+
+/// Action that sets the [`UnicodeMode`] that is used by the [`Unicode`] action.
+///
+/// This action requires [`UnicodeModeState`] to be part of the state.
+pub struct SetUnicodeMode {
+    mode: UnicodeMode,
+}
+
+impl SetUnicodeMode {
+    pub const fn new(mode: UnicodeMode) -> Self {
+        Self { mode }
+    }
+}
+
+impl Action for SetUnicodeMode {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        match context.state.try_get::<UnicodeModeState>() {
+            Some(state) => state.set(self.mode),
+            None => error!("SetUnicodeMode action requires UnicodeModeState"),
+        }
+    }
+
+    async fn on_release<D, T, S>(&self, _: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+    }
+}
+
+pub struct Layer {
+    pub layer: LayerId,
+    layer_manager_entry: Mutex<CriticalSectionRawMutex, Option<LayerManagerEntry>>,
+}
+
+impl Layer {
+    pub const fn new(layer: LayerId) -> Self {
+        Self {
+            layer,
+            layer_manager_entry: Mutex::new(None),
+        }
+    }
+}
+
+impl Action for Layer {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        // Keep the lock while pushing the layer so that a concurrent `on_release` can not miss the
+        // entry and leave the layer active.
+        let mut layer_manager_entry = self.layer_manager_entry.lock().await;
+        if let Some(entry) = layer_manager_entry.take() {
+            warn!(
+                "on_press was called again without calling on_release first for layer {}",
+                self.layer.0
+            );
+            if let Some(layer_manager) = context.state.try_query::<LayerManagerQuery>() {
+                layer_manager.remove(entry);
+            }
+        }
+        if let Some(layer_manager) = context.state.try_query::<LayerManagerQuery>() {
+            *layer_manager_entry = Some(layer_manager.push(self.layer));
+        }
+    }
+
+    async fn on_release<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        if let Some(entry) = self.layer_manager_entry.lock().await.take()
+            && let Some(layer_manager) = context.state.try_query::<LayerManagerQuery>()
+        {
+            layer_manager.remove(entry);
+        }
+    }
+}
+
+/// Sets the default layer of the [`LayerManager`](lokey_layer::LayerManager), i.e. the layer that
+/// is active while no other layer is activated.
+pub struct DefaultLayer {
+    pub layer: LayerId,
+    pub persist: bool,
+}
+
+impl DefaultLayer {
+    pub const fn new(layer: LayerId) -> Self {
+        Self {
+            layer,
+            persist: false,
+        }
+    }
+
+    /// Sets whether the default layer is written to the storage of the device (defaults to
+    /// `false`).
+    ///
+    /// The stored layer can be restored at startup with
+    /// [`LayerManager::load_default`](lokey_layer::LayerManager::load_default).
+    pub const fn persist(mut self, value: bool) -> Self {
+        self.persist = value;
+        self
+    }
+}
+
+impl Action for DefaultLayer {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let Some(layer_manager) = context.state.try_query::<LayerManagerQuery>() else {
+            error!("DefaultLayer action requires LayerManager");
+            return;
+        };
+        if layer_manager.default_layer() == self.layer {
+            return;
+        }
+        layer_manager.set_default(self.layer);
+        if self.persist
+            && let Err(e) = layer_manager.store_default(context.storage).await
+        {
+            #[cfg(feature = "defmt")]
+            let e = defmt::Debug2Format(&e);
+            error!("Failed to store default layer: {}", e);
+        }
+    }
+
+    async fn on_release<D, T, S>(&self, _: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+    }
+}
+
+/// Deactivates all layers that are currently active and activates the specified layer instead.
+///
+/// The layer stays active after the key is released, until another layer action replaces it. In
+/// contrast to [`DefaultLayer`], the default layer is not changed, so the layer can still be
+/// deactivated. Keys that are held while the layers are switched are still released with the
+/// action of the layer on which they were pressed.
+pub struct ToLayer {
+    pub layer: LayerId,
+}
+
+impl ToLayer {
+    pub const fn new(layer: LayerId) -> Self {
+        Self { layer }
+    }
+}
+
+impl Action for ToLayer {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        match context.state.try_query::<LayerManagerQuery>() {
+            Some(layer_manager) => {
+                layer_manager.switch_to(self.layer);
+            }
+            None => error!("ToLayer action requires LayerManager"),
+        }
+    }
+
+    async fn on_release<D, T, S>(&self, _: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+    }
+}
+
+/// The way the layer of a [`SmartLayer`] action is activated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SmartLayerState {
+    /// The layer is not activated by the action.
+    Inactive,
+    /// The layer is active while the key is held.
+    Held,
+    /// The layer was toggled on and is deactivated by the next key press.
+    Toggled,
+    /// The layer was locked and is only deactivated by the lock tap count.
+    Locked,
+}
+
+impl SmartLayerState {
+    /// Returns the state after a key press with the specified number of consecutive taps.
+    ///
+    /// A tap count of 0 in `toggle_taps` or `lock_taps` disables toggling or locking respectively.
+    fn on_press(self, tap_count: u8, toggle_taps: u8, lock_taps: u8) -> Self {
+        let is_lock = lock_taps != 0 && tap_count == lock_taps;
+        let is_toggle = toggle_taps != 0 && tap_count == toggle_taps;
+        match self {
+            Self::Locked if is_lock => Self::Inactive,
+            Self::Locked => Self::Locked,
+            Self::Toggled if is_lock => Self::Locked,
+            Self::Toggled => Self::Inactive,
+            Self::Inactive | Self::Held if is_lock => Self::Locked,
+            Self::Inactive | Self::Held if is_toggle => Self::Toggled,
+            Self::Inactive | Self::Held => Self::Held,
+        }
+    }
+
+    /// Returns the state after the key was released.
+    fn on_release(self) -> Self {
+        match self {
+            Self::Held => Self::Inactive,
+            state => state,
+        }
+    }
+
+    fn is_active(self) -> bool {
+        self != Self::Inactive
+    }
+}
+
+/// Internal message that is sent when a [`SmartLayer`] action changes the state of its layer.
+///
+/// This can be used to show the state of a layer with an indicator (e.g. an LED).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SmartLayerEvent {
+    pub layer: LayerId,
+    pub state: SmartLayerState,
+}
+
+impl internal::Message for SmartLayerEvent {
+    type Size = typenum::U2;
+
+    const TAG: [u8; 4] = [0x8d, 0x24, 0xf1, 0x6b];
+
+    fn from_bytes(bytes: GenericArray<u8, Self::Size>) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        let [layer, state] = bytes.into_array::<2>();
+        let state = match state {
+            0 => SmartLayerState::Inactive,
+            1 => SmartLayerState::Held,
+            2 => SmartLayerState::Toggled,
+            3 => SmartLayerState::Locked,
+            _ => return None,
+        };
+        Some(Self {
+            layer: LayerId(layer),
+            state,
+        })
+    }
+
+    fn to_bytes(&self) -> GenericArray<u8, Self::Size> {
+        let state = match self.state {
+            SmartLayerState::Inactive => 0,
+            SmartLayerState::Held => 1,
+            SmartLayerState::Toggled => 2,
+            SmartLayerState::Locked => 3,
+        };
+        [self.layer.0, state].into()
+    }
+}
+
+struct SmartLayerInner {
+    state: SmartLayerState,
+    entry: Option<LayerManagerEntry>,
+    tap_count: u8,
+    last_release: Option<Instant>,
+}
+
+impl SmartLayerInner {
+    /// Pushes or removes the layer so that it matches the current state.
+    ///
+    /// At most one entry is pushed to the layer manager at any time.
+    fn sync_entry(&mut self, layer: LayerId, layer_manager: &LayerManagerQuery) {
+        match (self.state.is_active(), self.entry.take()) {
+            (true, None) => self.entry = Some(layer_manager.push(layer)),
+            (true, Some(entry)) => self.entry = Some(entry),
+            (false, Some(entry)) => {
+                layer_manager.remove(entry);
+            }
+            (false, None) => {}
+        }
+    }
+}
+
+/// Action that covers the common ways of accessing a layer with a single key.
+///
+/// The layer is active while the key is held. Tapping the key [`toggle_taps`](Self::toggle_taps)
+/// times in a row toggles the layer on until the key is pressed again, and tapping it
+/// [`lock_taps`](Self::lock_taps) times in a row locks the layer until the key is tapped that many
+/// times again. Taps count as consecutive if the key is pressed again within the
+/// [`tap_interval`](Self::tap_interval) after it was released.
+///
+/// A [`SmartLayerEvent`] is sent to the internal channel whenever the state of the layer changes.
+pub struct SmartLayer {
+    pub layer: LayerId,
+    toggle_taps: u8,
+    lock_taps: u8,
+    tap_interval: Duration,
+    inner: Mutex<CriticalSectionRawMutex, SmartLayerInner>,
+}
+
+impl SmartLayer {
+    pub const fn new(layer: LayerId) -> Self {
+        Self {
+            layer,
+            toggle_taps: 2,
+            lock_taps: 3,
+            tap_interval: Duration::from_millis(200),
+            inner: Mutex::new(SmartLayerInner {
+                state: SmartLayerState::Inactive,
+                entry: None,
+                tap_count: 0,
+                last_release: None,
+            }),
+        }
+    }
+
+    /// Sets the number of consecutive taps that toggle the layer (defaults to 2, 0 disables
+    /// toggling).
+    pub const fn toggle_taps(mut self, value: u8) -> Self {
+        self.toggle_taps = value;
+        self
+    }
+
+    /// Sets the number of consecutive taps that lock the layer (defaults to 3, 0 disables locking).
+    pub const fn lock_taps(mut self, value: u8) -> Self {
+        self.lock_taps = value;
+        self
+    }
+
+    /// Sets the maximum duration between releasing and pressing the key again for the taps to
+    /// count as consecutive.
+    pub const fn tap_interval(mut self, value: Duration) -> Self {
+        self.tap_interval = value;
+        self
+    }
+
+    async fn update<D, T, S>(&self, context: Context<D, T, S>, f: impl FnOnce(&mut SmartLayerInner))
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let Some(layer_manager) = context.state.try_query::<LayerManagerQuery>() else {
+            error!("SmartLayer action requires LayerManager");
+            return;
+        };
+        let mut inner = self.inner.lock().await;
+        let previous_state = inner.state;
+        f(&mut inner);
+        inner.sync_entry(self.layer, &layer_manager);
+        if inner.state != previous_state {
+            context
+                .internal_channel
+                .send(SmartLayerEvent {
+                    layer: self.layer,
+                    state: inner.state,
+                })
+                .await;
+        }
+    }
+}
+
+impl Action for SmartLayer {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        self.update(context, |inner| {
+            let is_consecutive = inner
+                .last_release
+                .is_some_and(|v| v.elapsed() <= self.tap_interval);
+            inner.tap_count = if is_consecutive {
+                inner.tap_count.saturating_add(1)
+            } else {
+                1
+            };
+            inner.state = inner
+                .state
+                .on_press(inner.tap_count, self.toggle_taps, self.lock_taps);
+        })
+        .await;
+    }
+
+    async fn on_release<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        self.update(context, |inner| {
+            inner.last_release = Some(Instant::now());
+            inner.state = inner.state.on_release();
+        })
+        .await;
+    }
+}
+
+pub struct PerLayer<A: ActionContainer> {
+    actions: A,
+    layer_ids: GenericArray<LayerId, A::NumChildren>,
+    active_action_index: Mutex<CriticalSectionRawMutex, Option<usize>>,
+}
+
+impl<A: ActionContainer> PerLayer<A> {
+    pub const fn new(actions: A, layer_ids: GenericArray<LayerId, A::NumChildren>) -> Self {
+        Self {
+            actions,
+            layer_ids,
+            active_action_index: Mutex::new(None),
+        }
+    }
+}
+
+impl<A: ActionContainer> Action for PerLayer<A> {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        if let Some(layer_manager) = context.state.try_query::<LayerManagerQuery>() {
+            let active_layer_id = layer_manager.active();
+            if let Some(index) = self
+                .layer_ids
+                .iter()
+                .position(|layer_id| *layer_id == active_layer_id)
+            {
+                *self.active_action_index.lock().await = Some(index);
+                unwrap!(self.actions.child_on_press(index, context).await);
+            }
+        }
+    }
+
+    async fn on_release<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        // The index is taken, so that a release is never forwarded to an action again if no layer ID
+        // matched the active layer at the next press.
+        let index = self.active_action_index.lock().await.take();
+        if let Some(index) = index {
+            unwrap!(self.actions.child_on_release(index, context).await);
+        }
+    }
+}
+
+pub struct Toggle<A> {
+    action: A,
+    active: AtomicBool,
+}
+
+impl<A: Action> Toggle<A> {
+    pub const fn new(action: A) -> Self {
+        Self {
+            action,
+            active: AtomicBool::new(false),
+        }
+    }
+}
+
+impl<A: Action> Action for Toggle<A> {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let active = self.active.load(Ordering::SeqCst);
+        if active {
+            self.action.on_release(context).await;
+        } else {
+            self.action.on_press(context).await;
+        }
+        self.active.store(!active, Ordering::SeqCst);
+    }
+
+    async fn on_release<D, T, S>(&self, _context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+    }
+}
+
+/// Only performs the inner action if no modifier keys are held.
+///
+/// This can be used for keys that should do nothing while a modifier is held, e.g. to avoid
+/// triggering shortcuts by accident. The modifier state is read from the [`KeyboardReportState`]
+/// when the key is pressed. If the inner action was not pressed, releasing the key does nothing.
+pub struct WhenNoMod<A> {
+    action: A,
+    was_pressed: AtomicBool,
+}
+
+impl<A: Action> WhenNoMod<A> {
+    pub const fn new(action: A) -> Self {
+        Self {
+            action,
+            was_pressed: AtomicBool::new(false),
+        }
+    }
+}
+
+impl<A: Action> Action for WhenNoMod<A> {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let modifier_byte = match context.state.try_get::<KeyboardReportState>() {
+            Some(report) => report.get().modifier_byte(),
+            None => {
+                error!("WhenNoMod action requires KeyboardReportState");
+                return;
+            }
+        };
+        if modifier_byte != 0 {
+            self.was_pressed.store(false, Ordering::SeqCst);
+            return;
+        }
+        self.was_pressed.store(true, Ordering::SeqCst);
+        self.action.on_press(context).await;
+    }
+
+    async fn on_release<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        if self.was_pressed.swap(false, Ordering::SeqCst) {
+            self.action.on_release(context).await;
+        }
+    }
+}
+
+/// An entry of an alternate repeat table that maps the previously typed key to the key that is
+/// typed by the [`AltRepeat`] action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AltRepeatEntry {
+    /// The previously typed key.
+    pub last_key: Key,
+    /// The key that is typed if the previously typed key is [`last_key`](Self::last_key).
+    pub alternate: Key,
+}
+
+impl AltRepeatEntry {
+    pub const fn new(last_key: Key, alternate: Key) -> Self {
+        Self {
+            last_key,
+            alternate,
+        }
+    }
+}
+
+/// Action that types a key depending on the previously typed key.
+///
+/// The previously typed key is the non-modifier key that was most recently added to the
+/// [`KeyboardReportState`] (see [`KeyboardReportState::last_key`]). If the table contains an entry
+/// for that key, the alternate key of the entry is pressed, otherwise nothing happens.
+pub struct AltRepeat {
+    table: &'static [AltRepeatEntry],
+    pressed_key: blocking_mutex::Mutex<CriticalSectionRawMutex, Cell<Option<Key>>>,
+}
+
+impl AltRepeat {
+    pub const fn new(table: &'static [AltRepeatEntry]) -> Self {
+        Self {
+            table,
+            pressed_key: blocking_mutex::Mutex::new(Cell::new(None)),
+        }
+    }
+}
+
+impl Action for AltRepeat {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let last_key = match context.state.try_get::<KeyboardReportState>() {
+            Some(report) => report.last_key(),
+            None => {
+                error!("AltRepeat action requires KeyboardReportState");
+                return;
+            }
+        };
+        let Some(last_key) = last_key else {
+            return;
+        };
+        let Some(entry) = self.table.iter().find(|entry| entry.last_key == last_key) else {
+            return;
+        };
+        self.pressed_key.lock(|v| v.set(Some(entry.alternate)));
+        entry.alternate.on_press(context).await;
+    }
+
+    async fn on_release<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        // The pressed key is remembered because pressing the alternate key changes the last key.
+        if let Some(key) = self.pressed_key.lock(|v| v.take()) {
+            key.on_release(context).await;
+        }
+    }
+}
+
+/// Action that presses a keypad key or a navigation key depending on the Num Lock state of the
+/// host.
+///
+/// If Num Lock is on (see [`HostLeds::num_lock`]), the keypad key is pressed, otherwise the
+/// navigation key is pressed. This allows a number pad layer to behave the same way as a
+/// physical number pad, even if the host does not interpret the keypad keys itself.
+///
+/// # Example
+///
+/// ```
+/// use lokey_keyboard::Key;
+/// use lokey_keyboard::action::SmartKeypad;
+///
+/// let action = SmartKeypad::new(Key::Kp7, Key::Home);
+/// ```
+pub struct SmartKeypad {
+    keypad: Key,
+    navigation: Key,
+    pressed_key: blocking_mutex::Mutex<CriticalSectionRawMutex, Cell<Option<Key>>>,
+}
+
+impl SmartKeypad {
+    pub const fn new(keypad: Key, navigation: Key) -> Self {
+        Self {
+            keypad,
+            navigation,
+            pressed_key: blocking_mutex::Mutex::new(Cell::new(None)),
+        }
+    }
+}
+
+impl Action for SmartKeypad {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let key = if HostLeds::current().num_lock() {
+            self.keypad
+        } else {
+            self.navigation
+        };
+        self.pressed_key.lock(|v| v.set(Some(key)));
+        key.on_press(context).await;
+    }
+
+    async fn on_release<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        // The pressed key is remembered because the Num Lock state may change while holding the key.
+        if let Some(key) = self.pressed_key.lock(|v| v.take()) {
+            key.on_release(context).await;
+        }
+    }
+}
+
+pub struct Sticky<A> {
+    action: A,
+    timeout: Duration,
+    lazy: bool,
+    ignore_modifiers: bool,
+    is_held: AtomicBool,
+    was_released: AtomicBool,
+}
+
+impl<A: Action> Sticky<A> {
+    pub const fn new(action: A) -> Self {
+        Self {
+            action,
+            timeout: Duration::from_secs(1),
+            lazy: false,
+            ignore_modifiers: true,
+            is_held: AtomicBool::new(false),
+            was_released: AtomicBool::new(false),
+        }
+    }
+
+    pub const fn timeout(mut self, value: Duration) -> Self {
+        self.timeout = value;
+        self
+    }
+
+    pub const fn lazy(mut self, value: bool) -> Self {
+        self.lazy = value;
+        self
+    }
+
+    pub const fn ignore_modifiers(mut self, value: bool) -> Self {
+        self.ignore_modifiers = value;
+        self
+    }
+}
+
+impl<A: Action> Action for Sticky<A> {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let previous_keyboard_report = match context.state.try_get::<KeyboardReportState>() {
+            Some(report) => report.get(),
+            None => {
+                error!("Sticky action requires KeyboardReportState");
+                return;
+            }
+        };
+        self.is_held.store(true, Ordering::SeqCst);
+        let mut observer = unwrap!(context.external_channel.try_observer::<KeyboardReport>());
+        if !self.lazy {
+            self.action.on_press(context).await;
+        }
+        let fut1 = async {
+            loop {
+                let keyboard_report = observer.next().await;
+                let new_keys = keyboard_report
+                    .keys
+                    .difference(previous_keyboard_report.keys);
+                if self.ignore_modifiers && new_keys.iter().all(|key| key.is_modifier()) {
+                    continue;
+                }
+                if self.lazy {
+                    self.action.on_press(context).await;
+                }
+                break;
+            }
+        };
+        let fut2 = Timer::after(self.timeout);
+        let was_pressed = match select(fut1, fut2).await {
+            Either::First(()) => true,
+            Either::Second(()) => !self.lazy,
+        };
+        if !was_pressed {
+            self.action.on_press(context).await;
+        }
+        if !self.is_held.load(Ordering::SeqCst) {
+            self.was_released.store(true, Ordering::SeqCst);
+            self.action.on_release(context).await;
+        }
+    }
+
+    async fn on_release<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        self.is_held.store(false, Ordering::SeqCst);
+        if !self.was_released.load(Ordering::SeqCst) {
+            self.action.on_release(context).await;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OneShotState {
+    Idle,
+    Queued { since: Instant },
+    Locked,
+}
+
+/// Holds an action down until a number of non-modifier keys were pressed.
+///
+/// When the key is tapped, the action is pressed and stays pressed until the next non-modifier key
+/// (or the number of keys set by [`max_presses`](Self::max_presses)) was pressed, after which the
+/// action is released. If no key is pressed within the timeout, the action is released as well.
+/// While the key is held, the action stays pressed like a regular key.
+///
+/// Pressing the key again while the action is queued releases it, unless the key is pressed within
+/// the [`lock_term`](Self::lock_term). In that case the action stays pressed until the key is
+/// pressed another time.
+pub struct OneShot<A> {
+    action: A,
+    timeout: Duration,
+    max_presses: u8,
+    lock_term: Option<Duration>,
+    state: blocking_mutex::Mutex<CriticalSectionRawMutex, Cell<OneShotState>>,
+    state_changed: Signal<CriticalSectionRawMutex, ()>,
+    is_held: AtomicBool,
+    is_action_pressed: AtomicBool,
+}
+
+impl<A: Action> OneShot<A> {
+    pub const fn new(action: A) -> Self {
+        Self {
+            action,
+            timeout: Duration::from_secs(1),
+            max_presses: 1,
+            lock_term: None,
+            state: blocking_mutex::Mutex::new(Cell::new(OneShotState::Idle)),
+            state_changed: Signal::new(),
+            is_held: AtomicBool::new(false),
+            is_action_pressed: AtomicBool::new(false),
+        }
+    }
+
+    /// Sets the duration after which the action is released if no other key is pressed (defaults
+    /// to 1 second).
+    ///
+    /// The timeout starts again with each key press that is counted.
+    pub const fn timeout(mut self, value: Duration) -> Self {
+        self.timeout = value;
+        self
+    }
+
+    /// Sets the number of non-modifier key presses after which the action is released (defaults
+    /// to 1).
+    ///
+    /// Each non-modifier key that is added to the keyboard report counts as a press, so a report
+    /// that adds several keys at once (e.g. from a [`KeyCombo`]) counts as several presses.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is zero.
+    pub const fn max_presses(mut self, value: u8) -> Self {
+        assert!(value > 0, "max_presses must be greater than zero");
+        self.max_presses = value;
+        self
+    }
+
+    /// Enables locking the action by pressing the key twice within the specified duration.
+    ///
+    /// A locked action stays pressed until the key is pressed again.
+    pub const fn lock_term(mut self, value: Duration) -> Self {
+        self.lock_term = Some(value);
+        self
+    }
+
+    fn set_state(&self, state: OneShotState) {
+        self.state.lock(|v| v.set(state));
+        self.state_changed.signal(());
+    }
+
+    async fn release<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        if self.is_action_pressed.swap(false, Ordering::SeqCst) {
+            self.action.on_release(context).await;
+        }
+    }
+}
+
+impl<A: Action> Action for OneShot<A> {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let mut previous_keys = match context.state.try_get::<KeyboardReportState>() {
+            Some(report) => report.get().keys,
+            None => {
+                error!("OneShot action requires KeyboardReportState");
+                return;
+            }
+        };
+        self.is_held.store(true, Ordering::SeqCst);
+        match self.state.lock(|v| v.get()) {
+            OneShotState::Idle => {}
+            OneShotState::Queued { since }
+                if self
+                    .lock_term
+                    .is_some_and(|lock_term| since.elapsed() < lock_term) =>
+            {
+                debug!("Locking one-shot action");
+                self.set_state(OneShotState::Locked);
+                return;
+            }
+            OneShotState::Queued { .. } | OneShotState::Locked => {
+                self.set_state(OneShotState::Idle);
+                self.release(context).await;
+                return;
+            }
+        }
+
+        let mut observer = unwrap!(context.external_channel.try_observer::<KeyboardReport>());
+        self.is_action_pressed.store(true, Ordering::SeqCst);
+        self.action.on_press(context).await;
+        let mut since = Instant::now();
+        self.set_state(OneShotState::Queued { since });
+        let mut remaining_presses = self.max_presses;
+        loop {
+            if self.state.lock(|v| v.get()) != (OneShotState::Queued { since }) {
+                // The action was locked or released by another key press.
+                return;
+            }
+            match select3(
+                observer.next(),
+                Timer::at(since + self.timeout),
+                self.state_changed.wait(),
+            )
+            .await
+            {
+                Either3::First(keyboard_report) => {
+                    let new_keys = keyboard_report.keys.difference(previous_keys);
+                    previous_keys = keyboard_report.keys;
+                    let num_presses = new_keys.iter().filter(|key| !key.is_modifier()).count();
+                    if num_presses == 0 {
+                        continue;
+                    }
+                    remaining_presses = remaining_presses
+                        .saturating_sub(u8::try_from(num_presses).unwrap_or(u8::MAX));
+                    if remaining_presses == 0 {
+                        break;
+                    }
+                    since = Instant::now();
+                    self.set_state(OneShotState::Queued { since });
+                }
+                Either3::Second(()) => break,
+                Either3::Third(()) => {}
+            }
+        }
+        self.set_state(OneShotState::Idle);
+        if !self.is_held.load(Ordering::SeqCst) {
+            self.release(context).await;
+        }
+    }
+
+    async fn on_release<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        self.is_held.store(false, Ordering::SeqCst);
+        if self.state.lock(|v| v.get()) == OneShotState::Idle {
+            self.release(context).await;
+        }
+    }
+}
+
+/// Shifts the letters of the next word.
+///
+/// When the key is pressed, caps word is activated and Left Shift is added to the keyboard report
+/// for each pressed shifted key (letters and Minus by default, so that `-` becomes `_`). Continue
+/// keys (digits, Backspace and Delete by default) are sent without Shift and keep caps word active.
+/// Caps word is deactivated when any other key is pressed, when no key was pressed or released for
+/// the idle timeout, or when the key is pressed again. Shift is never left pressed after caps word
+/// is deactivated.
+///
+/// The state of caps word is stored in the [`KeyboardReportState`], so it can be queried with
+/// [`KeyboardReportState::is_caps_word_active`] (e.g. to show it with an LED).
+pub struct CapsWord {
+    shifted_keys: KeySet,
+    continue_keys: KeySet,
+    idle_timeout: Duration,
+    deactivated: Signal<CriticalSectionRawMutex, ()>,
+}
+
+impl CapsWord {
+    pub const fn new() -> Self {
+        Self {
+            shifted_keys: enum_set!(
+                Key::A
+                    | Key::B
+                    | Key::C
+                    | Key::D
+                    | Key::E
+                    | Key::F
+                    | Key::G
+                    | Key::H
+                    | Key::I
+                    | Key::J
+                    | Key::K
+                    | Key::L
+                    | Key::M
+                    | Key::N
+                    | Key::O
+                    | Key::P
+                    | Key::Q
+                    | Key::R
+                    | Key::S
+                    | Key::T
+                    | Key::U
+                    | Key::V
+                    | Key::W
+                    | Key::X
+                    | Key::Y
+                    | Key::Z
+                    | Key::Minus
+            ),
+            continue_keys: enum_set!(
+                Key::N1
+                    | Key::N2
+                    | Key::N3
+                    | Key::N4
+                    | Key::N5
+                    | Key::N6
+                    | Key::N7
+                    | Key::N8
+                    | Key::N9
+                    | Key::N0
+                    | Key::Backspace
+                    | Key::Delete
+            ),
+            idle_timeout: Duration::from_secs(5),
+            deactivated: Signal::new(),
+        }
+    }
+
+    /// Sets the keys that are sent with Shift while caps word is active.
+    pub const fn shifted_keys(mut self, value: KeySet) -> Self {
+        self.shifted_keys = value;
+        self
+    }
+
+    /// Sets the keys that are sent without Shift and do not deactivate caps word.
+    pub const fn continue_keys(mut self, value: KeySet) -> Self {
+        self.continue_keys = value;
+        self
+    }
+
+    /// Sets the duration without key events after which caps word is deactivated (defaults to 5
+    /// seconds).
+    pub const fn idle_timeout(mut self, value: Duration) -> Self {
+        self.idle_timeout = value;
+        self
+    }
+}
+
+impl Default for CapsWord {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Action for CapsWord {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let report = match context.state.try_get::<KeyboardReportState>() {
+            Some(report) => report,
+            None => {
+                error!("CapsWord action requires KeyboardReportState");
+                return;
+            }
+        };
+        let deactivate = async || {
+            if let Some(keyboard_report) = report.deactivate_caps_word()
+                && let Err(e) = context.external_channel.try_send(keyboard_report).await
+            {
+                error!("Failed to send keyboard report: {:?}", e);
+            }
+        };
+        if report.is_caps_word_active() {
+            debug!("Deactivating caps word");
+            deactivate().await;
+            self.deactivated.signal(());
+            return;
+        }
+        debug!("Activating caps word");
+        self.deactivated.reset();
+        report.activate_caps_word(CapsWordState::new(self.shifted_keys, self.continue_keys));
+        loop {
+            match select3(
+                report.wait_for_change(),
+                Timer::after(self.idle_timeout),
+                self.deactivated.wait(),
+            )
+            .await
+            {
+                Either3::First(_) if report.is_caps_word_active() => {}
+                Either3::First(_) | Either3::Third(()) => break,
+                Either3::Second(()) => {
+                    debug!("Caps word timed out");
+                    deactivate().await;
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn on_release<D, T, S>(&self, _context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+    }
+}
+
+/// Determines how a [`HoldTap`] action decides between the hold and the tap action once the
+/// tapping term has elapsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HoldTapMode {
+    /// The hold action is triggered once the key was held for the tapping term.
+    TappingTerm,
+    /// The hold action is only triggered if no other key was pressed within `streak_term` before
+    /// the tapping term elapsed, otherwise the key resolves to the tap action.
+    ///
+    /// The other key may also have been pressed before the key itself, so a `streak_term` that is
+    /// longer than the tapping term also resolves to the tap action if the key was pressed right
+    /// after another key.
+    ///
+    /// This prevents accidental holds while rolling over keys during fast typing, as a key that is
+    /// held during a typing streak is treated as a tap.
+    StreakBreak { streak_term: Duration },
+}
+
+impl HoldTapMode {
+    /// Returns `true` if the key should resolve to the tap action when the tapping term elapses.
+    ///
+    /// `since_last_key_press` is the time that elapsed since the most recent press of another key.
+    fn resolves_to_tap(self, since_last_key_press: Option<Duration>) -> bool {
+        match self {
+            Self::TappingTerm => false,
+            Self::StreakBreak { streak_term } => {
+                since_last_key_press.is_some_and(|v| v < streak_term)
+            }
+        }
+    }
+}
+
+/// Determines which key events resolve a [`HoldTap`] action to the hold action before the tapping
+/// term has elapsed.
+///
+/// With any flavor other than [`TapPreferred`](Self::TapPreferred), the events of the other keys
+/// are held back until the decision was made, so that they are handled after the hold or tap
+/// action was pressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HoldTapFlavor {
+    /// The hold action is only triggered by the tapping term.
+    TapPreferred,
+    /// The hold action is triggered as soon as another key is pressed and released while the key
+    /// is held ("permissive hold").
+    ///
+    /// If the key is released before the other key, the key resolves to the tap action.
+    Balanced,
+    /// The hold action is triggered as soon as another key is pressed while the key is held
+    /// ("hold on other key press").
+    HoldPreferred,
+}
+
+/// The tapping term of a [`HoldTap`] action if neither the action nor the [`TappingTermState`]
+/// specify a tapping term.
+pub(crate) const DEFAULT_TAPPING_TERM: Duration = Duration::from_millis(200);
+
+pub struct HoldTap<Hold, Tap> {
+    hold_action: Hold,
+    tap_action: Tap,
+    tapping_term: Option<Duration>,
+    require_prior_idle: Option<Duration>,
+    prior_idle_keys: Option<&'static [u16]>,
+    quick_tap: Option<Duration>,
+    mode: HoldTapMode,
+    flavor: HoldTapFlavor,
+    retro_tap: bool,
+    decision: HoldTapDecision,
+    activated_tap: Signal<CriticalSectionRawMutex, ()>,
+    pressed_tap: AtomicBool,
+    /// The time at which the tap action was released most recently.
+    last_tap: blocking_mutex::Mutex<CriticalSectionRawMutex, Cell<Option<Instant>>>,
+    /// Holds back the events of other keys until the decision was made.
+    deferral: blocking_mutex::Mutex<CriticalSectionRawMutex, Cell<Option<KeyDeferral>>>,
+    /// Locked while the hold action is being pressed, so that it is never released before the
+    /// press has completed.
+    pressing_hold: Mutex<CriticalSectionRawMutex, ()>,
+}
+
+impl<Hold: Action, Tap: Action> HoldTap<Hold, Tap> {
+    pub const fn new(hold_action: Hold, tap_action: Tap) -> Self {
+        Self {
+            hold_action,
+            tap_action,
+            tapping_term: None,
+            require_prior_idle: None,
+            prior_idle_keys: None,
+            quick_tap: None,
+            mode: HoldTapMode::TappingTerm,
+            flavor: HoldTapFlavor::TapPreferred,
+            retro_tap: false,
+            decision: HoldTapDecision::new(),
+            activated_tap: Signal::new(),
+            pressed_tap: AtomicBool::new(false),
+            last_tap: blocking_mutex::Mutex::new(Cell::new(None)),
+            deferral: blocking_mutex::Mutex::new(Cell::new(None)),
+            pressing_hold: Mutex::new(()),
+        }
+    }
+
+    /// Sets how long a key must be pressed to trigger the hold action.
+    ///
+    /// Defaults to the tapping term of the [`TappingTermState`] (see
+    /// [`Layout::tapping_term`](crate::Layout::tapping_term)), or 200ms if the state type is not
+    /// part of the state.
+    pub const fn tapping_term(mut self, value: Duration) -> Self {
+        self.tapping_term = Some(value);
+        self
+    }
+
+    /// Sets how long no other key must have been pressed before this key to allow triggering the
+    /// hold action.
+    ///
+    /// If another key was pressed within this duration before this key, the tap action is
+    /// immediately pressed and released together with the key. This prevents the hold action from
+    /// being triggered accidentally while typing fast (e.g. when using home row modifiers).
+    ///
+    /// By default, the presses of all keys and combos count, see
+    /// [`prior_idle_keys`](Self::prior_idle_keys) for only considering some keys.
+    pub const fn require_prior_idle(mut self, value: Duration) -> Self {
+        self.require_prior_idle = Some(value);
+        self
+    }
+
+    /// Sets the indices of the keys whose presses are considered by
+    /// [`require_prior_idle`](Self::require_prior_idle).
+    ///
+    /// The presses of other keys and of combos are ignored, so e.g. only the alpha keys can be
+    /// specified to allow holding a home row modifier right after pressing a thumb key.
+    pub const fn prior_idle_keys(mut self, key_indices: &'static [u16]) -> Self {
+        self.prior_idle_keys = Some(key_indices);
+        self
+    }
+
+    /// Returns `true` if the press counts as activity for
+    /// [`require_prior_idle`](Self::require_prior_idle).
+    fn participates_in_prior_idle(&self, event: ComboEvent) -> bool {
+        match (self.prior_idle_keys, event) {
+            (None, _) => true,
+            (
+                Some(key_indices),
+                ComboEvent::Key(
+                    crate::Message::Press { key_index } | crate::Message::Release { key_index },
+                ),
+            ) => key_indices.contains(&key_index),
+            (Some(_), ComboEvent::Combo { .. }) => false,
+        }
+    }
+
+    /// Sets the duration after a tap within which pressing the key again holds the tap action
+    /// (disabled by default).
+    ///
+    /// If the key is pressed again within this duration after the tap action was released, the
+    /// tap action is immediately pressed and held until the key is released, instead of waiting
+    /// for the tapping term. This makes it possible to use the auto-repeat of the host for the
+    /// tap action (e.g. for a Backspace on a hold-tap key).
+    pub const fn quick_tap(mut self, value: Duration) -> Self {
+        self.quick_tap = Some(value);
+        self
+    }
+
+    /// Sets how the decision between the hold and the tap action is made once the tapping term
+    /// has elapsed (defaults to [`HoldTapMode::TappingTerm`]).
+    pub const fn mode(mut self, value: HoldTapMode) -> Self {
+        self.mode = value;
+        self
+    }
+
+    /// Sets which key events trigger the hold action before the tapping term has elapsed
+    /// (defaults to [`HoldTapFlavor::TapPreferred`]).
+    pub const fn flavor(mut self, value: HoldTapFlavor) -> Self {
+        self.flavor = value;
+        self
+    }
+
+    /// Sets whether the hold action is triggered as soon as another key is pressed while the key
+    /// is held (defaults to `false`).
+    ///
+    /// If this is enabled, the tap action is only triggered if no other key was pressed before
+    /// the key was released. If another key is pressed, the hold action is triggered immediately,
+    /// even if the tapping term has not elapsed yet. Like with
+    /// [`HoldTapFlavor::HoldPreferred`], the events of the other keys are held back until the
+    /// decision was made, so the hold action is always pressed before the key that triggered it.
+    /// A [`Combo`](crate::Combo) counts as a single key press once it was detected, so the presses
+    /// of its keys do not trigger the hold action on their own.
+    pub const fn retro_tap(mut self, value: bool) -> Self {
+        self.retro_tap = value;
+        self
+    }
+}
+
+impl<Hold: Action, Tap: Action> HoldTap<Hold, Tap> {
+    /// Returns the tapping term of the action, falling back to the tapping term of the state.
+    fn effective_tapping_term(&self, state: Option<&TappingTermState>) -> Duration {
+        self.tapping_term
+            .unwrap_or_else(|| state.map_or(DEFAULT_TAPPING_TERM, |v| v.get()))
+    }
+
+    /// Returns `true` if the key was pressed again within the quick tap duration after the last
+    /// tap.
+    fn is_quick_tap(&self) -> bool {
+        let last_tap = self.last_tap.lock(|v| v.get());
+        within_quick_tap(self.quick_tap, last_tap, Instant::now())
+    }
+
+    /// Returns the flavor of the action, where a retro tap behaves like
+    /// [`HoldTapFlavor::HoldPreferred`].
+    fn effective_flavor(&self) -> HoldTapFlavor {
+        if self.retro_tap {
+            HoldTapFlavor::HoldPreferred
+        } else {
+            self.flavor
+        }
+    }
+
+    /// Dispatches the key events that were held back while the decision was made.
+    fn end_deferral(&self) {
+        self.deferral.lock(|deferral| drop(deferral.take()));
+    }
+
+    /// Waits until another key or combo interrupts the key according to the flavor.
+    async fn interrupted(&self) {
+        let flavor = self.effective_flavor();
+        let mut pressed = ArrayVec::new();
+        while !interrupts(flavor, &mut pressed, defer::next_deferred().await) {}
+    }
+}
+
+/// Returns `true` if the deferred event interrupts a [`HoldTap`] with the specified flavor.
+///
+/// `pressed` contains the presses of the other keys and combos that were deferred so far. A combo
+/// is handled like a single key.
+fn interrupts(
+    flavor: HoldTapFlavor,
+    pressed: &mut ArrayVec<ComboEvent, { defer::MAX_DEFERRED_EVENTS }>,
+    event: ComboEvent,
+) -> bool {
+    if !event.is_press() {
+        return pressed.contains(&event.to_press());
+    }
+    if flavor == HoldTapFlavor::HoldPreferred {
+        return true;
+    }
+    let _ = pressed.try_push(event);
+    false
+}
+
+impl<Hold: Action, Tap: Action> Action for HoldTap<Hold, Tap> {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let key_index = crate::current_key_index();
+        self.decision.reset();
+        self.activated_tap.reset();
+        if self.is_quick_tap() {
+            debug!("Key was pressed again after a tap, holding tap action");
+            self.pressed_tap.store(true, Ordering::SeqCst);
+            self.tap_action.on_press(context).await;
+            return;
+        }
+        if let Some(require_prior_idle) = self.require_prior_idle
+            && crate::prior_idle_time(|event| self.participates_in_prior_idle(event))
+                .is_some_and(|v| v < require_prior_idle)
+        {
+            self.pressed_tap.store(true, Ordering::SeqCst);
+            self.tap_action.on_press(context).await;
+            return;
+        }
+        let tapping_term = self.effective_tapping_term(context.state.try_get::<TappingTermState>());
+        let mut is_deferring = false;
+        if self.effective_flavor() != HoldTapFlavor::TapPreferred {
+            match KeyDeferral::start(key_index) {
+                Some(deferral) => {
+                    self.deferral.lock(|v| v.set(Some(deferral)));
+                    is_deferring = true;
+                }
+                None => warn!("Key events are already deferred, falling back to the tapping term"),
+            }
+        }
+        let interrupted = async {
+            if !is_deferring {
+                return core::future::pending().await;
+            }
+            self.interrupted().await
+        };
+        match select3(
+            Timer::after(tapping_term),
+            self.activated_tap.wait(),
+            interrupted,
+        )
+        .await
+        {
+            Either3::First(()) => {
+                let since_last_key_press =
+                    crate::last_other_key_press(key_index).map(|v| v.elapsed());
+                if self.mode.resolves_to_tap(since_last_key_press) {
+                    // The tap action is released together with the key.
+                    if self.decision.decide_tap() {
+                        self.pressed_tap.store(true, Ordering::SeqCst);
+                        self.tap_action.on_press(context).await;
+                    }
+                    self.end_deferral();
+                    return;
+                }
+            }
+            // The deferral is ended by `on_release` after pressing the tap action.
+            Either3::Second(()) => return,
+            Either3::Third(()) => debug!("Another key was pressed, triggering hold action"),
+        }
+        // The key may have been released right at the end of the tapping term, in which case the
+        // tap action was already chosen by `on_release`.
+        let _guard = self.pressing_hold.lock().await;
+        if self.decision.decide_hold() {
+            self.hold_action.on_press(context).await;
+            self.end_deferral();
+        }
+    }
+
+    async fn on_release<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        if self.pressed_tap.swap(false, Ordering::SeqCst) {
+            self.tap_action.on_release(context).await;
+            self.last_tap.lock(|v| v.set(Some(Instant::now())));
+        } else if self.decision.decide_tap() {
+            self.activated_tap.signal(());
+            self.tap_action.on_press(context).await;
+            self.end_deferral();
+            Timer::after_millis(10).await;
+            self.tap_action.on_release(context).await;
+            self.last_tap.lock(|v| v.set(Some(Instant::now())));
+        } else {
+            self.last_tap.lock(|v| v.set(None));
+            let _guard = self.pressing_hold.lock().await;
+            self.hold_action.on_release(context).await;
+        }
+    }
+}
+
+/// Returns `true` if a key that is pressed at `now` was pressed within the quick tap duration after
+/// the last tap.
+fn within_quick_tap(quick_tap: Option<Duration>, last_tap: Option<Instant>, now: Instant) -> bool {
+    match (quick_tap, last_tap) {
+        (Some(quick_tap), Some(last_tap)) => now.saturating_duration_since(last_tap) < quick_tap,
+        _ => false,
+    }
+}
+
+/// Action that activates a layer while the key is held and presses a key if it is tapped.
+///
+/// This is a [`HoldTap`] with a [`Layer`] as the hold action, so the decision between the hold and
+/// the tap action is made in the same way and can be configured with the same methods. The layer
+/// is removed from the [`LayerManager`](lokey_layer::LayerManager) when the key is released,
+/// regardless of whether the hold action was triggered by the tapping term or by another key.
+///
+/// As this is a type alias of `HoldTap<Layer, Key>`, it is created with [`HoldTap::new`], which
+/// takes the [`Layer`] action for the layer instead of its [`LayerId`](lokey_layer::LayerId), and
+/// all methods of [`HoldTap`] can be used to configure it.
+///
+/// # Example
+///
+/// ```
+/// use embassy_time::Duration;
+/// use lokey_keyboard::Key;
+/// use lokey_keyboard::action::{Layer, LayerTap};
+/// use lokey_layer::LayerId;
+///
+/// // Activates layer 1 while the key is held, otherwise sends Escape
+/// let action = LayerTap::new(Layer::new(LayerId(1)), Key::Escape);
+///
+/// // The same action, which is only triggered if the key is held for at least 150ms
+/// let action = LayerTap::new(Layer::new(LayerId(1)), Key::Escape)
+///     .tapping_term(Duration::from_millis(150));
+/// ```
+pub type LayerTap = HoldTap<Layer, Key>;
+
+/// A set of modifier keys, stored in the format of the modifier byte of the HID keyboard report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Modifiers(pub u8);
+
+impl Modifiers {
+    pub const LCONTROL: Self = Self(0b0000_0001);
+    pub const LSHIFT: Self = Self(0b0000_0010);
+    pub const LALT: Self = Self(0b0000_0100);
+    pub const LGUI: Self = Self(0b0000_1000);
+    pub const RCONTROL: Self = Self(0b0001_0000);
+    pub const RSHIFT: Self = Self(0b0010_0000);
+    pub const RALT: Self = Self(0b0100_0000);
+    pub const RGUI: Self = Self(0b1000_0000);
+
+    /// Returns the union of both sets of modifiers.
+    pub const fn with(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// Returns the modifier keys of the set.
+    pub fn keys(self) -> KeySet {
+        [
+            Key::LControl,
+            Key::LShift,
+            Key::LAlt,
+            Key::LGui,
+            Key::RControl,
+            Key::RShift,
+            Key::RAlt,
+            Key::RGui,
+        ]
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| self.0 & (1 << i) != 0)
+        .map(|(_, key)| key)
+        .collect()
+    }
+}
+
+impl core::ops::BitOr for Modifiers {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        self.with(rhs)
+    }
+}
+
+/// Holds the modifiers while the key is held.
+///
+/// Like a [`KeyCombo`], all modifiers are pressed and released in a single keyboard report.
+impl Action for Modifiers {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let keys = self.keys();
+        update_keys(context, "Modifiers", |report| report.insert_all(keys)).await;
+    }
+
+    async fn on_release<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let keys = self.keys();
+        update_keys(context, "Modifiers", |report| report.remove_all(keys)).await;
+    }
+}
+
+/// Action that holds a combination of modifiers while the key is held and presses a key if it is
+/// tapped.
+///
+/// This is a [`HoldTap`] with the [`Modifiers`] as the hold action, so the decision between the
+/// hold and the tap action is made in the same way and can be configured with the same methods.
+/// All modifiers are pressed and released in a single keyboard report, so the host never sees a
+/// part of the combination.
+///
+/// # Example
+///
+/// ```
+/// use lokey_keyboard::Key;
+/// use lokey_keyboard::action::{ModTap, Modifiers};
+///
+/// // Holds Control and Shift while the key is held, otherwise sends Escape
+/// let action = ModTap::new(Modifiers::LCONTROL | Modifiers::LSHIFT, Key::Escape);
+/// ```
+pub type ModTap = HoldTap<Modifiers, Key>;
+
+/// Action that triggers a different action if the key is pressed while specific modifiers are
+/// held (also known as mod-morph).
+///
+/// If all of the modifiers are held when the key is pressed, the replacement action is triggered,
+/// otherwise the default action is triggered. This is the same condition as for a
+/// [`KeyOverrideEntry`](crate::KeyOverrideEntry), but it is evaluated when the key is pressed, so
+/// the replacement can be any action and the override only applies to keys of the layout that use
+/// this action.
+///
+/// By default, the modifiers are suppressed while the replacement action is active, i.e. they are
+/// removed from the keyboard reports that are sent to the host. When the key is released, the
+/// modifiers that are still held are sent again, so e.g. releasing Shift before releasing the key
+/// does not leave Shift pressed. Use [`keep_modifiers`](Self::keep_modifiers) to send the
+/// modifiers together with the replacement action instead.
+///
+/// # Example
+///
+/// ```
+/// use lokey_keyboard::Key;
+/// use lokey_keyboard::action::{ModMorph, Modifiers};
+///
+/// // Sends Backspace, or Delete if the key is pressed while Left Shift is held
+/// let action = ModMorph::new(Modifiers::LSHIFT, Key::Backspace, Key::Delete);
+/// ```
+pub struct ModMorph<A, B> {
+    modifiers: Modifiers,
+    default: A,
+    replacement: B,
+    keep_modifiers: bool,
+    was_morphed: AtomicBool,
+}
+
+impl<A: Action, B: Action> ModMorph<A, B> {
+    pub const fn new(modifiers: Modifiers, default: A, replacement: B) -> Self {
+        Self {
+            modifiers,
+            default,
+            replacement,
+            keep_modifiers: false,
+            was_morphed: AtomicBool::new(false),
+        }
+    }
+
+    /// Sets whether the modifiers are kept while the replacement action is active instead of
+    /// being suppressed.
+    ///
+    /// Defaults to `false`.
+    pub const fn keep_modifiers(mut self, value: bool) -> Self {
+        self.keep_modifiers = value;
+        self
+    }
+}
+
+impl<A: Action, B: Action> Action for ModMorph<A, B> {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let Some(report) = context.state.try_get::<KeyboardReportState>() else {
+            error!("ModMorph action requires KeyboardReportState");
+            return;
+        };
+        let modifiers = self.modifiers.keys();
+        if !report.get().keys.is_superset(modifiers) {
+            self.was_morphed.store(false, Ordering::SeqCst);
+            self.default.on_press(context).await;
+            return;
+        }
+        self.was_morphed.store(true, Ordering::SeqCst);
+        if !self.keep_modifiers {
+            let keyboard_report = report.suppress(modifiers);
+            if let Err(e) = context.external_channel.try_send(keyboard_report).await {
+                error!("Failed to send keyboard report: {:?}", e);
+            }
+        }
+        self.replacement.on_press(context).await;
+    }
+
+    async fn on_release<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        if !self.was_morphed.swap(false, Ordering::SeqCst) {
+            self.default.on_release(context).await;
+            return;
+        }
+        self.replacement.on_release(context).await;
+        if !self.keep_modifiers
+            && let Some(report) = context.state.try_get::<KeyboardReportState>()
+        {
+            let keyboard_report = report.restore(self.modifiers.keys());
+            if let Err(e) = context.external_channel.try_send(keyboard_report).await {
+                error!("Failed to send keyboard report: {:?}", e);
+            }
+        }
+    }
+}
+
+/// The decision between the hold and the tap action of a [`HoldTap`] action.
+///
+/// The decision is made exactly once per key press: whichever of [`decide_hold`](Self::decide_hold)
+/// and [`decide_tap`](Self::decide_tap) is called first wins.
+struct HoldTapDecision(AtomicU8);
+
+impl HoldTapDecision {
+    const UNDECIDED: u8 = 0;
+    const HOLD: u8 = 1;
+    const TAP: u8 = 2;
+
+    const fn new() -> Self {
+        Self(AtomicU8::new(Self::UNDECIDED))
+    }
+
+    fn reset(&self) {
+        self.0.store(Self::UNDECIDED, Ordering::SeqCst);
+    }
+
+    /// Decides for the hold action and returns `true`, or returns `false` if the tap action was
+    /// already decided.
+    fn decide_hold(&self) -> bool {
+        match self.0.compare_exchange(
+            Self::UNDECIDED,
+            Self::HOLD,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        ) {
+            Ok(_) => true,
+            Err(v) => v == Self::HOLD,
+        }
+    }
+
+    /// Decides for the tap action and returns `true`, or returns `false` if the hold action was
+    /// already decided.
+    fn decide_tap(&self) -> bool {
+        match self.0.compare_exchange(
+            Self::UNDECIDED,
+            Self::TAP,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        ) {
+            Ok(_) => true,
+            Err(v) => v == Self::TAP,
+        }
+    }
+}
+
+/// Sends the action with Shift if the key is held past a threshold.
+///
+/// The action is not performed while the key is held. If the key is released before the
+/// threshold, the action is tapped. Otherwise the action is tapped with Left Shift held as soon as
+/// the threshold elapses: Shift is pressed, then the action, and then both are released again.
+///
+/// This works with any action, e.g. a [`Key`] that types a different character with Shift or a
+/// [`KeyCombo`] that triggers a different shortcut with Shift.
+pub struct AutoShift<A> {
+    action: A,
+    threshold: Duration,
+    released: Signal<CriticalSectionRawMutex, ()>,
+}
+
+impl<A: Action> AutoShift<A> {
+    pub const fn new(action: A) -> Self {
+        Self {
+            action,
+            threshold: Duration::from_millis(175),
+            released: Signal::new(),
+        }
+    }
+
+    /// Sets how long the key must be held to send the action with Shift (defaults to 175ms).
+    pub const fn threshold(mut self, value: Duration) -> Self {
+        self.threshold = value;
+        self
+    }
+}
+
+impl<A: Action> Action for AutoShift<A> {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        self.released.reset();
+        match select(Timer::after(self.threshold), self.released.wait()).await {
+            Either::First(()) => {
+                Key::LShift.on_press(context).await;
+                self.action.on_press(context).await;
+                Timer::after_millis(10).await;
+                self.action.on_release(context).await;
+                Key::LShift.on_release(context).await;
+            }
+            Either::Second(()) => {
+                self.action.on_press(context).await;
+                Timer::after_millis(10).await;
+                self.action.on_release(context).await;
+            }
+        }
+    }
+
+    async fn on_release<D, T, S>(&self, _context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        self.released.signal(());
+    }
+}
+
+/// Performs a different action depending on how many times the key was tapped.
+///
+/// Each action is associated with a tap count. The tap dance ends once the key was not pressed
+/// again within the tapping term after the last press, or as soon as another key is pressed. Then
+/// the action with the highest tap count that is not greater than the number of taps is performed,
+/// i.e. tap counts without an associated action fall through to the next lower associated count.
+/// Nothing is performed if the number of taps is lower than all associated counts.
+///
+/// The events of the other keys are held back during the tap dance, so the key press that ends
+/// the tap dance is handled after the action was pressed.
+///
+/// If the key is still held when the tap dance ends, the action is pressed until the key is
+/// released, otherwise it is tapped. This allows actions like [`Layer`] to stay active while the key
+/// is held after the last tap.
+pub struct TapDance<A: ActionContainer> {
+    actions: A,
+    tap_counts: GenericArray<u8, A::NumChildren>,
+    tapping_term: Option<Duration>,
+    tap_count: AtomicU8,
+    is_pressed: AtomicBool,
+    pressed_again: Signal<CriticalSectionRawMutex, ()>,
+    held_action_index: blocking_mutex::Mutex<CriticalSectionRawMutex, Cell<Option<usize>>>,
+}
+
+impl<A: ActionContainer> TapDance<A> {
+    pub const fn new(actions: A, tap_counts: GenericArray<u8, A::NumChildren>) -> Self {
+        Self {
+            actions,
+            tap_counts,
+            tapping_term: None,
+            tap_count: AtomicU8::new(0),
+            is_pressed: AtomicBool::new(false),
+            pressed_again: Signal::new(),
+            held_action_index: blocking_mutex::Mutex::new(Cell::new(None)),
+        }
+    }
+
+    /// Sets the duration within which the key must be pressed again to continue the tap dance.
+    ///
+    /// Defaults to the tapping term of the [`TappingTermState`] (see
+    /// [`Layout::tapping_term`](crate::Layout::tapping_term)), or 200ms if the state type is not
+    /// part of the state.
+    pub const fn tapping_term(mut self, value: Duration) -> Self {
+        self.tapping_term = Some(value);
+        self
+    }
+
+    /// Returns the index of the action for the specified number of taps.
+    fn action_index(&self, tap_count: u8) -> Option<usize> {
+        self.tap_counts
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| **v <= tap_count)
+            .max_by_key(|(_, v)| **v)
+            .map(|(index, _)| index)
+    }
+}
+
+impl<A: ActionContainer> Action for TapDance<A> {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let key_index = crate::current_key_index();
+        self.is_pressed.store(true, Ordering::SeqCst);
+        if self.tap_count.fetch_add(1, Ordering::SeqCst) > 0 {
+            self.pressed_again.signal(());
+            return;
+        }
+        self.pressed_again.reset();
+        let tapping_term = self.tapping_term.unwrap_or_else(|| {
+            context
+                .state
+                .try_get::<TappingTermState>()
+                .map_or(DEFAULT_TAPPING_TERM, |v| v.get())
+        });
+        let deferral = KeyDeferral::start(key_index);
+        let is_deferring = deferral.is_some();
+        let mut key_presses = crate::KeyPressObserver::new();
+        let other_key_pressed = async {
+            if is_deferring {
+                while !defer::next_deferred().await.is_press() {}
+                return;
+            }
+            // Another action defers the key events, so the presses can only be observed.
+            loop {
+                match key_presses.next().await {
+                    ComboEvent::Key(crate::Message::Press { key_index: v }) if v == key_index => {}
+                    _ => break,
+                }
+            }
+        };
+        let mut other_key_pressed = core::pin::pin!(other_key_pressed);
+        loop {
+            match select3(
+                Timer::after(tapping_term),
+                self.pressed_again.wait(),
+                &mut other_key_pressed,
+            )
+            .await
+            {
+                Either3::First(()) => break,
+                Either3::Second(()) => {}
+                Either3::Third(()) => {
+                    debug!("Another key was pressed, ending tap dance");
+                    break;
+                }
+            }
+        }
+
+        let tap_count = self.tap_count.swap(0, Ordering::SeqCst);
+        let Some(index) = self.action_index(tap_count) else {
+            return;
+        };
+        if self.is_pressed.load(Ordering::SeqCst) {
+            self.held_action_index.lock(|v| v.set(Some(index)));
+            unwrap!(self.actions.child_on_press(index, context).await);
+        } else {
+            unwrap!(self.actions.child_on_press(index, context).await);
+            drop(deferral);
+            Timer::after_millis(10).await;
+            unwrap!(self.actions.child_on_release(index, context).await);
+        }
+    }
+
+    async fn on_release<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        self.is_pressed.store(false, Ordering::SeqCst);
+        if let Some(index) = self.held_action_index.lock(|v| v.take()) {
+            unwrap!(self.actions.child_on_release(index, context).await);
+        }
+    }
+}
+
+/// Performs an action when a sequence of keys is typed after the leader key.
+///
+/// Each action is associated with a sequence of key indices. After the leader key was pressed,
+/// the following key presses are captured, so they do not perform the actions of the keys, until
+/// they match a sequence. If a sequence matches that is not the beginning of a longer sequence,
+/// its action is tapped immediately. Otherwise the leader waits for the next key press until the
+/// idle timeout elapses, in which case the action of the typed sequence is tapped if it exactly
+/// matches a sequence. Nothing is performed if the typed keys are not the beginning of any
+/// sequence, or if the whole sequence was not typed within the timeout.
+pub struct Leader<A: ActionContainer> {
+    actions: A,
+    sequences: GenericArray<&'static [u16], A::NumChildren>,
+    timeout: Duration,
+    idle_timeout: Duration,
+}
+
+impl<A: ActionContainer> Leader<A> {
+    pub const fn new(actions: A, sequences: GenericArray<&'static [u16], A::NumChildren>) -> Self {
+        Self {
+            actions,
+            sequences,
+            timeout: Duration::from_secs(2),
+            idle_timeout: Duration::from_millis(500),
+        }
+    }
+
+    /// Sets the duration within which the whole sequence must be typed (defaults to 2 seconds).
+    pub const fn timeout(mut self, value: Duration) -> Self {
+        self.timeout = value;
+        self
+    }
+
+    /// Sets the duration within which the next key of the sequence must be pressed (defaults to
+    /// 500ms).
+    pub const fn idle_timeout(mut self, value: Duration) -> Self {
+        self.idle_timeout = value;
+        self
+    }
+
+    /// Removes the sequences from the candidates that do not have the key at the specified
+    /// position.
+    ///
+    /// Returns `true` if there are candidates left.
+    fn filter_candidates(
+        &self,
+        candidates: &mut GenericArray<bool, A::NumChildren>,
+        position: usize,
+        key_index: u16,
+    ) -> bool {
+        for (candidate, sequence) in candidates.iter_mut().zip(&self.sequences) {
+            *candidate &= sequence.get(position) == Some(&key_index);
+        }
+        candidates.iter().any(|v| *v)
+    }
+
+    /// Returns the index of the candidate that consists of exactly `len` keys and whether there
+    /// is a longer candidate.
+    fn exact_match(
+        &self,
+        candidates: &GenericArray<bool, A::NumChildren>,
+        len: usize,
+    ) -> (Option<usize>, bool) {
+        let mut exact_match = None;
+        let mut has_longer_candidates = false;
+        for (index, sequence) in self.sequences.iter().enumerate() {
+            if !candidates[index] {
+                continue;
+            }
+            if sequence.len() == len {
+                exact_match.get_or_insert(index);
+            } else {
+                has_longer_candidates = true;
+            }
+        }
+        (exact_match, has_longer_candidates)
+    }
+}
+
+impl<A: ActionContainer> Action for Leader<A> {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let Some(mut capture) = KeyCapture::start() else {
+            warn!("Another action is already capturing key presses");
+            return;
+        };
+        debug!("Waiting for leader sequence");
+        let deadline = Instant::now() + self.timeout;
+        let mut candidates = GenericArray::<bool, A::NumChildren>::default();
+        candidates.fill(true);
+        let mut len = 0;
+        let index = loop {
+            let idle_deadline = (Instant::now() + self.idle_timeout).min(deadline);
+            let key_index = match select(capture.next(), Timer::at(idle_deadline)).await {
+                Either::First(key_index) => key_index,
+                Either::Second(()) if Instant::now() >= deadline => {
+                    debug!("Leader sequence timed out");
+                    break None;
+                }
+                Either::Second(()) => break self.exact_match(&candidates, len).0,
+            };
+            if !self.filter_candidates(&mut candidates, len, key_index) {
+                debug!("Unknown leader sequence");
+                break None;
+            }
+            len += 1;
+            if let (Some(index), false) = self.exact_match(&candidates, len) {
+                break Some(index);
+            }
+        };
+        drop(capture);
+        if let Some(index) = index {
+            unwrap!(self.actions.child_on_press(index, context).await);
+            Timer::after_millis(10).await;
+            unwrap!(self.actions.child_on_release(index, context).await);
+        }
+    }
+
+    async fn on_release<D, T, S>(&self, _context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+    }
+}
+
+/// Only performs the inner action if the key is tapped.
+///
+/// Nothing happens when the key is pressed. When the key is released within the tapping term, the
+/// inner action is pressed and released, otherwise the key press is ignored. This can be used for
+/// keys that must never trigger on long presses (e.g. to ignore accidental palm presses).
+pub struct TapOnly<A> {
+    action: A,
+    tapping_term: Duration,
+    pressed_at: blocking_mutex::Mutex<CriticalSectionRawMutex, Cell<Option<Instant>>>,
+}
+
+impl<A: Action> TapOnly<A> {
+    pub const fn new(action: A) -> Self {
+        Self {
+            action,
+            tapping_term: Duration::from_millis(200),
+            pressed_at: blocking_mutex::Mutex::new(Cell::new(None)),
+        }
+    }
+
+    /// Sets the duration within which the key must be released to perform the inner action.
+    pub const fn tapping_term(mut self, value: Duration) -> Self {
+        self.tapping_term = value;
+        self
+    }
+}
+
+impl<A: Action> Action for TapOnly<A> {
+    async fn on_press<D, T, S>(&self, _context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        self.pressed_at.lock(|v| v.set(Some(Instant::now())));
+    }
+
+    async fn on_release<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let Some(pressed_at) = self.pressed_at.lock(|v| v.take()) else {
+            return;
+        };
+        if pressed_at.elapsed() <= self.tapping_term {
+            self.action.on_press(context).await;
+            Timer::after_millis(10).await;
+            self.action.on_release(context).await;
+        }
+    }
+}
+
+/// Action for switching between windows or applications (e.g. with Alt+Tab).
+///
+/// The first key press presses the modifier action and taps the step action. Subsequent key presses
+/// only tap the step action while the modifier action stays pressed. The modifier action is released
+/// once the key was not pressed for the configured timeout.
+pub struct SuperTab<Modifier, Step> {
+    modifier_action: Modifier,
+    step_action: Step,
+    timeout: Duration,
+    modifier_pressed: AtomicBool,
+    is_pressed: AtomicBool,
+    activity: Signal<CriticalSectionRawMutex, ()>,
+}
+
+impl<Modifier: Action, Step: Action> SuperTab<Modifier, Step> {
+    pub const fn new(modifier_action: Modifier, step_action: Step) -> Self {
+        Self {
+            modifier_action,
+            step_action,
+            timeout: Duration::from_secs(1),
+            modifier_pressed: AtomicBool::new(false),
+            is_pressed: AtomicBool::new(false),
+            activity: Signal::new(),
+        }
+    }
+
+    /// Sets how long the key must not be pressed to release the modifier action.
+    pub const fn timeout(mut self, value: Duration) -> Self {
+        self.timeout = value;
+        self
+    }
+}
+
+impl<Modifier: Action, Step: Action> Action for SuperTab<Modifier, Step> {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        self.is_pressed.store(true, Ordering::SeqCst);
+        if self.modifier_pressed.swap(true, Ordering::SeqCst) {
+            self.activity.signal(());
+            self.step_action.on_press(context).await;
+            return;
+        }
+
+        self.activity.reset();
+        self.modifier_action.on_press(context).await;
+        self.step_action.on_press(context).await;
+        loop {
+            match select(Timer::after(self.timeout), self.activity.wait()).await {
+                Either::First(()) if !self.is_pressed.load(Ordering::SeqCst) => break,
+                Either::First(()) => self.activity.wait().await,
+                Either::Second(()) => {}
+            }
+        }
+        self.modifier_pressed.store(false, Ordering::SeqCst);
+        self.modifier_action.on_release(context).await;
+    }
+
+    async fn on_release<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        self.is_pressed.store(false, Ordering::SeqCst);
+        self.step_action.on_release(context).await;
+        self.activity.signal(());
+    }
+}
+
+/// Action that toggles the [toggle transport](toggle::Transport) of the device with the
+/// specified address.
+pub struct ToggleExternalTransport {
+    address: Address,
+    group: u8,
+}
+
+impl ToggleExternalTransport {
+    pub const fn new(address: Address) -> Self {
+        Self { address, group: 0 }
+    }
+
+    /// Sets the group of the toggle transport (defaults to 0).
+    pub const fn group(mut self, value: u8) -> Self {
+        self.group = value;
+        self
+    }
+}
+
+impl Action for ToggleExternalTransport {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        context
+            .internal_channel
+            .send(toggle::Message::Toggle(self.address, self.group))
+            .await;
+    }
+
+    async fn on_release<D, T, S>(&self, _: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+    }
+}
+
+/// Action that activates the [toggle transport](toggle::Transport) of the device with the
+/// specified address.
+pub struct ActivateExternalTransport {
+    address: Address,
+    group: u8,
+}
+
+impl ActivateExternalTransport {
+    pub const fn new(address: Address) -> Self {
+        Self { address, group: 0 }
+    }
+
+    /// Sets the group of the toggle transport (defaults to 0).
+    pub const fn group(mut self, value: u8) -> Self {
+        self.group = value;
+        self
+    }
+}
+
+impl Action for ActivateExternalTransport {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
     where
         D: Device,
         T: Transports<D::Mcu>,
@@ -623,7 +3255,7 @@ impl Action for ActivateExternalTransport {
     {
         context
             .internal_channel
-            .send(toggle::Message::Activate(self.0))
+            .send(toggle::Message::Activate(self.address, self.group))
             .await;
     }
 
@@ -636,7 +3268,24 @@ impl Action for ActivateExternalTransport {
     }
 }
 
-pub struct DeactivateExternalTransport(pub Address);
+/// Action that deactivates the [toggle transport](toggle::Transport) of the device with the
+/// specified address.
+pub struct DeactivateExternalTransport {
+    address: Address,
+    group: u8,
+}
+
+impl DeactivateExternalTransport {
+    pub const fn new(address: Address) -> Self {
+        Self { address, group: 0 }
+    }
+
+    /// Sets the group of the toggle transport (defaults to 0).
+    pub const fn group(mut self, value: u8) -> Self {
+        self.group = value;
+        self
+    }
+}
 
 impl Action for DeactivateExternalTransport {
     async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
@@ -647,7 +3296,7 @@ impl Action for DeactivateExternalTransport {
     {
         context
             .internal_channel
-            .send(toggle::Message::Deactivate(self.0))
+            .send(toggle::Message::Deactivate(self.address, self.group))
             .await;
     }
 
@@ -660,9 +3309,169 @@ impl Action for DeactivateExternalTransport {
     }
 }
 
+/// The number of times the [`FactoryReset`] action tries to clear the storage.
+const FACTORY_RESET_ATTEMPTS: u8 = 3;
+
+/// Action that resets the device to its factory state once the key was held for the configured
+/// duration.
+///
+/// All entries are removed from the storage of the device, which includes the bonds of the BLE
+/// transport, the stored default layer and all values of a
+/// [`ConfigStore`](lokey::storage::ConfigStore). Afterwards the MCU is rebooted, so that all
+/// components start again with their default settings. Erasing the storage is attempted up to
+/// three times. If it still fails, the error is logged and the reset is aborted without rebooting,
+/// so it can be retried by holding the key again.
+///
+/// If the reboot is disabled, the BLE transport is instructed to clear its bonds and to select the
+/// first profile instead (if the `ble` feature is enabled). Other components keep the settings they
+/// loaded until the next reboot.
+///
+/// On split keyboards, only the storage of the device that runs the action is erased.
+///
+/// # Example
+///
+/// ```
+/// use embassy_time::Duration;
+/// use lokey_keyboard::action::FactoryReset;
+///
+/// let action = FactoryReset::new()
+///     .hold_duration(Duration::from_secs(10)) // optional, defaults to 5 seconds
+///     .reboot(false); // optional, defaults to true
+/// ```
+pub struct FactoryReset {
+    hold_duration: Duration,
+    reboot: bool,
+    released: Signal<CriticalSectionRawMutex, ()>,
+}
+
+impl Default for FactoryReset {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FactoryReset {
+    pub const fn new() -> Self {
+        Self {
+            hold_duration: Duration::from_secs(5),
+            reboot: true,
+            released: Signal::new(),
+        }
+    }
+
+    /// Sets how long the key has to be held until the reset is performed (defaults to 5 seconds).
+    pub const fn hold_duration(mut self, value: Duration) -> Self {
+        self.hold_duration = value;
+        self
+    }
+
+    /// Sets whether the MCU is rebooted after the reset (defaults to true).
+    pub const fn reboot(mut self, value: bool) -> Self {
+        self.reboot = value;
+        self
+    }
+}
+
+impl Action for FactoryReset {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        self.released.reset();
+        if let Either::Second(()) =
+            select(Timer::after(self.hold_duration), self.released.wait()).await
+        {
+            return;
+        }
+
+        info!("Performing factory reset");
+        let mut attempt = 1;
+        while let Err(e) = context.storage.clear().await {
+            #[cfg(feature = "defmt")]
+            let e = defmt::Debug2Format(&e);
+            error!("Failed to clear storage (attempt {}): {}", attempt, e);
+            if attempt == FACTORY_RESET_ATTEMPTS {
+                error!("Aborting factory reset");
+                return;
+            }
+            attempt += 1;
+        }
+        if self.reboot {
+            context.mcu.reboot();
+        }
+        #[cfg(feature = "ble")]
+        {
+            use lokey_ble::external::Message;
+            context.internal_channel.send(Message::ClearAll).await;
+            context
+                .internal_channel
+                .send(Message::SelectProfile { index: 0 })
+                .await;
+        }
+    }
+
+    async fn on_release<D, T, S>(&self, _: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        self.released.signal(());
+    }
+}
+
+/// Action that reboots the MCU.
+pub struct Reboot;
+
+impl Action for Reboot {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        context.mcu.reboot()
+    }
+
+    async fn on_release<D, T, S>(&self, _: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+    }
+}
+
+/// Action that reboots the MCU into its bootloader, e.g. to flash a new firmware.
+///
+/// See [`Mcu::reboot_to_bootloader`] for the bootloader that is entered.
+pub struct RebootToBootloader;
+
+impl Action for RebootToBootloader {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        context.mcu.reboot_to_bootloader()
+    }
+
+    async fn on_release<D, T, S>(&self, _: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+    }
+}
+
 #[cfg(feature = "ble")]
 pub use ble::{
-    BleClear, BleClearActive, BleClearAll, BleDisconnectActive, BleNextProfile, BlePreviousProfile,
+    BleClear, BleClearActive, BleClearAll, BleDisconnectActive, BleEnterPairingMode,
+    BleNextBondedProfile, BleNextProfile, BlePreviousBondedProfile, BlePreviousProfile,
     BleSelectProfile,
 };
 
@@ -763,6 +3572,36 @@ mod ble {
         }
     }
 
+    /// Enters the pairing mode of the BLE transport.
+    ///
+    /// While the pairing mode is active, new hosts can pair with profiles that have no bond yet.
+    /// This only has an effect if
+    /// [`TransportConfig::require_pairing_mode`](lokey_ble::external::TransportConfig::require_pairing_mode)
+    /// is enabled.
+    pub struct BleEnterPairingMode;
+
+    impl Action for BleEnterPairingMode {
+        async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+        where
+            D: Device,
+            T: Transports<D::Mcu>,
+            S: AnyState,
+        {
+            context
+                .internal_channel
+                .send(Message::EnterPairingMode)
+                .await;
+        }
+
+        async fn on_release<D, T, S>(&self, _context: Context<D, T, S>)
+        where
+            D: Device,
+            T: Transports<D::Mcu>,
+            S: AnyState,
+        {
+        }
+    }
+
     pub struct BleSelectProfile(pub u8);
 
     impl Action for BleSelectProfile {
@@ -834,6 +3673,56 @@ mod ble {
         {
         }
     }
+
+    /// Selects the next BLE profile that has a bond, skipping the profiles without a bond.
+    pub struct BleNextBondedProfile;
+
+    impl Action for BleNextBondedProfile {
+        async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+        where
+            D: Device,
+            T: Transports<D::Mcu>,
+            S: AnyState,
+        {
+            context
+                .internal_channel
+                .send(Message::SelectNextBondedProfile)
+                .await;
+        }
+
+        async fn on_release<D, T, S>(&self, _context: Context<D, T, S>)
+        where
+            D: Device,
+            T: Transports<D::Mcu>,
+            S: AnyState,
+        {
+        }
+    }
+
+    /// Selects the previous BLE profile that has a bond, skipping the profiles without a bond.
+    pub struct BlePreviousBondedProfile;
+
+    impl Action for BlePreviousBondedProfile {
+        async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+        where
+            D: Device,
+            T: Transports<D::Mcu>,
+            S: AnyState,
+        {
+            context
+                .internal_channel
+                .send(Message::SelectPreviousBondedProfile)
+                .await;
+        }
+
+        async fn on_release<D, T, S>(&self, _context: Context<D, T, S>)
+        where
+            D: Device,
+            T: Transports<D::Mcu>,
+            S: AnyState,
+        {
+        }
+    }
 }
 
 #[cfg(feature = "usb-ble")]
@@ -900,3 +3789,835 @@ mod usb_ble {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::Layout;
+    use lokey_layer::LayerManager;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    #[test]
+    fn modifier_keys() {
+        let modifiers = Modifiers::LCONTROL | Modifiers::LSHIFT | Modifiers::RGUI;
+        assert_eq!(modifiers.keys(), Key::LControl | Key::LShift | Key::RGui);
+        assert!(Modifiers(0).keys().is_empty());
+    }
+
+    #[test]
+    fn hold_tap_decision_is_made_once() {
+        let decision = HoldTapDecision::new();
+        assert!(decision.decide_hold());
+        assert!(!decision.decide_tap());
+        assert!(decision.decide_hold());
+
+        decision.reset();
+        assert!(decision.decide_tap());
+        assert!(!decision.decide_hold());
+        assert!(decision.decide_tap());
+    }
+
+    #[test]
+    fn hold_tap_layer_is_removed_when_released_at_tapping_term() {
+        use crate::testing::{Record, Recorded, TestState, press, release, run_layout};
+
+        let layout = Layout::new((
+            HoldTap::new(Layer::new(LayerId(1)), Record("tap")),
+            PerLayer::new(
+                (Record("base"), Record("layer")),
+                [LayerId(0), LayerId(1)].into(),
+            ),
+        ));
+        let state = Box::leak(Box::new(TestState::default()));
+        let recorded = run_layout(
+            &layout,
+            state,
+            &[
+                // The key is released exactly when the tapping term elapses.
+                press(1_000, 0),
+                release(201_000, 0),
+                press(210_000, 1),
+                release(220_000, 1),
+            ],
+            230_000,
+        );
+        let recorded: Vec<_> = recorded
+            .iter()
+            .map(|Recorded { name, pressed, .. }| (*name, *pressed))
+            .filter(|(name, _)| *name != "tap")
+            .collect();
+        assert_eq!(recorded, [("base", true), ("base", false)]);
+        assert_eq!(state.layer_manager.active(), LayerId(0));
+    }
+
+    #[test]
+    fn startup_suppression() {
+        use crate::testing::{Record, Recorded, TestState, press, release, run_layout};
+
+        let layout = Layout::new((Record("a"),)).startup_suppression(Duration::from_millis(50));
+        let state = Box::leak(Box::new(TestState::default()));
+        let recorded = run_layout(
+            &layout,
+            state,
+            &[
+                press(10_000, 0),
+                release(20_000, 0),
+                // The release of a suppressed press is dropped as well.
+                press(45_000, 0),
+                release(55_000, 0),
+                press(60_000, 0),
+                release(70_000, 0),
+            ],
+            80_000,
+        );
+        let recorded: Vec<_> = recorded
+            .iter()
+            .map(|Recorded { at, name, pressed }| (at.as_millis(), *name, *pressed))
+            .collect();
+        assert_eq!(recorded, [(60, "a", true), (70, "a", false)]);
+    }
+
+    #[test]
+    fn release_is_dispatched_to_pressed_action_after_layer_change() {
+        use crate::testing::{Record, Recorded, TestState, press, release, run_layout};
+
+        let layout = Layout::new((
+            Layer::new(LayerId(1)),
+            PerLayer::new(
+                (Record("base"), Record("layer")),
+                [LayerId(0), LayerId(1)].into(),
+            ),
+        ));
+        let state = Box::leak(Box::new(TestState::default()));
+        let recorded = run_layout(
+            &layout,
+            state,
+            &[
+                // The layer is activated while the key is held.
+                press(1_000, 1),
+                press(10_000, 0),
+                release(20_000, 1),
+                release(30_000, 0),
+                // The layer is deactivated while the key is held.
+                press(40_000, 0),
+                press(50_000, 1),
+                release(60_000, 0),
+                release(70_000, 1),
+            ],
+            80_000,
+        );
+        let recorded: Vec<_> = recorded
+            .iter()
+            .map(|Recorded { at, name, pressed }| (at.as_millis(), *name, *pressed))
+            .collect();
+        assert_eq!(
+            recorded,
+            [
+                (1, "base", true),
+                (20, "base", false),
+                (50, "layer", true),
+                (70, "layer", false),
+            ]
+        );
+        assert_eq!(state.layer_manager.active(), LayerId(0));
+    }
+
+    #[test]
+    fn layer_tap_removes_layer_when_released_after_tapping_term() {
+        use crate::testing::{Record, Recorded, TestState, press, release, run_layout};
+
+        let layout = Layout::new((
+            LayerTap::new(Layer::new(LayerId(1)), Key::Escape),
+            PerLayer::new(
+                (Record("base"), Record("layer")),
+                [LayerId(0), LayerId(1)].into(),
+            ),
+        ));
+        let state = Box::leak(Box::new(TestState::default()));
+        let recorded = run_layout(
+            &layout,
+            state,
+            &[
+                // The hold action is triggered when the tapping term elapses at 201ms.
+                press(1_000, 0),
+                press(250_000, 1),
+                release(260_000, 1),
+                release(270_000, 0),
+                press(280_000, 1),
+                release(290_000, 1),
+            ],
+            300_000,
+        );
+        let recorded: Vec<_> = recorded
+            .iter()
+            .map(|Recorded { name, pressed, .. }| (*name, *pressed))
+            .collect();
+        assert_eq!(
+            recorded,
+            [
+                ("layer", true),
+                ("layer", false),
+                ("base", true),
+                ("base", false)
+            ]
+        );
+        assert_eq!(state.layer_manager.active(), LayerId(0));
+    }
+
+    #[test]
+    fn hold_tap_tapping_term_fallback() {
+        let state = TappingTermState::new(Duration::from_millis(150));
+        let hold_tap = HoldTap::new(NoOp, NoOp);
+        assert_eq!(hold_tap.effective_tapping_term(None), DEFAULT_TAPPING_TERM);
+        assert_eq!(
+            hold_tap.effective_tapping_term(Some(&state)),
+            Duration::from_millis(150)
+        );
+
+        // An explicit tapping term takes precedence over the state.
+        let hold_tap = HoldTap::new(NoOp, NoOp).tapping_term(Duration::from_millis(300));
+        assert_eq!(
+            hold_tap.effective_tapping_term(Some(&state)),
+            Duration::from_millis(300)
+        );
+    }
+
+    #[test]
+    fn hold_tap_quick_tap() {
+        let quick_tap = Some(Duration::from_millis(150));
+        let last_tap = Some(Instant::from_millis(1000));
+        assert!(within_quick_tap(
+            quick_tap,
+            last_tap,
+            Instant::from_millis(1100)
+        ));
+        assert!(!within_quick_tap(
+            quick_tap,
+            last_tap,
+            Instant::from_millis(1150)
+        ));
+        // Disabled or no previous tap
+        assert!(!within_quick_tap(
+            None,
+            last_tap,
+            Instant::from_millis(1100)
+        ));
+        assert!(!within_quick_tap(
+            quick_tap,
+            None,
+            Instant::from_millis(1100)
+        ));
+    }
+
+    #[test]
+    fn hold_tap_streak_break() {
+        use crate::testing::{Record, Recorded, TestState, press, release, run_layout};
+
+        let layout = Layout::new((
+            HoldTap::new(Record("hold"), Record("tap")).mode(HoldTapMode::StreakBreak {
+                streak_term: Duration::from_millis(100),
+            }),
+            Record("a"),
+            Record("b"),
+        ));
+        let state = Box::leak(Box::new(TestState::default()));
+        let recorded = run_layout(
+            &layout,
+            state,
+            &[
+                // Simulated fast typing: other keys are pressed in short intervals while the
+                // hold-tap key is held, so the tapping term elapses at 201ms during the streak.
+                press(1_000, 0),
+                press(60_000, 1),
+                release(80_000, 1),
+                press(150_000, 2),
+                release(170_000, 2),
+                release(300_000, 0),
+                // The streak was broken long before the tapping term elapses at 600ms.
+                press(400_000, 0),
+                press(460_000, 1),
+                release(480_000, 1),
+                release(700_000, 0),
+            ],
+            800_000,
+        );
+        let recorded: Vec<_> = recorded
+            .iter()
+            .filter(|Recorded { name, .. }| *name == "hold" || *name == "tap")
+            .map(|Recorded { at, name, pressed }| (at.as_millis(), *name, *pressed))
+            .collect();
+        assert_eq!(
+            recorded,
+            [
+                (201, "tap", true),
+                (300, "tap", false),
+                (600, "hold", true),
+                (700, "hold", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn alt_repeat() {
+        use crate::testing::{TestState, press, release, run_layout};
+
+        static TABLE: [AltRepeatEntry; 2] = [
+            AltRepeatEntry::new(Key::A, Key::B),
+            AltRepeatEntry::new(Key::B, Key::A),
+        ];
+        let layout = Layout::new((Key::A, Key::LShift, AltRepeat::new(&TABLE), Key::C));
+
+        // A modifier that is pressed after the key does not change the previously typed key.
+        let state = Box::leak(Box::new(TestState::default()));
+        run_layout(
+            &layout,
+            state,
+            &[
+                press(1_000, 0),
+                release(10_000, 0),
+                press(20_000, 1),
+                release(30_000, 1),
+                press(40_000, 2),
+            ],
+            50_000,
+        );
+        assert_eq!(state.keyboard_report.get().keys, KeySet::only(Key::B));
+
+        // Repeating the alternate key types the original key again.
+        let state = Box::leak(Box::new(TestState::default()));
+        run_layout(
+            &layout,
+            state,
+            &[
+                press(1_000, 0),
+                release(10_000, 0),
+                press(20_000, 2),
+                release(30_000, 2),
+                press(40_000, 2),
+            ],
+            50_000,
+        );
+        assert_eq!(state.keyboard_report.get().keys, KeySet::only(Key::A));
+
+        // Nothing is typed if the table has no entry for the previously typed key.
+        let state = Box::leak(Box::new(TestState::default()));
+        run_layout(
+            &layout,
+            state,
+            &[press(1_000, 3), release(10_000, 3), press(20_000, 2)],
+            30_000,
+        );
+        assert_eq!(state.keyboard_report.get().keys, KeySet::empty());
+    }
+
+    #[test]
+    fn one_shot_max_presses() {
+        use crate::testing::{Record, Recorded, TestState, press, release, run_layout};
+
+        let layout = Layout::new((
+            OneShot::new(Record("one_shot")).max_presses(2),
+            Key::A,
+            Key::B,
+            KeyCombo::new(&[Key::C, Key::D]),
+        ));
+        let state = Box::leak(Box::new(TestState::default()));
+        let recorded = run_layout(
+            &layout,
+            state,
+            &[
+                // The action is released after the second key press.
+                press(1_000, 0),
+                release(10_000, 0),
+                press(20_000, 1),
+                release(30_000, 1),
+                press(40_000, 2),
+                release(50_000, 2),
+                // Each key of a report counts as a press.
+                press(100_000, 0),
+                release(110_000, 0),
+                press(120_000, 3),
+                release(130_000, 3),
+            ],
+            200_000,
+        );
+        let recorded: Vec<_> = recorded
+            .iter()
+            .map(|Recorded { at, name, pressed }| (at.as_millis(), *name, *pressed))
+            .collect();
+        assert_eq!(
+            recorded,
+            [
+                (1, "one_shot", true),
+                (40, "one_shot", false),
+                (100, "one_shot", true),
+                (120, "one_shot", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn one_shot_timeout() {
+        use crate::testing::{Record, Recorded, TestState, press, release, run_layout};
+
+        let layout = Layout::new((
+            OneShot::new(Record("one_shot"))
+                .timeout(Duration::from_millis(100))
+                .max_presses(2),
+            Key::LShift,
+            Key::A,
+        ));
+        let state = Box::leak(Box::new(TestState::default()));
+        let recorded = run_layout(
+            &layout,
+            state,
+            &[
+                // The action is released after the timeout.
+                press(1_000, 0),
+                release(10_000, 0),
+                // Modifiers do not count as a press and do not restart the timeout.
+                press(200_000, 0),
+                release(210_000, 0),
+                press(250_000, 1),
+                release(260_000, 1),
+                // A counted press restarts the timeout.
+                press(400_000, 0),
+                release(410_000, 0),
+                press(450_000, 2),
+                release(460_000, 2),
+            ],
+            700_000,
+        );
+        let recorded: Vec<_> = recorded
+            .iter()
+            .map(|Recorded { at, name, pressed }| (at.as_millis(), *name, *pressed))
+            .collect();
+        assert_eq!(
+            recorded,
+            [
+                (1, "one_shot", true),
+                (101, "one_shot", false),
+                (200, "one_shot", true),
+                (300, "one_shot", false),
+                (400, "one_shot", true),
+                (550, "one_shot", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn one_shot_lock_term() {
+        use crate::testing::{Record, Recorded, TestState, press, release, run_layout};
+
+        let layout = Layout::new((
+            OneShot::new(Record("one_shot")).lock_term(Duration::from_millis(200)),
+            Key::A,
+        ));
+        let state = Box::leak(Box::new(TestState::default()));
+        let recorded = run_layout(
+            &layout,
+            state,
+            &[
+                // Pressing the key twice within the lock term locks the action.
+                press(1_000, 0),
+                release(10_000, 0),
+                press(100_000, 0),
+                release(110_000, 0),
+                press(200_000, 1),
+                release(210_000, 1),
+                // Pressing the key again unlocks it.
+                press(300_000, 0),
+                release(310_000, 0),
+                // Pressing the key again after the lock term releases the action.
+                press(1_000_000, 0),
+                release(1_010_000, 0),
+                press(1_500_000, 0),
+                release(1_510_000, 0),
+            ],
+            1_600_000,
+        );
+        let recorded: Vec<_> = recorded
+            .iter()
+            .map(|Recorded { at, name, pressed }| (at.as_millis(), *name, *pressed))
+            .collect();
+        assert_eq!(
+            recorded,
+            [
+                (1, "one_shot", true),
+                (300, "one_shot", false),
+                (1_000, "one_shot", true),
+                (1_500, "one_shot", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn when_no_mod() {
+        use crate::testing::{Record, Recorded, TestState, press, release, run_layout};
+
+        let layout = Layout::new((Key::LShift, WhenNoMod::new(Record("a"))));
+        let state = Box::leak(Box::new(TestState::default()));
+        let recorded = run_layout(
+            &layout,
+            state,
+            &[
+                // The inner action is not performed while a modifier is held.
+                press(1_000, 0),
+                press(10_000, 1),
+                release(20_000, 1),
+                release(30_000, 0),
+                press(40_000, 1),
+                release(50_000, 1),
+                // Releasing the modifier before the key still releases the inner action.
+                press(60_000, 1),
+                press(70_000, 0),
+                release(80_000, 1),
+                release(90_000, 0),
+            ],
+            100_000,
+        );
+        let recorded: Vec<_> = recorded
+            .iter()
+            .map(|Recorded { at, name, pressed }| (at.as_millis(), *name, *pressed))
+            .collect();
+        assert_eq!(
+            recorded,
+            [
+                (40, "a", true),
+                (50, "a", false),
+                (60, "a", true),
+                (80, "a", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn hold_tap_require_prior_idle() {
+        use crate::testing::{Record, Recorded, TestState, press, release, run_layout};
+
+        let layout = Layout::new((
+            HoldTap::new(Record("hold"), Record("tap"))
+                .require_prior_idle(Duration::from_millis(100))
+                .prior_idle_keys(&[1]),
+            Record("a"),
+            Record("b"),
+        ));
+        let state = Box::leak(Box::new(TestState::default()));
+        let recorded = run_layout(
+            &layout,
+            state,
+            &[
+                // The key with index 1 was pressed 50ms before.
+                press(1_000, 1),
+                release(10_000, 1),
+                press(51_000, 0),
+                release(300_000, 0),
+                // The key with index 2 does not count as activity.
+                press(500_000, 2),
+                release(510_000, 2),
+                press(550_000, 0),
+                release(800_000, 0),
+                // The key with index 1 was pressed 150ms before.
+                press(1_000_000, 1),
+                release(1_010_000, 1),
+                press(1_150_000, 0),
+                release(1_400_000, 0),
+            ],
+            1_500_000,
+        );
+        let recorded: Vec<_> = recorded
+            .iter()
+            .filter(|Recorded { name, .. }| *name == "hold" || *name == "tap")
+            .map(|Recorded { at, name, pressed }| (at.as_millis(), *name, *pressed))
+            .collect();
+        assert_eq!(
+            recorded,
+            [
+                (51, "tap", true),
+                (300, "tap", false),
+                (750, "hold", true),
+                (800, "hold", false),
+                (1350, "hold", true),
+                (1400, "hold", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn hold_tap_streak_break_longer_than_tapping_term() {
+        use crate::testing::{Record, Recorded, TestState, press, release, run_layout};
+
+        let layout = Layout::new((
+            HoldTap::new(Record("hold"), Record("tap"))
+                .tapping_term(Duration::from_millis(200))
+                .mode(HoldTapMode::StreakBreak {
+                    streak_term: Duration::from_millis(300),
+                }),
+            Record("a"),
+        ));
+        let state = Box::leak(Box::new(TestState::default()));
+        let recorded = run_layout(
+            &layout,
+            state,
+            &[
+                // The press of the hold-tap key itself does not count as a streak.
+                press(1_000, 0),
+                release(300_000, 0),
+                // Another key was pressed 250ms before the tapping term elapses at 650ms.
+                press(400_000, 1),
+                release(410_000, 1),
+                press(450_000, 0),
+                release(700_000, 0),
+                // The last press of another key was long before the tapping term elapses.
+                press(1_000_000, 0),
+                release(1_300_000, 0),
+            ],
+            1_400_000,
+        );
+        let recorded: Vec<_> = recorded
+            .iter()
+            .filter(|Recorded { name, .. }| *name == "hold" || *name == "tap")
+            .map(|Recorded { at, name, pressed }| (at.as_millis(), *name, *pressed))
+            .collect();
+        assert_eq!(
+            recorded,
+            [
+                (201, "hold", true),
+                (300, "hold", false),
+                (650, "tap", true),
+                (700, "tap", false),
+                (1200, "hold", true),
+                (1300, "hold", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn hold_tap_interruption() {
+        let key = |key_index, pressed| {
+            if pressed {
+                ComboEvent::Key(crate::Message::Press { key_index })
+            } else {
+                ComboEvent::Key(crate::Message::Release { key_index })
+            }
+        };
+        let combo = |index, pressed| ComboEvent::Combo { index, pressed };
+
+        let mut pressed = ArrayVec::new();
+        assert!(interrupts(
+            HoldTapFlavor::HoldPreferred,
+            &mut pressed,
+            combo(0, true)
+        ));
+
+        // A combo is handled like a single key, even if it has the same index as a key
+        let flavor = HoldTapFlavor::Balanced;
+        let mut pressed = ArrayVec::new();
+        assert!(!interrupts(flavor, &mut pressed, combo(0, true)));
+        assert!(!interrupts(flavor, &mut pressed, key(1, true)));
+        assert!(!interrupts(flavor, &mut pressed, key(0, false)));
+        assert!(!interrupts(flavor, &mut pressed, combo(1, false)));
+        assert!(interrupts(flavor, &mut pressed, combo(0, false)));
+    }
+
+    #[test]
+    fn hold_tap_defers_back_to_back_presses() {
+        use crate::testing::{Record, Recorded, TestState, press, release, run_layout};
+
+        // The press of the hold-tap key is held back by the combo until the other key is pressed,
+        // so both presses are passed on at the same time.
+        let layout = Layout::new((
+            HoldTap::new(Record("hold"), Record("tap")).flavor(HoldTapFlavor::HoldPreferred),
+            Record("other"),
+            NoOp,
+        ))
+        .combos((crate::Combo::new(&[0, 2], NoOp),));
+        let state = Box::leak(Box::new(TestState::default()));
+        let recorded = run_layout(
+            &layout,
+            state,
+            &[
+                press(1_000, 0),
+                press(1_500, 1),
+                release(5_000, 1),
+                release(10_000, 0),
+            ],
+            20_000,
+        );
+        let recorded: Vec<_> = recorded
+            .iter()
+            .map(|Recorded { name, pressed, .. }| (*name, *pressed))
+            .collect();
+        assert_eq!(
+            recorded,
+            [
+                ("hold", true),
+                ("other", true),
+                ("other", false),
+                ("hold", false)
+            ]
+        );
+    }
+
+    #[test]
+    fn hold_tap_retro_tap_defers_other_keys() {
+        use crate::testing::{Record, Recorded, Slow, TestState, press, release, run_layout};
+
+        let layout = Layout::new((
+            HoldTap::new(Slow(Record("hold")), Record("tap")).retro_tap(true),
+            Record("other"),
+        ));
+        let state = Box::leak(Box::new(TestState::default()));
+        let recorded = run_layout(
+            &layout,
+            state,
+            &[
+                press(1_000, 0),
+                press(50_000, 1),
+                release(60_000, 1),
+                release(70_000, 0),
+            ],
+            100_000,
+        );
+        let recorded: Vec<_> = recorded
+            .iter()
+            .map(|Recorded { name, pressed, .. }| (*name, *pressed))
+            .collect();
+        assert_eq!(
+            recorded,
+            [
+                ("hold", true),
+                ("other", true),
+                ("other", false),
+                ("hold", false)
+            ]
+        );
+    }
+
+    #[test]
+    fn tap_dance_falls_through_to_lower_tap_count() {
+        let tap_dance = TapDance::new((NoOp, NoOp, NoOp), [1, 2, 4].into());
+        assert_eq!(tap_dance.action_index(0), None);
+        assert_eq!(tap_dance.action_index(1), Some(0));
+        assert_eq!(tap_dance.action_index(2), Some(1));
+        assert_eq!(tap_dance.action_index(3), Some(1));
+        assert_eq!(tap_dance.action_index(4), Some(2));
+        assert_eq!(tap_dance.action_index(7), Some(2));
+    }
+
+    #[test]
+    fn tap_dance_is_ended_by_other_key() {
+        use crate::testing::{Record, Recorded, Slow, TestState, press, release, run_layout};
+
+        let layout = Layout::new((
+            TapDance::new((Record("one"), Slow(Record("two"))), [1, 2].into()),
+            Record("other"),
+        ));
+        let state = Box::leak(Box::new(TestState::default()));
+        state.tapping_term.set(Duration::from_millis(100));
+        let recorded = run_layout(
+            &layout,
+            state,
+            &[
+                press(1_000, 0),
+                release(20_000, 0),
+                press(40_000, 0),
+                release(60_000, 0),
+                press(80_000, 1),
+                release(150_000, 1),
+                press(300_000, 0),
+                release(320_000, 0),
+            ],
+            500_000,
+        );
+        let names: Vec<_> = recorded
+            .iter()
+            .map(|Recorded { name, pressed, .. }| (*name, *pressed))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("two", true),
+                ("other", true),
+                ("two", false),
+                ("other", false),
+                ("one", true),
+                ("one", false)
+            ]
+        );
+        // The single tap ends after the tapping term of the state.
+        assert!(recorded[4].at < Instant::from_millis(450));
+    }
+
+    #[test]
+    fn leader_prefers_longest_sequence() {
+        let leader = Leader::new((NoOp, NoOp), [&[1, 2][..], &[1, 2, 3][..]].into());
+        let mut candidates = GenericArray::default();
+        candidates.fill(true);
+
+        assert!(leader.filter_candidates(&mut candidates, 0, 1));
+        assert_eq!(leader.exact_match(&candidates, 1), (None, true));
+        assert!(leader.filter_candidates(&mut candidates, 1, 2));
+        // The shorter sequence matches, but the longer sequence could still be typed.
+        assert_eq!(leader.exact_match(&candidates, 2), (Some(0), true));
+        assert!(leader.filter_candidates(&mut candidates, 2, 3));
+        assert_eq!(leader.exact_match(&candidates, 3), (Some(1), false));
+        assert!(!leader.filter_candidates(&mut candidates, 3, 4));
+    }
+
+    #[test]
+    fn smart_layer_state_transitions() {
+        use SmartLayerState::*;
+
+        // Single press is momentary.
+        assert_eq!(Inactive.on_press(1, 2, 3), Held);
+        assert_eq!(Held.on_release(), Inactive);
+
+        // Double tap toggles, the next press turns the layer off again.
+        assert_eq!(Inactive.on_press(2, 2, 3), Toggled);
+        assert_eq!(Toggled.on_release(), Toggled);
+        assert_eq!(Toggled.on_press(1, 2, 3), Inactive);
+
+        // Triple tap locks, only another triple tap unlocks.
+        assert_eq!(Toggled.on_press(3, 2, 3), Locked);
+        assert_eq!(Locked.on_release(), Locked);
+        assert_eq!(Locked.on_press(1, 2, 3), Locked);
+        assert_eq!(Locked.on_press(2, 2, 3), Locked);
+        assert_eq!(Locked.on_press(3, 2, 3), Inactive);
+
+        // A tap count of 0 disables toggling and locking.
+        assert_eq!(Inactive.on_press(2, 0, 0), Held);
+        assert_eq!(Toggled.on_press(3, 2, 0), Inactive);
+    }
+
+    #[test]
+    fn smart_layer_does_not_leak_entries() {
+        use lokey::state::ToStateQuery;
+
+        let layer_manager = LayerManager::new();
+        let query = layer_manager.to_query();
+        let mut inner = SmartLayerInner {
+            state: SmartLayerState::Inactive,
+            entry: None,
+            tap_count: 0,
+            last_release: None,
+        };
+
+        for state in [
+            SmartLayerState::Held,
+            SmartLayerState::Toggled,
+            SmartLayerState::Locked,
+        ] {
+            inner.state = state;
+            inner.sync_entry(LayerId(1), &query);
+            assert_eq!(layer_manager.active(), LayerId(1));
+        }
+
+        inner.state = SmartLayerState::Inactive;
+        inner.sync_entry(LayerId(1), &query);
+        assert!(inner.entry.is_none());
+        assert_eq!(layer_manager.active(), LayerId(0));
+    }
+}
@@ -0,0 +1,241 @@
+use super::{Message, ScannerDriver};
+use crate::DynContext;
+use core::future::Future;
+use embassy_time::{Duration, Timer};
+
+/// An input that reads analog values (e.g. an ADC with a Hall-effect sensor per key).
+pub trait AnalogInput<const NUM_CHANNELS: usize> {
+    /// Reads the raw values of all channels.
+    fn read(&mut self) -> impl Future<Output = [u16; NUM_CHANNELS]>;
+}
+
+/// The raw values of a key at the top and at the bottom of its travel.
+///
+/// The bottom value can be lower than the rest value, e.g. if the magnetic field becomes weaker
+/// when the key is pressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KeyCalibration {
+    /// The raw value while the key is not pressed.
+    pub rest: u16,
+    /// The raw value while the key is fully pressed.
+    pub bottom: u16,
+}
+
+impl KeyCalibration {
+    pub const fn new(rest: u16, bottom: u16) -> Self {
+        Self { rest, bottom }
+    }
+
+    /// Returns the travel distance of the key for the specified raw value, where 0 is the rest
+    /// position and 255 is the bottom position.
+    pub const fn travel(&self, value: u16) -> u8 {
+        let rest = self.rest as i32;
+        let range = self.bottom as i32 - rest;
+        if range == 0 {
+            return 0;
+        }
+        let travel = (value as i32 - rest) * u8::MAX as i32 / range;
+        if travel < 0 {
+            0
+        } else if travel > u8::MAX as i32 {
+            u8::MAX
+        } else {
+            travel as u8
+        }
+    }
+}
+
+/// Configuration for the [`AnalogKeys`] scanner.
+///
+/// All travel distances are specified in the range of 0 (rest position) to 255 (bottom position).
+#[derive(Clone)]
+pub struct AnalogKeysConfig {
+    /// The travel distance at which a key is pressed. Defaults to `128`.
+    pub actuation_point: u8,
+    /// The distance that a key has to move back above the actuation point before it is released,
+    /// so that noise around the actuation point does not cause repeated key events. Defaults to
+    /// `16`.
+    pub hysteresis: u8,
+    /// Enables rapid trigger with the specified sensitivity. Defaults to `None`.
+    ///
+    /// With rapid trigger, a key that passed the actuation point is released as soon as it moves
+    /// up by the sensitivity, and pressed again as soon as it moves down by the sensitivity. The
+    /// key is always released once it moves back above the actuation point minus the hysteresis.
+    pub rapid_trigger: Option<u8>,
+    /// The interval in which the analog values are read. Defaults to 1ms.
+    pub scan_interval: Duration,
+    /// Send the key events as [`TimestampedMessage`](crate::TimestampedMessage)s.
+    ///
+    /// This should be enabled on the peripheral of a split keyboard if the central uses a
+    /// [reorder window](crate::Layout::reorder_window). Defaults to `false`.
+    pub timestamps: bool,
+}
+
+impl Default for AnalogKeysConfig {
+    fn default() -> Self {
+        Self {
+            actuation_point: 128,
+            hysteresis: 16,
+            rapid_trigger: None,
+            scan_interval: Duration::from_millis(1),
+            timestamps: false,
+        }
+    }
+}
+
+/// The state of a single key of the [`AnalogKeys`] scanner.
+#[derive(Clone, Copy, Default)]
+struct KeyState {
+    pressed: bool,
+    /// The deepest travel distance since the key was pressed, or the shallowest travel distance
+    /// since the key was released.
+    extreme: u8,
+}
+
+impl KeyState {
+    /// Updates the state with the current travel distance of the key.
+    ///
+    /// Returns the new pressed state if it changed.
+    fn update(&mut self, travel: u8, config: &AnalogKeysConfig) -> Option<bool> {
+        let release_point = config.actuation_point.saturating_sub(config.hysteresis);
+        let pressed = match config.rapid_trigger {
+            None if self.pressed => travel >= release_point,
+            None => travel >= config.actuation_point,
+            Some(sensitivity) if self.pressed => {
+                travel >= release_point && travel > self.extreme.saturating_sub(sensitivity)
+            }
+            Some(sensitivity) => {
+                travel >= config.actuation_point
+                    && travel >= self.extreme.saturating_add(sensitivity)
+            }
+        };
+        let changed = pressed != self.pressed;
+        self.extreme = match (pressed, changed) {
+            (_, true) => travel,
+            (true, false) => self.extreme.max(travel),
+            (false, false) => self.extreme.min(travel),
+        };
+        self.pressed = pressed;
+        changed.then_some(pressed)
+    }
+}
+
+/// Scanner for analog keys (e.g. Hall-effect switches), whose travel distance is read from an
+/// [`AnalogInput`].
+pub struct AnalogKeys<A, const NUM_CHANNELS: usize, const NUM_KEYS: usize> {
+    input: A,
+    transform: [Option<usize>; NUM_KEYS],
+    calibration: [KeyCalibration; NUM_KEYS],
+}
+
+impl<A, const NUM_CHANNELS: usize> AnalogKeys<A, NUM_CHANNELS, 0> {
+    /// Creates a new scanner where all keys use the specified calibration.
+    pub const fn new<const NUM_KEYS: usize>(
+        input: A,
+        calibration: KeyCalibration,
+    ) -> AnalogKeys<A, NUM_CHANNELS, NUM_KEYS> {
+        AnalogKeys {
+            input,
+            transform: [None; NUM_KEYS],
+            calibration: [calibration; NUM_KEYS],
+        }
+    }
+}
+
+impl<A, const NUM_CHANNELS: usize, const NUM_KEYS: usize> AnalogKeys<A, NUM_CHANNELS, NUM_KEYS> {
+    pub const fn map<const INDEX_CHANNEL: usize, const INDEX_KEYS: usize>(mut self) -> Self {
+        self.transform[INDEX_KEYS] = Some(INDEX_CHANNEL);
+        self
+    }
+
+    pub const fn continuous<const OFFSET: usize>(mut self) -> Self {
+        let mut i = 0;
+        while i < NUM_CHANNELS {
+            self.transform[i + OFFSET] = Some(i);
+            i += 1;
+        }
+        self
+    }
+
+    /// Sets the calibration of the key with the specified index.
+    pub const fn calibrate(mut self, key_index: usize, calibration: KeyCalibration) -> Self {
+        self.calibration[key_index] = calibration;
+        self
+    }
+
+    /// Returns the travel distance of every key for the specified raw channel values.
+    fn travel(&self, values: &[u16; NUM_CHANNELS]) -> [Option<u8>; NUM_KEYS] {
+        core::array::from_fn(|key_index| {
+            let channel = self.transform[key_index]?;
+            Some(self.calibration[key_index].travel(values[channel]))
+        })
+    }
+}
+
+impl<A: AnalogInput<NUM_CHANNELS>, const NUM_CHANNELS: usize, const NUM_KEYS: usize>
+    ScannerDriver<NUM_KEYS> for AnalogKeys<A, NUM_CHANNELS, NUM_KEYS>
+{
+    type Config = AnalogKeysConfig;
+
+    async fn scan_once(&mut self, config: &Self::Config) -> [bool; NUM_KEYS] {
+        let values = self.input.read().await;
+        self.travel(&values)
+            .map(|travel| travel.is_some_and(|v| v >= config.actuation_point))
+    }
+
+    async fn run(mut self, config: Self::Config, key_offset: u16, context: DynContext) {
+        let mut states = [KeyState::default(); NUM_KEYS];
+        loop {
+            let values = self.input.read().await;
+            for (key_index, travel) in self.travel(&values).into_iter().enumerate() {
+                let Some(travel) = travel else {
+                    continue;
+                };
+                if let Some(pressed) = states[key_index].update(travel, &config) {
+                    let key_index = u16::try_from(key_index).expect("too many keys");
+                    let message = if pressed {
+                        Message::Press { key_index }
+                    } else {
+                        Message::Release { key_index }
+                    };
+                    message.send(context, key_offset, config.timestamps).await;
+                }
+            }
+            Timer::after(config.scan_interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inverted_calibration() {
+        let calibration = KeyCalibration::new(3000, 1000);
+        assert_eq!(calibration.travel(3100), 0);
+        assert_eq!(calibration.travel(2000), 127);
+        assert_eq!(calibration.travel(500), 255);
+    }
+
+    #[test]
+    fn rapid_trigger() {
+        let config = AnalogKeysConfig {
+            rapid_trigger: Some(20),
+            ..Default::default()
+        };
+        let mut state = KeyState::default();
+        assert_eq!(state.update(100, &config), None);
+        assert_eq!(state.update(130, &config), Some(true));
+        assert_eq!(state.update(200, &config), None);
+        // Released after moving back up by the sensitivity, even though it is still past the
+        // actuation point
+        assert_eq!(state.update(180, &config), Some(false));
+        assert_eq!(state.update(170, &config), None);
+        // Pressed again after moving down by the sensitivity
+        assert_eq!(state.update(190, &config), Some(true));
+        // Always released once it moves back above the release point
+        assert_eq!(state.update(100, &config), Some(false));
+    }
+}
@@ -1,23 +1,36 @@
 #![allow(clippy::needless_borrows_for_generic_args)] // Produced by the macros from trouble_host
 
-use crate::KeyboardReport;
+use crate::consumer::{CONSUMER_REPORT_ID, CONSUMER_REPORT_SIZE};
+#[cfg(feature = "nkro")]
+use crate::hid_report::NKRO_HID_REPORT_DESCRIPTOR;
+use crate::hid_report::{HID_REPORT_DESCRIPTOR, HID_REPORT_DESCRIPTOR_LEN, KEYBOARD_REPORT_ID};
+#[cfg(feature = "nkro")]
+use crate::nkro::NKRO_REPORT_DESCRIPTOR;
+use crate::system_control::{SYSTEM_CONTROL_REPORT_ID, SYSTEM_CONTROL_REPORT_SIZE};
+use crate::{
+    HidReport, HostLeds, KeyboardReport, KeyboardReportConfig, KeyboardReportEncoder,
+    MAX_KEYBOARD_REPORT_SIZE,
+};
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use generic_array::GenericArray;
-use lokey::util::error;
-use lokey_ble::external::{InitMessageService, TxMessage, TxMessageService};
+use heapless::Vec;
+use lokey::util::{error, unwrap};
+use lokey_ble::external::{InitMessageService, MessageConfigs, TxMessage, TxMessageService};
+use trouble_host::gatt::WriteEvent;
 use trouble_host::prelude::*;
-use usbd_hid::descriptor::{
-    AsInputReport, KeyboardReport as HidKeyboardReport, SerializedDescriptor,
-};
+use usbd_hid::descriptor::{KeyboardReport as HidKeyboardReport, SerializedDescriptor};
 
-const INPUT_KEYBOARD_SIZE: usize = 8;
 const OUTPUT_KEYBOARD_SIZE: usize = 1;
 
+/// The maximum length of the report map of the keyboard report service, which is the length of the
+/// boot keyboard report descriptor as the NKRO report descriptor is shorter.
+const KEYBOARD_REPORT_MAP_SIZE: usize = 69;
+
 impl TxMessage for KeyboardReport {
     type MessageService = KeyboardReportService;
 
-    const ATTRIBUTE_COUNT: usize = HidService::ATTRIBUTE_COUNT;
-    const CCCD_COUNT: usize = HidService::CCCD_COUNT;
+    const ATTRIBUTE_COUNT: usize = KeyboardHidService::ATTRIBUTE_COUNT;
+    const CCCD_COUNT: usize = KeyboardHidService::CCCD_COUNT;
 
     type LenServiceUuids16 = typenum::U1;
     type LenServiceUuids128 = typenum::U0;
@@ -29,36 +42,60 @@ impl TxMessage for KeyboardReport {
     fn service_uuids_128() -> GenericArray<[u8; 16], Self::LenServiceUuids128> {
         [].into()
     }
+
+    /// A keyboard report only replaces the previous report if it does not press any key that is
+    /// not contained in it.
+    ///
+    /// Merging reports that press keys could change the order in which the keys are reported to the
+    /// host (e.g. a fast roll of B and A would be typed as "ab"), so only releases are merged. The
+    /// transport never replaces a report that pressed keys itself, so quick taps are never lost.
+    fn replaces(&self, previous: &Self) -> bool {
+        self.keys.is_subset(previous.keys)
+    }
 }
 
 #[gatt_service(uuid = service::HUMAN_INTERFACE_DEVICE)]
-struct HidService {
+struct KeyboardHidService {
     #[characteristic(uuid = "2a4a", read, value = [0x01, 0x01, 0x00, 0x03])]
     pub hid_info: [u8; 4],
-    #[characteristic(uuid = "2a4b", read, value = HidKeyboardReport::desc().try_into().unwrap())]
-    pub report_map: [u8; 69], // 69 is the length of the slice returned by HidKeyboardReport::desc()
+    #[characteristic(uuid = "2a4b", read)]
+    pub report_map: Vec<u8, KEYBOARD_REPORT_MAP_SIZE>,
     #[characteristic(uuid = "2a4c", write_without_response)]
     pub hid_control_point: u8,
     #[characteristic(uuid = "2a4e", read, write_without_response, value = 1)]
     pub protocol_mode: u8,
     #[descriptor(uuid = "2908", read, value = [0u8, 1u8])]
     #[characteristic(uuid = "2a4d", read, notify)]
-    pub input_keyboard: [u8; INPUT_KEYBOARD_SIZE],
+    pub input_keyboard: Vec<u8, MAX_KEYBOARD_REPORT_SIZE>,
     #[descriptor(uuid = "2908", read, value = [0u8, 2u8])]
     #[characteristic(uuid = "2a4d", read, write, write_without_response)]
     pub output_keyboard: [u8; OUTPUT_KEYBOARD_SIZE],
 }
 
 pub struct KeyboardReportService {
-    hid_service: HidService,
+    hid_service: KeyboardHidService,
+    encoder: KeyboardReportEncoder,
 }
 
 impl InitMessageService for KeyboardReportService {
     fn init<'a, const ATT_MAX: usize>(
         attribute_table: &mut AttributeTable<'static, NoopRawMutex, ATT_MAX>,
+        configs: MessageConfigs,
     ) -> Self {
-        let hid_service = HidService::new(attribute_table);
-        Self { hid_service }
+        let encoder =
+            KeyboardReportEncoder::new(KeyboardReportConfig::from_message_configs(configs));
+        let hid_service = KeyboardHidService::new(attribute_table);
+        #[cfg_attr(not(feature = "nkro"), expect(unused_mut))]
+        let mut report_map = HidKeyboardReport::desc();
+        #[cfg(feature = "nkro")]
+        if encoder.is_nkro() {
+            report_map = &NKRO_REPORT_DESCRIPTOR;
+        }
+        set_report_map(attribute_table, &hid_service.report_map, report_map);
+        Self {
+            hid_service,
+            encoder,
+        }
     }
 }
 
@@ -68,25 +105,248 @@ impl TxMessageService<KeyboardReport> for KeyboardReportService {
         message: KeyboardReport,
         connection: &GattConnection<'stack, 'server, DefaultPacketPool>,
     ) {
-        let hid_keyboard_report = message.to_hid_report();
-        let mut buf = [0; INPUT_KEYBOARD_SIZE];
-        let len = match hid_keyboard_report.serialize(&mut buf) {
-            Ok(v) => v,
-            Err(e) => {
-                #[cfg(feature = "defmt")]
-                let e = defmt::Debug2Format(&e);
-                error!("Failed to serialize keyboard report: {}", e);
+        // The BLE HID service only supports the report protocol, so the NKRO report is always
+        // used if it is enabled.
+        notify_keyboard_report(
+            &self.hid_service.input_keyboard,
+            &self.encoder,
+            &message,
+            connection,
+        )
+        .await;
+    }
+
+    fn handle_write<'stack, 'server>(
+        &self,
+        event: &WriteEvent<'stack, 'server, DefaultPacketPool>,
+    ) {
+        handle_output_keyboard_write(&self.hid_service.output_keyboard, event);
+    }
+}
+
+impl TxMessage for HidReport {
+    type MessageService = HidReportService;
+
+    const ATTRIBUTE_COUNT: usize = CombinedHidService::ATTRIBUTE_COUNT;
+    const CCCD_COUNT: usize = CombinedHidService::CCCD_COUNT;
+
+    type LenServiceUuids16 = typenum::U1;
+    type LenServiceUuids128 = typenum::U0;
+
+    fn service_uuids_16() -> GenericArray<[u8; 2], Self::LenServiceUuids16> {
+        [service::HUMAN_INTERFACE_DEVICE.to_le_bytes()].into()
+    }
+
+    fn service_uuids_128() -> GenericArray<[u8; 16], Self::LenServiceUuids128> {
+        [].into()
+    }
+
+    /// A HID report only replaces the previous report if both are of the same kind and it does not
+    /// press any key or usage that is not contained in it.
+    ///
+    /// Like for the [`KeyboardReport`], only releases are merged so that the order of the presses
+    /// is kept.
+    fn replaces(&self, previous: &Self) -> bool {
+        match (self, previous) {
+            (Self::Keyboard(report), Self::Keyboard(previous)) => report.replaces(previous),
+            (Self::Consumer(report), Self::Consumer(previous)) => {
+                report.usages.is_subset(previous.usages)
+            }
+            (Self::SystemControl(report), Self::SystemControl(previous)) => {
+                report.usage.is_none() || report.usage == previous.usage
+            }
+            _ => false,
+        }
+    }
+}
+
+/// HID service that contains the keyboard, consumer control and system control reports.
+#[gatt_service(uuid = service::HUMAN_INTERFACE_DEVICE)]
+struct CombinedHidService {
+    #[characteristic(uuid = "2a4a", read, value = [0x01, 0x01, 0x00, 0x03])]
+    pub hid_info: [u8; 4],
+    #[characteristic(uuid = "2a4b", read)]
+    pub report_map: Vec<u8, HID_REPORT_DESCRIPTOR_LEN>,
+    #[characteristic(uuid = "2a4c", write_without_response)]
+    pub hid_control_point: u8,
+    #[characteristic(uuid = "2a4e", read, write_without_response, value = 1)]
+    pub protocol_mode: u8,
+    #[descriptor(uuid = "2908", read, value = [KEYBOARD_REPORT_ID, 1u8])]
+    #[characteristic(uuid = "2a4d", read, notify)]
+    pub input_keyboard: Vec<u8, MAX_KEYBOARD_REPORT_SIZE>,
+    #[descriptor(uuid = "2908", read, value = [KEYBOARD_REPORT_ID, 2u8])]
+    #[characteristic(uuid = "2a4d", read, write, write_without_response)]
+    pub output_keyboard: [u8; OUTPUT_KEYBOARD_SIZE],
+    #[descriptor(uuid = "2908", read, value = [CONSUMER_REPORT_ID, 1u8])]
+    #[characteristic(uuid = "2a4d", read, notify)]
+    pub input_consumer: [u8; CONSUMER_REPORT_SIZE],
+    #[descriptor(uuid = "2908", read, value = [SYSTEM_CONTROL_REPORT_ID, 1u8])]
+    #[characteristic(uuid = "2a4d", read, notify)]
+    pub input_system_control: [u8; SYSTEM_CONTROL_REPORT_SIZE],
+}
+
+/// Message service that sends the keyboard, consumer control and system control reports through a
+/// single HID service, using a different report reference for each report.
+pub struct HidReportService {
+    hid_service: CombinedHidService,
+    encoder: KeyboardReportEncoder,
+}
+
+impl InitMessageService for HidReportService {
+    fn init<'a, const ATT_MAX: usize>(
+        attribute_table: &mut AttributeTable<'static, NoopRawMutex, ATT_MAX>,
+        configs: MessageConfigs,
+    ) -> Self {
+        let encoder =
+            KeyboardReportEncoder::new(KeyboardReportConfig::from_message_configs(configs));
+        let hid_service = CombinedHidService::new(attribute_table);
+        #[cfg_attr(not(feature = "nkro"), expect(unused_mut))]
+        let mut report_map: &[u8] = &HID_REPORT_DESCRIPTOR;
+        #[cfg(feature = "nkro")]
+        if encoder.is_nkro() {
+            report_map = &NKRO_HID_REPORT_DESCRIPTOR;
+        }
+        set_report_map(attribute_table, &hid_service.report_map, report_map);
+        Self {
+            hid_service,
+            encoder,
+        }
+    }
+}
+
+impl TxMessageService<HidReport> for HidReportService {
+    async fn send<'stack, 'server>(
+        &self,
+        message: HidReport,
+        connection: &GattConnection<'stack, 'server, DefaultPacketPool>,
+    ) {
+        let result = match message {
+            HidReport::Keyboard(report) => {
+                notify_keyboard_report(
+                    &self.hid_service.input_keyboard,
+                    &self.encoder,
+                    &report,
+                    connection,
+                )
+                .await;
                 return;
             }
+            HidReport::Consumer(report) => {
+                self.hid_service
+                    .input_consumer
+                    .notify(connection, &report.to_hid_report())
+                    .await
+            }
+            HidReport::SystemControl(report) => {
+                self.hid_service
+                    .input_system_control
+                    .notify(connection, &report.to_hid_report())
+                    .await
+            }
         };
-        assert_eq!(len, INPUT_KEYBOARD_SIZE);
-        if let Err(e) = self
-            .hid_service
-            .input_keyboard
-            .notify(connection, &buf)
-            .await
-        {
+        if let Err(e) = result {
             error!("Failed to set input report: {}", e);
         }
     }
+
+    fn handle_write<'stack, 'server>(
+        &self,
+        event: &WriteEvent<'stack, 'server, DefaultPacketPool>,
+    ) {
+        handle_output_keyboard_write(&self.hid_service.output_keyboard, event);
+    }
+}
+
+fn set_report_map<const ATT_MAX: usize, const N: usize>(
+    attribute_table: &mut AttributeTable<'static, NoopRawMutex, ATT_MAX>,
+    characteristic: &Characteristic<Vec<u8, N>>,
+    report_map: &[u8],
+) {
+    // The characteristic is sized for the longest report map, so setting it cannot fail.
+    unwrap!(attribute_table.set(characteristic, &unwrap!(Vec::from_slice(report_map).ok())));
+}
+
+async fn notify_keyboard_report(
+    characteristic: &Characteristic<Vec<u8, MAX_KEYBOARD_REPORT_SIZE>>,
+    encoder: &KeyboardReportEncoder,
+    message: &KeyboardReport,
+    connection: &GattConnection<'_, '_, DefaultPacketPool>,
+) {
+    let Some(report) = encoder.encode(message, false) else {
+        return;
+    };
+    let report = unwrap!(Vec::from_slice(&report).ok());
+    if let Err(e) = characteristic.notify(connection, &report).await {
+        error!("Failed to set input report: {}", e);
+    }
+}
+
+fn handle_output_keyboard_write(
+    characteristic: &Characteristic<[u8; OUTPUT_KEYBOARD_SIZE]>,
+    event: &WriteEvent<'_, '_, DefaultPacketPool>,
+) {
+    if event.handle() == characteristic.handle
+        && let Some(&bits) = event.data().first()
+    {
+        HostLeds::from_bits(bits).set_current();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ConsumerReport, ConsumerUsage, ConsumerUsageSet, Key, KeySet, SystemControlReport,
+        SystemControlUsage,
+    };
+
+    fn keyboard_report(keys: KeySet) -> KeyboardReport {
+        KeyboardReport { keys }
+    }
+
+    #[test]
+    fn keyboard_report_replaces_only_releases() {
+        let empty = keyboard_report(KeySet::empty());
+        let a = keyboard_report(Key::A.into());
+        let ab = keyboard_report(Key::A | Key::B);
+        assert!(empty.replaces(&a));
+        assert!(a.replaces(&ab));
+        assert!(ab.replaces(&ab));
+        assert!(!a.replaces(&empty));
+        assert!(!ab.replaces(&a));
+        assert!(!keyboard_report(Key::B.into()).replaces(&a));
+    }
+
+    #[test]
+    fn hid_report_replaces_only_releases() {
+        let consumer_report =
+            |usages: ConsumerUsageSet| HidReport::Consumer(ConsumerReport { usages });
+        let empty = consumer_report(ConsumerUsageSet::empty());
+        let mute = consumer_report(ConsumerUsage::Mute.into());
+        let mute_play = consumer_report(ConsumerUsage::Mute | ConsumerUsage::Play);
+        assert!(empty.replaces(&mute));
+        assert!(mute.replaces(&mute_play));
+        assert!(!mute.replaces(&empty));
+        assert!(!mute_play.replaces(&mute));
+        assert!(!consumer_report(ConsumerUsage::Play.into()).replaces(&mute));
+
+        let system_control_report = |usage| HidReport::SystemControl(SystemControlReport { usage });
+        let released = system_control_report(None);
+        let sleep = system_control_report(Some(SystemControlUsage::Sleep));
+        assert!(released.replaces(&sleep));
+        assert!(sleep.replaces(&sleep));
+        assert!(!sleep.replaces(&released));
+        assert!(!system_control_report(Some(SystemControlUsage::WakeUp)).replaces(&sleep));
+
+        let keyboard_report = |keys| HidReport::Keyboard(keyboard_report(keys));
+        let a = keyboard_report(Key::A.into());
+        let ab = keyboard_report(Key::A | Key::B);
+        assert!(a.replaces(&ab));
+        assert!(!ab.replaces(&a));
+
+        assert!(!sleep.replaces(&empty));
+        assert!(!mute.replaces(&released));
+        assert!(!a.replaces(&empty));
+        assert!(!empty.replaces(&a));
+    }
 }
@@ -0,0 +1,101 @@
+use crate::Action;
+use core::future::Future;
+use lokey::util::info;
+use lokey::{AnyState, Context, Device, Transports};
+
+/// Action that is triggered if the specified keys are held while the device starts.
+///
+/// The keys are detected with a single scan that is performed before the scanner starts its normal
+/// operation (see [`Scanner::run_with_bootmagic`](crate::Scanner::run_with_bootmagic)). If all
+/// keys are held, the action is pressed and released once.
+///
+/// Multiple bootmagic entries can be combined by using a tuple of them.
+///
+/// # Example
+///
+/// ```ignore
+/// use lokey_keyboard::Bootmagic;
+/// use lokey_keyboard::action::BleClearAll;
+///
+/// // Clear all BLE bonds if the keys with index 0 and 1 are held during startup.
+/// let bootmagic = Bootmagic::new(&[0, 1], BleClearAll);
+/// ```
+pub struct Bootmagic<A> {
+    key_indices: &'static [u16],
+    action: A,
+}
+
+impl<A: Action> Bootmagic<A> {
+    /// Creates a new [`Bootmagic`] that triggers the action if all of the keys at the specified
+    /// indices are held during startup.
+    pub const fn new(key_indices: &'static [u16], action: A) -> Self {
+        Self {
+            key_indices,
+            action,
+        }
+    }
+}
+
+/// Trait for handlers that are run with the result of the initial scan of a scanner.
+pub trait BootmagicHook {
+    /// Runs the handler.
+    ///
+    /// `pressed` contains for each key index whether the key is held.
+    fn run<D, T, S>(&self, pressed: &[bool], context: Context<D, T, S>) -> impl Future<Output = ()>
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState;
+}
+
+impl<A: Action> BootmagicHook for Bootmagic<A> {
+    async fn run<D, T, S>(&self, pressed: &[bool], context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let triggered = !self.key_indices.is_empty()
+            && self
+                .key_indices
+                .iter()
+                .all(|key_index| pressed.get(*key_index as usize).copied().unwrap_or(false));
+        if triggered {
+            info!("Triggering bootmagic for keys {:?}", self.key_indices);
+            self.action.on_press(context).await;
+            self.action.on_release(context).await;
+        }
+    }
+}
+
+impl BootmagicHook for () {
+    async fn run<D, T, S>(&self, _: &[bool], _: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+    }
+}
+
+macro_rules! impl_bootmagic_hook_for_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: BootmagicHook),+> BootmagicHook for ($($name,)+) {
+            async fn run<D, T, S>(&self, pressed: &[bool], context: Context<D, T, S>)
+            where
+                D: Device,
+                T: Transports<D::Mcu>,
+                S: AnyState,
+            {
+                #[allow(non_snake_case)]
+                let ($($name,)+) = self;
+                $($name.run(pressed, context).await;)+
+            }
+        }
+    };
+}
+
+impl_bootmagic_hook_for_tuple!(A);
+impl_bootmagic_hook_for_tuple!(A, B);
+impl_bootmagic_hook_for_tuple!(A, B, C);
+impl_bootmagic_hook_for_tuple!(A, B, C, D1);
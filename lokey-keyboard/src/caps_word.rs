@@ -0,0 +1,84 @@
+use crate::{Key, KeySet};
+
+/// The state of an active [`CapsWord`](crate::action::CapsWord) action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CapsWordState {
+    shifted_keys: KeySet,
+    continue_keys: KeySet,
+    /// Whether Left Shift was added to the keyboard report by caps word.
+    shift_applied: bool,
+}
+
+impl CapsWordState {
+    pub(crate) const fn new(shifted_keys: KeySet, continue_keys: KeySet) -> Self {
+        Self {
+            shifted_keys,
+            continue_keys,
+            shift_applied: false,
+        }
+    }
+
+    /// Returns `true` if Left Shift was added to the keyboard report by caps word.
+    pub(crate) const fn shift_applied(&self) -> bool {
+        self.shift_applied
+    }
+
+    /// Applies caps word to the keys of a new keyboard report.
+    ///
+    /// Left Shift is added to the report if the most recently pressed key is a shifted key and
+    /// removed again once a continue key is pressed. Returns `false` if caps word was deactivated
+    /// because a key was pressed that is neither a shifted key nor a continue key.
+    pub(crate) fn apply(&mut self, keys: &mut KeySet, previous_keys: KeySet) -> bool {
+        let mut previous_keys = previous_keys;
+        if self.shift_applied {
+            keys.remove(Key::LShift);
+            previous_keys.remove(Key::LShift);
+        }
+        let mut is_active = true;
+        let mut shift = self.shift_applied;
+        for key in keys.difference(previous_keys) {
+            if matches!(key, Key::LShift | Key::RShift) {
+                continue;
+            }
+            if self.shifted_keys.contains(key) {
+                shift = true;
+            } else if self.continue_keys.contains(key) {
+                shift = false;
+            } else {
+                is_active = false;
+                shift = false;
+            }
+        }
+        self.shift_applied = shift && !keys.contains(Key::LShift);
+        if self.shift_applied {
+            keys.insert(Key::LShift);
+        }
+        is_active
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enumset::enum_set;
+
+    #[test]
+    fn shifts_letters_until_word_ends() {
+        let mut state = CapsWordState::new(enum_set!(Key::A | Key::Minus), enum_set!(Key::N1));
+
+        let mut keys = enum_set!(Key::A);
+        assert!(state.apply(&mut keys, KeySet::empty()));
+        assert_eq!(keys, enum_set!(Key::A | Key::LShift));
+
+        // Continue keys are not shifted, but keep caps word active.
+        let previous_keys = keys;
+        let mut keys = enum_set!(Key::LShift | Key::N1);
+        assert!(state.apply(&mut keys, previous_keys));
+        assert_eq!(keys, enum_set!(Key::N1));
+
+        // Any other key ends the word and leaves no shift behind.
+        let mut keys = enum_set!(Key::Space);
+        assert!(!state.apply(&mut keys, KeySet::empty()));
+        assert_eq!(keys, enum_set!(Key::Space));
+    }
+}
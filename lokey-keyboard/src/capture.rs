@@ -0,0 +1,48 @@
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use portable_atomic::{AtomicBool, Ordering};
+
+/// The maximum number of captured key presses that have not been received yet.
+const CAPTURE_QUEUE_SIZE: usize = 8;
+
+static IS_CAPTURING: AtomicBool = AtomicBool::new(false);
+static CAPTURED_KEYS: Channel<CriticalSectionRawMutex, u16, CAPTURE_QUEUE_SIZE> = Channel::new();
+
+/// Passes a key press to the active [`KeyCapture`].
+///
+/// Returns `false` if no capture is active, in which case the key press has to be dispatched as
+/// usual.
+pub(crate) fn try_capture(key_index: u16) -> bool {
+    IS_CAPTURING.load(Ordering::SeqCst) && CAPTURED_KEYS.try_send(key_index).is_ok()
+}
+
+/// Captures the key presses that the [`Layout`](crate::Layout) receives, so that they are not
+/// passed to the actions of the keys.
+///
+/// The releases of the captured keys are ignored as well. The capture ends when this value is
+/// dropped.
+pub(crate) struct KeyCapture(());
+
+impl KeyCapture {
+    /// Starts capturing key presses.
+    ///
+    /// Returns `None` if the key presses are already being captured.
+    pub(crate) fn start() -> Option<Self> {
+        IS_CAPTURING
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .ok()?;
+        CAPTURED_KEYS.clear();
+        Some(Self(()))
+    }
+
+    /// Waits for the next key press and returns the index of the key.
+    pub(crate) async fn next(&mut self) -> u16 {
+        CAPTURED_KEYS.receive().await
+    }
+}
+
+impl Drop for KeyCapture {
+    fn drop(&mut self) {
+        IS_CAPTURING.store(false, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,466 @@
+use crate::Message;
+use crate::action::{Action, ActionContainer};
+use arrayvec::ArrayVec;
+use embassy_time::{Duration, Instant};
+use lokey::util::warn;
+use lokey::{AnyState, Context, Device, Transports};
+use seq_macro::seq;
+use typenum::Unsigned;
+
+/// The maximum number of keys of a combo.
+const MAX_COMBO_KEYS: usize = 8;
+
+/// The maximum number of combos that can be pressed at the same time.
+///
+/// The keys of a combo that is pressed while this many combos are held are passed on as regular key
+/// presses.
+const MAX_ACTIVE_COMBOS: usize = 8;
+
+/// The maximum number of events that are produced by processing a single key event.
+pub(crate) const MAX_COMBO_EVENTS: usize = MAX_COMBO_KEYS + 1;
+
+/// An action that is performed when multiple keys are pressed at the same time.
+///
+/// The combos of a layout are set with [`Layout::combos`](crate::Layout::combos). When all keys of
+/// a combo are pressed within the timeout, the action of the combo is pressed instead of the
+/// actions of the individual keys. The action is released once all keys of the combo are released.
+/// If the timeout elapses before all keys are pressed, or if one of the keys is released before,
+/// the key presses are passed to the layout as usual.
+///
+/// # Example
+///
+/// ```no_run
+/// use embassy_time::Duration;
+/// use lokey_keyboard::{Combo, Key};
+///
+/// let combos = (
+///     // Pressing the keys with the indices 0 and 1 at the same time sends Escape
+///     Combo::new(&[0, 1], Key::Escape),
+///     Combo::new(&[1, 2], Key::Tab).timeout(Duration::from_millis(30)),
+/// );
+/// ```
+pub struct Combo<A> {
+    keys: &'static [u16],
+    action: A,
+    timeout: Duration,
+}
+
+impl<A: Action> Combo<A> {
+    /// Creates a new combo that performs the action when the keys with the specified indices are
+    /// pressed at the same time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no or more than eight keys are specified.
+    pub const fn new(keys: &'static [u16], action: A) -> Self {
+        assert!(
+            !keys.is_empty() && keys.len() <= MAX_COMBO_KEYS,
+            "A combo must consist of one to eight keys"
+        );
+        Self {
+            keys,
+            action,
+            timeout: Duration::from_millis(50),
+        }
+    }
+
+    /// Sets the duration within which all keys must be pressed to perform the combo (defaults to
+    /// 50ms).
+    pub const fn timeout(mut self, value: Duration) -> Self {
+        self.timeout = value;
+        self
+    }
+}
+
+impl<A: Action> Action for Combo<A> {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        self.action.on_press(context).await;
+    }
+
+    async fn on_release<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        self.action.on_release(context).await;
+    }
+}
+
+/// Container of [`Combo`]s.
+///
+/// This trait is implemented for tuples of combos.
+pub trait ComboContainer: ActionContainer {
+    #[doc(hidden)]
+    fn combo_keys(&self, index: usize) -> &'static [u16];
+    #[doc(hidden)]
+    fn combo_timeout(&self, index: usize) -> Duration;
+}
+
+macro_rules! impl_combo_container_for_tuples {
+    ($num:literal) => {
+        seq!(N in 0..=$num {
+            #(impl_combo_container_for_tuples!(@ N);)*
+        });
+    };
+    (@ $num:literal) => {
+        seq!(N in 0..$num {
+            impl<#(A~N,)*> ComboContainer for (#(Combo<A~N>,)*)
+            where
+                #(A~N: Action,)*
+            {
+                fn combo_keys(&self, index: usize) -> &'static [u16] {
+                    match index {
+                        #(N => self.N.keys,)*
+                        _ => &[],
+                    }
+                }
+
+                fn combo_timeout(&self, index: usize) -> Duration {
+                    match index {
+                        #(N => self.N.timeout,)*
+                        _ => Duration::from_ticks(0),
+                    }
+                }
+            }
+        });
+    };
+}
+
+impl_combo_container_for_tuples!(16);
+
+/// An event that is produced by the [`ComboEngine`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) enum ComboEvent {
+    /// A key event that is not part of a combo.
+    Key(Message),
+    /// The combo with the specified index was pressed or released.
+    Combo { index: usize, pressed: bool },
+}
+
+impl ComboEvent {
+    /// Returns `true` if the event is the press of a key or a combo.
+    pub(crate) fn is_press(self) -> bool {
+        matches!(
+            self,
+            Self::Key(Message::Press { .. }) | Self::Combo { pressed: true, .. }
+        )
+    }
+
+    /// Returns the press of the key or combo of this event.
+    pub(crate) fn to_press(self) -> Self {
+        match self {
+            Self::Key(Message::Press { key_index } | Message::Release { key_index }) => {
+                Self::Key(Message::Press { key_index })
+            }
+            Self::Combo { index, .. } => Self::Combo {
+                index,
+                pressed: true,
+            },
+        }
+    }
+}
+
+/// Detects the combos in the key events.
+pub(crate) struct ComboEngine {
+    /// The keys that were pressed and may become part of a combo, in the order of the presses.
+    pending: ArrayVec<(Instant, u16), MAX_COMBO_KEYS>,
+    /// The pressed combos and a bitmask of their keys that are still held.
+    active: ArrayVec<(usize, u8), MAX_ACTIVE_COMBOS>,
+}
+
+impl ComboEngine {
+    pub(crate) const fn new() -> Self {
+        Self {
+            pending: ArrayVec::new_const(),
+            active: ArrayVec::new_const(),
+        }
+    }
+
+    fn candidates<C: ComboContainer>(&self, combos: &C) -> impl Iterator<Item = usize> {
+        (0..C::NumChildren::USIZE).filter(|index| {
+            let keys = combos.combo_keys(*index);
+            self.pending.iter().all(|(_, key)| keys.contains(key))
+        })
+    }
+
+    /// Returns the point in time at which the pending keys are resolved.
+    pub(crate) fn deadline<C: ComboContainer>(&self, combos: &C) -> Option<Instant> {
+        let (first_press, _) = self.pending.first()?;
+        let timeout = self
+            .candidates(combos)
+            .map(|index| combos.combo_timeout(index))
+            .min()?;
+        Some(*first_press + timeout)
+    }
+
+    /// Processes a key event and writes the resulting events to `events`.
+    pub(crate) fn process<C: ComboContainer>(
+        &mut self,
+        combos: &C,
+        message: Message,
+        now: Instant,
+        events: &mut ArrayVec<ComboEvent, MAX_COMBO_EVENTS>,
+    ) {
+        match message {
+            Message::Press { key_index } => {
+                let is_combo_key = (0..C::NumChildren::USIZE)
+                    .any(|index| combos.combo_keys(index).contains(&key_index));
+                if !is_combo_key {
+                    self.flush(events);
+                    events.push(ComboEvent::Key(message));
+                    return;
+                }
+                if self.pending.is_full() {
+                    self.flush(events);
+                }
+                self.pending.push((now, key_index));
+                self.resolve(combos, false, events);
+            }
+            Message::Release { key_index } => {
+                if self.pending.iter().any(|(_, key)| *key == key_index) {
+                    self.resolve(combos, true, events);
+                }
+                let active = self
+                    .active
+                    .iter_mut()
+                    .enumerate()
+                    .find_map(|(i, (index, held))| {
+                        let position = combos
+                            .combo_keys(*index)
+                            .iter()
+                            .position(|key| *key == key_index)?;
+                        (*held & (1 << position) != 0).then_some((i, index, held, position))
+                    });
+                match active {
+                    Some((i, index, held, position)) => {
+                        *held &= !(1 << position);
+                        if *held == 0 {
+                            events.push(ComboEvent::Combo {
+                                index: *index,
+                                pressed: false,
+                            });
+                            self.active.remove(i);
+                        }
+                    }
+                    None => events.push(ComboEvent::Key(message)),
+                }
+            }
+        }
+    }
+
+    /// Resolves the pending keys if the deadline has elapsed.
+    pub(crate) fn expire<C: ComboContainer>(
+        &mut self,
+        combos: &C,
+        now: Instant,
+        events: &mut ArrayVec<ComboEvent, MAX_COMBO_EVENTS>,
+    ) {
+        if self.deadline(combos).is_some_and(|v| v <= now) {
+            self.resolve(combos, true, events);
+        }
+    }
+
+    /// Presses the combo that matches the pending keys.
+    ///
+    /// If another combo also contains all pending keys, the combo is only pressed if `force` is
+    /// `true`. If no combo matches the pending keys, they are passed on as regular key presses.
+    fn resolve<C: ComboContainer>(
+        &mut self,
+        combos: &C,
+        force: bool,
+        events: &mut ArrayVec<ComboEvent, MAX_COMBO_EVENTS>,
+    ) {
+        while !self.pending.is_empty() {
+            let mut exact_match = None;
+            let mut has_candidates = false;
+            let mut has_larger_candidates = false;
+            for index in self.candidates(combos) {
+                has_candidates = true;
+                if combos.combo_keys(index).len() == self.pending.len() {
+                    exact_match.get_or_insert(index);
+                } else {
+                    has_larger_candidates = true;
+                }
+            }
+            if !has_candidates && self.pending.len() == 1 {
+                self.flush(events);
+                return;
+            }
+            if !has_candidates {
+                // The last key does not belong to a combo with the previous keys, so the previous
+                // keys are passed on and the last key may start a new combo.
+                let last = self.pending.pop();
+                self.flush(events);
+                self.pending.extend(last);
+                continue;
+            }
+            match exact_match {
+                Some(index) if force || !has_larger_candidates => {
+                    if self.active.is_full() {
+                        // All active combos are still held, so none of them can be dropped without
+                        // leaving its action pressed.
+                        warn!("Too many combos pressed at once, passing on the keys of the combo");
+                        self.flush(events);
+                        return;
+                    }
+                    let num_keys = combos.combo_keys(index).len();
+                    self.pending.clear();
+                    self.active
+                        .push((index, (1u16 << num_keys).wrapping_sub(1) as u8));
+                    events.push(ComboEvent::Combo {
+                        index,
+                        pressed: true,
+                    });
+                }
+                _ if force => self.flush(events),
+                _ => {}
+            }
+            return;
+        }
+    }
+
+    /// Passes on all pending keys as regular key presses.
+    fn flush(&mut self, events: &mut ArrayVec<ComboEvent, MAX_COMBO_EVENTS>) {
+        for (_, key_index) in self.pending.drain(..) {
+            events.push(ComboEvent::Key(Message::Press { key_index }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::NoOp;
+
+    fn process(
+        engine: &mut ComboEngine,
+        combos: &impl ComboContainer,
+        message: Message,
+    ) -> ArrayVec<ComboEvent, MAX_COMBO_EVENTS> {
+        let mut events = ArrayVec::new();
+        engine.process(combos, message, Instant::from_millis(0), &mut events);
+        events
+    }
+
+    #[test]
+    fn combo_is_released_after_all_keys() {
+        let combos = (Combo::new(&[0, 1], NoOp),);
+        let mut engine = ComboEngine::new();
+        let press = |key_index| Message::Press { key_index };
+        let release = |key_index| Message::Release { key_index };
+
+        assert!(process(&mut engine, &combos, press(0)).is_empty());
+        assert_eq!(
+            process(&mut engine, &combos, press(1)).as_slice(),
+            [ComboEvent::Combo {
+                index: 0,
+                pressed: true
+            }]
+        );
+        assert!(process(&mut engine, &combos, release(0)).is_empty());
+        assert_eq!(
+            process(&mut engine, &combos, release(1)).as_slice(),
+            [ComboEvent::Combo {
+                index: 0,
+                pressed: false
+            }]
+        );
+
+        // Keys that are not part of a combo are passed on.
+        assert_eq!(
+            process(&mut engine, &combos, press(2)).as_slice(),
+            [ComboEvent::Key(press(2))]
+        );
+    }
+
+    #[test]
+    fn combo_is_refused_if_too_many_combos_are_held() {
+        let combos = (
+            Combo::new(&[0], NoOp),
+            Combo::new(&[1], NoOp),
+            Combo::new(&[2], NoOp),
+            Combo::new(&[3], NoOp),
+            Combo::new(&[4], NoOp),
+            Combo::new(&[5], NoOp),
+            Combo::new(&[6], NoOp),
+            Combo::new(&[7], NoOp),
+            Combo::new(&[8], NoOp),
+        );
+        let mut engine = ComboEngine::new();
+        let press = |key_index| Message::Press { key_index };
+        let release = |key_index| Message::Release { key_index };
+
+        for index in 0..8 {
+            assert_eq!(
+                process(&mut engine, &combos, press(index as u16)).as_slice(),
+                [ComboEvent::Combo {
+                    index,
+                    pressed: true
+                }]
+            );
+        }
+        // The held combos stay active, and the key of the new combo is passed on instead.
+        assert_eq!(
+            process(&mut engine, &combos, press(8)).as_slice(),
+            [ComboEvent::Key(press(8))]
+        );
+        assert_eq!(
+            process(&mut engine, &combos, release(8)).as_slice(),
+            [ComboEvent::Key(release(8))]
+        );
+        assert_eq!(
+            process(&mut engine, &combos, release(0)).as_slice(),
+            [ComboEvent::Combo {
+                index: 0,
+                pressed: false
+            }]
+        );
+    }
+
+    #[test]
+    fn overlapping_combos_wait_for_timeout() {
+        let combos = (Combo::new(&[0, 1], NoOp), Combo::new(&[0, 1, 2], NoOp));
+        let mut engine = ComboEngine::new();
+        let press = |key_index| Message::Press { key_index };
+
+        assert!(process(&mut engine, &combos, press(0)).is_empty());
+        assert!(process(&mut engine, &combos, press(1)).is_empty());
+        assert_eq!(engine.deadline(&combos), Some(Instant::from_millis(50)));
+
+        let mut events = ArrayVec::new();
+        engine.expire(&combos, Instant::from_millis(50), &mut events);
+        assert_eq!(
+            events.as_slice(),
+            [ComboEvent::Combo {
+                index: 0,
+                pressed: true
+            }]
+        );
+    }
+
+    #[test]
+    fn timeout_passes_on_key_presses() {
+        let combos = (Combo::new(&[0, 1], NoOp),);
+        let mut engine = ComboEngine::new();
+        let press = |key_index| Message::Press { key_index };
+
+        assert!(process(&mut engine, &combos, press(0)).is_empty());
+        let mut events = ArrayVec::new();
+        engine.expire(&combos, Instant::from_millis(50), &mut events);
+        assert_eq!(events.as_slice(), [ComboEvent::Key(press(0))]);
+
+        // A key that is not part of a combo ends the pending combo.
+        assert!(process(&mut engine, &combos, press(1)).is_empty());
+        assert_eq!(
+            process(&mut engine, &combos, press(3)).as_slice(),
+            [ComboEvent::Key(press(1)), ComboEvent::Key(press(3))]
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use enumset::{EnumSet, EnumSetType};
+use lokey::external;
+
+/// A usage of the consumer page of the HID usage tables (e.g. media keys).
+#[derive(Debug, EnumSetType)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConsumerUsage {
+    VolumeUp,
+    VolumeDown,
+    Mute,
+    PlayPause,
+    Play,
+    Pause,
+    Stop,
+    NextTrack,
+    PreviousTrack,
+    FastForward,
+    Rewind,
+    Record,
+    Shuffle,
+    Eject,
+    BrightnessUp,
+    BrightnessDown,
+}
+
+impl ConsumerUsage {
+    /// Returns the usage ID of the consumer page.
+    pub const fn usage_id(self) -> u8 {
+        match self {
+            Self::VolumeUp => 0xe9,
+            Self::VolumeDown => 0xea,
+            Self::Mute => 0xe2,
+            Self::PlayPause => 0xcd,
+            Self::Play => 0xb0,
+            Self::Pause => 0xb1,
+            Self::Stop => 0xb7,
+            Self::NextTrack => 0xb5,
+            Self::PreviousTrack => 0xb6,
+            Self::FastForward => 0xb3,
+            Self::Rewind => 0xb4,
+            Self::Record => 0xb2,
+            Self::Shuffle => 0xb9,
+            Self::Eject => 0xb8,
+            Self::BrightnessUp => 0x6f,
+            Self::BrightnessDown => 0x70,
+        }
+    }
+}
+
+pub type ConsumerUsageSet = EnumSet<ConsumerUsage>;
+
+/// The size of the HID report for a [`ConsumerReport`] in bytes.
+#[cfg(any(feature = "usb", feature = "ble"))]
+pub(crate) const CONSUMER_REPORT_SIZE: usize = 2;
+
+/// The report ID of a [`ConsumerReport`], as declared in [`CONSUMER_REPORT_DESCRIPTOR`].
+#[cfg(any(feature = "usb", feature = "ble"))]
+pub(crate) const CONSUMER_REPORT_ID: u8 = 1;
+
+/// The HID report descriptor for a [`ConsumerReport`].
+///
+/// Each usage is a single bit of the report, in the order of the variants of [`ConsumerUsage`], so
+/// that any number of usages can be pressed at the same time.
+#[cfg(any(feature = "usb", feature = "ble"))]
+#[rustfmt::skip]
+pub(crate) const CONSUMER_REPORT_DESCRIPTOR: [u8; 51] = [
+    0x05, 0x0c, // Usage Page (Consumer)
+    0x09, 0x01, // Usage (Consumer Control)
+    0xa1, 0x01, // Collection (Application)
+    0x85, 0x01, //   Report ID (1)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x01, //   Logical Maximum (1)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x10, //   Report Count (16)
+    0x09, 0xe9, //   Usage (Volume Increment)
+    0x09, 0xea, //   Usage (Volume Decrement)
+    0x09, 0xe2, //   Usage (Mute)
+    0x09, 0xcd, //   Usage (Play/Pause)
+    0x09, 0xb0, //   Usage (Play)
+    0x09, 0xb1, //   Usage (Pause)
+    0x09, 0xb7, //   Usage (Stop)
+    0x09, 0xb5, //   Usage (Scan Next Track)
+    0x09, 0xb6, //   Usage (Scan Previous Track)
+    0x09, 0xb3, //   Usage (Fast Forward)
+    0x09, 0xb4, //   Usage (Rewind)
+    0x09, 0xb2, //   Usage (Record)
+    0x09, 0xb9, //   Usage (Random Play)
+    0x09, 0xb8, //   Usage (Eject)
+    0x09, 0x6f, //   Usage (Display Brightness Increment)
+    0x09, 0x70, //   Usage (Display Brightness Decrement)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0xc0,       // End Collection
+];
+
+/// The report sent by the device to represent the pressed consumer control keys.
+#[derive(Debug, Clone, Default, PartialEq, Eq, external::Message)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConsumerReport {
+    pub usages: ConsumerUsageSet,
+}
+
+impl ConsumerReport {
+    pub const fn empty() -> Self {
+        Self {
+            usages: EnumSet::empty(),
+        }
+    }
+
+    /// Returns the bytes of the HID report.
+    #[cfg(any(feature = "usb", feature = "ble"))]
+    pub(crate) fn to_hid_report(&self) -> [u8; CONSUMER_REPORT_SIZE] {
+        self.usages.as_u16().to_le_bytes()
+    }
+}
+
+/// State type for a consumer report.
+///
+/// This type contains a [`ConsumerReport`] and provides methods for accessing it and modifying it
+/// via interior mutability. It is required by the
+/// [`ConsumerControl`](crate::action::ConsumerControl) action to keep track of the keys that are
+/// held at the same time.
+pub struct ConsumerReportState {
+    inner: Mutex<CriticalSectionRawMutex, ConsumerReport>,
+}
+
+impl Default for ConsumerReportState {
+    fn default() -> Self {
+        Self::new(ConsumerReport::default())
+    }
+}
+
+impl ConsumerReportState {
+    /// Creates a new [`ConsumerReportState`] with the specified initial consumer report.
+    pub fn new(consumer_report: ConsumerReport) -> Self {
+        Self {
+            inner: Mutex::new(consumer_report),
+        }
+    }
+
+    /// Gets a clone of the current consumer report.
+    pub fn get(&self) -> ConsumerReport {
+        self.inner.lock(|v| v.clone())
+    }
+
+    /// Sets the current consumer report.
+    pub fn set(&self, consumer_report: ConsumerReport) {
+        // SAFETY: This method is guaranteed to never be called within another `lock` or `lock_mut`
+        //         method as the lock methods are not exposed in the public API of
+        //         ConsumerReportState.
+        unsafe { self.inner.lock_mut(|report| *report = consumer_report) };
+    }
+
+    /// Modifies the current consumer report by applying the specified function to it and returns
+    /// a clone of the modified report.
+    pub fn modify_and_get(&self, f: impl FnOnce(&mut ConsumerReport)) -> ConsumerReport {
+        let mut report = self.get();
+        f(&mut report);
+        self.set(report.clone());
+        report
+    }
+}
@@ -1,6 +1,9 @@
-use crate::switch::WaitableInputSwitch;
-use embassy_futures::select::{Either, select};
-use embassy_time::{Duration, Timer};
+use crate::Message;
+use embassy_time::{Duration, Instant};
+
+/// The interval in which a scanner that waits for pin changes instead of polling the keys (e.g.
+/// [`DirectPins`](crate::DirectPins)) samples a pin while the [`Debouncer`] is settling.
+pub(crate) const SAMPLE_INTERVAL: Duration = Duration::from_millis(1);
 
 /// Configuration for debouncing key switches.
 #[derive(Clone)]
@@ -13,6 +16,15 @@ pub enum Debounce {
     ///
     /// This debounce algorithm is not noise-resistant.
     Eager { duration: Duration },
+    /// Reports the key change once the key was read in the new state for the specified number of
+    /// consecutive samples.
+    ///
+    /// For scanners that poll the keys (e.g. [`Matrix`](crate::Matrix)) a sample is a single scan,
+    /// so the debounce time scales with the scan rate. Scanners that wait for pin changes instead
+    /// (e.g. [`DirectPins`](crate::DirectPins)) take a sample every millisecond.
+    ///
+    /// This debounce algorithm is noise-resistant.
+    SymmetricCountBased { samples: u8 },
     /// Performs no debouncing.
     None,
 }
@@ -25,88 +37,298 @@ impl Default for Debounce {
     }
 }
 
-impl Debounce {
-    pub async fn wait_for_active<T: WaitableInputSwitch>(
-        &self,
-        pin: &mut T,
-    ) -> Result<Duration, T::Error> {
-        match self {
-            Debounce::Defer { duration } => {
-                loop {
-                    pin.wait_for_active().await?;
-                    let fut1 = Timer::after(*duration);
-                    let fut2 = pin.wait_for_inactive();
-                    match select(fut1, fut2).await {
-                        Either::First(()) => break,
-                        Either::Second(result) => result?,
-                    }
-                }
-                Ok(Duration::from_ticks(0))
-            }
-            Debounce::Eager { duration } => {
-                pin.wait_for_active().await?;
-                Ok(*duration)
-            }
-            Debounce::None => {
-                pin.wait_for_active().await?;
-                Ok(Duration::from_ticks(0))
-            }
+/// Debounces the raw samples of keys.
+///
+/// This can be used by scanners that periodically sample the state of the keys (e.g. by polling
+/// the pins of an IO expander or by comparing the values of analog inputs with a threshold). Each
+/// sample is passed to [`sample`](Self::sample), which returns the debounced key press or release.
+/// [`Debounce::SymmetricCountBased`] counts the samples, the other configurations compare the
+/// timestamps of the samples with the configured duration.
+///
+/// This is the debouncing that is used by the [`Matrix`](crate::Matrix) scanner.
+///
+/// # Example
+///
+/// ```ignore
+/// use embassy_time::Instant;
+/// use lokey_keyboard::{Debounce, Debouncer};
+///
+/// let mut debouncer = Debouncer::<4>::new(Debounce::default(), Debounce::default());
+/// loop {
+///     for (key_index, is_active) in read_keys().into_iter().enumerate() {
+///         if let Some(message) = debouncer.sample(key_index as u16, is_active, Instant::now()) {
+///             message.send(context, key_offset, false).await;
+///         }
+///     }
+///     if !debouncer.is_settling(Instant::now()) {
+///         wait_for_any_key().await;
+///     }
+/// }
+/// ```
+pub struct Debouncer<const NUM_KEYS: usize> {
+    key_press: Debounce,
+    key_release: Debounce,
+    keys: [KeyState; NUM_KEYS],
+}
+
+/// The debounce state of a single key.
+#[derive(Clone, Copy, Default)]
+struct KeyState {
+    /// The state of the last accepted sample.
+    state: bool,
+    /// The reported state.
+    debounced: bool,
+    /// The number of consecutive samples for [`Debounce::SymmetricCountBased`].
+    count: u8,
+    /// The time of the last change that was accepted with [`Debounce::Eager`].
+    eager_change: Option<Instant>,
+    /// The time of the change that is not reported yet and the new state.
+    pending: Option<(Instant, bool)>,
+}
+
+impl<const NUM_KEYS: usize> Debouncer<NUM_KEYS> {
+    /// Creates a new [`Debouncer`] for `NUM_KEYS` keys, which are initially released.
+    pub fn new(key_press: Debounce, key_release: Debounce) -> Self {
+        Self {
+            key_press,
+            key_release,
+            keys: [KeyState::default(); NUM_KEYS],
         }
     }
 
-    pub async fn wait_for_inactive<T: WaitableInputSwitch>(
-        &self,
-        pin: &mut T,
-    ) -> Result<Duration, T::Error> {
-        match self {
-            Debounce::Defer { duration } => {
-                loop {
-                    pin.wait_for_inactive().await?;
-                    let fut1 = Timer::after(*duration);
-                    let fut2 = pin.wait_for_active();
-                    match select(fut1, fut2).await {
-                        Either::First(()) => break,
-                        Either::Second(result) => result?,
-                    }
-                }
-                Ok(Duration::from_ticks(0))
-            }
-            Debounce::Eager { duration } => {
-                pin.wait_for_inactive().await?;
-                Ok(*duration)
+    /// Processes a sample of the key.
+    ///
+    /// Returns the message that has to be sent if the debounced state of the key changed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key index is not less than `NUM_KEYS` or if the time is earlier than the
+    /// time of a previous sample.
+    pub fn sample(&mut self, key_index: u16, is_active: bool, now: Instant) -> Option<Message> {
+        let debounce = if is_active {
+            &self.key_press
+        } else {
+            &self.key_release
+        };
+        let key = &mut self.keys[key_index as usize];
+        let message = |is_active| {
+            if is_active {
+                Message::Press { key_index }
+            } else {
+                Message::Release { key_index }
             }
-            Debounce::None => {
-                pin.wait_for_inactive().await?;
-                Ok(Duration::from_ticks(0))
+        };
+
+        if let Debounce::SymmetricCountBased { samples } = debounce
+            && key.pending.is_none()
+        {
+            key.state = is_active;
+            let changed = count_sample(&mut key.count, &mut key.debounced, is_active, *samples);
+            return changed.then(|| message(is_active));
+        }
+
+        if let Debounce::Eager { duration } = debounce
+            && is_active != key.state
+        {
+            // Changes within the duration after the last change are ignored
+            if let Some(instant) = key.eager_change
+                && now.duration_since(instant) <= *duration
+            {
+                return None;
             }
+            key.eager_change = Some(now);
         }
-    }
 
-    pub async fn wait_for_change<T: WaitableInputSwitch>(
-        &self,
-        pin: &mut T,
-    ) -> Result<Duration, T::Error> {
-        match self {
-            Debounce::Defer { duration } => {
-                pin.wait_for_change().await?;
-                loop {
-                    let fut1 = Timer::after(*duration);
-                    let fut2 = pin.wait_for_change();
-                    match select(fut1, fut2).await {
-                        Either::First(()) => break,
-                        Either::Second(result) => result?,
-                    }
+        let mut result = None;
+        if let Some((instant, pending_state)) = key.pending {
+            let defer_duration = match debounce {
+                Debounce::Defer { duration } => *duration,
+                Debounce::Eager { .. } | Debounce::SymmetricCountBased { .. } | Debounce::None => {
+                    Duration::from_ticks(0)
                 }
-                Ok(Duration::from_ticks(0))
-            }
-            Debounce::Eager { duration } => {
-                pin.wait_for_change().await?;
-                Ok(*duration)
-            }
-            Debounce::None => {
-                pin.wait_for_change().await?;
-                Ok(Duration::from_ticks(0))
+            };
+            if is_active == key.state && now.duration_since(instant) > defer_duration {
+                key.pending = None;
+                key.debounced = pending_state;
+                result = Some(message(pending_state));
             }
+        } else if is_active != key.state {
+            key.pending = Some((now, is_active));
         }
+        key.state = is_active;
+        result
+    }
+
+    /// Returns the state of the last sample of the key that was not ignored.
+    pub fn state(&self, key_index: u16) -> bool {
+        self.keys[key_index as usize].state
+    }
+
+    /// Returns `true` if the debounced state of any key can still change without the key changing
+    /// its state.
+    ///
+    /// While this returns `true`, the keys have to be sampled, even if no key is active.
+    pub fn is_settling(&self, now: Instant) -> bool {
+        let eager_duration = match (&self.key_press, &self.key_release) {
+            (Debounce::Eager { duration: a }, Debounce::Eager { duration: b }) => (*a).max(*b),
+            (Debounce::Eager { duration }, _) | (_, Debounce::Eager { duration }) => *duration,
+            _ => Duration::from_ticks(0),
+        };
+        self.keys.iter().any(|key| {
+            key.count > 0
+                || key.pending.is_some()
+                || key
+                    .eager_change
+                    .is_some_and(|instant| now.duration_since(instant) <= eager_duration)
+        })
+    }
+
+    /// Returns `true` if a change of the key to the specified state would currently be debounced
+    /// instead of being reported (i.e. the key is bouncing).
+    #[cfg_attr(not(feature = "chatter-diagnostics"), allow(dead_code))]
+    pub(crate) fn is_bouncing(&self, key_index: u16, is_active: bool, now: Instant) -> bool {
+        let key = &self.keys[key_index as usize];
+        let debounce = if is_active {
+            &self.key_press
+        } else {
+            &self.key_release
+        };
+        let in_eager_window = match debounce {
+            Debounce::Eager { duration } => key
+                .eager_change
+                .is_some_and(|instant| now.duration_since(instant) <= *duration),
+            Debounce::Defer { .. } | Debounce::SymmetricCountBased { .. } | Debounce::None => false,
+        };
+        in_eager_window || key.pending.is_some() || key.count > 0
+    }
+}
+
+/// Updates the counter of a key for [`Debounce::SymmetricCountBased`] with a new sample.
+///
+/// Returns `true` if the debounced state changed.
+fn count_sample(count: &mut u8, debounced: &mut bool, is_active: bool, samples: u8) -> bool {
+    if is_active == *debounced {
+        *count = 0;
+        return false;
+    }
+    *count = count.saturating_add(1);
+    if *count < samples {
+        return false;
+    }
+    *count = 0;
+    *debounced = is_active;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrayvec::ArrayVec;
+
+    #[test]
+    fn count_based_debounce_resets_on_bounce() {
+        let mut count = 0;
+        let mut debounced = false;
+        let samples = [true, true, false, true, true, true, true];
+        let changes = samples.map(|v| count_sample(&mut count, &mut debounced, v, 3));
+        assert_eq!(changes, [false, false, false, false, false, true, false]);
+        assert!(debounced);
+    }
+
+    /// Passes the samples with the specified times (in milliseconds) to the debouncer and returns
+    /// the times at which a message was returned.
+    fn run_debouncer(debounce: Debounce, samples: &[(u64, bool)]) -> ArrayVec<(u64, Message), 4> {
+        let mut debouncer = Debouncer::<1>::new(debounce.clone(), debounce);
+        samples
+            .iter()
+            .filter_map(|(ms, is_active)| {
+                let message = debouncer.sample(0, *is_active, Instant::from_millis(*ms))?;
+                Some((*ms, message))
+            })
+            .collect()
+    }
+
+    const PRESS: Message = Message::Press { key_index: 0 };
+    const RELEASE: Message = Message::Release { key_index: 0 };
+
+    #[test]
+    fn defer_debouncer() {
+        let debounce = Debounce::Defer {
+            duration: Duration::from_millis(5),
+        };
+        // The change is reported once more than the duration has passed
+        let samples = [
+            (0, true),
+            (5, true),
+            (6, true),
+            (7, true),
+            (10, false),
+            (16, false),
+        ];
+        assert_eq!(
+            run_debouncer(debounce.clone(), &samples)[..],
+            [(6, PRESS), (16, RELEASE)]
+        );
+        // The change is not reported in a sample in which the key changes
+        let samples = [(0, true), (6, false), (7, true), (8, true)];
+        assert_eq!(run_debouncer(debounce, &samples)[..], [(8, PRESS)]);
+    }
+
+    #[test]
+    fn eager_debouncer() {
+        let debounce = Debounce::Eager {
+            duration: Duration::from_millis(5),
+        };
+        // Changes within the duration after the last change are ignored
+        let samples = [
+            (0, true),
+            (1, true),
+            (2, false),
+            (5, false),
+            (6, false),
+            (7, false),
+        ];
+        assert_eq!(
+            run_debouncer(debounce.clone(), &samples)[..],
+            [(1, PRESS), (7, RELEASE)]
+        );
+        let mut debouncer = Debouncer::<1>::new(debounce.clone(), debounce);
+        debouncer.sample(0, true, Instant::from_millis(0));
+        debouncer.sample(0, true, Instant::from_millis(1));
+        assert!(debouncer.is_settling(Instant::from_millis(5)));
+        assert!(!debouncer.is_settling(Instant::from_millis(6)));
+    }
+
+    #[test]
+    fn no_debouncer() {
+        let samples = [
+            (0, true),
+            (1, true),
+            (2, false),
+            (3, false),
+            (4, true),
+            (4, true),
+        ];
+        assert_eq!(
+            run_debouncer(Debounce::None, &samples)[..],
+            [(1, PRESS), (3, RELEASE)]
+        );
+    }
+
+    #[test]
+    fn count_based_debouncer() {
+        let debounce = Debounce::SymmetricCountBased { samples: 2 };
+        let samples = [
+            (0, true),
+            (1, false),
+            (2, true),
+            (3, true),
+            (4, false),
+            (5, false),
+        ];
+        assert_eq!(
+            run_debouncer(debounce, &samples)[..],
+            [(3, PRESS), (5, RELEASE)]
+        );
     }
 }
@@ -0,0 +1,128 @@
+use crate::Message;
+use crate::combo::ComboEvent;
+use arrayvec::ArrayVec;
+use core::cell::RefCell;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+use portable_atomic::{AtomicBool, AtomicU16, Ordering};
+
+/// The maximum number of key events that can be deferred at the same time.
+pub(crate) const MAX_DEFERRED_EVENTS: usize = 16;
+
+static IS_DEFERRING: AtomicBool = AtomicBool::new(false);
+/// The index of the key that started the deferral, whose events are never deferred.
+static DEFERRING_KEY_INDEX: AtomicU16 = AtomicU16::new(0);
+static DEFERRED_EVENTS: Mutex<
+    CriticalSectionRawMutex,
+    RefCell<ArrayVec<ComboEvent, MAX_DEFERRED_EVENTS>>,
+> = Mutex::new(RefCell::new(ArrayVec::new_const()));
+static OBSERVED_EVENTS: Channel<CriticalSectionRawMutex, ComboEvent, MAX_DEFERRED_EVENTS> =
+    Channel::new();
+static ENDED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Passes a key or combo event to the active [`KeyDeferral`].
+///
+/// The release of a combo is only deferred if its press was deferred as well, so that a combo
+/// whose action started the deferral can still be released.
+///
+/// Returns `false` if no deferral is active or if the event can not be deferred, in which case
+/// the event has to be dispatched as usual.
+pub(crate) fn try_defer(event: ComboEvent) -> bool {
+    if !IS_DEFERRING.load(Ordering::SeqCst) {
+        return false;
+    }
+    let is_deferred = DEFERRED_EVENTS.lock(|events| {
+        let mut events = events.borrow_mut();
+        let can_defer = match event {
+            ComboEvent::Key(Message::Press { key_index } | Message::Release { key_index }) => {
+                key_index != DEFERRING_KEY_INDEX.load(Ordering::SeqCst)
+            }
+            ComboEvent::Combo { pressed: true, .. } => true,
+            ComboEvent::Combo { pressed: false, .. } => events.contains(&event.to_press()),
+        };
+        can_defer && events.try_push(event).is_ok()
+    });
+    if is_deferred {
+        // The observer only decides based on the events, so a full queue can be ignored.
+        let _ = OBSERVED_EVENTS.try_send(event);
+    }
+    is_deferred
+}
+
+/// Takes the deferred key and combo events, so that they can be dispatched.
+///
+/// Returns no events while a deferral is active.
+pub(crate) fn take_deferred() -> ArrayVec<ComboEvent, MAX_DEFERRED_EVENTS> {
+    if IS_DEFERRING.load(Ordering::SeqCst) {
+        return ArrayVec::new();
+    }
+    DEFERRED_EVENTS.lock(|events| events.take())
+}
+
+/// Waits for the next key or combo event that was deferred by the active [`KeyDeferral`].
+pub(crate) async fn next_deferred() -> ComboEvent {
+    OBSERVED_EVENTS.receive().await
+}
+
+/// Waits until a deferral ended.
+pub(crate) async fn wait_for_end() {
+    ENDED.wait().await
+}
+
+/// Holds back the events of all other keys and of the combos from the actions of the
+/// [`Layout`](crate::Layout), while an action decides how to handle a key press.
+///
+/// The deferred events can be observed with [`next_deferred`] and are dispatched in their
+/// original order once this value is dropped.
+pub(crate) struct KeyDeferral(());
+
+impl KeyDeferral {
+    /// Starts deferring the events of all keys except the key with the specified index.
+    ///
+    /// Returns `None` if the key events are already being deferred.
+    pub(crate) fn start(key_index: u16) -> Option<Self> {
+        IS_DEFERRING
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .ok()?;
+        DEFERRING_KEY_INDEX.store(key_index, Ordering::SeqCst);
+        OBSERVED_EVENTS.clear();
+        Some(Self(()))
+    }
+}
+
+impl Drop for KeyDeferral {
+    fn drop(&mut self) {
+        IS_DEFERRING.store(false, Ordering::SeqCst);
+        ENDED.signal(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combo_events_are_deferred() {
+        let key = |key_index| ComboEvent::Key(Message::Press { key_index });
+        let combo = |index, pressed| ComboEvent::Combo { index, pressed };
+
+        let _guard = crate::testing::lock();
+        let deferral = KeyDeferral::start(0).unwrap();
+        // The events of the key that started the deferral are not deferred
+        assert!(!try_defer(key(0)));
+        assert!(try_defer(key(1)));
+        assert!(try_defer(combo(0, true)));
+        assert!(try_defer(combo(0, false)));
+        // The combo was pressed before the deferral started
+        assert!(!try_defer(combo(1, false)));
+        assert!(take_deferred().is_empty());
+
+        drop(deferral);
+        assert_eq!(
+            take_deferred().as_slice(),
+            [key(1), combo(0, true), combo(0, false)]
+        );
+    }
+}
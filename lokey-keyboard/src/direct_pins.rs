@@ -1,7 +1,9 @@
-use super::{Debounce, Message, ScannerDriver};
+use super::{Debounce, Debouncer, Message, ScannerDriver};
 use crate::DynContext;
+use crate::debounce::SAMPLE_INTERVAL;
 use crate::switch::{InputSwitch, WaitableInputSwitch};
-use embassy_time::Timer;
+use embassy_futures::select::{Either, select};
+use embassy_time::{Instant, Timer};
 use lokey::util::error;
 
 /// Configuration for the [`DirectPins`] scanner.
@@ -9,6 +11,11 @@ use lokey::util::error;
 pub struct DirectPinsConfig {
     pub debounce_key_press: Debounce,
     pub debounce_key_release: Debounce,
+    /// Send the key events as [`TimestampedMessage`](crate::TimestampedMessage)s.
+    ///
+    /// This should be enabled on the peripheral of a split keyboard if the central uses a
+    /// [reorder window](crate::Layout::reorder_window). Defaults to `false`.
+    pub timestamps: bool,
 }
 
 /// Scanner for keys that are each connected to a single pin.
@@ -47,51 +54,67 @@ impl<I: InputSwitch + WaitableInputSwitch + 'static, const NUM_IS: usize, const
 {
     type Config = DirectPinsConfig;
 
-    async fn run(self, config: Self::Config, context: DynContext) {
+    async fn scan_once(&mut self, _config: &Self::Config) -> [bool; NUM_KEYS] {
+        let mut pressed = [false; NUM_KEYS];
+        for (i, pin) in self.pins.iter().enumerate() {
+            let Some(key_index) = self.transform.iter().position(|v| *v == Some(i)) else {
+                continue;
+            };
+            let Ok(is_active) = pin.is_active() else {
+                error!("failed to get active status of pin");
+                continue;
+            };
+            pressed[key_index] = is_active;
+        }
+        pressed
+    }
+
+    async fn run(self, config: Self::Config, key_offset: u16, context: DynContext) {
         let DirectPins { pins, transform } = self;
         let mut pins_iter = pins.into_iter();
 
         let futures: [_; NUM_IS] = core::array::from_fn(|i| {
             let mut pin = pins_iter.next().expect("pin count mismatch");
-            let debounce_key_press = config.debounce_key_press.clone();
-            let debounce_key_release = config.debounce_key_release.clone();
+            let key_index = transform
+                .iter()
+                .position(|v| *v == Some(i))
+                .map(|v| u16::try_from(v).expect("too many keys"));
+            let mut debouncer = Debouncer::<1>::new(
+                config.debounce_key_press.clone(),
+                config.debounce_key_release.clone(),
+            );
+            let timestamps = config.timestamps;
 
             async move {
-                let mut active = false;
                 loop {
-                    let wait_duration = if active {
-                        let Ok(wait_duration) =
-                            debounce_key_release.wait_for_inactive(&mut pin).await
-                        else {
-                            error!("failed to get active status of pin");
-                            continue;
+                    let Ok(is_active) = pin.is_active() else {
+                        error!("failed to get active status of pin");
+                        Timer::after(SAMPLE_INTERVAL).await;
+                        continue;
+                    };
+                    if let Some(message) = debouncer.sample(0, is_active, Instant::now())
+                        && let Some(key_index) = key_index
+                    {
+                        let message = match message {
+                            Message::Press { .. } => Message::Press { key_index },
+                            Message::Release { .. } => Message::Release { key_index },
                         };
-                        active = false;
-                        wait_duration
+                        message.send(context, key_offset, timestamps).await;
+                    }
+                    // The pin has to be sampled until the debounced state settled
+                    let result = if debouncer.is_settling(Instant::now())
+                        || debouncer.state(0) != is_active
+                    {
+                        match select(Timer::after(SAMPLE_INTERVAL), pin.wait_for_change()).await {
+                            Either::First(()) => Ok(()),
+                            Either::Second(result) => result,
+                        }
                     } else {
-                        let Ok(wait_duration) = debounce_key_press.wait_for_active(&mut pin).await
-                        else {
-                            error!("failed to get active status of pin");
-                            continue;
-                        };
-                        active = true;
-                        wait_duration
+                        pin.wait_for_change().await
                     };
-                    if let Some(key_index) = transform.iter().position(|v| *v == Some(i)) {
-                        let key_index = u16::try_from(key_index).expect("too many keys");
-                        if active {
-                            context
-                                .internal_channel
-                                .send(Message::Press { key_index })
-                                .await;
-                        } else {
-                            context
-                                .internal_channel
-                                .send(Message::Release { key_index })
-                                .await;
-                        }
+                    if result.is_err() {
+                        error!("failed to get active status of pin");
                     }
-                    Timer::after(wait_duration).await;
                 }
             }
         });
@@ -0,0 +1,396 @@
+use super::ScannerDriver;
+use crate::DynContext;
+use crate::matrix::{MatrixConfig, MatrixPins, run_matrix, scan_matrix_once};
+use core::future::Future;
+use embassy_time::{Duration, Timer};
+use embedded_hal_async::i2c::I2c;
+use lokey::util::error;
+
+/// A GPIO expander whose pins are read and written all at once.
+///
+/// The state of pin `n` is the bit `n` of the values. Pins are active low, so a pin that is used
+/// as an input has to be pulled up and an output pin is activated by driving it low.
+pub trait IoExpander {
+    type Error;
+
+    /// The number of pins of the expander.
+    const NUM_PINS: u8;
+
+    /// Configures the pins whose bit is set in the mask as outputs and all other pins as inputs
+    /// with a pull-up resistor.
+    fn configure(&mut self, output_mask: u16) -> impl Future<Output = Result<(), Self::Error>>;
+
+    /// Sets the levels of the output pins.
+    fn write(&mut self, levels: u16) -> impl Future<Output = Result<(), Self::Error>>;
+
+    /// Reads the levels of all pins.
+    fn read(&mut self) -> impl Future<Output = Result<u16, Self::Error>>;
+}
+
+/// Driver for the PCF8574 8-bit I/O expander.
+pub struct Pcf8574<I> {
+    i2c: I,
+    address: u8,
+}
+
+impl<I> Pcf8574<I> {
+    /// Creates a new driver for the expander with the specified 7-bit I2C address.
+    pub const fn new(i2c: I, address: u8) -> Self {
+        Self { i2c, address }
+    }
+}
+
+impl<I: I2c> IoExpander for Pcf8574<I> {
+    type Error = I::Error;
+
+    const NUM_PINS: u8 = 8;
+
+    async fn configure(&mut self, _output_mask: u16) -> Result<(), Self::Error> {
+        // The pins of the PCF8574 are quasi-bidirectional, a pin that is written high is weakly
+        // pulled up and can be used as an input.
+        self.write(u16::MAX).await
+    }
+
+    async fn write(&mut self, levels: u16) -> Result<(), Self::Error> {
+        self.i2c.write(self.address, &[levels as u8]).await
+    }
+
+    async fn read(&mut self) -> Result<u16, Self::Error> {
+        let mut buf = [0];
+        self.i2c.read(self.address, &mut buf).await?;
+        Ok(buf[0].into())
+    }
+}
+
+/// Driver for the MCP23017 16-bit I/O expander.
+///
+/// The pins `GPA0` to `GPA7` are the pins 0 to 7 and the pins `GPB0` to `GPB7` are the pins 8 to
+/// 15.
+pub struct Mcp23017<I> {
+    i2c: I,
+    address: u8,
+}
+
+impl<I> Mcp23017<I> {
+    const REGISTER_IODIRA: u8 = 0x00;
+    const REGISTER_GPPUA: u8 = 0x0c;
+    const REGISTER_GPIOA: u8 = 0x12;
+    const REGISTER_OLATA: u8 = 0x14;
+
+    /// Creates a new driver for the expander with the specified 7-bit I2C address.
+    pub const fn new(i2c: I, address: u8) -> Self {
+        Self { i2c, address }
+    }
+}
+
+impl<I: I2c> Mcp23017<I> {
+    async fn write_register_pair(&mut self, register: u8, value: u16) -> Result<(), I::Error> {
+        let [a, b] = value.to_le_bytes();
+        self.i2c.write(self.address, &[register, a, b]).await
+    }
+}
+
+impl<I: I2c> IoExpander for Mcp23017<I> {
+    type Error = I::Error;
+
+    const NUM_PINS: u8 = 16;
+
+    async fn configure(&mut self, output_mask: u16) -> Result<(), Self::Error> {
+        // Set the output latches before changing the direction, so that the outputs are not
+        // briefly activated.
+        self.write(u16::MAX).await?;
+        self.write_register_pair(Self::REGISTER_IODIRA, !output_mask)
+            .await?;
+        self.write_register_pair(Self::REGISTER_GPPUA, !output_mask)
+            .await
+    }
+
+    async fn write(&mut self, levels: u16) -> Result<(), Self::Error> {
+        self.write_register_pair(Self::REGISTER_OLATA, levels).await
+    }
+
+    async fn read(&mut self) -> Result<u16, Self::Error> {
+        let mut buf = [0; 2];
+        self.i2c
+            .write_read(self.address, &[Self::REGISTER_GPIOA], &mut buf)
+            .await?;
+        Ok(u16::from_le_bytes(buf))
+    }
+}
+
+/// Scanner for keys that are arranged in a keyboard matrix, whose rows and columns are connected
+/// to the pins of an [`IoExpander`].
+///
+/// The expander is polled while no key is pressed, as the interrupt output of the expander is not
+/// used. The debouncing and the [`MatrixConfig`] are the same as for the [`Matrix`](crate::Matrix)
+/// scanner.
+pub struct ExpanderMatrix<E, const NUM_IS: usize, const NUM_OS: usize, const NUM_KEYS: usize> {
+    expander: E,
+    input_pins: [u8; NUM_IS],
+    output_pins: [u8; NUM_OS],
+    transform: [Option<(usize, usize)>; NUM_KEYS],
+    poll_interval: Duration,
+}
+
+impl<E: IoExpander, const NUM_IS: usize, const NUM_OS: usize> ExpanderMatrix<E, NUM_IS, NUM_OS, 0> {
+    /// Creates a new scanner with the specified expander pins (see [`IoExpander`]) that are
+    /// connected to the inputs and outputs of the matrix.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of the pins is not a pin of the expander (i.e. not less than
+    /// [`IoExpander::NUM_PINS`]).
+    pub const fn new<const NUM_KEYS: usize>(
+        expander: E,
+        input_pins: [u8; NUM_IS],
+        output_pins: [u8; NUM_OS],
+    ) -> ExpanderMatrix<E, NUM_IS, NUM_OS, NUM_KEYS> {
+        // Use while loops so that the function can be `const`
+        let mut i = 0;
+        while i < input_pins.len() {
+            assert!(input_pins[i] < E::NUM_PINS, "input pin out of range");
+            i += 1;
+        }
+        let mut i = 0;
+        while i < output_pins.len() {
+            assert!(output_pins[i] < E::NUM_PINS, "output pin out of range");
+            i += 1;
+        }
+        ExpanderMatrix {
+            expander,
+            input_pins,
+            output_pins,
+            transform: [None; NUM_KEYS],
+            poll_interval: Duration::from_millis(1),
+        }
+    }
+}
+
+impl<E, const NUM_IS: usize, const NUM_OS: usize, const NUM_KEYS: usize>
+    ExpanderMatrix<E, NUM_IS, NUM_OS, NUM_KEYS>
+{
+    /// Sets the interval in which the expander is polled while no key is pressed.
+    ///
+    /// Defaults to 1ms.
+    pub const fn poll_interval(mut self, value: Duration) -> Self {
+        self.poll_interval = value;
+        self
+    }
+
+    pub const fn map<const I_INDEX: usize, const O_INDEX: usize, const KEY_INDEX: usize>(
+        mut self,
+    ) -> Self {
+        self.transform[KEY_INDEX] = Some((I_INDEX, O_INDEX));
+        self
+    }
+
+    pub const fn map_next<const I_INDEX: usize, const O_INDEX: usize>(mut self) -> Self {
+        // Use while loops so that the function can be `const`
+        let mut i = 0;
+        while i < self.transform.len() {
+            if self.transform[i].is_none() {
+                self.transform[i] = Some((I_INDEX, O_INDEX));
+                break;
+            }
+            i += 1;
+        }
+        self
+    }
+
+    pub const fn map_rows_and_cols<const NUM_ROWS: usize, const NUM_COLS: usize>(
+        mut self,
+        input_indices: [usize; NUM_ROWS],
+        output_indices: [usize; NUM_COLS],
+        mut start_key_index: usize,
+    ) -> Self {
+        // Use while loops so that the function can be `const`
+        let mut i = 0;
+        while i < input_indices.len() {
+            let input_index = input_indices[i];
+            let mut j = 0;
+            while j < output_indices.len() {
+                let output_index = output_indices[j];
+                self.transform[start_key_index] = Some((input_index, output_index));
+                start_key_index += 1;
+                j += 1;
+            }
+            i += 1;
+        }
+        self
+    }
+
+    fn output_mask(&self) -> u16 {
+        self.output_pins
+            .iter()
+            .fold(0, |mask, pin| mask | (1 << pin))
+    }
+}
+
+impl<E: IoExpander, const NUM_IS: usize, const NUM_OS: usize, const NUM_KEYS: usize>
+    ExpanderMatrix<E, NUM_IS, NUM_OS, NUM_KEYS>
+{
+    async fn configure(&mut self) {
+        if self.expander.configure(self.output_mask()).await.is_err() {
+            error!("failed to configure I/O expander");
+        }
+    }
+
+    /// Returns the state of the input pins for the specified levels of the expander.
+    fn inputs(&self, levels: u16) -> [bool; NUM_IS] {
+        self.input_pins.map(|pin| levels & (1 << pin) == 0)
+    }
+}
+
+impl<E: IoExpander, const NUM_IS: usize, const NUM_OS: usize, const NUM_KEYS: usize>
+    MatrixPins<NUM_IS, NUM_OS> for ExpanderMatrix<E, NUM_IS, NUM_OS, NUM_KEYS>
+{
+    async fn wait_for_any_active(&mut self) {
+        if self.expander.write(!self.output_mask()).await.is_err() {
+            error!("failed to turn output pins on");
+        }
+        loop {
+            match self.expander.read().await {
+                Ok(levels) if self.inputs(levels).contains(&true) => break,
+                Ok(_) => {}
+                Err(_) => error!("failed to read I/O expander"),
+            }
+            Timer::after(self.poll_interval).await;
+        }
+        if self.expander.write(u16::MAX).await.is_err() {
+            error!("failed to turn output pins off");
+        }
+    }
+
+    async fn read_inputs(&mut self, output_index: usize) -> [Option<bool>; NUM_IS] {
+        let output_pin = self.output_pins[output_index];
+        if self.expander.write(!(1 << output_pin)).await.is_err() {
+            error!("failed to turn output pin on");
+            return [None; NUM_IS];
+        }
+        let inputs = match self.expander.read().await {
+            Ok(levels) => self.inputs(levels).map(Some),
+            Err(_) => {
+                error!("failed to read I/O expander");
+                [None; NUM_IS]
+            }
+        };
+        if self.expander.write(u16::MAX).await.is_err() {
+            error!("failed to turn output pin off");
+        }
+        inputs
+    }
+}
+
+impl<E: IoExpander, const NUM_IS: usize, const NUM_OS: usize, const NUM_KEYS: usize>
+    ScannerDriver<NUM_KEYS> for ExpanderMatrix<E, NUM_IS, NUM_OS, NUM_KEYS>
+{
+    type Config = MatrixConfig;
+
+    async fn scan_once(&mut self, config: &Self::Config) -> [bool; NUM_KEYS] {
+        self.configure().await;
+        let transform = self.transform;
+        scan_matrix_once(self, &transform, config).await
+    }
+
+    async fn run(mut self, config: Self::Config, key_offset: u16, context: DynContext) {
+        self.configure().await;
+        let transform = self.transform;
+        run_matrix(&mut self, &transform, &config, key_offset, context).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::Message;
+    use crate::testing::run_matrix_scan;
+    use core::convert::Infallible;
+    use embassy_time::Instant;
+    use std::vec::Vec;
+
+    /// A 16-bit expander whose switches connect an output pin to an input pin while they are
+    /// pressed.
+    struct MockExpander {
+        /// The input pin, the output pin and the time span (in microseconds) of each key press.
+        presses: Vec<(u8, u8, u64, u64)>,
+        output_mask: Option<u16>,
+        levels: u16,
+    }
+
+    impl MockExpander {
+        fn new(presses: Vec<(u8, u8, u64, u64)>) -> Self {
+            Self {
+                presses,
+                output_mask: None,
+                levels: u16::MAX,
+            }
+        }
+    }
+
+    impl IoExpander for MockExpander {
+        type Error = Infallible;
+
+        const NUM_PINS: u8 = 16;
+
+        async fn configure(&mut self, output_mask: u16) -> Result<(), Self::Error> {
+            self.output_mask = Some(output_mask);
+            Ok(())
+        }
+
+        async fn write(&mut self, levels: u16) -> Result<(), Self::Error> {
+            self.levels = levels;
+            Ok(())
+        }
+
+        async fn read(&mut self) -> Result<u16, Self::Error> {
+            let now = Instant::now().as_micros();
+            let mut levels = self.levels;
+            for &(input, output, from_us, to_us) in &self.presses {
+                if (from_us..to_us).contains(&now) && self.levels & (1 << output) == 0 {
+                    levels &= !(1 << input);
+                }
+            }
+            Ok(levels)
+        }
+    }
+
+    #[test]
+    fn scan_expander_matrix() {
+        let expander =
+            MockExpander::new(Vec::from([(1, 9, 1_000, 20_000), (0, 8, 30_000, 50_000)]));
+        let mut matrix =
+            ExpanderMatrix::new::<4>(expander, [0, 1], [8, 9]).map_rows_and_cols([0, 1], [0, 1], 0);
+        assert_eq!(matrix.output_mask(), 0b11 << 8);
+        embassy_futures::block_on(matrix.configure());
+        assert_eq!(matrix.expander.output_mask, Some(0b11 << 8));
+
+        // The mock expander never has to wait for the bus, so let the scanner wait between the
+        // outputs to advance the virtual time.
+        let config = MatrixConfig {
+            drain_delay: Duration::from_micros(100),
+            ..Default::default()
+        };
+        let transform = matrix.transform;
+        let messages: Vec<_> = run_matrix_scan(&mut matrix, &transform, &config, 60_000)
+            .into_iter()
+            .map(|(_, message)| message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                Message::Press { key_index: 3 },
+                Message::Release { key_index: 3 },
+                Message::Press { key_index: 0 },
+                Message::Release { key_index: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "output pin out of range")]
+    fn expander_matrix_rejects_pins_out_of_range() {
+        let _ = ExpanderMatrix::new::<2>(MockExpander::new(Vec::new()), [0, 1], [16]);
+    }
+}
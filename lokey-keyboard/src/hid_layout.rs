@@ -0,0 +1,257 @@
+use crate::Key;
+use core::cell::Cell;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+
+/// A key together with the modifiers that have to be held to type a character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KeyStroke {
+    pub key: Key,
+    /// Whether Shift has to be held.
+    pub shift: bool,
+    /// Whether AltGr (Right Alt) has to be held.
+    pub alt_gr: bool,
+}
+
+impl KeyStroke {
+    /// Creates a new [`KeyStroke`] that presses the key without modifiers.
+    pub const fn new(key: Key) -> Self {
+        Self {
+            key,
+            shift: false,
+            alt_gr: false,
+        }
+    }
+
+    /// Adds Shift to the key stroke.
+    pub const fn shift(mut self) -> Self {
+        self.shift = true;
+        self
+    }
+
+    /// Adds AltGr (Right Alt) to the key stroke.
+    pub const fn alt_gr(mut self) -> Self {
+        self.alt_gr = true;
+        self
+    }
+}
+
+impl From<Key> for KeyStroke {
+    fn from(key: Key) -> Self {
+        Self::new(key)
+    }
+}
+
+/// The keyboard layout that is configured on the host.
+///
+/// The layout determines which key has to be pressed to type a character, e.g. for the
+/// [`SendString`](crate::action::SendString) and [`Unicode`](crate::action::Unicode) actions.
+/// Custom layouts can be defined by implementing this trait or with a [`TableLayout`].
+pub trait HidLayout: Send + Sync + 'static {
+    /// Returns the key stroke that types the character.
+    ///
+    /// Returns `None` if the character can not be typed with a single key stroke (e.g. control
+    /// characters or characters that require a dead key).
+    fn key_stroke(&self, c: char) -> Option<KeyStroke>;
+}
+
+/// The US QWERTY layout.
+pub struct UsLayout;
+
+impl HidLayout for UsLayout {
+    fn key_stroke(&self, c: char) -> Option<KeyStroke> {
+        let (key, shift) = Key::from_ascii(u8::try_from(c).ok()?)?;
+        Some(KeyStroke {
+            key,
+            shift,
+            alt_gr: false,
+        })
+    }
+}
+
+/// The UK QWERTY layout.
+pub struct UkLayout;
+
+impl HidLayout for UkLayout {
+    fn key_stroke(&self, c: char) -> Option<KeyStroke> {
+        let stroke = match c {
+            '"' => KeyStroke::new(Key::N2).shift(),
+            '£' => KeyStroke::new(Key::N3).shift(),
+            '€' => KeyStroke::new(Key::N4).alt_gr(),
+            '@' => KeyStroke::new(Key::Apostrophe).shift(),
+            '#' => KeyStroke::new(Key::Hash),
+            '~' => KeyStroke::new(Key::Hash).shift(),
+            '\\' => KeyStroke::new(Key::NonUsBackslash),
+            '|' => KeyStroke::new(Key::NonUsBackslash).shift(),
+            '¬' => KeyStroke::new(Key::Grave).shift(),
+            _ => return UsLayout.key_stroke(c),
+        };
+        Some(stroke)
+    }
+}
+
+/// The German QWERTZ layout.
+///
+/// Characters that are typed with a dead key (`^`, `` ` `` and `´`) are not supported.
+pub struct GermanLayout;
+
+impl HidLayout for GermanLayout {
+    fn key_stroke(&self, c: char) -> Option<KeyStroke> {
+        let stroke = match c {
+            'y' => KeyStroke::new(Key::Z),
+            'Y' => KeyStroke::new(Key::Z).shift(),
+            'z' => KeyStroke::new(Key::Y),
+            'Z' => KeyStroke::new(Key::Y).shift(),
+            'a'..='x' | 'A'..='X' | '0'..='9' | ' ' | '\n' | '\t' | '!' | '%' | ',' | '.' => {
+                return UsLayout.key_stroke(c);
+            }
+            '"' => KeyStroke::new(Key::N2).shift(),
+            '²' => KeyStroke::new(Key::N2).alt_gr(),
+            '§' => KeyStroke::new(Key::N3).shift(),
+            '³' => KeyStroke::new(Key::N3).alt_gr(),
+            '$' => KeyStroke::new(Key::N4).shift(),
+            '&' => KeyStroke::new(Key::N6).shift(),
+            '/' => KeyStroke::new(Key::N7).shift(),
+            '{' => KeyStroke::new(Key::N7).alt_gr(),
+            '(' => KeyStroke::new(Key::N8).shift(),
+            '[' => KeyStroke::new(Key::N8).alt_gr(),
+            ')' => KeyStroke::new(Key::N9).shift(),
+            ']' => KeyStroke::new(Key::N9).alt_gr(),
+            '=' => KeyStroke::new(Key::N0).shift(),
+            '}' => KeyStroke::new(Key::N0).alt_gr(),
+            'ß' => KeyStroke::new(Key::Minus),
+            '?' => KeyStroke::new(Key::Minus).shift(),
+            '\\' => KeyStroke::new(Key::Minus).alt_gr(),
+            '@' => KeyStroke::new(Key::Q).alt_gr(),
+            '€' => KeyStroke::new(Key::E).alt_gr(),
+            'µ' => KeyStroke::new(Key::M).alt_gr(),
+            'ü' => KeyStroke::new(Key::LeftBracket),
+            'Ü' => KeyStroke::new(Key::LeftBracket).shift(),
+            '+' => KeyStroke::new(Key::RightBracket),
+            '*' => KeyStroke::new(Key::RightBracket).shift(),
+            '~' => KeyStroke::new(Key::RightBracket).alt_gr(),
+            'ö' => KeyStroke::new(Key::Semicolon),
+            'Ö' => KeyStroke::new(Key::Semicolon).shift(),
+            'ä' => KeyStroke::new(Key::Apostrophe),
+            'Ä' => KeyStroke::new(Key::Apostrophe).shift(),
+            '#' => KeyStroke::new(Key::Hash),
+            '\'' => KeyStroke::new(Key::Hash).shift(),
+            '°' => KeyStroke::new(Key::Grave).shift(),
+            ';' => KeyStroke::new(Key::Comma).shift(),
+            ':' => KeyStroke::new(Key::Dot).shift(),
+            '-' => KeyStroke::new(Key::Slash),
+            '_' => KeyStroke::new(Key::Slash).shift(),
+            '<' => KeyStroke::new(Key::NonUsBackslash),
+            '>' => KeyStroke::new(Key::NonUsBackslash).shift(),
+            '|' => KeyStroke::new(Key::NonUsBackslash).alt_gr(),
+            _ => return None,
+        };
+        Some(stroke)
+    }
+}
+
+/// A [`HidLayout`] that is defined by a table of characters.
+///
+/// Characters that are not contained in the table are looked up in the base layout, so a layout
+/// that only differs in a few keys from an existing layout can be defined by only listing the
+/// differences.
+///
+/// # Example
+///
+/// ```
+/// use lokey_keyboard::{HidLayout, Key, KeyStroke, TableLayout, UsLayout};
+///
+/// // The US layout with the Y and Z keys swapped
+/// static LAYOUT: TableLayout<UsLayout> = TableLayout::new(
+///     &[
+///         ('y', KeyStroke::new(Key::Z)),
+///         ('Y', KeyStroke::new(Key::Z).shift()),
+///         ('z', KeyStroke::new(Key::Y)),
+///         ('Z', KeyStroke::new(Key::Y).shift()),
+///     ],
+///     UsLayout,
+/// );
+///
+/// assert_eq!(LAYOUT.key_stroke('z'), Some(KeyStroke::new(Key::Y)));
+/// assert_eq!(LAYOUT.key_stroke('a'), Some(KeyStroke::new(Key::A)));
+/// ```
+pub struct TableLayout<L> {
+    entries: &'static [(char, KeyStroke)],
+    base: L,
+}
+
+impl<L: HidLayout> TableLayout<L> {
+    pub const fn new(entries: &'static [(char, KeyStroke)], base: L) -> Self {
+        Self { entries, base }
+    }
+}
+
+impl<L: HidLayout> HidLayout for TableLayout<L> {
+    fn key_stroke(&self, c: char) -> Option<KeyStroke> {
+        match self.entries.iter().find(|(character, _)| *character == c) {
+            Some((_, stroke)) => Some(*stroke),
+            None => self.base.key_stroke(c),
+        }
+    }
+}
+
+/// State type for the [`HidLayout`] of the host.
+///
+/// If this type is not part of the state, the [`UsLayout`] is used.
+pub struct HidLayoutState {
+    inner: Mutex<CriticalSectionRawMutex, Cell<&'static dyn HidLayout>>,
+}
+
+impl Default for HidLayoutState {
+    fn default() -> Self {
+        Self::new(&UsLayout)
+    }
+}
+
+impl HidLayoutState {
+    /// Creates a new [`HidLayoutState`] with the specified initial layout.
+    pub const fn new(layout: &'static dyn HidLayout) -> Self {
+        Self {
+            inner: Mutex::new(Cell::new(layout)),
+        }
+    }
+
+    /// Returns the current layout.
+    pub fn get(&self) -> &'static dyn HidLayout {
+        self.inner.lock(|v| v.get())
+    }
+
+    /// Sets the current layout.
+    pub fn set(&self, layout: &'static dyn HidLayout) {
+        self.inner.lock(|v| v.set(layout));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn german_layout() {
+        assert_eq!(GermanLayout.key_stroke('z'), Some(KeyStroke::new(Key::Y)));
+        assert_eq!(GermanLayout.key_stroke('a'), Some(KeyStroke::new(Key::A)));
+        assert_eq!(
+            GermanLayout.key_stroke('@'),
+            Some(KeyStroke::new(Key::Q).alt_gr())
+        );
+        assert_eq!(GermanLayout.key_stroke('^'), None);
+    }
+
+    #[test]
+    fn uk_layout() {
+        assert_eq!(
+            UkLayout.key_stroke('"'),
+            Some(KeyStroke::new(Key::N2).shift())
+        );
+        assert_eq!(
+            UkLayout.key_stroke('!'),
+            Some(KeyStroke::new(Key::N1).shift())
+        );
+    }
+}
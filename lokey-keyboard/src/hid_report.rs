@@ -0,0 +1,186 @@
+#[cfg(feature = "usb")]
+use crate::MAX_KEYBOARD_REPORT_SIZE;
+#[cfg(any(feature = "usb", feature = "ble"))]
+use crate::consumer::CONSUMER_REPORT_DESCRIPTOR;
+#[cfg(feature = "usb")]
+use crate::consumer::CONSUMER_REPORT_SIZE;
+#[cfg(all(feature = "nkro", any(feature = "usb", feature = "ble")))]
+use crate::nkro::NKRO_REPORT_DESCRIPTOR;
+#[cfg(any(feature = "usb", feature = "ble"))]
+use crate::system_control::SYSTEM_CONTROL_REPORT_DESCRIPTOR;
+#[cfg(feature = "usb")]
+use crate::system_control::SYSTEM_CONTROL_REPORT_SIZE;
+use crate::{ConsumerReport, KeyboardReport, SystemControlReport};
+use lokey::external;
+
+/// The report ID of the keyboard report in a [`HidReport`], as declared in
+/// [`HID_REPORT_DESCRIPTOR`].
+#[cfg(any(feature = "usb", feature = "ble"))]
+pub(crate) const KEYBOARD_REPORT_ID: u8 = 3;
+
+/// The maximum size of the HID report for a [`HidReport`] in bytes, including the report ID.
+#[cfg(feature = "usb")]
+pub(crate) const HID_REPORT_SIZE: usize = 1 + max(
+    MAX_KEYBOARD_REPORT_SIZE,
+    max(CONSUMER_REPORT_SIZE, SYSTEM_CONTROL_REPORT_SIZE),
+);
+
+/// The HID report descriptor for a boot keyboard report with the report ID
+/// [`KEYBOARD_REPORT_ID`].
+#[cfg(any(feature = "usb", feature = "ble"))]
+#[rustfmt::skip]
+const KEYBOARD_REPORT_DESCRIPTOR: [u8; 67] = [
+    0x05, 0x01,       // Usage Page (Generic Desktop)
+    0x09, 0x06,       // Usage (Keyboard)
+    0xa1, 0x01,       // Collection (Application)
+    0x85, 0x03,       //   Report ID (3)
+    0x05, 0x07,       //   Usage Page (Keyboard/Keypad)
+    0x19, 0xe0,       //   Usage Minimum (Left Control)
+    0x29, 0xe7,       //   Usage Maximum (Right GUI)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x25, 0x01,       //   Logical Maximum (1)
+    0x75, 0x01,       //   Report Size (1)
+    0x95, 0x08,       //   Report Count (8)
+    0x81, 0x02,       //   Input (Data, Variable, Absolute)
+    0x75, 0x08,       //   Report Size (8)
+    0x95, 0x01,       //   Report Count (1)
+    0x81, 0x01,       //   Input (Constant)
+    0x05, 0x08,       //   Usage Page (LEDs)
+    0x19, 0x01,       //   Usage Minimum (Num Lock)
+    0x29, 0x05,       //   Usage Maximum (Kana)
+    0x75, 0x01,       //   Report Size (1)
+    0x95, 0x05,       //   Report Count (5)
+    0x91, 0x02,       //   Output (Data, Variable, Absolute)
+    0x75, 0x03,       //   Report Size (3)
+    0x95, 0x01,       //   Report Count (1)
+    0x91, 0x01,       //   Output (Constant)
+    0x05, 0x07,       //   Usage Page (Keyboard/Keypad)
+    0x19, 0x00,       //   Usage Minimum (0)
+    0x2a, 0xff, 0x00, //   Usage Maximum (255)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x26, 0xff, 0x00, //   Logical Maximum (255)
+    0x75, 0x08,       //   Report Size (8)
+    0x95, 0x06,       //   Report Count (6)
+    0x81, 0x00,       //   Input (Data, Array, Absolute)
+    0xc0,             // End Collection
+];
+
+/// The length of [`HID_REPORT_DESCRIPTOR`].
+#[cfg(any(feature = "usb", feature = "ble"))]
+pub(crate) const HID_REPORT_DESCRIPTOR_LEN: usize = KEYBOARD_REPORT_DESCRIPTOR.len()
+    + CONSUMER_REPORT_DESCRIPTOR.len()
+    + SYSTEM_CONTROL_REPORT_DESCRIPTOR.len();
+
+/// The HID report descriptor for a [`HidReport`], which contains the collections of the boot
+/// keyboard, the consumer control and the system control reports.
+#[cfg(any(feature = "usb", feature = "ble"))]
+pub(crate) const HID_REPORT_DESCRIPTOR: [u8; HID_REPORT_DESCRIPTOR_LEN] = concat(
+    &KEYBOARD_REPORT_DESCRIPTOR,
+    &concat::<_, _, { HID_REPORT_DESCRIPTOR_LEN - KEYBOARD_REPORT_DESCRIPTOR.len() }>(
+        &CONSUMER_REPORT_DESCRIPTOR,
+        &SYSTEM_CONTROL_REPORT_DESCRIPTOR,
+    ),
+);
+
+/// The length of [`NKRO_HID_REPORT_DESCRIPTOR`].
+#[cfg(all(feature = "nkro", any(feature = "usb", feature = "ble")))]
+pub(crate) const NKRO_HID_REPORT_DESCRIPTOR_LEN: usize =
+    HID_REPORT_DESCRIPTOR_LEN - KEYBOARD_REPORT_DESCRIPTOR.len() + NKRO_REPORT_DESCRIPTOR.len() + 2;
+
+/// The HID report descriptor for a [`HidReport`] whose keyboard report is an NKRO keyboard
+/// report.
+#[cfg(all(feature = "nkro", any(feature = "usb", feature = "ble")))]
+pub(crate) const NKRO_HID_REPORT_DESCRIPTOR: [u8; NKRO_HID_REPORT_DESCRIPTOR_LEN] = concat(
+    &with_report_id::<_, { NKRO_REPORT_DESCRIPTOR.len() + 2 }>(
+        &NKRO_REPORT_DESCRIPTOR,
+        KEYBOARD_REPORT_ID,
+    ),
+    &concat::<_, _, { HID_REPORT_DESCRIPTOR_LEN - KEYBOARD_REPORT_DESCRIPTOR.len() }>(
+        &CONSUMER_REPORT_DESCRIPTOR,
+        &SYSTEM_CONTROL_REPORT_DESCRIPTOR,
+    ),
+);
+
+#[cfg(feature = "usb")]
+const fn max(a: usize, b: usize) -> usize {
+    if a > b { a } else { b }
+}
+
+/// Concatenates two report descriptors.
+#[cfg(any(feature = "usb", feature = "ble"))]
+const fn concat<const A: usize, const B: usize, const C: usize>(
+    a: &[u8; A],
+    b: &[u8; B],
+) -> [u8; C] {
+    assert!(A + B == C);
+    let mut descriptor = [0; C];
+    let mut i = 0;
+    while i < A {
+        descriptor[i] = a[i];
+        i += 1;
+    }
+    let mut j = 0;
+    while j < B {
+        descriptor[i + j] = b[j];
+        j += 1;
+    }
+    descriptor
+}
+
+/// Inserts a report ID item into a report descriptor that starts with the usage page, the usage
+/// and the application collection.
+#[cfg(all(feature = "nkro", any(feature = "usb", feature = "ble")))]
+const fn with_report_id<const A: usize, const B: usize>(
+    descriptor: &[u8; A],
+    report_id: u8,
+) -> [u8; B] {
+    assert!(A + 2 == B);
+    assert!(descriptor[4] == 0xa1);
+    let mut result = [0; B];
+    let mut i = 0;
+    while i < A {
+        result[if i < 6 { i } else { i + 2 }] = descriptor[i];
+        i += 1;
+    }
+    result[6] = 0x85;
+    result[7] = report_id;
+    result
+}
+
+/// The report sent by the device for the keyboard, consumer control and system control keys.
+///
+/// All kinds of reports are sent through the same HID interface and are distinguished by their
+/// report ID. To use the [`ConsumerControl`](crate::action::ConsumerControl) or
+/// [`SystemControl`](crate::action::SystemControl) actions, this message has to be part of the
+/// messages of the external transport, where it replaces the [`KeyboardReport`] message.
+///
+/// The keyboard report is configured with the [`KeyboardReportConfig`](crate::KeyboardReportConfig)
+/// like a plain [`KeyboardReport`], but as the interface uses report IDs, it does not support the
+/// boot protocol that some hosts (e.g. a BIOS) require. Use a [`KeyboardReport`] instead if the
+/// keyboard has to work in such hosts.
+#[derive(Debug, Clone, PartialEq, Eq, external::Message)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HidReport {
+    Keyboard(KeyboardReport),
+    Consumer(ConsumerReport),
+    SystemControl(SystemControlReport),
+}
+
+#[cfg(all(test, feature = "nkro", any(feature = "usb", feature = "ble")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nkro_hid_report_descriptor_contains_report_id() {
+        assert_eq!(NKRO_HID_REPORT_DESCRIPTOR[..6], NKRO_REPORT_DESCRIPTOR[..6]);
+        assert_eq!(NKRO_HID_REPORT_DESCRIPTOR[6..8], [0x85, KEYBOARD_REPORT_ID]);
+        assert_eq!(
+            NKRO_HID_REPORT_DESCRIPTOR[8..NKRO_REPORT_DESCRIPTOR.len() + 2],
+            NKRO_REPORT_DESCRIPTOR[6..]
+        );
+        assert_eq!(
+            NKRO_HID_REPORT_DESCRIPTOR[NKRO_REPORT_DESCRIPTOR.len() + 2..],
+            HID_REPORT_DESCRIPTOR[KEYBOARD_REPORT_DESCRIPTOR.len()..]
+        );
+    }
+}
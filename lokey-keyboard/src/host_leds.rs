@@ -0,0 +1,106 @@
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::task::Poll;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::waitqueue::MultiWakerRegistration;
+
+/// The maximum number of tasks that can wait on [`HostLeds::wait_for_change`] at the same time.
+const MAX_CHANGE_WAITERS: usize = 4;
+
+struct HostLedsChanges {
+    leds: HostLeds,
+    version: u32,
+    wakers: MultiWakerRegistration<MAX_CHANGE_WAITERS>,
+}
+
+static HOST_LEDS: Mutex<CriticalSectionRawMutex, RefCell<HostLedsChanges>> =
+    Mutex::new(RefCell::new(HostLedsChanges {
+        leds: HostLeds(0),
+        version: 0,
+        wakers: MultiWakerRegistration::new(),
+    }));
+
+/// The state of the keyboard LEDs as reported by the host (e.g. Num Lock or Caps Lock).
+///
+/// The host sends the state in the output report of the keyboard whenever it changes. The most
+/// recently received state can be read with [`HostLeds::current`] and changes of it can be awaited
+/// with [`HostLeds::wait_for_change`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HostLeds(u8);
+
+impl HostLeds {
+    const NUM_LOCK: u8 = 1 << 0;
+    const CAPS_LOCK: u8 = 1 << 1;
+    const SCROLL_LOCK: u8 = 1 << 2;
+    const COMPOSE: u8 = 1 << 3;
+    const KANA: u8 = 1 << 4;
+
+    /// Creates the LED state from the byte of a HID keyboard output report.
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    /// Returns the byte of the HID keyboard output report.
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Returns the most recently received LED state of the host.
+    ///
+    /// All LEDs are off until the host sent its first output report.
+    pub fn current() -> Self {
+        HOST_LEDS.lock(|changes| changes.borrow().leds)
+    }
+
+    /// Waits until the host sends an LED state that differs from the current one and returns it.
+    pub async fn wait_for_change() -> Self {
+        let mut initial_version = None;
+        poll_fn(|cx| {
+            HOST_LEDS.lock(|changes| {
+                let mut changes = changes.borrow_mut();
+                let initial_version = *initial_version.get_or_insert(changes.version);
+                if changes.version != initial_version {
+                    Poll::Ready(changes.leds)
+                } else {
+                    changes.wakers.register(cx.waker());
+                    Poll::Pending
+                }
+            })
+        })
+        .await
+    }
+
+    #[cfg_attr(not(any(feature = "usb", feature = "ble")), allow(dead_code))]
+    pub(crate) fn set_current(self) {
+        HOST_LEDS.lock(|changes| {
+            let mut changes = changes.borrow_mut();
+            if changes.leds != self {
+                changes.leds = self;
+                changes.version = changes.version.wrapping_add(1);
+                changes.wakers.wake();
+            }
+        });
+    }
+
+    pub const fn num_lock(self) -> bool {
+        self.0 & Self::NUM_LOCK != 0
+    }
+
+    pub const fn caps_lock(self) -> bool {
+        self.0 & Self::CAPS_LOCK != 0
+    }
+
+    pub const fn scroll_lock(self) -> bool {
+        self.0 & Self::SCROLL_LOCK != 0
+    }
+
+    pub const fn compose(self) -> bool {
+        self.0 & Self::COMPOSE != 0
+    }
+
+    pub const fn kana(self) -> bool {
+        self.0 & Self::KANA != 0
+    }
+}
@@ -137,6 +137,99 @@ pub enum Key {
     Mute,
     VolumeUp,
     VolumeDown,
+    LockingCapsLock,
+    LockingNumLock,
+    LockingScrollLock,
+    KpComma,
+    KpEqualAs400,
+    /// International key 1 (Ro on Japanese keyboards).
+    International1,
+    /// International key 2 (Katakana/Hiragana on Japanese keyboards).
+    International2,
+    /// International key 3 (Yen on Japanese keyboards).
+    International3,
+    /// International key 4 (Henkan on Japanese keyboards).
+    International4,
+    /// International key 5 (Muhenkan on Japanese keyboards).
+    International5,
+    /// International key 6 (Kanma on PC9800 keyboards).
+    International6,
+    /// International key 7 (single byte/double byte toggle).
+    International7,
+    International8,
+    International9,
+    /// Language key 1 (Hangul/English toggle on Korean keyboards).
+    Lang1,
+    /// Language key 2 (Hanja conversion on Korean keyboards).
+    Lang2,
+    /// Language key 3 (Katakana on Japanese keyboards).
+    Lang3,
+    /// Language key 4 (Hiragana on Japanese keyboards).
+    Lang4,
+    /// Language key 5 (Zenkaku/Hankaku on Japanese keyboards).
+    Lang5,
+    Lang6,
+    Lang7,
+    Lang8,
+    Lang9,
+    AlternateErase,
+    SysReq,
+    Cancel,
+    Clear,
+    Prior,
+    Return,
+    Separator,
+    Out,
+    Oper,
+    ClearAgain,
+    CrSel,
+    ExSel,
+    Kp00,
+    Kp000,
+    ThousandsSeparator,
+    DecimalSeparator,
+    CurrencyUnit,
+    CurrencySubunit,
+    KpLeftParen,
+    KpRightParen,
+    KpLeftBrace,
+    KpRightBrace,
+    KpTab,
+    KpBackspace,
+    KpA,
+    KpB,
+    KpC,
+    KpD,
+    KpE,
+    KpF,
+    KpXor,
+    KpCaret,
+    KpPercent,
+    KpLessThan,
+    KpGreaterThan,
+    KpAmpersand,
+    KpDoubleAmpersand,
+    KpPipe,
+    KpDoublePipe,
+    KpColon,
+    KpHash,
+    KpSpace,
+    KpAt,
+    KpExclamation,
+    KpMemoryStore,
+    KpMemoryRecall,
+    KpMemoryClear,
+    KpMemoryAdd,
+    KpMemorySubtract,
+    KpMemoryMultiply,
+    KpMemoryDivide,
+    KpPlusMinus,
+    KpClear,
+    KpClearEntry,
+    KpBinary,
+    KpOctal,
+    KpDecimal,
+    KpHexadecimal,
 }
 
 pub enum HidReportByte {
@@ -153,6 +246,93 @@ impl Key {
         )
     }
 
+    /// Returns the key that types the specified ASCII character on a US keyboard layout.
+    ///
+    /// The returned boolean indicates whether shift must be held while pressing the key. Returns
+    /// `None` for characters that can not be typed with a single key (e.g. control characters).
+    pub const fn from_ascii(c: u8) -> Option<(Key, bool)> {
+        let result = match c {
+            b'a'..=b'z' | b'A'..=b'Z' => {
+                let key = match c.to_ascii_lowercase() {
+                    b'a' => Key::A,
+                    b'b' => Key::B,
+                    b'c' => Key::C,
+                    b'd' => Key::D,
+                    b'e' => Key::E,
+                    b'f' => Key::F,
+                    b'g' => Key::G,
+                    b'h' => Key::H,
+                    b'i' => Key::I,
+                    b'j' => Key::J,
+                    b'k' => Key::K,
+                    b'l' => Key::L,
+                    b'm' => Key::M,
+                    b'n' => Key::N,
+                    b'o' => Key::O,
+                    b'p' => Key::P,
+                    b'q' => Key::Q,
+                    b'r' => Key::R,
+                    b's' => Key::S,
+                    b't' => Key::T,
+                    b'u' => Key::U,
+                    b'v' => Key::V,
+                    b'w' => Key::W,
+                    b'x' => Key::X,
+                    b'y' => Key::Y,
+                    _ => Key::Z,
+                };
+                (key, c.is_ascii_uppercase())
+            }
+            b'\n' => (Key::Enter, false),
+            b'\t' => (Key::Tab, false),
+            b' ' => (Key::Space, false),
+            b'1' => (Key::N1, false),
+            b'!' => (Key::N1, true),
+            b'2' => (Key::N2, false),
+            b'@' => (Key::N2, true),
+            b'3' => (Key::N3, false),
+            b'#' => (Key::N3, true),
+            b'4' => (Key::N4, false),
+            b'$' => (Key::N4, true),
+            b'5' => (Key::N5, false),
+            b'%' => (Key::N5, true),
+            b'6' => (Key::N6, false),
+            b'^' => (Key::N6, true),
+            b'7' => (Key::N7, false),
+            b'&' => (Key::N7, true),
+            b'8' => (Key::N8, false),
+            b'*' => (Key::N8, true),
+            b'9' => (Key::N9, false),
+            b'(' => (Key::N9, true),
+            b'0' => (Key::N0, false),
+            b')' => (Key::N0, true),
+            b'-' => (Key::Minus, false),
+            b'_' => (Key::Minus, true),
+            b'=' => (Key::Equal, false),
+            b'+' => (Key::Equal, true),
+            b'[' => (Key::LeftBracket, false),
+            b'{' => (Key::LeftBracket, true),
+            b']' => (Key::RightBracket, false),
+            b'}' => (Key::RightBracket, true),
+            b'\\' => (Key::Backslash, false),
+            b'|' => (Key::Backslash, true),
+            b';' => (Key::Semicolon, false),
+            b':' => (Key::Semicolon, true),
+            b'\'' => (Key::Apostrophe, false),
+            b'"' => (Key::Apostrophe, true),
+            b'`' => (Key::Grave, false),
+            b'~' => (Key::Grave, true),
+            b',' => (Key::Comma, false),
+            b'<' => (Key::Comma, true),
+            b'.' => (Key::Dot, false),
+            b'>' => (Key::Dot, true),
+            b'/' => (Key::Slash, false),
+            b'?' => (Key::Slash, true),
+            _ => return None,
+        };
+        Some(result)
+    }
+
     pub fn to_hid_report_byte(&self) -> HidReportByte {
         match self {
             Key::LControl => HidReportByte::Modifier(0b0000_0001),
@@ -289,6 +469,144 @@ impl Key {
             Key::Mute => HidReportByte::Key(0x7f),
             Key::VolumeUp => HidReportByte::Key(0x80),
             Key::VolumeDown => HidReportByte::Key(0x81),
+            Key::LockingCapsLock => HidReportByte::Key(0x82),
+            Key::LockingNumLock => HidReportByte::Key(0x83),
+            Key::LockingScrollLock => HidReportByte::Key(0x84),
+            Key::KpComma => HidReportByte::Key(0x85),
+            Key::KpEqualAs400 => HidReportByte::Key(0x86),
+            Key::International1 => HidReportByte::Key(0x87),
+            Key::International2 => HidReportByte::Key(0x88),
+            Key::International3 => HidReportByte::Key(0x89),
+            Key::International4 => HidReportByte::Key(0x8a),
+            Key::International5 => HidReportByte::Key(0x8b),
+            Key::International6 => HidReportByte::Key(0x8c),
+            Key::International7 => HidReportByte::Key(0x8d),
+            Key::International8 => HidReportByte::Key(0x8e),
+            Key::International9 => HidReportByte::Key(0x8f),
+            Key::Lang1 => HidReportByte::Key(0x90),
+            Key::Lang2 => HidReportByte::Key(0x91),
+            Key::Lang3 => HidReportByte::Key(0x92),
+            Key::Lang4 => HidReportByte::Key(0x93),
+            Key::Lang5 => HidReportByte::Key(0x94),
+            Key::Lang6 => HidReportByte::Key(0x95),
+            Key::Lang7 => HidReportByte::Key(0x96),
+            Key::Lang8 => HidReportByte::Key(0x97),
+            Key::Lang9 => HidReportByte::Key(0x98),
+            Key::AlternateErase => HidReportByte::Key(0x99),
+            Key::SysReq => HidReportByte::Key(0x9a),
+            Key::Cancel => HidReportByte::Key(0x9b),
+            Key::Clear => HidReportByte::Key(0x9c),
+            Key::Prior => HidReportByte::Key(0x9d),
+            Key::Return => HidReportByte::Key(0x9e),
+            Key::Separator => HidReportByte::Key(0x9f),
+            Key::Out => HidReportByte::Key(0xa0),
+            Key::Oper => HidReportByte::Key(0xa1),
+            Key::ClearAgain => HidReportByte::Key(0xa2),
+            Key::CrSel => HidReportByte::Key(0xa3),
+            Key::ExSel => HidReportByte::Key(0xa4),
+            Key::Kp00 => HidReportByte::Key(0xb0),
+            Key::Kp000 => HidReportByte::Key(0xb1),
+            Key::ThousandsSeparator => HidReportByte::Key(0xb2),
+            Key::DecimalSeparator => HidReportByte::Key(0xb3),
+            Key::CurrencyUnit => HidReportByte::Key(0xb4),
+            Key::CurrencySubunit => HidReportByte::Key(0xb5),
+            Key::KpLeftParen => HidReportByte::Key(0xb6),
+            Key::KpRightParen => HidReportByte::Key(0xb7),
+            Key::KpLeftBrace => HidReportByte::Key(0xb8),
+            Key::KpRightBrace => HidReportByte::Key(0xb9),
+            Key::KpTab => HidReportByte::Key(0xba),
+            Key::KpBackspace => HidReportByte::Key(0xbb),
+            Key::KpA => HidReportByte::Key(0xbc),
+            Key::KpB => HidReportByte::Key(0xbd),
+            Key::KpC => HidReportByte::Key(0xbe),
+            Key::KpD => HidReportByte::Key(0xbf),
+            Key::KpE => HidReportByte::Key(0xc0),
+            Key::KpF => HidReportByte::Key(0xc1),
+            Key::KpXor => HidReportByte::Key(0xc2),
+            Key::KpCaret => HidReportByte::Key(0xc3),
+            Key::KpPercent => HidReportByte::Key(0xc4),
+            Key::KpLessThan => HidReportByte::Key(0xc5),
+            Key::KpGreaterThan => HidReportByte::Key(0xc6),
+            Key::KpAmpersand => HidReportByte::Key(0xc7),
+            Key::KpDoubleAmpersand => HidReportByte::Key(0xc8),
+            Key::KpPipe => HidReportByte::Key(0xc9),
+            Key::KpDoublePipe => HidReportByte::Key(0xca),
+            Key::KpColon => HidReportByte::Key(0xcb),
+            Key::KpHash => HidReportByte::Key(0xcc),
+            Key::KpSpace => HidReportByte::Key(0xcd),
+            Key::KpAt => HidReportByte::Key(0xce),
+            Key::KpExclamation => HidReportByte::Key(0xcf),
+            Key::KpMemoryStore => HidReportByte::Key(0xd0),
+            Key::KpMemoryRecall => HidReportByte::Key(0xd1),
+            Key::KpMemoryClear => HidReportByte::Key(0xd2),
+            Key::KpMemoryAdd => HidReportByte::Key(0xd3),
+            Key::KpMemorySubtract => HidReportByte::Key(0xd4),
+            Key::KpMemoryMultiply => HidReportByte::Key(0xd5),
+            Key::KpMemoryDivide => HidReportByte::Key(0xd6),
+            Key::KpPlusMinus => HidReportByte::Key(0xd7),
+            Key::KpClear => HidReportByte::Key(0xd8),
+            Key::KpClearEntry => HidReportByte::Key(0xd9),
+            Key::KpBinary => HidReportByte::Key(0xda),
+            Key::KpOctal => HidReportByte::Key(0xdb),
+            Key::KpDecimal => HidReportByte::Key(0xdc),
+            Key::KpHexadecimal => HidReportByte::Key(0xdd),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enumset::EnumSet;
+
+    #[test]
+    fn hid_report_bytes_cover_usage_page() {
+        let mut usage_codes = [None; 256];
+        let mut modifiers = 0u8;
+        for key in EnumSet::<Key>::all() {
+            match key.to_hid_report_byte() {
+                HidReportByte::Key(v) => {
+                    assert_eq!(
+                        usage_codes[v as usize], None,
+                        "duplicate usage code {v:#04x}"
+                    );
+                    usage_codes[v as usize] = Some(key);
+                }
+                HidReportByte::Modifier(v) => {
+                    assert_eq!(v.count_ones(), 1);
+                    assert_eq!(modifiers & v, 0);
+                    modifiers |= v;
+                }
+            }
+        }
+        assert_eq!(modifiers, 0xff);
+        for v in (0x04..=0xa4).chain(0xb0..=0xdd) {
+            assert!(usage_codes[v].is_some(), "missing usage code {v:#04x}");
+        }
+    }
+
+    #[test]
+    fn hid_report_bytes() {
+        let expected = [
+            (Key::A, 0x04),
+            (Key::Z, 0x1d),
+            (Key::N1, 0x1e),
+            (Key::N0, 0x27),
+            (Key::Hash, 0x32),
+            (Key::NonUsBackslash, 0x64),
+            (Key::F13, 0x68),
+            (Key::F24, 0x73),
+            (Key::KpComma, 0x85),
+            (Key::International1, 0x87),
+            (Key::International3, 0x89),
+            (Key::Lang1, 0x90),
+            (Key::Lang2, 0x91),
+            (Key::ExSel, 0xa4),
+            (Key::Kp00, 0xb0),
+            (Key::KpHexadecimal, 0xdd),
+        ];
+        for (key, usage_code) in expected {
+            assert!(matches!(key.to_hid_report_byte(), HidReportByte::Key(v) if v == usage_code));
         }
     }
 }
@@ -0,0 +1,55 @@
+use crate::{Message, TimestampedMessage};
+use embassy_futures::select::{Either, select};
+use lokey::DynContext;
+use lokey::internal::{MaximumReceiversReached, Receiver};
+
+/// Subscription to the key events that are sent by the scanners.
+///
+/// The key events of the local [`Scanner`](crate::Scanner) are received as well as the key events
+/// that are forwarded from the peripherals of a split keyboard, regardless of whether they are
+/// sent as a [`Message`] or as a [`TimestampedMessage`]. The events are received as they are sent
+/// by the scanners, i.e. before the [`Layout`](crate::Layout) handles them, so e.g. key presses
+/// during the [`startup_suppression`](crate::Layout::startup_suppression) are included.
+///
+/// Each subscription occupies two receiver slots of the internal channel. Any number of
+/// subscriptions can be used at the same time (up to the number of receiver slots), and each of
+/// them receives every key event. As sending a key event waits until all receivers have received
+/// it, a subscription has to be polled continuously with [`next`](Self::next).
+///
+/// # Example
+///
+/// ```ignore
+/// use lokey_keyboard::{KeyEvents, Message};
+///
+/// let mut key_events = KeyEvents::subscribe(context.as_dyn())?;
+/// let mut num_presses = 0;
+/// loop {
+///     if let Message::Press { .. } = key_events.next().await {
+///         num_presses += 1;
+///     }
+/// }
+/// ```
+pub struct KeyEvents {
+    receiver: Receiver<'static, Message>,
+    timestamped_receiver: Receiver<'static, TimestampedMessage>,
+}
+
+impl KeyEvents {
+    /// Creates a new subscription to the key events.
+    ///
+    /// Returns an error if the internal channel has no free receiver slots left.
+    pub fn subscribe(context: DynContext) -> Result<Self, MaximumReceiversReached> {
+        Ok(Self {
+            receiver: context.internal_channel.receiver::<Message>()?,
+            timestamped_receiver: context.internal_channel.receiver::<TimestampedMessage>()?,
+        })
+    }
+
+    /// Waits for the next key event and returns it.
+    pub async fn next(&mut self) -> Message {
+        match select(self.receiver.next(), self.timestamped_receiver.next()).await {
+            Either::First(message) => message,
+            Either::Second(TimestampedMessage { message, .. }) => message,
+        }
+    }
+}
@@ -7,41 +7,84 @@
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
 pub mod action;
+mod analog;
 #[cfg(feature = "ble")]
 pub mod ble;
+mod bootmagic;
+mod caps_word;
+mod capture;
+mod combo;
+mod consumer;
 mod debounce;
+mod defer;
 mod direct_pins;
+mod expander;
+mod hid_layout;
+mod hid_report;
+mod host_leds;
 mod key;
+mod key_events;
 mod key_override;
 mod matrix;
+#[cfg(all(feature = "nkro", any(feature = "usb", feature = "ble")))]
+mod nkro;
+mod reorder;
+mod shift_register;
+mod sleep;
+mod socd;
 pub mod switch;
+mod system_control;
+#[cfg(test)]
+mod testing;
+mod unicode;
 #[cfg(feature = "usb")]
 pub mod usb;
 
 use action::InvalidChildActionIndex;
 pub use action::{Action, ActionContainer};
+pub use analog::{AnalogInput, AnalogKeys, AnalogKeysConfig, KeyCalibration};
+use arrayvec::ArrayVec;
+pub use bootmagic::{Bootmagic, BootmagicHook};
+use caps_word::CapsWordState;
+pub use combo::{Combo, ComboContainer};
+use combo::{ComboEngine, ComboEvent};
+pub use consumer::{ConsumerReport, ConsumerReportState, ConsumerUsage, ConsumerUsageSet};
 use core::array;
-use core::future::Future;
-pub use debounce::Debounce;
+use core::cell::{Cell, RefCell};
+use core::future::{Future, poll_fn};
+use core::task::Poll;
+pub use debounce::{Debounce, Debouncer};
 pub use direct_pins::{DirectPins, DirectPinsConfig};
 use embassy_futures::join::{join, join_array};
+use embassy_futures::select::{Either3, select, select3};
+use embassy_futures::yield_now;
 use embassy_sync::blocking_mutex::Mutex;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::Channel;
+use embassy_sync::waitqueue::MultiWakerRegistration;
+use embassy_time::{Duration, Instant, Timer};
 use enumset::EnumSet;
+pub use expander::{ExpanderMatrix, IoExpander, Mcp23017, Pcf8574};
 #[doc(hidden)]
 pub use generic_array; // Re-exported for use in the `layout!` macro.
 use generic_array::GenericArray;
+pub use hid_layout::{
+    GermanLayout, HidLayout, HidLayoutState, KeyStroke, TableLayout, UkLayout, UsLayout,
+};
+pub use hid_report::HidReport;
+pub use host_leds::HostLeds;
 pub use key::{HidReportByte, Key};
+pub use key_events::KeyEvents;
 pub use key_override::{KeyOverride, KeyOverrideEntry};
-use lokey::util::{debug, error, unwrap};
+use lokey::util::{debug, error, unwrap, warn};
 use lokey::{AnyState, Component, Context, Device, DynContext, Transports, external, internal};
 /// Macro for building a [`Layout`].
 ///
-/// The arguments must be arrays where the type of the items must be either an [`Action`] or the
-/// symbol `Transparent`. Each array represents a layer and must have the same amount of items as
-/// the other arrays. The symbol `Transparent` means that the action at the same position from the
-/// previous layer is used or [`NoOp`](action::NoOp) if it is the first layer.
+/// The arguments must be arrays where the items must be either an expression of a type that
+/// implements [`Action`] (including actions from other crates) or the symbol `Transparent`. Each
+/// array represents a layer and must have the same amount of items as the other arrays. The symbol
+/// `Transparent` means that the action at the same position from the previous layer is used or
+/// [`NoOp`](action::NoOp) if it is the first layer.
 ///
 /// # Example
 ///
@@ -93,27 +136,436 @@ use lokey::{AnyState, Component, Context, Device, DynContext, Transports, extern
 /// ```
 #[cfg(feature = "macros")]
 pub use lokey_keyboard_macros::layout;
+/// Macro for building the [`Layout`] of a split keyboard.
+///
+/// This works like the [`layout!`] macro, but each layer is split into the actions of the central
+/// half and the actions of the peripheral half, separated by `|`. The halves are concatenated,
+/// so the keys of the peripheral half start at the index that equals the number of keys on the
+/// central half. This is the offset that the [`Scanner`] of the peripheral has to use as its
+/// `KEY_OFFSET`.
+///
+/// To keep the offset of the peripheral in sync with the layout, the number of central keys can
+/// be specified with `central_keys = <expr>;` before the layers. The macro then fails to compile
+/// if the central half of the layout has a different number of actions.
+///
+/// # Example
+///
+/// ```no_run
+/// use lokey_keyboard::action::Layer;
+/// use lokey_keyboard::{Key, split_layout};
+/// use lokey_layer::LayerId;
+///
+/// // Shared between the central and the peripheral device
+/// const NUM_CENTRAL_KEYS: usize = 2;
+///
+/// let layout = split_layout!(
+///     central_keys = NUM_CENTRAL_KEYS;
+///     // Layer 0
+///     [Key::A, Layer::new(LayerId(1))] | [Key::B, Key::C],
+///     // Layer 1
+///     [Key::D, Transparent] | [Transparent, Key::E],
+/// );
+/// ```
+#[cfg(feature = "macros")]
+pub use lokey_keyboard_macros::split_layout;
 #[doc(hidden)]
 pub use lokey_layer; // Re-exported for use in the `layout!` macro.
-pub use matrix::{Matrix, MatrixConfig};
+#[cfg(feature = "chatter-diagnostics")]
+pub use matrix::ChatterReport;
+pub use matrix::{Matrix, MatrixConfig, MatrixSnapshot, MatrixSnapshotRequest};
+use portable_atomic::{AtomicU16, Ordering};
+use reorder::{ClockOffset, ReorderBuffer};
+pub use shift_register::{Hc165Pins, Hc595Pins, ShiftRegisterMatrix};
+pub use sleep::IdleSleep;
+pub use socd::{Socd, SocdPair, SocdResolution};
+pub use system_control::{SystemControlReport, SystemControlUsage};
 #[doc(hidden)]
 pub use typenum; // Re-exported for use in the `layout!` macro.
+pub use unicode::{UnicodeMode, UnicodeModeState};
+
+/// The index of the key whose action is currently called by an action worker.
+static CURRENT_KEY_INDEX: AtomicU16 = AtomicU16::new(0);
+
+/// Returns the index of the key whose `on_press` or `on_release` method is being called.
+///
+/// For the action of a [`Combo`], this is the index of the first key of the combo. The value is
+/// only valid if it is read before the first `.await` point of the method, as the
+/// action workers run concurrently.
+pub fn current_key_index() -> u16 {
+    CURRENT_KEY_INDEX.load(Ordering::Relaxed)
+}
+
+/// A key press and the point in time at which it was dispatched.
+type TimedKeyPress = (ComboEvent, Instant);
+
+/// The number of the most recent presses whose points in time are kept.
+const KEY_PRESS_INSTANTS_LEN: usize = 8;
+
+/// The most recent key presses (the most recent one first).
+static KEY_PRESS_INSTANTS: Mutex<
+    CriticalSectionRawMutex,
+    Cell<[Option<TimedKeyPress>; KEY_PRESS_INSTANTS_LEN]>,
+> = Mutex::new(Cell::new([None; KEY_PRESS_INSTANTS_LEN]));
+
+fn record_key_press(event: ComboEvent) {
+    KEY_PRESS_INSTANTS.lock(|instants| {
+        let mut presses = instants.get();
+        presses.rotate_right(1);
+        presses[0] = Some((event, Instant::now()));
+        instants.set(presses);
+    });
+}
+
+/// Forgets the recorded key presses, as the virtual time of the tests starts at zero again.
+#[cfg(test)]
+pub(crate) fn reset_key_presses() {
+    KEY_PRESS_INSTANTS.lock(|instants| instants.set([None; KEY_PRESS_INSTANTS_LEN]));
+}
+
+/// Returns the time that elapsed between the most recent key press and the most recent press
+/// before it for which `participates` returns `true`.
+///
+/// Returns `None` if no such press is among the recently recorded presses.
+pub(crate) fn prior_idle_time(participates: impl Fn(ComboEvent) -> bool) -> Option<Duration> {
+    KEY_PRESS_INSTANTS.lock(|instants| {
+        let [last, previous @ ..] = instants.get();
+        let (_, last) = last?;
+        previous
+            .into_iter()
+            .flatten()
+            .find(|(event, _)| participates(*event))
+            .map(|(_, instant)| last.duration_since(instant))
+    })
+}
+
+/// Returns the point in time of the most recent press of a key or combo other than the key with
+/// the specified index.
+pub(crate) fn last_other_key_press(key_index: u16) -> Option<Instant> {
+    let own_press = ComboEvent::Key(Message::Press { key_index });
+    KEY_PRESS_INSTANTS.lock(|instants| {
+        instants
+            .get()
+            .into_iter()
+            .flatten()
+            .find(|(event, _)| *event != own_press)
+            .map(|(_, instant)| instant)
+    })
+}
+
+/// The maximum number of actions that can wait on a [`KeyPressObserver`] at the same time.
+const MAX_KEY_PRESS_WAITERS: usize = 8;
+
+/// The number of the most recent presses that are kept for the [`KeyPressObserver`]s.
+const KEY_PRESS_HISTORY_LEN: usize = 8;
+
+struct KeyPresses {
+    /// The number of presses so far, which is used as the version of the most recent press.
+    version: u32,
+    /// The most recent presses, where the press with version `v` is stored at the index
+    /// `v % KEY_PRESS_HISTORY_LEN`.
+    history: [Option<ComboEvent>; KEY_PRESS_HISTORY_LEN],
+    wakers: MultiWakerRegistration<MAX_KEY_PRESS_WAITERS>,
+}
+
+/// The presses of keys and combos that were passed on by the combo detection of the layout.
+static KEY_PRESSES: Mutex<CriticalSectionRawMutex, RefCell<KeyPresses>> =
+    Mutex::new(RefCell::new(KeyPresses {
+        version: 0,
+        history: [None; KEY_PRESS_HISTORY_LEN],
+        wakers: MultiWakerRegistration::new(),
+    }));
+
+fn announce_key_press(event: ComboEvent) {
+    KEY_PRESSES.lock(|presses| {
+        let mut presses = presses.borrow_mut();
+        presses.version = presses.version.wrapping_add(1);
+        let index = presses.version as usize % KEY_PRESS_HISTORY_LEN;
+        presses.history[index] = Some(event);
+        presses.wakers.wake();
+    });
+}
+
+/// Observes the presses of keys and combos in the order in which they happened.
+///
+/// Only the presses after creating the observer are returned. The presses are observed after the
+/// combos were detected (see [`Layout`]), so the keys of a combo are never returned, only the press
+/// of the combo itself. This is how actions should observe the presses of other keys.
+pub(crate) struct KeyPressObserver {
+    /// The version of the next press that is returned.
+    next_version: u32,
+}
+
+impl KeyPressObserver {
+    pub(crate) fn new() -> Self {
+        let version = KEY_PRESSES.lock(|presses| presses.borrow().version);
+        Self {
+            next_version: version.wrapping_add(1),
+        }
+    }
+
+    /// Waits for the next press and returns it.
+    ///
+    /// If more presses happened since the previous call than the history can contain, the oldest
+    /// press that is still contained in it is returned.
+    pub(crate) async fn next(&mut self) -> ComboEvent {
+        poll_fn(|cx| {
+            KEY_PRESSES.lock(|presses| {
+                let mut presses = presses.borrow_mut();
+                let num_pending = presses
+                    .version
+                    .wrapping_sub(self.next_version.wrapping_sub(1))
+                    as usize;
+                if num_pending == 0 {
+                    presses.wakers.register(cx.waker());
+                    return Poll::Pending;
+                }
+                if num_pending > KEY_PRESS_HISTORY_LEN {
+                    warn!("Missed {} key presses", num_pending - KEY_PRESS_HISTORY_LEN);
+                    self.next_version = presses
+                        .version
+                        .wrapping_sub(KEY_PRESS_HISTORY_LEN as u32 - 1);
+                }
+                let index = self.next_version as usize % KEY_PRESS_HISTORY_LEN;
+                self.next_version = self.next_version.wrapping_add(1);
+                Poll::Ready(unwrap!(presses.history[index]))
+            })
+        })
+        .await
+    }
+}
+
+/// The state of a key as seen by [`Layout::dispatch`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum KeyState {
+    #[default]
+    Released,
+    Pressed,
+    /// The key press was passed to a [`KeyCapture`](capture::KeyCapture) instead of the action of
+    /// the key.
+    Captured,
+}
 
 /// The layout of the keys.
-pub struct Layout<A: ActionContainer> {
+///
+/// # Resolution order
+///
+/// The key events are passed through the following stages in this order, where each stage only
+/// sees the events that are passed on by the previous stages:
+///
+/// 1. **Reordering:** The events within the [reorder window](Self::reorder_window) are sorted by
+///    the time at which they happened.
+/// 2. **Startup suppression:** Key presses during the
+///    [startup suppression](Self::startup_suppression) are dropped.
+/// 3. **Combos:** The presses of keys that belong to a [`Combo`] are held back until the combo is
+///    detected or ruled out. The events of the keys of a detected combo are replaced by the press
+///    and release of the combo, so the later stages never see them.
+/// 4. **Observation:** Actions that react to the presses of other keys are notified, e.g. a
+///    [`TapDance`](action::TapDance) while another action defers the key events. A combo counts
+///    as a single key press.
+/// 5. **Deferral:** While a [`HoldTap`](action::HoldTap) with a
+///    [flavor](action::HoldTap::flavor) other than
+///    [`TapPreferred`](action::HoldTapFlavor::TapPreferred) or with a
+///    [retro tap](action::HoldTap::retro_tap) decides between the hold and the tap action, or
+///    while a [`TapDance`](action::TapDance) counts the taps, the events of the other keys and
+///    combos are held back. They are passed on to the next stage once the decision was made, and
+///    can end it (e.g. according to the flavor of the `HoldTap`).
+/// 6. **Capture:** While an action captures the key presses (e.g. a [`Leader`](action::Leader)
+///    key), the presses of keys are passed to it instead of the actions of the keys.
+/// 7. **Actions:** The action of the key or combo is called.
+///
+/// As a consequence, a combo always captures its keys before a hold-tap key can see them: if a key
+/// with a `HoldTap` action is part of a combo, the hold-tap only starts once the combo was ruled
+/// out, and pressing a combo while a hold-tap is undecided interrupts it like a single key.
+pub struct Layout<A: ActionContainer, C: ComboContainer = ()> {
     actions: A,
+    combos: C,
+    reorder_window: Duration,
+    startup_suppression: Duration,
+    tapping_term: Option<Duration>,
 }
 
-impl<A: ActionContainer> Component for Layout<A> {}
+impl<A: ActionContainer, C: ComboContainer> Component for Layout<A, C> {}
 
 impl<A: ActionContainer> Layout<A> {
     pub const fn new(actions: A) -> Self {
-        Self { actions }
+        Self {
+            actions,
+            combos: (),
+            reorder_window: Duration::from_ticks(0),
+            startup_suppression: Duration::from_ticks(0),
+            tapping_term: None,
+        }
+    }
+
+    /// Sets the combos of the layout.
+    ///
+    /// The combos are detected before the key events are passed to the actions of the layout, so
+    /// the key indices of a combo refer to the physical keys regardless of the active layer. See
+    /// [`Combo`] for more information.
+    pub fn combos<C: ComboContainer>(self, combos: C) -> Layout<A, C> {
+        let Self {
+            actions,
+            combos: (),
+            reorder_window,
+            startup_suppression,
+            tapping_term,
+        } = self;
+        Layout {
+            actions,
+            combos,
+            reorder_window,
+            startup_suppression,
+            tapping_term,
+        }
+    }
+}
+
+impl<A: ActionContainer, C: ComboContainer> Layout<A, C> {
+    /// Sets the duration for which key events are held back to dispatch them in the order in
+    /// which they happened (defaults to zero).
+    ///
+    /// On split keyboards, the key events of the peripheral arrive with some latency, which can
+    /// reorder near-simultaneous key presses on both halves. If the peripheral sends
+    /// [`TimestampedMessage`]s (see [`MatrixConfig::timestamps`] and
+    /// [`DirectPinsConfig::timestamps`]), the events within this window are sorted by the time at
+    /// which they happened before they are dispatched. The window is added to the latency of all
+    /// key events, so it should only be slightly longer than the variation of the latency of the
+    /// split connection (e.g. the connection interval).
+    pub const fn reorder_window(mut self, value: Duration) -> Self {
+        self.reorder_window = value;
+        self
+    }
+
+    /// Sets the duration after startup during which key presses are ignored (defaults to zero).
+    ///
+    /// Some switches report spurious key events while the voltages settle after power-on. Key
+    /// presses during this window are not passed to the actions, and the corresponding releases
+    /// are ignored as well. The window starts when the layout starts running, so it is not
+    /// shortened by a slow initialization of the device. The keys are still scanned, so [`Bootmagic`] keeps working.
+    pub const fn startup_suppression(mut self, value: Duration) -> Self {
+        self.startup_suppression = value;
+        self
+    }
+
+    /// Sets the tapping term of the [`HoldTap`](action::HoldTap) actions that do not set a
+    /// tapping term themselves (defaults to 200ms).
+    ///
+    /// The tapping term is stored in the [`TappingTermState`] when the layout is run, so the
+    /// `TappingTermState` has to be part of the application state. A tapping term that is set on
+    /// an action always takes precedence over this default.
+    pub const fn tapping_term(mut self, value: Duration) -> Self {
+        self.tapping_term = Some(value);
+        self
+    }
+
+    /// Returns `true` if the key event is dropped because of the startup suppression.
+    ///
+    /// `started` is the point in time at which the layout started running.
+    fn is_suppressed(&self, message: Message, started: Instant) -> bool {
+        let is_suppressed = matches!(message, Message::Press { .. })
+            && Instant::now() < started + self.startup_suppression;
+        if is_suppressed {
+            debug!("Ignoring key press during startup: {}", message);
+        }
+        is_suppressed
+    }
+
+    /// Passes the event to the action workers.
+    ///
+    /// Each release is only dispatched if the press of the key was dispatched before, and a key
+    /// can not be pressed again before it was released. Together with the actions being looked up
+    /// by the key index, this guarantees that the release of a key is handled by the same action
+    /// that handled its press, even if the active layer changed in the meantime.
+    ///
+    /// While a [`KeyCapture`](capture::KeyCapture) is active, key presses are passed to it instead
+    /// and the releases of the captured keys are dropped. While a
+    /// [`KeyDeferral`](defer::KeyDeferral) is active, the events of the other keys and combos are
+    /// held back and dispatched once the deferral ends. After a key press was dispatched, the
+    /// action workers get the chance to start its action before the next event is dispatched, so
+    /// that an action can start a deferral before the events that directly follow the press.
+    async fn dispatch<D, T, S, const N: usize>(
+        &self,
+        event: ComboEvent,
+        pressed: &mut GenericArray<KeyState, A::NumChildren>,
+        queue: &Channel<CriticalSectionRawMutex, ComboEvent, N>,
+        context: Context<D, T, S>,
+    ) where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        if defer::try_defer(event) {
+            debug!("Deferred event: {}", event);
+            return;
+        }
+        if let ComboEvent::Key(message) = event {
+            let (key_index, is_press) = match message {
+                Message::Press { key_index } => (key_index, true),
+                Message::Release { key_index } => (key_index, false),
+            };
+            let Some(state) = pressed.get_mut(key_index as usize) else {
+                warn!("Ignoring event of a key outside of the layout: {}", message);
+                return;
+            };
+            match (*state, is_press) {
+                (KeyState::Released, true) if capture::try_capture(key_index) => {
+                    debug!("Captured key press: {}", message);
+                    *state = KeyState::Captured;
+                    return;
+                }
+                (KeyState::Captured, false) => {
+                    *state = KeyState::Released;
+                    return;
+                }
+                (KeyState::Released, true) => *state = KeyState::Pressed,
+                (KeyState::Pressed, false) => *state = KeyState::Released,
+                (KeyState::Released, false) | (KeyState::Pressed | KeyState::Captured, true) => {
+                    warn!("Ignoring unpaired key event: {}", message);
+                    return;
+                }
+            }
+            if let Some(pressed_keys) = context.state.try_get::<PressedKeysState>() {
+                pressed_keys.update(&message);
+            }
+        }
+        if event.is_press() {
+            record_key_press(event);
+            queue.send(event).await;
+            // Lets the action of the key start before the next event is dispatched, in case it
+            // defers the following key events (see `HoldTap`).
+            yield_now().await;
+        } else {
+            queue.send(event).await;
+        }
+    }
+
+    /// Passes the events of the combo engine to the action workers.
+    ///
+    /// The presses are announced to the actions that observe the presses of other keys (see
+    /// [`KeyPressObserver`]) before they are dispatched, so that they are also observed if they are
+    /// deferred.
+    async fn dispatch_combo_events<D, T, S, const N: usize>(
+        &self,
+        events: &mut ArrayVec<ComboEvent, { combo::MAX_COMBO_EVENTS }>,
+        pressed: &mut GenericArray<KeyState, A::NumChildren>,
+        queue: &Channel<CriticalSectionRawMutex, ComboEvent, N>,
+        context: Context<D, T, S>,
+    ) where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        for event in events.drain(..) {
+            if event.is_press() {
+                announce_key_press(event);
+            }
+            self.dispatch(event, pressed, queue, context).await;
+        }
     }
 
     async fn run_action_worker<D, T, S, const N: usize>(
         &self,
-        queue: &Channel<CriticalSectionRawMutex, Message, N>,
+        queue: &Channel<CriticalSectionRawMutex, ComboEvent, N>,
         context: Context<D, T, S>,
     ) where
         D: Device,
@@ -121,9 +573,9 @@ impl<A: ActionContainer> Layout<A> {
         S: AnyState,
     {
         loop {
-            let message = queue.receive().await;
-            match message {
-                Message::Press { key_index } => {
+            match queue.receive().await {
+                ComboEvent::Key(Message::Press { key_index }) => {
+                    CURRENT_KEY_INDEX.store(key_index, Ordering::Relaxed);
                     if let Err(InvalidChildActionIndex { .. }) = self
                         .actions
                         .child_on_press(key_index as usize, context)
@@ -132,7 +584,8 @@ impl<A: ActionContainer> Layout<A> {
                         error!("Layout has no action at key index {}", key_index);
                     }
                 }
-                Message::Release { key_index } => {
+                ComboEvent::Key(Message::Release { key_index }) => {
+                    CURRENT_KEY_INDEX.store(key_index, Ordering::Relaxed);
                     if let Err(InvalidChildActionIndex { .. }) = self
                         .actions
                         .child_on_release(key_index as usize, context)
@@ -141,10 +594,33 @@ impl<A: ActionContainer> Layout<A> {
                         error!("Layout has no action at key index {}", key_index);
                     }
                 }
+                ComboEvent::Combo { index, pressed } => {
+                    if let Some(key_index) = self.combos.combo_keys(index).first() {
+                        CURRENT_KEY_INDEX.store(*key_index, Ordering::Relaxed);
+                    }
+                    let result = if pressed {
+                        self.combos.child_on_press(index, context).await
+                    } else {
+                        self.combos.child_on_release(index, context).await
+                    };
+                    if let Err(InvalidChildActionIndex { .. }) = result {
+                        error!("Layout has no combo at index {}", index);
+                    }
+                }
             }
         }
     }
 
+    /// Runs the layout, which receives the key events from the internal channel and calls the
+    /// actions of the keys.
+    ///
+    /// The key events do not have to be sent by a [`Scanner`] on the same device. On a split
+    /// keyboard, the peripheral only runs its `Scanner`, and the internal transport forwards
+    /// every [`Message`] and [`TimestampedMessage`] that the scanner sends to the central, where
+    /// they are handled by this layout like the key events of a local scanner. So the central
+    /// does not need a scanner of its own, e.g. if all keys are on the peripherals. The key
+    /// indices of the peripheral have to be shifted into the range of the layout with the
+    /// `KEY_OFFSET` of its `Scanner`.
     pub async fn run<D, T, S>(&self, context: Context<D, T, S>)
     where
         D: Device,
@@ -154,14 +630,94 @@ impl<A: ActionContainer> Layout<A> {
         const ACTION_QUEUE_SIZE: usize = 32;
         const NUM_ACTION_WORKERS: usize = 8;
 
+        if let Some(tapping_term) = self.tapping_term {
+            match context.state.try_get::<TappingTermState>() {
+                Some(state) => state.set(tapping_term),
+                None => warn!("The tapping term of the layout requires TappingTermState"),
+            }
+        }
         let mut receiver = unwrap!(context.internal_channel.receiver::<Message>());
-        let action_queue = Channel::<CriticalSectionRawMutex, Message, ACTION_QUEUE_SIZE>::new();
+        let mut timestamped_receiver =
+            unwrap!(context.internal_channel.receiver::<TimestampedMessage>());
+        let action_queue = Channel::<CriticalSectionRawMutex, ComboEvent, ACTION_QUEUE_SIZE>::new();
 
+        let started = Instant::now();
         let receive_messages = async {
+            let mut clock_offset = ClockOffset::new();
+            let mut reorder_buffer = ReorderBuffer::new(self.reorder_window);
+            let mut combo_engine = ComboEngine::new();
+            let mut combo_events = ArrayVec::new();
+            let mut pressed = GenericArray::<KeyState, A::NumChildren>::default();
             loop {
-                let message = receiver.next().await;
-                debug!("Received layout message: {}", message);
-                action_queue.send(message).await;
+                let deadline = match (
+                    reorder_buffer.next_deadline(),
+                    combo_engine.deadline(&self.combos),
+                ) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+                let release_due = async {
+                    match deadline {
+                        Some(v) => Timer::at(v).await,
+                        None => core::future::pending().await,
+                    }
+                };
+                let release_due = select(release_due, defer::wait_for_end());
+                let event = match select3(receiver.next(), timestamped_receiver.next(), release_due)
+                    .await
+                {
+                    Either3::First(message) => Some((Instant::now(), message)),
+                    Either3::Second(TimestampedMessage {
+                        message,
+                        timestamp_ms,
+                    }) => Some((
+                        clock_offset.event_time(timestamp_ms, Instant::now()),
+                        message,
+                    )),
+                    Either3::Third(_) => None,
+                };
+                if let Some((time, message)) = event {
+                    debug!("Received layout message: {}", message);
+                    if let Some(message) = reorder_buffer.insert(time, message)
+                        && !self.is_suppressed(message, started)
+                    {
+                        warn!("Reorder buffer is full, dispatching oldest key event");
+                        combo_engine.process(
+                            &self.combos,
+                            message,
+                            Instant::now(),
+                            &mut combo_events,
+                        );
+                        self.dispatch_combo_events(
+                            &mut combo_events,
+                            &mut pressed,
+                            &action_queue,
+                            context,
+                        )
+                        .await;
+                    }
+                }
+                let now = Instant::now();
+                while let Some(message) = reorder_buffer.pop_due(now) {
+                    if self.is_suppressed(message, started) {
+                        continue;
+                    }
+                    combo_engine.process(&self.combos, message, now, &mut combo_events);
+                    self.dispatch_combo_events(
+                        &mut combo_events,
+                        &mut pressed,
+                        &action_queue,
+                        context,
+                    )
+                    .await;
+                }
+                combo_engine.expire(&self.combos, now, &mut combo_events);
+                self.dispatch_combo_events(&mut combo_events, &mut pressed, &action_queue, context)
+                    .await;
+                for event in defer::take_deferred() {
+                    self.dispatch(event, &mut pressed, &action_queue, context)
+                        .await;
+                }
             }
         };
 
@@ -173,12 +729,21 @@ impl<A: ActionContainer> Layout<A> {
     }
 }
 
+/// Component that scans the keys with a [`ScannerDriver`].
+///
+/// The key indices of the scanner driver range from 0 to `NUM_KEYS`. They are shifted by
+/// `KEY_OFFSET` before the key events are sent, so the peripheral of a split keyboard can number
+/// its keys from 0 and use the number of keys of the central as the offset (see the
+/// `split_layout!` macro). The offset is applied to the key events of the scanner drivers of this
+/// crate, but not to the key indices that are passed to the [`BootmagicHook`]. The shifted key
+/// indices must fit into a `u16`, which is checked at compile time, and key events of keys that are
+/// not part of the [`Layout`] are ignored by it.
 #[derive(Default)]
-pub struct Scanner<C, const NUM_KEYS: usize> {
+pub struct Scanner<C, const NUM_KEYS: usize, const KEY_OFFSET: usize = 0> {
     config: C,
 }
 
-impl<C, const NUM_KEYS: usize> Scanner<C, NUM_KEYS> {
+impl<C, const NUM_KEYS: usize, const KEY_OFFSET: usize> Scanner<C, NUM_KEYS, KEY_OFFSET> {
     pub fn new() -> Self
     where
         C: Default,
@@ -190,16 +755,55 @@ impl<C, const NUM_KEYS: usize> Scanner<C, NUM_KEYS> {
         Self { config }
     }
 
+    /// The key offset, checked at compile time to keep all key indices of the scanner within the
+    /// range of `u16`.
+    const KEY_INDEX_OFFSET: u16 = {
+        assert!(
+            KEY_OFFSET + NUM_KEYS <= u16::MAX as usize + 1,
+            "The key indices of the scanner exceed the range of u16"
+        );
+        KEY_OFFSET as u16
+    };
+
     pub async fn run<S: ScannerDriver<NUM_KEYS, Config = C>>(
         self,
         scanner: S,
         context: DynContext,
     ) {
-        scanner.run(self.config, context).await;
+        scanner
+            .run(self.config, Self::KEY_INDEX_OFFSET, context)
+            .await;
+    }
+
+    /// Runs the scanner after checking which keys are held during startup.
+    ///
+    /// The keys are read with a single scan before the scanner starts its normal operation, and the
+    /// result is passed to the specified [`BootmagicHook`]. Keys that are held are afterwards
+    /// reported like any other key press.
+    pub async fn run_with_bootmagic<Sc, B, D, T, S>(
+        self,
+        mut scanner: Sc,
+        bootmagic: B,
+        context: Context<D, T, S>,
+    ) where
+        Sc: ScannerDriver<NUM_KEYS, Config = C>,
+        B: BootmagicHook,
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let pressed = scanner.scan_once(&self.config).await;
+        bootmagic.run(&pressed, context).await;
+        scanner
+            .run(self.config, Self::KEY_INDEX_OFFSET, context.as_dyn())
+            .await;
     }
 }
 
-impl<C, const NUM_KEYS: usize> Component for Scanner<C, NUM_KEYS> {}
+impl<C, const NUM_KEYS: usize, const KEY_OFFSET: usize> Component
+    for Scanner<C, NUM_KEYS, KEY_OFFSET>
+{
+}
 
 /// Trait for detecting key presses by scanning pins.
 pub trait ScannerDriver<const NUM_KEYS: usize> {
@@ -208,11 +812,28 @@ pub trait ScannerDriver<const NUM_KEYS: usize> {
     /// Runs the scanner.
     ///
     /// This function should send a [`Message`] to the internal channel for each key press and key
-    /// release.
-    fn run(self, config: Self::Config, context: DynContext) -> impl Future<Output = ()>;
+    /// release, where `key_offset` is added to the key indices of the scanner (see [`Scanner`]).
+    fn run(
+        self,
+        config: Self::Config,
+        key_offset: u16,
+        context: DynContext,
+    ) -> impl Future<Output = ()>;
+    /// Reads the current state of all keys once, without debouncing.
+    ///
+    /// The returned array contains for each key index whether the key is pressed. This is used to
+    /// detect keys that are held during startup before [`run`](Self::run) is called.
+    ///
+    /// The default implementation reports all keys as released, so scanners that do not implement
+    /// this method do not support detecting held keys during startup.
+    fn scan_once(&mut self, config: &Self::Config) -> impl Future<Output = [bool; NUM_KEYS]> {
+        let _ = config;
+        async { [false; NUM_KEYS] }
+    }
 }
 
 /// A message type for key press and key release events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Message {
     /// The key at the specified index was pressed.
@@ -259,9 +880,139 @@ impl internal::Message for Message {
     }
 }
 
+impl Message {
+    /// Returns the message with the key index shifted by the specified offset.
+    const fn with_offset(self, offset: u16) -> Self {
+        match self {
+            Message::Press { key_index } => Message::Press {
+                key_index: key_index + offset,
+            },
+            Message::Release { key_index } => Message::Release {
+                key_index: key_index + offset,
+            },
+        }
+    }
+
+    /// Sends the message to the internal channel.
+    ///
+    /// The key index is shifted by the key offset of the [`Scanner`]. If `timestamped` is `true`,
+    /// the message is sent as a [`TimestampedMessage`] with the current time.
+    pub(crate) async fn send(self, context: DynContext, key_offset: u16, timestamped: bool) {
+        let message = self.with_offset(key_offset);
+        if timestamped {
+            let timestamp_ms = Instant::now().as_millis() as u32;
+            context
+                .internal_channel
+                .send(TimestampedMessage {
+                    message,
+                    timestamp_ms,
+                })
+                .await;
+        } else {
+            context.internal_channel.send(message).await;
+        }
+    }
+}
+
+/// A [`Message`] together with the time at which the key event was detected.
+///
+/// Scanners send this message instead of [`Message`] if timestamps are enabled in their
+/// configuration. The timestamp is used by the [`Layout`] of the central device to restore the
+/// order of key events from both halves of a split keyboard (see [`Layout::reorder_window`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TimestampedMessage {
+    pub message: Message,
+    /// The time at which the key event was detected, in milliseconds since the device started
+    /// (wrapping around).
+    pub timestamp_ms: u32,
+}
+
+impl internal::Message for TimestampedMessage {
+    type Size = typenum::U7;
+
+    const TAG: [u8; 4] = [0x5c, 0x19, 0xa3, 0xe8];
+
+    fn from_bytes(bytes: GenericArray<u8, Self::Size>) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        let [m0, m1, m2, timestamp @ ..] = bytes.into_array::<7>();
+        let message = <Message as internal::Message>::from_bytes([m0, m1, m2].into())?;
+        Some(Self {
+            message,
+            timestamp_ms: u32::from_be_bytes(timestamp),
+        })
+    }
+
+    fn to_bytes(&self) -> GenericArray<u8, Self::Size> {
+        let [m0, m1, m2] = internal::Message::to_bytes(&self.message).into_array::<3>();
+        let [t0, t1, t2, t3] = self.timestamp_ms.to_be_bytes();
+        [m0, m1, m2, t0, t1, t2, t3].into()
+    }
+}
+
 pub type KeySet = EnumSet<Key>;
 
-#[derive(Debug, Clone, external::Message)]
+/// Policy for handling key presses when more keys are pressed than a HID report can contain.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RolloverPolicy {
+    /// Ignores newly pressed keys that do not fit into the report.
+    ///
+    /// An ignored key is not reported until it is released and pressed again, even if one of the
+    /// reported keys is released in the meantime.
+    #[default]
+    DropNewest,
+    /// Stops reporting the key that was pressed first to make room for the newly pressed key.
+    ///
+    /// The evicted key is not reported again until it is released and pressed again.
+    Rolling,
+}
+
+/// Configuration of the keyboard reports that are sent by the USB and BLE transports.
+///
+/// The configuration is passed to the transports with the message configs of their transport
+/// config. If no configuration is provided, the default configuration is used.
+///
+/// ```
+/// use lokey::external::MessageConfigs;
+/// use lokey_keyboard::{KeyboardReportConfig, RolloverPolicy};
+///
+/// let message_configs = MessageConfigs::new(&[&KeyboardReportConfig {
+///     rollover_policy: RolloverPolicy::Rolling,
+/// #   #[cfg(feature = "nkro")]
+///     nkro: true,
+/// }]);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KeyboardReportConfig {
+    /// The policy that is used if more keys are pressed than the HID report can contain.
+    ///
+    /// The policy is ignored for NKRO keyboard reports, as they can contain all keys.
+    pub rollover_policy: RolloverPolicy,
+    /// Whether the keys are reported with an NKRO (n-key rollover) keyboard report, so that any
+    /// number of keys can be pressed at the same time.
+    ///
+    /// Over USB, the keyboard falls back to the boot keyboard report with up to 6 keys if the host
+    /// requests the boot protocol (e.g. a BIOS).
+    #[cfg(feature = "nkro")]
+    pub nkro: bool,
+}
+
+impl lokey::external::MessageConfig for KeyboardReportConfig {}
+
+#[cfg(any(feature = "usb", feature = "ble"))]
+impl KeyboardReportConfig {
+    /// Returns the keyboard report configuration of the message configs, or the default
+    /// configuration if it does not contain one.
+    pub(crate) fn from_message_configs(configs: lokey::external::MessageConfigs) -> Self {
+        configs.get::<Self>().copied().unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, external::Message)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct KeyboardReport {
     pub keys: KeySet,
@@ -284,6 +1035,17 @@ impl KeyboardReport {
         self.keys = EnumSet::empty();
     }
 
+    /// Returns the modifier byte of the HID report for the currently pressed modifier keys.
+    pub fn modifier_byte(&self) -> u8 {
+        self.keys
+            .iter()
+            .filter_map(|key| match key.to_hid_report_byte() {
+                HidReportByte::Modifier(v) => Some(v),
+                HidReportByte::Key(_) => None,
+            })
+            .fold(0, |modifier, v| modifier | v)
+    }
+
     #[cfg(any(feature = "usb", feature = "ble"))]
     pub fn to_hid_report(&self) -> usbd_hid::descriptor::KeyboardReport {
         let mut hid_report = usbd_hid::descriptor::KeyboardReport {
@@ -308,12 +1070,169 @@ impl KeyboardReport {
     }
 }
 
+/// Builder for HID reports that keeps track of the order in which the keys were pressed.
+///
+/// Unlike [`KeyboardReport::to_hid_report`], keys keep their slot in the HID report while they are
+/// held and keys that do not fit into the report are handled according to the configured
+/// [`RolloverPolicy`]. The press order is derived from the sequence of built reports, so keys that
+/// are first contained in the same report are ordered by their usage ID.
+#[cfg(any(feature = "usb", feature = "ble"))]
+pub(crate) struct HidReportBuilder {
+    rollover_policy: RolloverPolicy,
+    /// The reported keys, ordered from the oldest to the newest key press.
+    reported: arrayvec::ArrayVec<Key, 6>,
+    /// Keys that are held but not reported because they were dropped or evicted.
+    ignored: KeySet,
+}
+
+#[cfg(any(feature = "usb", feature = "ble"))]
+impl HidReportBuilder {
+    pub(crate) fn new(rollover_policy: RolloverPolicy) -> Self {
+        Self {
+            rollover_policy,
+            reported: arrayvec::ArrayVec::new(),
+            ignored: KeySet::empty(),
+        }
+    }
+
+    pub(crate) fn build(
+        &mut self,
+        report: &KeyboardReport,
+    ) -> usbd_hid::descriptor::KeyboardReport {
+        self.reported.retain(|key| report.keys.contains(*key));
+        self.ignored = self.ignored.intersection(report.keys);
+
+        let mut hid_report = usbd_hid::descriptor::KeyboardReport {
+            modifier: 0,
+            reserved: 0,
+            leds: 0,
+            keycodes: [0; 6],
+        };
+        for key in report.keys {
+            match key.to_hid_report_byte() {
+                HidReportByte::Key(_) => {
+                    if self.reported.contains(&key) || self.ignored.contains(key) {
+                        continue;
+                    }
+                    if self.reported.is_full() {
+                        match self.rollover_policy {
+                            RolloverPolicy::DropNewest => {
+                                error!(
+                                    "Too many keys pressed at once, only 6 keys can be reported"
+                                );
+                                self.ignored.insert(key);
+                                continue;
+                            }
+                            RolloverPolicy::Rolling => {
+                                let evicted_key = self.reported.remove(0);
+                                self.ignored.insert(evicted_key);
+                            }
+                        }
+                    }
+                    self.reported.push(key);
+                }
+                HidReportByte::Modifier(v) => hid_report.modifier |= v,
+            }
+        }
+        for (keycode, key) in hid_report.keycodes.iter_mut().zip(&self.reported) {
+            if let HidReportByte::Key(v) = key.to_hid_report_byte() {
+                *keycode = v;
+            }
+        }
+        hid_report
+    }
+}
+
+/// The maximum size of the keyboard report that is sent by the USB and BLE transports in bytes,
+/// excluding the report ID.
+#[cfg(all(any(feature = "usb", feature = "ble"), not(feature = "nkro")))]
+pub(crate) const MAX_KEYBOARD_REPORT_SIZE: usize = 8;
+#[cfg(all(any(feature = "usb", feature = "ble"), feature = "nkro"))]
+pub(crate) const MAX_KEYBOARD_REPORT_SIZE: usize = nkro::NKRO_REPORT_SIZE;
+
+/// Encodes keyboard reports into the bytes of the HID report that is selected by the
+/// [`KeyboardReportConfig`].
+#[cfg(any(feature = "usb", feature = "ble"))]
+pub(crate) struct KeyboardReportEncoder {
+    hid_report_builder: Mutex<CriticalSectionRawMutex, RefCell<HidReportBuilder>>,
+    #[cfg(feature = "nkro")]
+    nkro: bool,
+}
+
+#[cfg(any(feature = "usb", feature = "ble"))]
+impl KeyboardReportEncoder {
+    pub(crate) fn new(config: KeyboardReportConfig) -> Self {
+        Self {
+            hid_report_builder: Mutex::new(RefCell::new(HidReportBuilder::new(
+                config.rollover_policy,
+            ))),
+            #[cfg(feature = "nkro")]
+            nkro: config.nkro,
+        }
+    }
+
+    /// Returns `true` if the keys are reported with an NKRO keyboard report.
+    #[cfg_attr(not(any(feature = "usb", feature = "nkro")), allow(dead_code))]
+    pub(crate) fn is_nkro(&self) -> bool {
+        #[cfg(feature = "nkro")]
+        return self.nkro;
+        #[cfg(not(feature = "nkro"))]
+        return false;
+    }
+
+    /// Returns the bytes of the HID report for the keyboard report.
+    ///
+    /// The boot keyboard report is used if NKRO is disabled or `boot_protocol` is `true`. Returns
+    /// `None` if the report could not be serialized.
+    pub(crate) fn encode(
+        &self,
+        report: &KeyboardReport,
+        boot_protocol: bool,
+    ) -> Option<ArrayVec<u8, MAX_KEYBOARD_REPORT_SIZE>> {
+        #[cfg(feature = "nkro")]
+        if self.nkro && !boot_protocol {
+            return Some(report.to_nkro_report().into_iter().collect());
+        }
+        #[cfg(not(feature = "nkro"))]
+        let _ = boot_protocol;
+
+        let hid_report = self
+            .hid_report_builder
+            .lock(|builder| builder.borrow_mut().build(report));
+        let mut buf = [0; MAX_KEYBOARD_REPORT_SIZE];
+        match usbd_hid::descriptor::AsInputReport::serialize(&hid_report, &mut buf) {
+            Ok(len) => Some(buf[..len].iter().copied().collect()),
+            Err(e) => {
+                #[cfg(feature = "defmt")]
+                let e = defmt::Debug2Format(&e);
+                error!("Failed to serialize keyboard report: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// The maximum number of tasks that can wait on [`KeyboardReportState::wait_for_change`] at the
+/// same time.
+const MAX_REPORT_CHANGE_WAITERS: usize = 4;
+
+struct KeyboardReportChanges {
+    version: u32,
+    last_key: Option<Key>,
+    wakers: MultiWakerRegistration<MAX_REPORT_CHANGE_WAITERS>,
+}
+
 /// State type for a keyboard report.
 ///
 /// This type contains a [`KeyboardReport`] and provides methods for accessing it and modifying it
-/// via interior mutability.
+/// via interior mutability. The actions keep it in sync with the reports that are sent to the
+/// external channel, so it can also be used to observe the currently held keys (e.g. to show them
+/// on a display).
 pub struct KeyboardReportState {
     inner: Mutex<CriticalSectionRawMutex, KeyboardReport>,
+    changes: Mutex<CriticalSectionRawMutex, RefCell<KeyboardReportChanges>>,
+    caps_word: Mutex<CriticalSectionRawMutex, Cell<Option<CapsWordState>>>,
+    suppressed_keys: Mutex<CriticalSectionRawMutex, Cell<KeySet>>,
 }
 
 impl Default for KeyboardReportState {
@@ -327,28 +1246,393 @@ impl KeyboardReportState {
     pub fn new(keyboard_report: KeyboardReport) -> Self {
         Self {
             inner: Mutex::new(keyboard_report),
+            changes: Mutex::new(RefCell::new(KeyboardReportChanges {
+                version: 0,
+                last_key: None,
+                wakers: MultiWakerRegistration::new(),
+            })),
+            caps_word: Mutex::new(Cell::new(None)),
+            suppressed_keys: Mutex::new(Cell::new(KeySet::empty())),
         }
     }
 
     /// Gets a clone of the current keyboard report.
+    ///
+    /// Keys that are suppressed (see [`ModMorph`](action::ModMorph)) are still contained in the
+    /// returned report.
     pub fn get(&self) -> KeyboardReport {
         self.inner.lock(|v| v.clone())
     }
 
     /// Sets the current keyboard report.
+    ///
+    /// If caps word is active, Left Shift may be added to or removed from the report (see
+    /// [`CapsWord`](action::CapsWord)).
     pub fn set(&self, keyboard_report: KeyboardReport) {
+        self.store(keyboard_report);
+    }
+
+    /// Sets the current keyboard report and returns a clone of the report that has to be sent,
+    /// i.e. the stored report without the suppressed keys.
+    fn store(&self, mut keyboard_report: KeyboardReport) -> KeyboardReport {
         // SAFETY: This method is guaranteed to never be called within another `lock` or `lock_mut`
         //         method as the lock methods are not exposed in the public API of
         //         KeyboardReportState.
-        unsafe { self.inner.lock_mut(|report| *report = keyboard_report) };
+        let (changed, pressed_keys) = unsafe {
+            self.inner.lock_mut(|report| {
+                self.caps_word.lock(|caps_word| {
+                    if let Some(mut state) = caps_word.get() {
+                        let is_active = state.apply(&mut keyboard_report.keys, report.keys);
+                        caps_word.set(is_active.then_some(state));
+                    }
+                });
+                let changed = *report != keyboard_report;
+                let pressed_keys = keyboard_report.keys.difference(report.keys);
+                *report = keyboard_report.clone();
+                (changed, pressed_keys)
+            })
+        };
+        if changed {
+            self.changes.lock(|changes| {
+                let mut changes = changes.borrow_mut();
+                if let Some(key) = pressed_keys.iter().rfind(|key| !key.is_modifier()) {
+                    changes.last_key = Some(key);
+                }
+                changes.version = changes.version.wrapping_add(1);
+                changes.wakers.wake();
+            });
+        }
+        self.without_suppressed_keys(keyboard_report)
+    }
+
+    fn without_suppressed_keys(&self, mut keyboard_report: KeyboardReport) -> KeyboardReport {
+        let suppressed_keys = self.suppressed_keys.lock(|v| v.get());
+        keyboard_report.keys.remove_all(suppressed_keys);
+        keyboard_report
+    }
+
+    /// Returns the non-modifier key that was most recently added to the keyboard report.
+    ///
+    /// Returns `None` if no non-modifier key was pressed yet.
+    pub fn last_key(&self) -> Option<Key> {
+        self.changes.lock(|changes| changes.borrow().last_key)
+    }
+
+    /// Waits until the keyboard report changes and returns a clone of the changed report.
+    pub async fn wait_for_change(&self) -> KeyboardReport {
+        let mut initial_version = None;
+        poll_fn(|cx| {
+            self.changes.lock(|changes| {
+                let mut changes = changes.borrow_mut();
+                let initial_version = *initial_version.get_or_insert(changes.version);
+                if changes.version != initial_version {
+                    Poll::Ready(())
+                } else {
+                    changes.wakers.register(cx.waker());
+                    Poll::Pending
+                }
+            })
+        })
+        .await;
+        self.get()
     }
 
     /// Modifies the current keyboard report by applying the specified function to it and returns a
-    /// clone of the modified report.
+    /// clone of the modified report without the suppressed keys.
     pub fn modify_and_get(&self, f: impl FnOnce(&mut KeyboardReport)) -> KeyboardReport {
         let mut report = self.get();
         f(&mut report);
-        self.set(report.clone());
-        report
+        self.store(report)
+    }
+
+    /// Returns `true` if caps word is active.
+    pub fn is_caps_word_active(&self) -> bool {
+        self.caps_word.lock(|caps_word| caps_word.get().is_some())
+    }
+
+    pub(crate) fn activate_caps_word(&self, state: CapsWordState) {
+        self.caps_word.lock(|caps_word| caps_word.set(Some(state)));
+    }
+
+    /// Suppresses the specified keys, so that they are removed from the reports that are sent until
+    /// they are restored with [`restore`](Self::restore).
+    ///
+    /// The keys stay part of the stored report, so that pressing and releasing them while they are
+    /// suppressed still updates the report. Returns the keyboard report that has to be sent.
+    pub(crate) fn suppress(&self, keys: KeySet) -> KeyboardReport {
+        self.suppressed_keys.lock(|v| v.set(v.get().union(keys)));
+        self.without_suppressed_keys(self.get())
+    }
+
+    /// Stops suppressing the specified keys.
+    ///
+    /// Only the keys that are still part of the stored report are sent again. Returns the keyboard
+    /// report that has to be sent.
+    pub(crate) fn restore(&self, keys: KeySet) -> KeyboardReport {
+        self.suppressed_keys
+            .lock(|v| v.set(v.get().difference(keys)));
+        self.without_suppressed_keys(self.get())
+    }
+
+    /// Deactivates caps word.
+    ///
+    /// Returns the keyboard report that has to be sent if Left Shift was removed from it.
+    pub(crate) fn deactivate_caps_word(&self) -> Option<KeyboardReport> {
+        let state = self.caps_word.lock(|caps_word| caps_word.take())?;
+        state.shift_applied().then(|| {
+            self.modify_and_get(|report| {
+                report.keys.remove(Key::LShift);
+            })
+        })
+    }
+}
+
+/// The maximum number of tasks that can wait on [`PressedKeysState::wait_for_all_released`] at the
+/// same time.
+const MAX_ALL_RELEASED_WAITERS: usize = 4;
+
+struct PressedKeysInner {
+    count: u16,
+    wakers: MultiWakerRegistration<MAX_ALL_RELEASED_WAITERS>,
+}
+
+/// State type that tracks the number of keys that are currently pressed.
+///
+/// If this type is part of the application state, the [`Layout`] keeps it up to date with every
+/// key press and key release it receives. This makes it possible to wait until no keys are held
+/// anymore, e.g. to apply a change at a clean boundary.
+pub struct PressedKeysState {
+    inner: Mutex<CriticalSectionRawMutex, RefCell<PressedKeysInner>>,
+}
+
+impl Default for PressedKeysState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PressedKeysState {
+    /// Creates a new [`PressedKeysState`] with no keys pressed.
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(PressedKeysInner {
+                count: 0,
+                wakers: MultiWakerRegistration::new(),
+            })),
+        }
+    }
+
+    /// Returns the number of keys that are currently pressed.
+    pub fn count(&self) -> u16 {
+        self.inner.lock(|inner| inner.borrow().count)
+    }
+
+    /// Returns `true` if no keys are currently pressed.
+    pub fn all_released(&self) -> bool {
+        self.count() == 0
+    }
+
+    /// Waits until no keys are pressed.
+    ///
+    /// Returns immediately if no keys are currently pressed.
+    pub async fn wait_for_all_released(&self) {
+        poll_fn(|cx| {
+            self.inner.lock(|inner| {
+                let mut inner = inner.borrow_mut();
+                if inner.count == 0 {
+                    Poll::Ready(())
+                } else {
+                    inner.wakers.register(cx.waker());
+                    Poll::Pending
+                }
+            })
+        })
+        .await;
+    }
+
+    fn update(&self, message: &Message) {
+        self.inner.lock(|inner| {
+            let mut inner = inner.borrow_mut();
+            match message {
+                Message::Press { .. } => inner.count = inner.count.saturating_add(1),
+                Message::Release { .. } => {
+                    inner.count = inner.count.saturating_sub(1);
+                    if inner.count == 0 {
+                        inner.wakers.wake();
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// State type for the tapping term of the [`HoldTap`](action::HoldTap) actions that do not set a
+/// tapping term themselves.
+///
+/// The tapping term can be configured with [`Layout::tapping_term`]. If this type is not part of
+/// the state, a tapping term of 200ms is used.
+pub struct TappingTermState {
+    inner: Mutex<CriticalSectionRawMutex, Cell<Duration>>,
+}
+
+impl Default for TappingTermState {
+    fn default() -> Self {
+        Self::new(action::DEFAULT_TAPPING_TERM)
+    }
+}
+
+impl TappingTermState {
+    /// Creates a new [`TappingTermState`] with the specified initial tapping term.
+    pub const fn new(tapping_term: Duration) -> Self {
+        Self {
+            inner: Mutex::new(Cell::new(tapping_term)),
+        }
+    }
+
+    /// Returns the current tapping term.
+    pub fn get(&self) -> Duration {
+        self.inner.lock(|v| v.get())
+    }
+
+    /// Sets the current tapping term.
+    pub fn set(&self, tapping_term: Duration) {
+        self.inner.lock(|v| v.set(tapping_term));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::pin::pin;
+    use core::task::Context;
+    use noop_waker::noop_waker;
+
+    #[cfg(any(feature = "usb", feature = "ble"))]
+    fn report(keys: KeySet) -> KeyboardReport {
+        KeyboardReport { keys }
+    }
+
+    #[test]
+    #[cfg(any(feature = "usb", feature = "ble"))]
+    fn hid_report_builder_drop_newest() {
+        let mut builder = HidReportBuilder::new(RolloverPolicy::DropNewest);
+        let keys = Key::A | Key::B | Key::C | Key::D | Key::E | Key::F;
+        let hid_report = builder.build(&report(keys));
+        assert_eq!(hid_report.keycodes, [0x04, 0x05, 0x06, 0x07, 0x08, 0x09]);
+
+        let hid_report = builder.build(&report(keys | Key::G));
+        assert_eq!(hid_report.keycodes, [0x04, 0x05, 0x06, 0x07, 0x08, 0x09]);
+
+        // The dropped key is not reported until it is pressed again.
+        let keys = keys - Key::A;
+        let hid_report = builder.build(&report(keys | Key::G));
+        assert_eq!(hid_report.keycodes, [0x05, 0x06, 0x07, 0x08, 0x09, 0]);
+
+        let hid_report = builder.build(&report(keys));
+        assert_eq!(hid_report.keycodes, [0x05, 0x06, 0x07, 0x08, 0x09, 0]);
+        let hid_report = builder.build(&report(keys | Key::G));
+        assert_eq!(hid_report.keycodes, [0x05, 0x06, 0x07, 0x08, 0x09, 0x0a]);
+    }
+
+    #[test]
+    #[cfg(any(feature = "usb", feature = "ble"))]
+    fn hid_report_builder_rolling() {
+        let mut builder = HidReportBuilder::new(RolloverPolicy::Rolling);
+        let keys = Key::A | Key::B | Key::C | Key::D | Key::E | Key::F;
+        builder.build(&report(keys));
+
+        let hid_report = builder.build(&report(keys | Key::G));
+        assert_eq!(hid_report.keycodes, [0x05, 0x06, 0x07, 0x08, 0x09, 0x0a]);
+
+        // The evicted key stays evicted while it is held and does not evict other keys.
+        let keys = keys | Key::G;
+        let hid_report = builder.build(&report(keys - Key::B));
+        assert_eq!(hid_report.keycodes, [0x06, 0x07, 0x08, 0x09, 0x0a, 0]);
+
+        let hid_report = builder.build(&report((keys - Key::A - Key::B) | Key::LShift));
+        assert_eq!(hid_report.modifier, 0b0000_0010);
+        assert_eq!(hid_report.keycodes, [0x06, 0x07, 0x08, 0x09, 0x0a, 0]);
+    }
+
+    #[test]
+    #[cfg(any(feature = "usb", feature = "ble"))]
+    fn hid_report_builder_rolling_evicts_by_press_order() {
+        let mut builder = HidReportBuilder::new(RolloverPolicy::Rolling);
+        let mut keys = KeySet::empty();
+        for key in [Key::F, Key::E, Key::D, Key::C, Key::B, Key::A] {
+            keys.insert(key);
+            builder.build(&report(keys));
+        }
+
+        // F was pressed first, so it is evicted even though it has the highest usage ID.
+        let hid_report = builder.build(&report(keys | Key::G));
+        assert_eq!(hid_report.keycodes, [0x08, 0x07, 0x06, 0x05, 0x04, 0x0a]);
+
+        // Releasing a key frees its slot without changing the order of the other keys.
+        let keys = keys - Key::F - Key::D;
+        let hid_report = builder.build(&report(keys | Key::G));
+        assert_eq!(hid_report.keycodes, [0x08, 0x06, 0x05, 0x04, 0x0a, 0]);
+        let hid_report = builder.build(&report(keys | Key::G | Key::H));
+        assert_eq!(hid_report.keycodes, [0x08, 0x06, 0x05, 0x04, 0x0a, 0x0b]);
+        let hid_report = builder.build(&report(keys | Key::G | Key::H | Key::I));
+        assert_eq!(hid_report.keycodes, [0x06, 0x05, 0x04, 0x0a, 0x0b, 0x0c]);
+    }
+
+    #[test]
+    fn key_press_observer_returns_presses_in_order() {
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let key_press = |key_index| ComboEvent::Key(Message::Press { key_index });
+        let combo = ComboEvent::Combo {
+            index: 0,
+            pressed: true,
+        };
+
+        announce_key_press(key_press(0));
+        let mut observer = KeyPressObserver::new();
+        {
+            let mut next = pin!(observer.next());
+            assert!(next.as_mut().poll(&mut cx).is_pending());
+            announce_key_press(combo);
+            announce_key_press(key_press(1));
+            assert_eq!(next.as_mut().poll(&mut cx), Poll::Ready(combo));
+        }
+        // The later press is not lost, even though it happened before the previous press was
+        // returned.
+        assert_eq!(
+            pin!(observer.next()).poll(&mut cx),
+            Poll::Ready(key_press(1))
+        );
+        assert!(pin!(observer.next()).poll(&mut cx).is_pending());
+
+        // Only the most recent presses are kept if the observer falls behind.
+        for key_index in 0..KEY_PRESS_HISTORY_LEN as u16 + 2 {
+            announce_key_press(key_press(key_index));
+        }
+        assert_eq!(
+            pin!(observer.next()).poll(&mut cx),
+            Poll::Ready(key_press(2))
+        );
+    }
+
+    #[test]
+    fn last_key_ignores_modifiers() {
+        let state = KeyboardReportState::default();
+        assert_eq!(state.last_key(), None);
+
+        let set = |keys: KeySet| state.set(KeyboardReport { keys });
+        set(KeySet::only(Key::LShift));
+        assert_eq!(state.last_key(), None);
+        set(Key::LShift | Key::A);
+        assert_eq!(state.last_key(), Some(Key::A));
+        // Reports that only change the modifiers keep the last key.
+        set(KeySet::only(Key::LShift));
+        set(KeySet::empty());
+        set(Key::LControl | Key::LAlt);
+        assert_eq!(state.last_key(), Some(Key::A));
+        // Releasing a key does not change the last key.
+        set(Key::B | Key::C);
+        set(KeySet::only(Key::B));
+        assert_eq!(state.last_key(), Some(Key::C));
     }
 }
@@ -1,24 +1,291 @@
-use super::{Debounce, Message, ScannerDriver};
-use crate::DynContext;
+use super::{Debounce, Debouncer, ScannerDriver};
 use crate::switch::{InputSwitch, OutputSwitch, WaitableInputSwitch};
-use arrayvec::ArrayVec;
+use crate::{DynContext, Message};
+use core::cell::Cell;
+#[cfg(not(feature = "chatter-diagnostics"))]
+use embassy_futures::join::join;
+#[cfg(feature = "chatter-diagnostics")]
+use embassy_futures::join::join3;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_time::{Duration, Instant, Timer};
-use lokey::Component;
-use lokey::util::error;
-
-/// Size of the debounce buffer. This limits the number of key state changes that can be tracked
-/// simultaneously.
-// TODO: Make configurable
-const DEBOUNCE_BUFFER_SIZE: usize = 64;
+use generic_array::GenericArray;
+#[cfg(feature = "chatter-diagnostics")]
+use lokey::util::warn;
+use lokey::util::{error, unwrap};
+use lokey::{Component, internal};
 
 /// Configuration for the [`Matrix`] scanner.
 #[derive(Clone, Default)]
 pub struct MatrixConfig {
     pub debounce_key_press: Debounce,
     pub debounce_key_release: Debounce,
+    /// Time to wait after turning an output pin off before the next output pin is turned on.
+    ///
+    /// The capacitance of the matrix can keep an input pin active for a short time after the
+    /// output pin was turned off, which can cause phantom key presses on the next output pin. A
+    /// short delay (e.g. a few microseconds) lets the lines discharge. Defaults to zero.
+    pub drain_delay: Duration,
+    /// Send the key events as [`TimestampedMessage`](crate::TimestampedMessage)s.
+    ///
+    /// This should be enabled on the peripheral of a split keyboard if the central uses a
+    /// [reorder window](crate::Layout::reorder_window). Defaults to `false`.
+    pub timestamps: bool,
+    /// Ignore key presses that could be caused by ghosting.
+    ///
+    /// In a matrix without diodes, pressing three keys at the corners of a rectangle also
+    /// activates the switch at the fourth corner. If this is enabled, a key is not pressed while
+    /// it completes a rectangle of active switches, as it can not be distinguished from such a
+    /// phantom key. Keys that were already pressed before the rectangle was completed stay
+    /// pressed. This is not needed for matrices with diodes. Defaults to `false`.
+    pub ghosting_detection: bool,
+}
+
+/// Internal request for a chunk of the raw state of the switches of the [`Matrix`] scanner.
+///
+/// The request is only answered by the matrix of the device part that sent it (see
+/// [`RequestId::address`](internal::RequestId::address)), so the halves of a split keyboard can be
+/// inspected independently. Requesting a chunk that does not exist returns an empty chunk.
+///
+/// # Example
+///
+/// ```ignore
+/// let first = context
+///     .internal_channel
+///     .request(context.address, |request_id| MatrixSnapshotRequest { request_id, chunk: 0 })
+///     .await?;
+/// for chunk in 1..first.num_chunks() {
+///     let snapshot = context
+///         .internal_channel
+///         .request(context.address, |request_id| MatrixSnapshotRequest { request_id, chunk })
+///         .await?;
+///     // ...
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MatrixSnapshotRequest {
+    pub request_id: internal::RequestId,
+    /// The index of the requested chunk (see [`MatrixSnapshot`]).
+    pub chunk: u8,
+}
+
+impl internal::Message for MatrixSnapshotRequest {
+    type Size = typenum::U9;
+
+    const TAG: [u8; 4] = [0x2e, 0x81, 0xc6, 0x5a];
+
+    fn from_bytes(bytes: GenericArray<u8, Self::Size>) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        let [r0, r1, r2, r3, r4, r5, r6, r7, chunk] = bytes.into_array::<9>();
+        Some(Self {
+            request_id: internal::RequestId::from_bytes([r0, r1, r2, r3, r4, r5, r6, r7]),
+            chunk,
+        })
+    }
+
+    fn to_bytes(&self) -> GenericArray<u8, Self::Size> {
+        let [r0, r1, r2, r3, r4, r5, r6, r7] = self.request_id.to_bytes();
+        [r0, r1, r2, r3, r4, r5, r6, r7, self.chunk].into()
+    }
+}
+
+impl internal::Request for MatrixSnapshotRequest {
+    type Response = MatrixSnapshot;
+
+    fn request_id(&self) -> internal::RequestId {
+        self.request_id
+    }
+}
+
+/// Internal message containing a part of the raw (not debounced) state of all switches in the
+/// matrix.
+///
+/// The [`Matrix`] scanner responds to a [`MatrixSnapshotRequest`] with the requested chunk of 32
+/// switches. The state of the switch at output pin `o` and input pin `i` is stored in the bit with
+/// the index `o * num_inputs + i`, where bit `n` is the bit `n % 32` (least significant bit first)
+/// of the chunk with the index `n / 32`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MatrixSnapshot {
+    pub request_id: internal::RequestId,
+    /// The number of input pins of the matrix.
+    pub num_inputs: u8,
+    /// The number of output pins of the matrix.
+    pub num_outputs: u8,
+    /// The index of the chunk that is contained in this message.
+    pub chunk: u8,
+    /// The states of the switches in this chunk (a set bit means that the switch is active).
+    pub bits: u32,
+}
+
+impl MatrixSnapshot {
+    /// The number of switches that are contained in a single message.
+    pub const CHUNK_SIZE: usize = 32;
+
+    /// Returns the number of chunks that contain the state of all switches of the matrix.
+    pub fn num_chunks(&self) -> usize {
+        (self.num_inputs as usize * self.num_outputs as usize).div_ceil(Self::CHUNK_SIZE)
+    }
+
+    /// Returns `true` if the switch at the specified input and output pin index is active.
+    ///
+    /// Returns `None` if the switch is not contained in this chunk.
+    pub fn is_active(&self, input_index: usize, output_index: usize) -> Option<bool> {
+        let n = output_index * self.num_inputs as usize + input_index;
+        if n / Self::CHUNK_SIZE != self.chunk as usize {
+            return None;
+        }
+        Some(self.bits & (1 << (n % Self::CHUNK_SIZE)) != 0)
+    }
+}
+
+impl internal::Message for MatrixSnapshot {
+    type Size = typenum::U15;
+
+    const TAG: [u8; 4] = [0x9a, 0x47, 0x0d, 0xe1];
+
+    fn from_bytes(bytes: GenericArray<u8, Self::Size>) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        let [
+            r0,
+            r1,
+            r2,
+            r3,
+            r4,
+            r5,
+            r6,
+            r7,
+            num_inputs,
+            num_outputs,
+            chunk,
+            bits @ ..,
+        ] = bytes.into_array::<15>();
+        Some(Self {
+            request_id: internal::RequestId::from_bytes([r0, r1, r2, r3, r4, r5, r6, r7]),
+            num_inputs,
+            num_outputs,
+            chunk,
+            bits: u32::from_be_bytes(bits),
+        })
+    }
+
+    fn to_bytes(&self) -> GenericArray<u8, Self::Size> {
+        let [r0, r1, r2, r3, r4, r5, r6, r7] = self.request_id.to_bytes();
+        let [b0, b1, b2, b3] = self.bits.to_be_bytes();
+        [
+            r0,
+            r1,
+            r2,
+            r3,
+            r4,
+            r5,
+            r6,
+            r7,
+            self.num_inputs,
+            self.num_outputs,
+            self.chunk,
+            b0,
+            b1,
+            b2,
+            b3,
+        ]
+        .into()
+    }
+}
+
+impl internal::Response for MatrixSnapshot {
+    fn request_id(&self) -> internal::RequestId {
+        self.request_id
+    }
+}
+
+/// Returns the chunk with the specified index of the raw state of the switches.
+fn snapshot_chunk<const NUM_IS: usize, const NUM_OS: usize>(
+    states: &[[bool; NUM_IS]; NUM_OS],
+    request_id: internal::RequestId,
+    chunk: u8,
+) -> MatrixSnapshot {
+    const {
+        assert!(
+            NUM_IS <= u8::MAX as usize && NUM_OS <= u8::MAX as usize,
+            "The matrix snapshot supports at most 255 input and output pins"
+        );
+        assert!(
+            (NUM_IS * NUM_OS).div_ceil(MatrixSnapshot::CHUNK_SIZE) <= u8::MAX as usize + 1,
+            "The matrix snapshot supports at most 256 chunks"
+        );
+    }
+    let mut bits = 0;
+    for (i, output_states) in states.iter().enumerate() {
+        for (j, is_active) in output_states.iter().enumerate() {
+            let n = i * NUM_IS + j;
+            if *is_active && n / MatrixSnapshot::CHUNK_SIZE == chunk as usize {
+                bits |= 1 << (n % MatrixSnapshot::CHUNK_SIZE);
+            }
+        }
+    }
+    MatrixSnapshot {
+        request_id,
+        num_inputs: NUM_IS as u8,
+        num_outputs: NUM_OS as u8,
+        chunk,
+        bits,
+    }
+}
+
+/// The interval in which [`ChatterReport`] messages are sent.
+#[cfg(feature = "chatter-diagnostics")]
+const CHATTER_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Internal message containing the number of bounces that were detected for a key.
+///
+/// A bounce is counted whenever the raw state of a switch changes while the key is still inside
+/// its debounce window. The [`Matrix`] scanner periodically sends this message for every key whose
+/// count changed since the last report. The count is the total since the scanner was started, so a
+/// key that keeps increasing its count likely has a failing switch.
+#[cfg(feature = "chatter-diagnostics")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChatterReport {
+    /// The index of the key.
+    pub key_index: u16,
+    /// The total number of bounces that were detected for the key.
+    pub count: u16,
+}
+
+#[cfg(feature = "chatter-diagnostics")]
+impl internal::Message for ChatterReport {
+    type Size = typenum::U4;
+
+    const TAG: [u8; 4] = [0x61, 0xf3, 0x2b, 0x88];
+
+    fn from_bytes(bytes: GenericArray<u8, Self::Size>) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        let [k0, k1, c0, c1] = bytes.into_array::<4>();
+        Some(Self {
+            key_index: u16::from_be_bytes([k0, k1]),
+            count: u16::from_be_bytes([c0, c1]),
+        })
+    }
+
+    fn to_bytes(&self) -> GenericArray<u8, Self::Size> {
+        let key_index = self.key_index.to_be_bytes();
+        let count = self.count.to_be_bytes();
+        [key_index[0], key_index[1], count[0], count[1]].into()
+    }
 }
 
 /// Scanner for keys that are arranged in a keyboard matrix.
+///
+/// While running, the scanner responds to a [`MatrixSnapshotRequest`] with the raw state of the
+/// switches (see [`MatrixSnapshot`]).
 pub struct Matrix<I, O, const NUM_IS: usize, const NUM_OS: usize, const NUM_KEYS: usize> {
     input_switches: [I; NUM_IS],
     output_switches: [O; NUM_OS],
@@ -98,6 +365,56 @@ impl<
 {
 }
 
+impl<
+    I: InputSwitch + WaitableInputSwitch + 'static,
+    O: OutputSwitch + 'static,
+    const NUM_IS: usize,
+    const NUM_OS: usize,
+    const NUM_KEYS: usize,
+> MatrixPins<NUM_IS, NUM_OS> for Matrix<I, O, NUM_IS, NUM_OS, NUM_KEYS>
+{
+    async fn wait_for_any_active(&mut self) {
+        for output_switch in &mut self.output_switches {
+            if output_switch.on().is_err() {
+                error!("failed to turn output pin on");
+            }
+        }
+
+        let input_switches_futures = self.input_switches.each_mut().map(|input_switch| async {
+            if input_switch.wait_for_active().await.is_err() {
+                error!("failed to get active status of pin");
+            }
+        });
+        embassy_futures::select::select_array(input_switches_futures).await;
+
+        for output_switch in self.output_switches.iter_mut() {
+            if output_switch.off().is_err() {
+                error!("failed to turn output pin off");
+            }
+        }
+    }
+
+    async fn read_inputs(&mut self, output_index: usize) -> [Option<bool>; NUM_IS] {
+        let output_switch = &mut self.output_switches[output_index];
+        if output_switch.on().is_err() {
+            error!("failed to turn output pin on");
+            return [None; NUM_IS];
+        }
+        Timer::after_ticks(1).await;
+        let inputs = self.input_switches.each_ref().map(|input_switch| {
+            let is_active = input_switch.is_active().ok();
+            if is_active.is_none() {
+                error!("failed to get active status of pin");
+            }
+            is_active
+        });
+        if output_switch.off().is_err() {
+            error!("failed to turn output pin off");
+        }
+        inputs
+    }
+}
+
 impl<
     I: InputSwitch + WaitableInputSwitch + 'static,
     O: OutputSwitch + 'static,
@@ -108,125 +425,421 @@ impl<
 {
     type Config = MatrixConfig;
 
-    async fn run(mut self, config: Self::Config, context: DynContext) {
-        let mut key_indices = [[None::<u16>; NUM_OS]; NUM_IS];
-        for (i, key_index_array) in key_indices.iter_mut().enumerate() {
-            for (j, key_index) in key_index_array.iter_mut().enumerate() {
-                *key_index = self
-                    .transform
-                    .iter()
-                    .position(|v| *v == Some((i, j)))
-                    .map(|v| v as u16);
+    async fn scan_once(&mut self, config: &Self::Config) -> [bool; NUM_KEYS] {
+        let transform = self.transform;
+        scan_matrix_once(self, &transform, config).await
+    }
+
+    async fn run(mut self, config: Self::Config, key_offset: u16, context: DynContext) {
+        let transform = self.transform;
+        run_matrix(&mut self, &transform, &config, key_offset, context).await;
+    }
+}
+
+/// Access to the switches of a keyboard matrix.
+///
+/// This is used to share the scanning and debouncing of the [`Matrix`] scanner with scanners that
+/// read the switches differently (e.g. through an I/O expander).
+pub(crate) trait MatrixPins<const NUM_IS: usize, const NUM_OS: usize> {
+    /// Activates all output pins, waits until any input pin becomes active and deactivates all
+    /// output pins again.
+    fn wait_for_any_active(&mut self) -> impl Future<Output = ()>;
+
+    /// Reads the state of all input pins while the output pin with the specified index is
+    /// activated.
+    ///
+    /// The state of an input pin is `None` if it could not be read.
+    fn read_inputs(&mut self, output_index: usize) -> impl Future<Output = [Option<bool>; NUM_IS]>;
+}
+
+/// Deactivates the switches that complete a rectangle of active switches and were not active in
+/// the previous scan, as they could be phantom keys caused by ghosting.
+fn suppress_ghost_keys<const NUM_IS: usize, const NUM_OS: usize>(
+    inputs: &mut [[Option<bool>; NUM_IS]; NUM_OS],
+    previous_states: &[[bool; NUM_IS]; NUM_OS],
+) {
+    let active = inputs.map(|output_inputs| output_inputs.map(|v| v == Some(true)));
+    let is_ghost = |i: usize, j: usize| {
+        (0..NUM_OS)
+            .filter(|i2| *i2 != i && active[*i2][j])
+            .any(|i2| (0..NUM_IS).any(|j2| j2 != j && active[i][j2] && active[i2][j2]))
+    };
+    for (i, output_inputs) in inputs.iter_mut().enumerate() {
+        for (j, is_active) in output_inputs.iter_mut().enumerate() {
+            if active[i][j] && !previous_states[i][j] && is_ghost(i, j) {
+                *is_active = Some(false);
+            }
+        }
+    }
+}
+
+/// Reads the current state of all keys of a matrix once, without debouncing.
+pub(crate) async fn scan_matrix_once<
+    P: MatrixPins<NUM_IS, NUM_OS>,
+    const NUM_IS: usize,
+    const NUM_OS: usize,
+    const NUM_KEYS: usize,
+>(
+    pins: &mut P,
+    transform: &[Option<(usize, usize)>; NUM_KEYS],
+    config: &MatrixConfig,
+) -> [bool; NUM_KEYS] {
+    let mut pressed = [false; NUM_KEYS];
+    for i in 0..NUM_OS {
+        let inputs = pins.read_inputs(i).await;
+        for (j, is_active) in inputs.into_iter().enumerate() {
+            let Some(key_index) = transform.iter().position(|v| *v == Some((j, i))) else {
+                continue;
+            };
+            if let Some(is_active) = is_active {
+                pressed[key_index] = is_active;
             }
         }
+        if config.drain_delay > Duration::from_ticks(0) {
+            Timer::after(config.drain_delay).await;
+        }
+    }
+    pressed
+}
+
+/// Scans the keys of a matrix and sends a [`Message`](crate::Message) for each debounced key press
+/// and key release.
+pub(crate) async fn run_matrix<
+    P: MatrixPins<NUM_IS, NUM_OS>,
+    const NUM_IS: usize,
+    const NUM_OS: usize,
+    const NUM_KEYS: usize,
+>(
+    pins: &mut P,
+    transform: &[Option<(usize, usize)>; NUM_KEYS],
+    config: &MatrixConfig,
+    key_offset: u16,
+    context: DynContext,
+) {
+    let key_indices = key_indices(transform);
+    let snapshot = Mutex::<CriticalSectionRawMutex, _>::new(Cell::new([[false; NUM_IS]; NUM_OS]));
 
-        let mut states = [[false; NUM_IS]; NUM_OS];
-        let mut timeouts = ArrayVec::<(u16, Instant), DEBOUNCE_BUFFER_SIZE>::new();
-        let mut defers = ArrayVec::<(u16, Instant, bool), DEBOUNCE_BUFFER_SIZE>::new();
+    let respond_to_snapshot_requests = async {
+        let mut receiver = unwrap!(context.internal_channel.receiver::<MatrixSnapshotRequest>());
         loop {
-            for output_switch in &mut self.output_switches {
-                if output_switch.on().is_err() {
-                    error!("failed to turn output pin on");
-                }
+            let request = receiver.next().await;
+            if request.request_id.address != context.address {
+                continue;
             }
+            let states = snapshot.lock(|v| v.get());
+            let response = snapshot_chunk(&states, request.request_id, request.chunk);
+            context.internal_channel.send(response).await;
+        }
+    };
 
-            let input_switches_futures = self.input_switches.each_mut().map(|input_switch| async {
-                if input_switch.wait_for_active().await.is_err() {
-                    error!("failed to get active status of pin");
-                }
-            });
-            embassy_futures::select::select_array(input_switches_futures).await;
+    #[cfg(feature = "chatter-diagnostics")]
+    let chatter_counts = Mutex::<CriticalSectionRawMutex, _>::new(Cell::new([0u16; NUM_KEYS]));
 
-            for output_switch in self.output_switches.iter_mut() {
-                if output_switch.off().is_err() {
-                    error!("failed to turn output pin on");
+    #[cfg(feature = "chatter-diagnostics")]
+    let report_chatter = async {
+        let mut reported_counts = [0u16; NUM_KEYS];
+        loop {
+            Timer::after(CHATTER_REPORT_INTERVAL).await;
+            let counts = chatter_counts.lock(|v| v.get());
+            for (key_index, (count, reported_count)) in
+                counts.iter().zip(reported_counts.iter_mut()).enumerate()
+            {
+                if count != reported_count {
+                    warn!("Detected {} bounces for key {}", count, key_index);
+                    *reported_count = *count;
+                    context
+                        .internal_channel
+                        .send(ChatterReport {
+                            key_index: key_index as u16,
+                            count: *count,
+                        })
+                        .await;
                 }
             }
+        }
+    };
+
+    let scan = scan_matrix::<_, NUM_IS, NUM_OS, NUM_KEYS>(
+        pins,
+        &key_indices,
+        config,
+        &snapshot,
+        #[cfg(feature = "chatter-diagnostics")]
+        &chatter_counts,
+        async |message: Message| message.send(context, key_offset, config.timestamps).await,
+    );
 
-            loop {
-                let mut any_active = false;
-                for (i, output_switch) in self.output_switches.iter_mut().enumerate() {
-                    if output_switch.on().is_err() {
-                        error!("failed to turn output pin on");
+    #[cfg(not(feature = "chatter-diagnostics"))]
+    join(scan, respond_to_snapshot_requests).await;
+    #[cfg(feature = "chatter-diagnostics")]
+    join3(scan, respond_to_snapshot_requests, report_chatter).await;
+}
+
+/// Returns the key index of the switch at each input and output pin index.
+pub(crate) fn key_indices<const NUM_IS: usize, const NUM_OS: usize, const NUM_KEYS: usize>(
+    transform: &[Option<(usize, usize)>; NUM_KEYS],
+) -> [[Option<u16>; NUM_OS]; NUM_IS] {
+    let mut key_indices = [[None::<u16>; NUM_OS]; NUM_IS];
+    for (i, key_index_array) in key_indices.iter_mut().enumerate() {
+        for (j, key_index) in key_index_array.iter_mut().enumerate() {
+            *key_index = transform
+                .iter()
+                .position(|v| *v == Some((i, j)))
+                .map(|v| v as u16);
+        }
+    }
+    key_indices
+}
+
+/// Scans the keys of a matrix and calls `send` for each debounced key press and key release.
+///
+/// The raw states of all switches, including the switches that are not mapped to a key index, are
+/// stored in `snapshot` after every scan. This does not depend on the context of the device, so
+/// that it can be driven by tests.
+pub(crate) async fn scan_matrix<
+    P: MatrixPins<NUM_IS, NUM_OS>,
+    const NUM_IS: usize,
+    const NUM_OS: usize,
+    const NUM_KEYS: usize,
+>(
+    pins: &mut P,
+    key_indices: &[[Option<u16>; NUM_OS]; NUM_IS],
+    config: &MatrixConfig,
+    snapshot: &Mutex<CriticalSectionRawMutex, Cell<[[bool; NUM_IS]; NUM_OS]>>,
+    #[cfg(feature = "chatter-diagnostics")] chatter_counts: &Mutex<
+        CriticalSectionRawMutex,
+        Cell<[u16; NUM_KEYS]>,
+    >,
+    mut send: impl AsyncFnMut(Message),
+) {
+    let mut states = [[false; NUM_IS]; NUM_OS];
+    let mut snapshot_states = [[false; NUM_IS]; NUM_OS];
+    let mut debouncer = Debouncer::<NUM_KEYS>::new(
+        config.debounce_key_press.clone(),
+        config.debounce_key_release.clone(),
+    );
+    #[cfg(feature = "chatter-diagnostics")]
+    let mut raw_states = [[false; NUM_IS]; NUM_OS];
+    loop {
+        pins.wait_for_any_active().await;
+
+        loop {
+            let mut any_active = false;
+            let mut inputs = [[None; NUM_IS]; NUM_OS];
+            for (i, output_inputs) in inputs.iter_mut().enumerate() {
+                *output_inputs = pins.read_inputs(i).await;
+                if config.drain_delay > Duration::from_ticks(0) {
+                    Timer::after(config.drain_delay).await;
+                }
+            }
+            for (output_states, output_inputs) in snapshot_states.iter_mut().zip(&inputs) {
+                for (state, is_active) in output_states.iter_mut().zip(output_inputs) {
+                    if let Some(is_active) = is_active {
+                        *state = *is_active;
+                    }
+                }
+            }
+            snapshot.lock(|v| v.set(snapshot_states));
+            if config.ghosting_detection {
+                suppress_ghost_keys(&mut inputs, &states);
+            }
+            for (i, output_inputs) in inputs.into_iter().enumerate() {
+                for (j, is_active) in output_inputs.into_iter().enumerate() {
+                    let Some(key_index) = key_indices[j][i] else {
+                        continue;
+                    };
+                    let Some(is_active) = is_active else {
                         continue;
+                    };
+                    if is_active {
+                        any_active = true;
                     }
-                    Timer::after_ticks(1).await;
-                    for (j, input_switch) in self.input_switches.iter_mut().enumerate() {
-                        let Some(key_index) = key_indices[j][i] else {
-                            continue;
-                        };
-                        let Ok(is_active) = input_switch.is_active() else {
-                            error!("failed to get active status of pin");
-                            continue;
-                        };
-                        if is_active {
-                            any_active = true;
-                        }
-                        let debounce = if is_active {
-                            &config.debounce_key_press
-                        } else {
-                            &config.debounce_key_release
-                        };
-                        if let Debounce::Eager { duration } = debounce
-                            && is_active != states[i][j]
-                        {
-                            if let Some(timeout_index) =
-                                timeouts.iter().position(|(v, _)| *v == key_index)
-                            {
-                                let (_, instant) = timeouts[timeout_index];
-                                if Instant::now().duration_since(instant) <= *duration {
-                                    continue;
-                                }
-                                timeouts.remove(timeout_index);
-                            }
-                            if timeouts.is_full() {
-                                error!("timeouts buffer overflow, dropping oldest event");
-                                timeouts.remove(0);
-                            }
-                            timeouts.push((key_index, Instant::now()));
+                    #[cfg(feature = "chatter-diagnostics")]
+                    if is_active != raw_states[i][j] {
+                        raw_states[i][j] = is_active;
+                        if debouncer.is_bouncing(key_index, is_active, Instant::now()) {
+                            chatter_counts.lock(|v| {
+                                let mut counts = v.get();
+                                counts[key_index as usize] =
+                                    counts[key_index as usize].saturating_add(1);
+                                v.set(counts);
+                            });
                         }
-                        if let Some(defer_index) =
-                            defers.iter().position(|(v, _, _)| *v == key_index)
-                        {
-                            let (_, mut last_change, was_active) = defers[defer_index];
-                            let defer_duration = match debounce {
-                                Debounce::Defer { duration } => *duration,
-                                Debounce::Eager { .. } | Debounce::None => Duration::from_ticks(0),
-                            };
-                            if is_active != states[i][j] {
-                                last_change = Instant::now();
-                            }
-                            if Instant::now().duration_since(last_change) > defer_duration {
-                                defers.remove(defer_index);
-                                if was_active {
-                                    context
-                                        .internal_channel
-                                        .send(Message::Press { key_index })
-                                        .await;
-                                } else {
-                                    context
-                                        .internal_channel
-                                        .send(Message::Release { key_index })
-                                        .await;
-                                }
-                            }
-                        } else if is_active != states[i][j] {
-                            if defers.is_full() {
-                                error!("defer buffer overflow, dropping oldest event");
-                                defers.remove(0);
-                            }
-                            defers.push((key_index, Instant::now(), is_active));
-                        }
-                        states[i][j] = is_active;
                     }
-                    if output_switch.off().is_err() {
-                        error!("failed to turn output pin on");
+                    if let Some(message) = debouncer.sample(key_index, is_active, Instant::now()) {
+                        send(message).await;
                     }
-                }
-                if !any_active && defers.is_empty() && timeouts.is_empty() {
-                    break;
+                    states[i][j] = debouncer.state(key_index);
                 }
             }
+            if !any_active && !debouncer.is_settling(Instant::now()) {
+                break;
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::testing::{MockMatrix, run_matrix_scan, run_matrix_scan_with_snapshot};
+    use std::vec::Vec;
+
+    /// Scans a 2x2 matrix with the keys 0 to 3 and returns the sent messages together with the
+    /// time (in microseconds) at which they were sent.
+    fn scan(matrix: MockMatrix<2, 2>, config: &MatrixConfig, end_us: u64) -> Vec<(u64, Message)> {
+        let (input_switches, output_switches) = matrix.into_switches();
+        let mut matrix =
+            Matrix::new::<4>(input_switches, output_switches).map_rows_and_cols([0, 1], [0, 1], 0);
+        let transform = matrix.transform;
+        run_matrix_scan(&mut matrix, &transform, config, end_us)
+            .into_iter()
+            .map(|(at, message)| (at.as_micros(), message))
+            .collect()
+    }
+
+    #[test]
+    fn scan_with_defer_debounce() {
+        let matrix = MockMatrix::new()
+            // Key 1 bounces when it is pressed and when it is released
+            .change(1_000, 0, 1, true)
+            .change(1_300, 0, 1, false)
+            .change(1_600, 0, 1, true)
+            .change(20_000, 0, 1, false)
+            .change(20_200, 0, 1, true)
+            .change(20_400, 0, 1, false);
+        let config = MatrixConfig {
+            debounce_key_press: Debounce::Defer {
+                duration: Duration::from_millis(5),
+            },
+            debounce_key_release: Debounce::Defer {
+                duration: Duration::from_millis(5),
+            },
+            ..Default::default()
+        };
+        let messages = scan(matrix, &config, 50_000);
+        assert_eq!(
+            messages
+                .iter()
+                .map(|(_, message)| *message)
+                .collect::<Vec<_>>(),
+            [
+                Message::Press { key_index: 1 },
+                Message::Release { key_index: 1 }
+            ]
+        );
+        // The changes are sent once the debounce duration has passed since the first change
+        assert!((6_000..7_000).contains(&messages[0].0));
+        assert!((25_000..26_000).contains(&messages[1].0));
+    }
+
+    #[test]
+    fn scan_with_eager_debounce() {
+        let matrix = MockMatrix::new()
+            .change(1_000, 1, 1, true)
+            .change(1_300, 1, 1, false)
+            .change(1_600, 1, 1, true)
+            .change(20_000, 1, 1, false);
+        let config = MatrixConfig {
+            debounce_key_press: Debounce::Eager {
+                duration: Duration::from_millis(5),
+            },
+            debounce_key_release: Debounce::Eager {
+                duration: Duration::from_millis(5),
+            },
+            ..Default::default()
+        };
+        let messages = scan(matrix, &config, 30_000);
+        assert_eq!(
+            messages
+                .iter()
+                .map(|(_, message)| *message)
+                .collect::<Vec<_>>(),
+            [
+                Message::Press { key_index: 3 },
+                Message::Release { key_index: 3 }
+            ]
+        );
+        // The changes are sent with the next scan after the scan that sees them
+        assert!((1_000..2_000).contains(&messages[0].0));
+        assert!((20_000..20_500).contains(&messages[1].0));
+    }
+
+    #[test]
+    fn snapshot_contains_unmapped_switches() {
+        let matrix = MockMatrix::<2, 2>::new()
+            .press(1, 0, 1_000, 50_000)
+            .press(0, 1, 1_000, 2_000);
+        let (input_switches, output_switches) = matrix.into_switches();
+        let mut matrix = Matrix::new::<1>(input_switches, output_switches);
+        // Only the switch at input pin 1 and output pin 0 is mapped to a key index.
+        let transform = [Some((1, 0))];
+        let (messages, snapshot) = run_matrix_scan_with_snapshot(
+            &mut matrix,
+            &transform,
+            &MatrixConfig::default(),
+            10_000,
+        );
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].1, Message::Press { key_index: 0 });
+        assert_eq!(snapshot, [[false, true], [false, false]]);
+
+        let matrix = MockMatrix::<2, 2>::new().press(0, 1, 1_000, 50_000);
+        let (input_switches, output_switches) = matrix.into_switches();
+        let mut matrix = Matrix::new::<1>(input_switches, output_switches);
+        let (messages, snapshot) = run_matrix_scan_with_snapshot(
+            &mut matrix,
+            &transform,
+            &MatrixConfig::default(),
+            10_000,
+        );
+        assert!(messages.is_empty());
+        assert_eq!(snapshot, [[false, false], [true, false]]);
+    }
+
+    #[test]
+    fn snapshot_chunks() {
+        let request_id = internal::RequestId {
+            address: lokey::Address([0; 6]),
+            counter: 0,
+        };
+        let mut states = [[false; 5]; 8];
+        states[0][1] = true;
+        states[6][2] = true;
+        states[7][4] = true;
+
+        let first = snapshot_chunk(&states, request_id, 0);
+        assert_eq!(first.num_chunks(), 2);
+        assert_eq!(first.bits, 0b10);
+        assert_eq!(first.is_active(1, 0), Some(true));
+        assert_eq!(first.is_active(2, 6), None);
+
+        // The switches at the indices 32 and 39 are the first and last switch of the second chunk.
+        let second = snapshot_chunk(&states, request_id, 1);
+        assert_eq!(second.bits, 0b1000_0001);
+        assert_eq!(second.is_active(2, 6), Some(true));
+        assert_eq!(second.is_active(4, 7), Some(true));
+        assert_eq!(second.is_active(1, 0), None);
+
+        assert_eq!(snapshot_chunk(&states, request_id, 2).bits, 0);
+    }
+
+    #[test]
+    fn ghost_key_is_suppressed() {
+        let mut inputs = [
+            [Some(true), Some(true), Some(false)],
+            [Some(true), Some(true), Some(false)],
+        ];
+        let previous_states = [[true, true, false], [true, false, false]];
+        suppress_ghost_keys(&mut inputs, &previous_states);
+        assert_eq!(
+            inputs,
+            [
+                [Some(true), Some(true), Some(false)],
+                [Some(true), Some(false), Some(false)],
+            ]
+        );
+    }
+}
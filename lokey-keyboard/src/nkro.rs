@@ -0,0 +1,78 @@
+use crate::{HidReportByte, KeyboardReport};
+
+/// The size of an NKRO keyboard report in bytes.
+pub(crate) const NKRO_REPORT_SIZE: usize = 29;
+
+/// The number of key usages that are covered by the bitmap of an NKRO keyboard report.
+const NKRO_KEY_USAGES: usize = (NKRO_REPORT_SIZE - 1) * 8;
+
+/// The HID report descriptor for an NKRO keyboard report.
+///
+/// The first byte of the report contains the modifiers, the remaining bytes are a bitmap with one
+/// bit per key usage from `0x00` to `0xdf`, so that any number of keys can be pressed at the same
+/// time. The output report contains the LED state of the host like the boot keyboard report.
+#[rustfmt::skip]
+pub(crate) const NKRO_REPORT_DESCRIPTOR: [u8; 45] = [
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x06, // Usage (Keyboard)
+    0xa1, 0x01, // Collection (Application)
+    0x05, 0x07, //   Usage Page (Keyboard/Keypad)
+    0x19, 0xe0, //   Usage Minimum (Left Control)
+    0x29, 0xe7, //   Usage Maximum (Right GUI)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x01, //   Logical Maximum (1)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x08, //   Report Count (8)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0x19, 0x00, //   Usage Minimum (0)
+    0x29, 0xdf, //   Usage Maximum (223)
+    0x95, 0xe0, //   Report Count (224)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0x05, 0x08, //   Usage Page (LEDs)
+    0x19, 0x01, //   Usage Minimum (Num Lock)
+    0x29, 0x05, //   Usage Maximum (Kana)
+    0x95, 0x05, //   Report Count (5)
+    0x91, 0x02, //   Output (Data, Variable, Absolute)
+    0x95, 0x03, //   Report Count (3)
+    0x91, 0x01, //   Output (Constant)
+    0xc0,       // End Collection
+];
+
+impl KeyboardReport {
+    /// Returns the bytes of the NKRO HID report.
+    ///
+    /// In contrast to the boot keyboard report, all pressed keys are reported regardless of the
+    /// [`RolloverPolicy`](crate::RolloverPolicy).
+    pub(crate) fn to_nkro_report(&self) -> [u8; NKRO_REPORT_SIZE] {
+        let mut bytes = [0; NKRO_REPORT_SIZE];
+        for key in self.keys {
+            match key.to_hid_report_byte() {
+                HidReportByte::Key(v) if (v as usize) < NKRO_KEY_USAGES => {
+                    bytes[1 + v as usize / 8] |= 1 << (v % 8);
+                }
+                HidReportByte::Key(_) => {}
+                HidReportByte::Modifier(v) => bytes[0] |= v,
+            }
+        }
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Key;
+
+    #[test]
+    fn nkro_report_contains_all_keys() {
+        let report = KeyboardReport {
+            keys: Key::A | Key::B | Key::C | Key::D | Key::E | Key::F | Key::G | Key::LShift,
+        };
+        let bytes = report.to_nkro_report();
+        assert_eq!(bytes[0], 0b0000_0010);
+        // A (0x04) to G (0x0a) are the bits 4 to 10 of the bitmap.
+        assert_eq!(bytes[1], 0b1111_0000);
+        assert_eq!(bytes[2], 0b0000_0111);
+        assert!(bytes[3..].iter().all(|v| *v == 0));
+    }
+}
@@ -0,0 +1,161 @@
+use crate::Message;
+use arrayvec::ArrayVec;
+use embassy_time::{Duration, Instant};
+
+/// The maximum number of key events that are held back by the [`ReorderBuffer`].
+const REORDER_BUFFER_SIZE: usize = 16;
+
+/// Duration after which the clock offset estimate is refreshed, so that drift between the clocks
+/// of the two devices does not accumulate.
+const CLOCK_OFFSET_WINDOW: Duration = Duration::from_secs(10);
+
+/// Estimates the offset between the local clock and the clock of another device from timestamped
+/// messages.
+///
+/// The offset is the minimum of the observed differences between the local receive time and the
+/// remote timestamp, i.e. the difference of the message that was delivered fastest. The delay of
+/// other messages relative to the fastest message is their additional transport latency.
+pub(crate) struct ClockOffset {
+    previous: Option<u32>,
+    current: Option<u32>,
+    window_start: Instant,
+}
+
+impl ClockOffset {
+    pub(crate) const fn new() -> Self {
+        Self {
+            previous: None,
+            current: None,
+            window_start: Instant::from_ticks(0),
+        }
+    }
+
+    fn min(a: Option<u32>, b: u32) -> u32 {
+        match a {
+            // The offsets wrap around, so they are compared by their difference.
+            Some(a) if (a.wrapping_sub(b) as i32) < 0 => a,
+            _ => b,
+        }
+    }
+
+    /// Returns the time at which an event with the specified remote timestamp (in milliseconds)
+    /// that was received at `now` happened in the local time base.
+    pub(crate) fn event_time(&mut self, timestamp_ms: u32, now: Instant) -> Instant {
+        if now.duration_since(self.window_start) >= CLOCK_OFFSET_WINDOW {
+            self.previous = self.current.take();
+            self.window_start = now;
+        }
+        let sample = (now.as_millis() as u32).wrapping_sub(timestamp_ms);
+        let current = Self::min(self.current, sample);
+        self.current = Some(current);
+        let offset = Self::min(self.previous, current);
+        let extra_latency = sample.wrapping_sub(offset) as i32;
+        if extra_latency <= 0 {
+            return now;
+        }
+        now.checked_sub(Duration::from_millis(extra_latency as u64))
+            .unwrap_or(now)
+    }
+}
+
+/// Buffer that holds key events back for a short window and releases them ordered by the time at
+/// which they happened.
+pub(crate) struct ReorderBuffer {
+    window: Duration,
+    events: ArrayVec<(Instant, Message), REORDER_BUFFER_SIZE>,
+    last_released: Option<Instant>,
+}
+
+impl ReorderBuffer {
+    pub(crate) const fn new(window: Duration) -> Self {
+        Self {
+            window,
+            events: ArrayVec::new_const(),
+            last_released: None,
+        }
+    }
+
+    /// Inserts an event that happened at the specified time.
+    ///
+    /// Returns the event that has to be released immediately because the buffer is full.
+    pub(crate) fn insert(&mut self, time: Instant, message: Message) -> Option<Message> {
+        // An event can not be ordered before events that were already released.
+        let time = match self.last_released {
+            Some(last_released) => time.max(last_released),
+            None => time,
+        };
+        let index = self.events.partition_point(|(v, _)| *v <= time);
+        let overflow = if self.events.is_full() {
+            self.events.pop_at(0).map(|(time, message)| {
+                self.last_released = Some(time);
+                message
+            })
+        } else {
+            None
+        };
+        let index = if overflow.is_some() {
+            index.saturating_sub(1)
+        } else {
+            index
+        };
+        self.events.insert(index, (time, message));
+        overflow
+    }
+
+    /// Returns the instant at which the next event has to be released.
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        self.events.first().map(|(time, _)| *time + self.window)
+    }
+
+    /// Removes and returns the next event if its window has elapsed at `now`.
+    pub(crate) fn pop_due(&mut self, now: Instant) -> Option<Message> {
+        match self.next_deadline() {
+            Some(deadline) if deadline <= now => {
+                let (time, message) = self.events.remove(0);
+                self.last_released = Some(time);
+                Some(message)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reorders_events_within_window() {
+        let mut buffer = ReorderBuffer::new(Duration::from_millis(10));
+        let press = |key_index| Message::Press { key_index };
+
+        // The event of the peripheral arrives later, but happened before the local event.
+        assert!(buffer.insert(Instant::from_millis(100), press(0)).is_none());
+        assert!(buffer.insert(Instant::from_millis(95), press(20)).is_none());
+
+        assert_eq!(buffer.next_deadline(), Some(Instant::from_millis(105)));
+        assert!(buffer.pop_due(Instant::from_millis(104)).is_none());
+        assert_eq!(buffer.pop_due(Instant::from_millis(110)), Some(press(20)));
+        assert_eq!(buffer.pop_due(Instant::from_millis(110)), Some(press(0)));
+        assert!(buffer.pop_due(Instant::from_millis(110)).is_none());
+
+        // Events that arrive too late are not ordered before released events.
+        assert!(buffer.insert(Instant::from_millis(90), press(21)).is_none());
+        assert_eq!(buffer.next_deadline(), Some(Instant::from_millis(110)));
+    }
+
+    #[test]
+    fn clock_offset_compensates_extra_latency() {
+        let mut clock_offset = ClockOffset::new();
+        // The remote clock is 1000ms behind the local clock, the first message arrives with the
+        // lowest latency.
+        let now = Instant::from_millis(2000);
+        assert_eq!(clock_offset.event_time(1000, now), now);
+        // This message took 8ms longer to arrive.
+        let now = Instant::from_millis(2108);
+        assert_eq!(
+            clock_offset.event_time(1100, now),
+            Instant::from_millis(2100)
+        );
+    }
+}
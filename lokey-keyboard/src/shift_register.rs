@@ -0,0 +1,247 @@
+use super::ScannerDriver;
+use crate::DynContext;
+use crate::matrix::{MatrixConfig, MatrixPins, run_matrix, scan_matrix_once};
+use embassy_time::{Duration, Timer, block_for};
+use embedded_hal::digital::{InputPin, OutputPin};
+use lokey::util::error;
+
+/// The pins that are connected to a chain of 74HC595 shift registers, whose outputs are connected
+/// to the outputs of the matrix.
+pub struct Hc595Pins<O> {
+    /// The serial data input (`SER`).
+    pub data: O,
+    /// The shift register clock (`SRCLK`).
+    pub shift_clock: O,
+    /// The storage register clock (`RCLK`), which latches the shifted bits to the outputs.
+    pub latch_clock: O,
+}
+
+/// The pins that are connected to a chain of 74HC165 shift registers, whose parallel inputs are
+/// connected to the inputs of the matrix.
+pub struct Hc165Pins<O, I> {
+    /// The shift/load input (`SH/LD`), which loads the parallel inputs while it is low.
+    pub load: O,
+    /// The clock input (`CLK`).
+    pub clock: O,
+    /// The serial output (`QH`) of the last shift register of the chain.
+    pub data: I,
+}
+
+/// Scanner for keys that are arranged in a keyboard matrix, whose outputs are driven by 74HC595
+/// shift registers and whose inputs are read by 74HC165 shift registers.
+///
+/// The output with index `n` is the output `n` of the 74HC595 chain, counted from the output `QA`
+/// of the shift register that is connected to the microcontroller. An output is activated by
+/// driving it high. The input with index `n` is the `n`th bit that is shifted out of the 74HC165
+/// chain after loading the parallel inputs. An input is active if it is high, so the inputs of the
+/// matrix need pull-down resistors.
+///
+/// The debouncing and the [`MatrixConfig`] are the same as for the [`Matrix`](crate::Matrix)
+/// scanner.
+pub struct ShiftRegisterMatrix<
+    O,
+    I,
+    const NUM_IS: usize,
+    const NUM_OS: usize,
+    const NUM_KEYS: usize,
+> {
+    outputs: Hc595Pins<O>,
+    inputs: Hc165Pins<O, I>,
+    transform: [Option<(usize, usize)>; NUM_KEYS],
+    clock_delay: Duration,
+    settle_delay: Duration,
+    poll_interval: Duration,
+}
+
+impl<O, I, const NUM_IS: usize, const NUM_OS: usize> ShiftRegisterMatrix<O, I, NUM_IS, NUM_OS, 0> {
+    pub const fn new<const NUM_KEYS: usize>(
+        outputs: Hc595Pins<O>,
+        inputs: Hc165Pins<O, I>,
+    ) -> ShiftRegisterMatrix<O, I, NUM_IS, NUM_OS, NUM_KEYS> {
+        ShiftRegisterMatrix {
+            outputs,
+            inputs,
+            transform: [None; NUM_KEYS],
+            clock_delay: Duration::from_ticks(0),
+            settle_delay: Duration::from_ticks(1),
+            poll_interval: Duration::from_millis(1),
+        }
+    }
+}
+
+impl<O, I, const NUM_IS: usize, const NUM_OS: usize, const NUM_KEYS: usize>
+    ShiftRegisterMatrix<O, I, NUM_IS, NUM_OS, NUM_KEYS>
+{
+    /// Sets the time that is waited between two edges of the clock and data signals.
+    ///
+    /// The delay is busy-waited, so it should only be used if the shift registers can not keep up
+    /// with the speed at which the pins are toggled. Defaults to zero.
+    pub const fn clock_delay(mut self, value: Duration) -> Self {
+        self.clock_delay = value;
+        self
+    }
+
+    /// Sets the time that is waited after an output of the matrix was latched before the inputs are
+    /// loaded, so that the output is stable before the inputs are sampled.
+    ///
+    /// Defaults to a single tick of the time driver.
+    pub const fn settle_delay(mut self, value: Duration) -> Self {
+        self.settle_delay = value;
+        self
+    }
+
+    /// Sets the interval in which the inputs are polled while no key is pressed.
+    ///
+    /// Defaults to 1ms.
+    pub const fn poll_interval(mut self, value: Duration) -> Self {
+        self.poll_interval = value;
+        self
+    }
+
+    pub const fn map<const I_INDEX: usize, const O_INDEX: usize, const KEY_INDEX: usize>(
+        mut self,
+    ) -> Self {
+        self.transform[KEY_INDEX] = Some((I_INDEX, O_INDEX));
+        self
+    }
+
+    pub const fn map_next<const I_INDEX: usize, const O_INDEX: usize>(mut self) -> Self {
+        // Use while loops so that the function can be `const`
+        let mut i = 0;
+        while i < self.transform.len() {
+            if self.transform[i].is_none() {
+                self.transform[i] = Some((I_INDEX, O_INDEX));
+                break;
+            }
+            i += 1;
+        }
+        self
+    }
+
+    pub const fn map_rows_and_cols<const NUM_ROWS: usize, const NUM_COLS: usize>(
+        mut self,
+        input_indices: [usize; NUM_ROWS],
+        output_indices: [usize; NUM_COLS],
+        mut start_key_index: usize,
+    ) -> Self {
+        // Use while loops so that the function can be `const`
+        let mut i = 0;
+        while i < input_indices.len() {
+            let input_index = input_indices[i];
+            let mut j = 0;
+            while j < output_indices.len() {
+                let output_index = output_indices[j];
+                self.transform[start_key_index] = Some((input_index, output_index));
+                start_key_index += 1;
+                j += 1;
+            }
+            i += 1;
+        }
+        self
+    }
+}
+
+impl<O: OutputPin, I: InputPin, const NUM_IS: usize, const NUM_OS: usize, const NUM_KEYS: usize>
+    ShiftRegisterMatrix<O, I, NUM_IS, NUM_OS, NUM_KEYS>
+{
+    fn wait_clock_delay(&self) {
+        if self.clock_delay > Duration::from_ticks(0) {
+            block_for(self.clock_delay);
+        }
+    }
+
+    /// Shifts the states of the outputs into the 74HC595 chain and latches them.
+    ///
+    /// Returns `Err` if a pin could not be set.
+    async fn write_outputs(&mut self, is_active: impl Fn(usize) -> bool) -> Result<(), O::Error> {
+        // The first bit that is shifted in ends up at the output that is farthest away from the
+        // microcontroller.
+        for i in (0..NUM_OS).rev() {
+            self.outputs.data.set_state(is_active(i).into())?;
+            self.wait_clock_delay();
+            self.outputs.shift_clock.set_high()?;
+            self.wait_clock_delay();
+            self.outputs.shift_clock.set_low()?;
+        }
+        self.outputs.latch_clock.set_high()?;
+        self.wait_clock_delay();
+        self.outputs.latch_clock.set_low()?;
+        // Wait until the latched output propagated through the matrix.
+        Timer::after(self.settle_delay).await;
+        Ok(())
+    }
+
+    /// Loads the parallel inputs of the 74HC165 chain and shifts them out.
+    ///
+    /// Returns `None` if a pin could not be read or set.
+    fn read_all_inputs(&mut self) -> Option<[bool; NUM_IS]> {
+        self.inputs.load.set_low().ok()?;
+        self.wait_clock_delay();
+        self.inputs.load.set_high().ok()?;
+        self.wait_clock_delay();
+        let mut inputs = [false; NUM_IS];
+        for input in &mut inputs {
+            *input = self.inputs.data.is_high().ok()?;
+            self.inputs.clock.set_high().ok()?;
+            self.wait_clock_delay();
+            self.inputs.clock.set_low().ok()?;
+            self.wait_clock_delay();
+        }
+        Some(inputs)
+    }
+}
+
+impl<O: OutputPin, I: InputPin, const NUM_IS: usize, const NUM_OS: usize, const NUM_KEYS: usize>
+    MatrixPins<NUM_IS, NUM_OS> for ShiftRegisterMatrix<O, I, NUM_IS, NUM_OS, NUM_KEYS>
+{
+    async fn wait_for_any_active(&mut self) {
+        if self.write_outputs(|_| true).await.is_err() {
+            error!("failed to turn output pins on");
+        }
+        loop {
+            match self.read_all_inputs() {
+                Some(inputs) if inputs.contains(&true) => break,
+                Some(_) => {}
+                None => error!("failed to read shift register"),
+            }
+            Timer::after(self.poll_interval).await;
+        }
+        if self.write_outputs(|_| false).await.is_err() {
+            error!("failed to turn output pins off");
+        }
+    }
+
+    async fn read_inputs(&mut self, output_index: usize) -> [Option<bool>; NUM_IS] {
+        if self.write_outputs(|i| i == output_index).await.is_err() {
+            error!("failed to turn output pin on");
+            return [None; NUM_IS];
+        }
+        let inputs = match self.read_all_inputs() {
+            Some(inputs) => inputs.map(Some),
+            None => {
+                error!("failed to read shift register");
+                [None; NUM_IS]
+            }
+        };
+        if self.write_outputs(|_| false).await.is_err() {
+            error!("failed to turn output pin off");
+        }
+        inputs
+    }
+}
+
+impl<O: OutputPin, I: InputPin, const NUM_IS: usize, const NUM_OS: usize, const NUM_KEYS: usize>
+    ScannerDriver<NUM_KEYS> for ShiftRegisterMatrix<O, I, NUM_IS, NUM_OS, NUM_KEYS>
+{
+    type Config = MatrixConfig;
+
+    async fn scan_once(&mut self, config: &Self::Config) -> [bool; NUM_KEYS] {
+        let transform = self.transform;
+        scan_matrix_once(self, &transform, config).await
+    }
+
+    async fn run(mut self, config: Self::Config, key_offset: u16, context: DynContext) {
+        let transform = self.transform;
+        run_matrix(&mut self, &transform, &config, key_offset, context).await;
+    }
+}
@@ -0,0 +1,134 @@
+use crate::{KeyEvents, Message};
+use embassy_futures::select::{Either, select};
+use embassy_time::{Duration, Timer};
+use lokey::util::{info, unwrap};
+use lokey::{AnyState, Component, Context, Device, SystemOff, Transports};
+
+/// The time that the external transport gets to disconnect from the host before the MCU is turned
+/// off.
+const DISCONNECT_DELAY: Duration = Duration::from_millis(100);
+
+/// Component that turns the MCU off after no key was pressed or released for the configured
+/// timeout.
+///
+/// The MCU is only turned off while no key is held. Before the MCU is turned off, the external
+/// transport is deactivated, so that e.g. the BLE transport disconnects from the host instead of
+/// letting the connection time out. Waking up resets the MCU, after which the transports connect
+/// again as usual.
+///
+/// The wake-up sources have to be configured by the device in the closure that is passed to
+/// [`run`](Self::run). If no wake-up source is configured, the MCU can only be woken up by a reset
+/// (e.g. with the reset button or by power cycling it). For a keyboard matrix, this usually means
+/// activating all outputs and enabling the wake-up on the inputs (e.g. with
+/// `lokey_nrf::enable_wake_up`, which uses the GPIO sense mechanism of nRF microcontrollers), so
+/// that any key press wakes the MCU up. The key that woke the MCU up is
+/// still held when the scanner starts, so the key press is not lost as long as the startup is
+/// shorter than the key press. For this reason, the
+/// [`startup_suppression`](crate::Layout::startup_suppression) of the layout should not be longer
+/// than necessary.
+///
+/// On split keyboards, the key events of the peripheral are forwarded to the central, so the
+/// central only turns off if both halves are idle, while the peripheral only considers its own
+/// keys.
+///
+/// # Example
+///
+/// ```ignore
+/// use embassy_nrf::gpio::{Level, Pull};
+/// use embassy_nrf::peripherals::{P0_02, P0_03};
+/// use lokey::{AnyState, ComponentSupport, Context, Transports};
+/// use lokey_keyboard::IdleSleep;
+///
+/// impl<S: AnyState> ComponentSupport<IdleSleep, S> for MyDevice {
+///     async fn enable<T>(component: IdleSleep, context: Context<Self, T, S>)
+///     where
+///         T: Transports<Self::Mcu>,
+///     {
+///         component
+///             .run(context, || {
+///                 // The keys are connected directly to the pins and pull them to ground
+///                 lokey_nrf::enable_wake_up(unsafe { P0_02::steal() }, Pull::Up, Level::Low);
+///                 lokey_nrf::enable_wake_up(unsafe { P0_03::steal() }, Pull::Up, Level::Low);
+///             })
+///             .await;
+///     }
+/// }
+/// ```
+pub struct IdleSleep {
+    timeout: Duration,
+}
+
+impl Component for IdleSleep {}
+
+impl IdleSleep {
+    /// Creates a new [`IdleSleep`] that turns the MCU off after no key event happened for the
+    /// specified duration.
+    pub const fn new(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+
+    /// Runs the component.
+    ///
+    /// `prepare_wake_up` is called right before the MCU is turned off and has to configure the
+    /// wake-up sources.
+    pub async fn run<D, T, S>(self, context: Context<D, T, S>, prepare_wake_up: impl FnOnce())
+    where
+        D: Device,
+        D::Mcu: SystemOff,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let mut key_events = unwrap!(KeyEvents::subscribe(context.as_dyn()));
+        let mut held_keys = HeldKeys::default();
+        loop {
+            match select(key_events.next(), Timer::after(self.timeout)).await {
+                Either::First(message) => held_keys.update(message),
+                Either::Second(()) if held_keys.any() => {}
+                Either::Second(()) => break,
+            }
+        }
+
+        info!("No key activity, turning MCU off");
+        if context.external_channel.set_active(false).await {
+            Timer::after(DISCONNECT_DELAY).await;
+        }
+        prepare_wake_up();
+        context.mcu.system_off()
+    }
+}
+
+/// Counts the keys that are currently held.
+#[derive(Default)]
+struct HeldKeys(u16);
+
+impl HeldKeys {
+    fn update(&mut self, message: Message) {
+        self.0 = match message {
+            Message::Press { .. } => self.0.saturating_add(1),
+            Message::Release { .. } => self.0.saturating_sub(1),
+        };
+    }
+
+    fn any(&self) -> bool {
+        self.0 > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn held_keys() {
+        let mut held_keys = HeldKeys::default();
+        // A release whose press happened before the component started
+        held_keys.update(Message::Release { key_index: 0 });
+        assert!(!held_keys.any());
+        held_keys.update(Message::Press { key_index: 1 });
+        held_keys.update(Message::Press { key_index: 2 });
+        held_keys.update(Message::Release { key_index: 1 });
+        assert!(held_keys.any());
+        held_keys.update(Message::Release { key_index: 2 });
+        assert!(!held_keys.any());
+    }
+}
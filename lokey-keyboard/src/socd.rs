@@ -0,0 +1,140 @@
+use super::{Key, KeyboardReport};
+use crate::KeySet;
+use lokey::external::{MessageSender, Override};
+
+/// Specifies which key is sent when both keys of a [`SocdPair`] are pressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SocdResolution {
+    /// Only the key that was pressed last is sent.
+    ///
+    /// When the last pressed key is released while the other key is still held, the other key is
+    /// sent again.
+    LastInputPriority,
+    /// Neither key is sent.
+    Neutral,
+}
+
+/// A pair of opposing keys (e.g. left and right) for the [`Socd`] override.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SocdPair {
+    first: Key,
+    second: Key,
+    resolution: SocdResolution,
+}
+
+impl SocdPair {
+    pub const fn new(first: Key, second: Key, resolution: SocdResolution) -> Self {
+        Self {
+            first,
+            second,
+            resolution,
+        }
+    }
+}
+
+/// Override that resolves simultaneous opposing cardinal directions (SOCD).
+///
+/// For each configured pair of keys, the keyboard reports never contain both keys at the same
+/// time. Which key is sent instead is determined by the [`SocdResolution`] of the pair.
+///
+/// # Example
+///
+/// ```no_run
+/// use lokey_keyboard::{Key, Socd, SocdPair, SocdResolution};
+///
+/// let socd = Socd::new([
+///     SocdPair::new(Key::A, Key::D, SocdResolution::LastInputPriority),
+///     SocdPair::new(Key::W, Key::S, SocdResolution::Neutral),
+/// ]);
+/// ```
+pub struct Socd<const NUM_PAIRS: usize> {
+    pairs: [SocdPair; NUM_PAIRS],
+    last_pressed: [Option<Key>; NUM_PAIRS],
+    previous_keys: KeySet,
+}
+
+impl<const NUM_PAIRS: usize> Socd<NUM_PAIRS> {
+    pub fn new(pairs: [SocdPair; NUM_PAIRS]) -> Self {
+        Self {
+            pairs,
+            last_pressed: [None; NUM_PAIRS],
+            previous_keys: KeySet::new(),
+        }
+    }
+
+    fn resolve(&mut self, keys: KeySet) -> KeySet {
+        let newly_pressed = keys.difference(self.previous_keys);
+        self.previous_keys = keys;
+
+        let mut resolved_keys = keys;
+        for (pair, last_pressed) in self.pairs.iter().zip(&mut self.last_pressed) {
+            if newly_pressed.contains(pair.first) {
+                *last_pressed = Some(pair.first);
+            } else if newly_pressed.contains(pair.second) {
+                *last_pressed = Some(pair.second);
+            }
+            if !keys.contains(pair.first) || !keys.contains(pair.second) {
+                continue;
+            }
+            match pair.resolution {
+                SocdResolution::LastInputPriority => {
+                    if *last_pressed == Some(pair.first) {
+                        resolved_keys.remove(pair.second);
+                    } else {
+                        resolved_keys.remove(pair.first);
+                    }
+                }
+                SocdResolution::Neutral => {
+                    resolved_keys.remove(pair.first);
+                    resolved_keys.remove(pair.second);
+                }
+            }
+        }
+        resolved_keys
+    }
+}
+
+impl<const NUM_PAIRS: usize> Override for Socd<NUM_PAIRS> {
+    type TxMessage = KeyboardReport;
+
+    async fn override_message(
+        &mut self,
+        message: Self::TxMessage,
+        sender: &MessageSender<Self::TxMessage>,
+    ) {
+        let mut new_keyboard_report = message.clone();
+        new_keyboard_report.keys = self.resolve(message.keys);
+        sender.send(new_keyboard_report).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_input_priority() {
+        let mut socd = Socd::new([SocdPair::new(
+            Key::A,
+            Key::D,
+            SocdResolution::LastInputPriority,
+        )]);
+        assert_eq!(socd.resolve(KeySet::from(Key::A)), KeySet::from(Key::A));
+        assert_eq!(socd.resolve(Key::A | Key::D), KeySet::from(Key::D));
+        assert_eq!(socd.resolve(KeySet::from(Key::A)), KeySet::from(Key::A));
+        assert_eq!(socd.resolve(Key::A | Key::D), KeySet::from(Key::D));
+        assert_eq!(socd.resolve(KeySet::from(Key::D)), KeySet::from(Key::D));
+        assert_eq!(socd.resolve(Key::A | Key::D), KeySet::from(Key::A));
+        assert_eq!(socd.resolve(Key::A | Key::D | Key::B), Key::A | Key::B);
+    }
+
+    #[test]
+    fn neutral() {
+        let mut socd = Socd::new([SocdPair::new(Key::W, Key::S, SocdResolution::Neutral)]);
+        assert_eq!(socd.resolve(KeySet::from(Key::W)), KeySet::from(Key::W));
+        assert_eq!(socd.resolve(Key::W | Key::S), KeySet::new());
+        assert_eq!(socd.resolve(KeySet::from(Key::S)), KeySet::from(Key::S));
+    }
+}
@@ -0,0 +1,76 @@
+use lokey::external;
+
+/// A system control usage of the generic desktop page of the HID usage tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SystemControlUsage {
+    PowerDown,
+    Sleep,
+    WakeUp,
+}
+
+impl SystemControlUsage {
+    /// Returns the usage ID of the generic desktop page.
+    pub const fn usage_id(self) -> u8 {
+        match self {
+            Self::PowerDown => 0x81,
+            Self::Sleep => 0x82,
+            Self::WakeUp => 0x83,
+        }
+    }
+}
+
+/// The size of the HID report for a [`SystemControlReport`] in bytes.
+#[cfg(any(feature = "usb", feature = "ble"))]
+pub(crate) const SYSTEM_CONTROL_REPORT_SIZE: usize = 1;
+
+/// The report ID of a [`SystemControlReport`], as declared in
+/// [`SYSTEM_CONTROL_REPORT_DESCRIPTOR`].
+#[cfg(any(feature = "usb", feature = "ble"))]
+pub(crate) const SYSTEM_CONTROL_REPORT_ID: u8 = 2;
+
+/// The HID report descriptor for a [`SystemControlReport`].
+///
+/// The report is a single array item that contains the index of the pressed usage, starting at 1
+/// for [`SystemControlUsage::PowerDown`], or 0 if no usage is pressed.
+#[cfg(any(feature = "usb", feature = "ble"))]
+#[rustfmt::skip]
+pub(crate) const SYSTEM_CONTROL_REPORT_DESCRIPTOR: [u8; 23] = [
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x80, // Usage (System Control)
+    0xa1, 0x01, // Collection (Application)
+    0x85, 0x02, //   Report ID (2)
+    0x19, 0x81, //   Usage Minimum (System Power Down)
+    0x29, 0x83, //   Usage Maximum (System Wake Up)
+    0x15, 0x01, //   Logical Minimum (1)
+    0x25, 0x03, //   Logical Maximum (3)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x01, //   Report Count (1)
+    0x81, 0x00, //   Input (Data, Array, Absolute)
+    0xc0,       // End Collection
+];
+
+/// The report sent by the device to represent the pressed system control key.
+///
+/// The system control usages are mutually exclusive, so at most one usage can be pressed at a
+/// time.
+#[derive(Debug, Clone, Default, PartialEq, Eq, external::Message)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SystemControlReport {
+    pub usage: Option<SystemControlUsage>,
+}
+
+impl SystemControlReport {
+    pub const fn empty() -> Self {
+        Self { usage: None }
+    }
+
+    /// Returns the bytes of the HID report.
+    #[cfg(any(feature = "usb", feature = "ble"))]
+    pub(crate) fn to_hid_report(&self) -> [u8; SYSTEM_CONTROL_REPORT_SIZE] {
+        match self.usage {
+            Some(usage) => [usage.usage_id() - 0x80],
+            None => [0],
+        }
+    }
+}
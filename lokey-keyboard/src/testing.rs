@@ -0,0 +1,478 @@
+//! Utilities for testing scanners and layouts without hardware.
+//!
+//! The scanners and layouts are driven with virtual time (using the mock driver of
+//! [`embassy_time`]), so that the results of a test only depend on the scripted switch states and
+//! key events.
+
+extern crate std;
+
+use crate::action::{Action, ActionContainer};
+use crate::matrix::{MatrixConfig, MatrixPins, key_indices, scan_matrix};
+use crate::switch::{InputSwitch, OutputSwitch, WaitableInputSwitch};
+use crate::{
+    ComboContainer, KeyboardReport, KeyboardReportState, Layout, Message, TappingTermState,
+};
+use core::cell::{Cell, RefCell};
+use core::convert::Infallible;
+use core::future::poll_fn;
+use core::pin::pin;
+use core::task::{Context, Poll};
+use embassy_futures::block_on;
+use embassy_futures::join::join4;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_time::{Duration, Instant, MockDriver, Timer};
+use lokey::external::{IdentityOverride, NoMessage, Transport as _};
+use lokey::internal::Transport as _;
+use lokey::storage::{EmptyStorage, EmptyStorageDriver};
+use lokey::{Address, AnyState, Device, DummyMcu, State, Transports, external, internal};
+use lokey_layer::LayerManager;
+use noop_waker::noop_waker;
+use std::boxed::Box;
+use std::sync::{Mutex as StdMutex, MutexGuard};
+use std::vec::Vec;
+
+/// The amount of virtual time that passes every time the scanner or layout has to wait.
+const STEP: Duration = Duration::from_micros(100);
+
+/// Lock that prevents tests from changing the virtual time or the global state of the layout
+/// (e.g. the deferred key events) at the same time.
+static TIME_LOCK: StdMutex<()> = StdMutex::new(());
+
+/// Acquires the lock for tests that use the global state of the layout without running a layout.
+pub(crate) fn lock() -> MutexGuard<'static, ()> {
+    TIME_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A change of the state of a switch in a [`MockMatrix`].
+struct SwitchChange {
+    at: Instant,
+    input: usize,
+    output: usize,
+    active: bool,
+}
+
+/// A keyboard matrix whose switches change their state according to a script.
+///
+/// An input switch is active if an output switch is on whose switch at the input is active, so the
+/// switches behave like a matrix with diodes.
+pub(crate) struct MockMatrix<const NUM_IS: usize, const NUM_OS: usize> {
+    changes: Vec<SwitchChange>,
+    outputs: Cell<[bool; NUM_OS]>,
+}
+
+impl<const NUM_IS: usize, const NUM_OS: usize> MockMatrix<NUM_IS, NUM_OS> {
+    /// Creates a new [`MockMatrix`] where all switches are inactive.
+    pub(crate) fn new() -> Self {
+        Self {
+            changes: Vec::new(),
+            outputs: Cell::new([false; NUM_OS]),
+        }
+    }
+
+    /// Changes the state of the switch at the specified input and output pin index at the
+    /// specified time (in microseconds).
+    ///
+    /// The changes of a switch have to be added in chronological order.
+    pub(crate) fn change(mut self, at_us: u64, input: usize, output: usize, active: bool) -> Self {
+        self.changes.push(SwitchChange {
+            at: Instant::from_micros(at_us),
+            input,
+            output,
+            active,
+        });
+        self
+    }
+
+    /// Activates the switch at the specified input and output pin index at the time `from_us` and
+    /// deactivates it again at the time `to_us` (in microseconds).
+    pub(crate) fn press(self, input: usize, output: usize, from_us: u64, to_us: u64) -> Self {
+        self.change(from_us, input, output, true)
+            .change(to_us, input, output, false)
+    }
+
+    fn is_switch_active(&self, input: usize, output: usize, now: Instant) -> bool {
+        self.changes
+            .iter()
+            .rev()
+            .find(|change| change.input == input && change.output == output && change.at <= now)
+            .is_some_and(|change| change.active)
+    }
+
+    fn is_input_active(&self, input: usize) -> bool {
+        let now = Instant::now();
+        let outputs = self.outputs.get();
+        (0..NUM_OS).any(|output| outputs[output] && self.is_switch_active(input, output, now))
+    }
+
+    /// Returns the input and output switches of the matrix.
+    pub(crate) fn into_switches(
+        self,
+    ) -> (
+        [MockInputSwitch<NUM_IS, NUM_OS>; NUM_IS],
+        [MockOutputSwitch<NUM_IS, NUM_OS>; NUM_OS],
+    ) {
+        let matrix = Box::leak(Box::new(self));
+        (
+            core::array::from_fn(|index| MockInputSwitch { matrix, index }),
+            core::array::from_fn(|index| MockOutputSwitch { matrix, index }),
+        )
+    }
+}
+
+/// An input switch of a [`MockMatrix`].
+pub(crate) struct MockInputSwitch<const NUM_IS: usize, const NUM_OS: usize> {
+    matrix: &'static MockMatrix<NUM_IS, NUM_OS>,
+    index: usize,
+}
+
+impl<const NUM_IS: usize, const NUM_OS: usize> MockInputSwitch<NUM_IS, NUM_OS> {
+    async fn wait_for_state(&self, active: bool) {
+        poll_fn(|_| {
+            if self.matrix.is_input_active(self.index) == active {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+impl<const NUM_IS: usize, const NUM_OS: usize> InputSwitch for MockInputSwitch<NUM_IS, NUM_OS> {
+    type Error = Infallible;
+
+    fn is_active(&self) -> Result<bool, Self::Error> {
+        Ok(self.matrix.is_input_active(self.index))
+    }
+}
+
+impl<const NUM_IS: usize, const NUM_OS: usize> WaitableInputSwitch
+    for MockInputSwitch<NUM_IS, NUM_OS>
+{
+    type Error = Infallible;
+
+    async fn wait_for_active(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_state(true).await;
+        Ok(())
+    }
+
+    async fn wait_for_inactive(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_state(false).await;
+        Ok(())
+    }
+
+    async fn wait_for_change(&mut self) -> Result<(), Self::Error> {
+        let is_active = self.matrix.is_input_active(self.index);
+        self.wait_for_state(!is_active).await;
+        Ok(())
+    }
+}
+
+/// An output switch of a [`MockMatrix`].
+pub(crate) struct MockOutputSwitch<const NUM_IS: usize, const NUM_OS: usize> {
+    matrix: &'static MockMatrix<NUM_IS, NUM_OS>,
+    index: usize,
+}
+
+impl<const NUM_IS: usize, const NUM_OS: usize> MockOutputSwitch<NUM_IS, NUM_OS> {
+    fn set(&self, on: bool) {
+        let mut outputs = self.matrix.outputs.get();
+        outputs[self.index] = on;
+        self.matrix.outputs.set(outputs);
+    }
+}
+
+impl<const NUM_IS: usize, const NUM_OS: usize> OutputSwitch for MockOutputSwitch<NUM_IS, NUM_OS> {
+    type Error = Infallible;
+
+    fn on(&mut self) -> Result<(), Self::Error> {
+        self.set(true);
+        Ok(())
+    }
+
+    fn off(&mut self) -> Result<(), Self::Error> {
+        self.set(false);
+        Ok(())
+    }
+}
+
+/// Runs the scan loop of a matrix scanner with virtual time and returns the sent messages
+/// together with the time at which they were sent.
+///
+/// The virtual time starts at zero and is advanced by a fixed step every time the scanner waits,
+/// until the specified end time (in microseconds) is reached.
+pub(crate) fn run_matrix_scan<
+    P: MatrixPins<NUM_IS, NUM_OS>,
+    const NUM_IS: usize,
+    const NUM_OS: usize,
+    const NUM_KEYS: usize,
+>(
+    pins: &mut P,
+    transform: &[Option<(usize, usize)>; NUM_KEYS],
+    config: &MatrixConfig,
+    end_us: u64,
+) -> Vec<(Instant, Message)> {
+    run_matrix_scan_with_snapshot(pins, transform, config, end_us).0
+}
+
+/// Like [`run_matrix_scan`], but also returns the switch states of the last matrix snapshot,
+/// indexed by the output and input pin index.
+pub(crate) fn run_matrix_scan_with_snapshot<
+    P: MatrixPins<NUM_IS, NUM_OS>,
+    const NUM_IS: usize,
+    const NUM_OS: usize,
+    const NUM_KEYS: usize,
+>(
+    pins: &mut P,
+    transform: &[Option<(usize, usize)>; NUM_KEYS],
+    config: &MatrixConfig,
+    end_us: u64,
+) -> (Vec<(Instant, Message)>, [[bool; NUM_IS]; NUM_OS]) {
+    let _guard = lock();
+    let driver = MockDriver::get();
+    driver.reset();
+
+    let messages = RefCell::new(Vec::new());
+    let key_indices = key_indices(transform);
+    let snapshot = Mutex::<CriticalSectionRawMutex, _>::new(Cell::new([[false; NUM_IS]; NUM_OS]));
+    #[cfg(feature = "chatter-diagnostics")]
+    let chatter_counts = Mutex::<CriticalSectionRawMutex, _>::new(Cell::new([0u16; NUM_KEYS]));
+    {
+        let mut scan = pin!(scan_matrix::<_, NUM_IS, NUM_OS, NUM_KEYS>(
+            pins,
+            &key_indices,
+            config,
+            &snapshot,
+            #[cfg(feature = "chatter-diagnostics")]
+            &chatter_counts,
+            async |message: Message| messages.borrow_mut().push((Instant::now(), message)),
+        ));
+
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        while Instant::now() < Instant::from_micros(end_us) {
+            if scan.as_mut().poll(&mut cx).is_ready() {
+                break;
+            }
+            driver.advance(STEP);
+        }
+    }
+    (messages.into_inner(), snapshot.lock(|v| v.get()))
+}
+
+/// The device that the layouts of the tests run on.
+pub(crate) struct TestDevice;
+
+impl Device for TestDevice {
+    type Mcu = DummyMcu;
+    type StorageDriver = EmptyStorageDriver<DummyMcu>;
+    const DEFAULT_ADDRESS: Address = Address([0; 6]);
+}
+
+/// The messages that are sent by the [`ReportTransport`].
+#[derive(Debug, Clone, external::Message)]
+pub(crate) enum TestTxMessage {
+    KeyboardReport(KeyboardReport),
+}
+
+/// An external transport that accepts keyboard reports but does not send them anywhere.
+///
+/// The reports can still be observed on the external channel, which some actions (e.g.
+/// [`OneShot`](crate::action::OneShot)) rely on.
+pub(crate) struct ReportTransport;
+
+impl external::Transport for ReportTransport {
+    type Config = ();
+    type Mcu = DummyMcu;
+    type TxMessage = TestTxMessage;
+    type RxMessage = NoMessage;
+
+    async fn create<T>(
+        _: Self::Config,
+        _: &'static Self::Mcu,
+        _: Address,
+        _: &'static internal::Channel<T>,
+    ) -> Self
+    where
+        T: internal::Transport<Mcu = Self::Mcu>,
+    {
+        Self
+    }
+
+    async fn run<Storage>(&self, _: &'static Storage)
+    where
+        Storage: lokey::storage::Storage,
+    {
+    }
+
+    async fn send(&self, _: Self::TxMessage) {}
+
+    async fn receive(&self) -> Self::RxMessage {
+        core::future::pending().await
+    }
+}
+
+/// The transports of [`TestDevice`], which do not send the messages anywhere.
+pub(crate) struct TestTransports;
+
+impl Transports<DummyMcu> for TestTransports {
+    type ExternalTransport = ReportTransport;
+    type InternalTransport = internal::empty::Transport<DummyMcu>;
+
+    fn external_transport_config() {}
+
+    fn internal_transport_config() -> internal::empty::TransportConfig {
+        internal::empty::TransportConfig
+    }
+}
+
+/// The state of the layouts of the tests.
+#[derive(Default, State)]
+pub(crate) struct TestState {
+    #[state(query)]
+    pub(crate) layer_manager: LayerManager<0>,
+    pub(crate) tapping_term: TappingTermState,
+    pub(crate) keyboard_report: KeyboardReportState,
+}
+
+/// A press or release of a [`Record`] action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Recorded {
+    pub(crate) at: Instant,
+    pub(crate) name: &'static str,
+    pub(crate) pressed: bool,
+}
+
+static RECORDED: StdMutex<Vec<Recorded>> = StdMutex::new(Vec::new());
+
+/// An action that records when it is pressed and released.
+pub(crate) struct Record(pub(crate) &'static str);
+
+impl Record {
+    fn record(&self, pressed: bool) {
+        RECORDED.lock().unwrap().push(Recorded {
+            at: Instant::now(),
+            name: self.0,
+            pressed,
+        });
+    }
+}
+
+impl Action for Record {
+    async fn on_press<D, T, S>(&self, _: lokey::Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        self.record(true);
+    }
+
+    async fn on_release<D, T, S>(&self, _: lokey::Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        self.record(false);
+    }
+}
+
+/// An action that takes one millisecond to press the wrapped action, like an action that has to
+/// wait for a report to be sent.
+pub(crate) struct Slow<A>(pub(crate) A);
+
+impl<A: Action> Action for Slow<A> {
+    async fn on_press<D, T, S>(&self, context: lokey::Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        Timer::after_millis(1).await;
+        self.0.on_press(context).await;
+    }
+
+    async fn on_release<D, T, S>(&self, context: lokey::Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        self.0.on_release(context).await;
+    }
+}
+
+/// Returns a message that presses the key with the specified index at the specified time (in
+/// microseconds).
+pub(crate) fn press(at_us: u64, key_index: u16) -> (u64, Message) {
+    (at_us, Message::Press { key_index })
+}
+
+/// Returns a message that releases the key with the specified index at the specified time (in
+/// microseconds).
+pub(crate) fn release(at_us: u64, key_index: u16) -> (u64, Message) {
+    (at_us, Message::Release { key_index })
+}
+
+/// Runs a layout with virtual time, sends the specified key events at their time (in
+/// microseconds) and returns the presses and releases of the [`Record`] actions in the order in
+/// which they happened.
+///
+/// The virtual time starts at zero and is advanced by a fixed step every time the layout waits,
+/// until the specified end time (in microseconds) is reached.
+pub(crate) fn run_layout<A: ActionContainer, C: ComboContainer>(
+    layout: &Layout<A, C>,
+    state: &'static TestState,
+    messages: &[(u64, Message)],
+    end_us: u64,
+) -> Vec<Recorded> {
+    let _guard = lock();
+    let driver = MockDriver::get();
+    driver.reset();
+    crate::reset_key_presses();
+    RECORDED.lock().unwrap().clear();
+
+    let address = TestDevice::DEFAULT_ADDRESS;
+    let mcu = Box::leak(Box::new(DummyMcu));
+    let internal_channel = Box::leak(Box::new(internal::Channel::new(block_on(
+        internal::empty::Transport::create(internal::empty::TransportConfig, mcu, address),
+    ))));
+    let external_channel = Box::leak(Box::new(external::Channel::new(block_on(
+        ReportTransport::create((), mcu, address, internal_channel),
+    ))));
+    let storage = Box::leak(Box::new(EmptyStorage));
+    let context = lokey::Context::<TestDevice, TestTransports, _> {
+        address,
+        mcu,
+        internal_channel,
+        external_channel,
+        storage,
+        state,
+    };
+
+    let send_messages = async {
+        for (at_us, message) in messages {
+            Timer::at(Instant::from_micros(*at_us)).await;
+            internal_channel.send(*message).await;
+        }
+        core::future::pending::<()>().await
+    };
+    {
+        let mut run = pin!(join4(
+            layout.run(context),
+            internal_channel.run(storage),
+            external_channel.run(storage, IdentityOverride::<TestTxMessage>::new()),
+            send_messages,
+        ));
+
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        while Instant::now() < Instant::from_micros(end_us) {
+            let _ = run.as_mut().poll(&mut cx);
+            driver.advance(STEP);
+        }
+    }
+    core::mem::take(&mut *RECORDED.lock().unwrap())
+}
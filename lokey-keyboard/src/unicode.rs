@@ -0,0 +1,82 @@
+use arrayvec::ArrayVec;
+use core::cell::Cell;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+
+/// The input method of the host that is used to type Unicode characters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UnicodeMode {
+    /// Input method of IBus (used by most Linux desktops).
+    ///
+    /// Ctrl+Shift+U is pressed, followed by the hexadecimal code point and Space.
+    #[default]
+    Linux,
+    /// The "Unicode Hex Input" input source of macOS.
+    ///
+    /// The hexadecimal UTF-16 code units are typed while Option is held.
+    MacOs,
+    /// The [WinCompose](https://github.com/samhocevar/wincompose) input method for Windows with
+    /// Right Alt as compose key.
+    ///
+    /// Right Alt and U are pressed, followed by the hexadecimal code point and Enter.
+    WinCompose,
+}
+
+/// State type for the [`UnicodeMode`] that is used by the [`Unicode`](crate::action::Unicode)
+/// action.
+///
+/// If this type is not part of the state, [`UnicodeMode::Linux`] is used.
+pub struct UnicodeModeState {
+    inner: Mutex<CriticalSectionRawMutex, Cell<UnicodeMode>>,
+}
+
+impl Default for UnicodeModeState {
+    fn default() -> Self {
+        Self::new(UnicodeMode::default())
+    }
+}
+
+impl UnicodeModeState {
+    /// Creates a new [`UnicodeModeState`] with the specified initial mode.
+    pub const fn new(mode: UnicodeMode) -> Self {
+        Self {
+            inner: Mutex::new(Cell::new(mode)),
+        }
+    }
+
+    /// Returns the current mode.
+    pub fn get(&self) -> UnicodeMode {
+        self.inner.lock(|v| v.get())
+    }
+
+    /// Sets the current mode.
+    pub fn set(&self, mode: UnicodeMode) {
+        self.inner.lock(|v| v.set(mode));
+    }
+}
+
+/// Returns the lowercase hexadecimal digits of the value as ASCII characters, padded with zeros
+/// to at least `min_len` digits.
+pub(crate) fn hex_digits(value: u32, min_len: usize) -> ArrayVec<u8, 8> {
+    let mut digits = ArrayVec::new();
+    for i in (0..8).rev() {
+        let nibble = (value >> (i * 4)) & 0xf;
+        if nibble != 0 || !digits.is_empty() || i < min_len {
+            digits.push(b"0123456789abcdef"[nibble as usize]);
+        }
+    }
+    digits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_digits_are_padded() {
+        assert_eq!(hex_digits(0xe9, 4).as_slice(), b"00e9");
+        assert_eq!(hex_digits(0x1f600, 4).as_slice(), b"1f600");
+        assert_eq!(hex_digits(0, 1).as_slice(), b"0");
+    }
+}
@@ -1,45 +1,134 @@
-use crate::KeyboardReport;
+use crate::consumer::CONSUMER_REPORT_ID;
+#[cfg(feature = "nkro")]
+use crate::hid_report::NKRO_HID_REPORT_DESCRIPTOR;
+use crate::hid_report::{HID_REPORT_DESCRIPTOR, HID_REPORT_SIZE, KEYBOARD_REPORT_ID};
+#[cfg(feature = "nkro")]
+use crate::nkro::NKRO_REPORT_DESCRIPTOR;
+use crate::system_control::SYSTEM_CONTROL_REPORT_ID;
+use crate::{
+    HidReport, HostLeds, KeyboardReport, KeyboardReportConfig, KeyboardReportEncoder,
+    MAX_KEYBOARD_REPORT_SIZE,
+};
+use arrayvec::ArrayVec;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::mutex::Mutex;
 use embassy_usb::Builder;
-use embassy_usb::class::hid::{HidBootProtocol, HidSubclass, HidWriter, State as HidState};
+use embassy_usb::class::hid::{
+    HidBootProtocol, HidProtocolMode, HidSubclass, HidWriter, ReportId, RequestHandler,
+    State as HidState,
+};
+use embassy_usb::control::OutResponse;
 use embassy_usb::driver::Driver;
 use lokey::util::error;
-use lokey_usb::external::{InitMessageService, TxMessage, TxMessageService};
-use usbd_hid::descriptor::{
-    AsInputReport, KeyboardReport as HidKeyboardReport, SerializedDescriptor,
-};
+use lokey_usb::external::{InitMessageService, MessageConfigs, TxMessage, TxMessageService};
+use portable_atomic::{AtomicBool, Ordering};
+use usbd_hid::descriptor::{KeyboardReport as HidKeyboardReport, SerializedDescriptor};
 
 impl TxMessage for KeyboardReport {
     type MessageService<'d, D: Driver<'d>> = KeyboardReportService<'d, D>;
 }
 
-const KEYBOARD_REPORT_SIZE: usize = 9;
+/// Whether the host selected the boot protocol, in which case the boot keyboard report has to be
+/// sent instead of the NKRO report.
+///
+/// The protocol is only selected by the host if NKRO is enabled in the [`KeyboardReportConfig`], as
+/// the interface does not support the boot protocol otherwise.
+static BOOT_PROTOCOL: AtomicBool = AtomicBool::new(false);
+
+pub struct KeyboardReportServiceParams<'d> {
+    hid_state: HidState<'d>,
+    request_handler: HostLedsRequestHandler,
+}
+
+/// Handles the output reports that contain the LED state of the host.
+struct HostLedsRequestHandler {
+    /// The report ID of the keyboard report, or `None` if the interface does not use report IDs.
+    report_id: Option<u8>,
+}
+
+impl RequestHandler for HostLedsRequestHandler {
+    fn set_report(&mut self, id: ReportId, data: &[u8]) -> OutResponse {
+        let bits = match (id, self.report_id) {
+            (ReportId::Out(_), None) => data.first(),
+            // The report ID is the first byte of the report if the interface uses report IDs.
+            (ReportId::Out(id), Some(report_id)) if id == report_id => match data {
+                [first, bits, ..] if *first == report_id => Some(bits),
+                _ => data.first(),
+            },
+            _ => None,
+        };
+        match bits {
+            Some(&bits) => {
+                HostLeds::from_bits(bits).set_current();
+                OutResponse::Accepted
+            }
+            None => OutResponse::Rejected,
+        }
+    }
+
+    fn get_protocol(&self) -> HidProtocolMode {
+        if self.report_id.is_none() && BOOT_PROTOCOL.load(Ordering::Acquire) {
+            HidProtocolMode::Boot
+        } else {
+            HidProtocolMode::Report
+        }
+    }
+
+    fn set_protocol(&mut self, protocol: HidProtocolMode) -> OutResponse {
+        if self.report_id.is_some() {
+            return OutResponse::Rejected;
+        }
+        BOOT_PROTOCOL.store(protocol == HidProtocolMode::Boot, Ordering::Release);
+        OutResponse::Accepted
+    }
+}
 
 pub struct KeyboardReportService<'d, D: Driver<'d>> {
-    hid_writer: Mutex<CriticalSectionRawMutex, HidWriter<'d, D, KEYBOARD_REPORT_SIZE>>,
+    hid_writer: Mutex<CriticalSectionRawMutex, HidWriter<'d, D, MAX_KEYBOARD_REPORT_SIZE>>,
+    encoder: KeyboardReportEncoder,
 }
 
 impl<'d, D: Driver<'d>> InitMessageService<'d, D> for KeyboardReportService<'d, D> {
-    type Params = HidState<'d>;
+    type Params = KeyboardReportServiceParams<'d>;
 
     fn create_params() -> Self::Params {
-        HidState::new()
+        KeyboardReportServiceParams {
+            hid_state: HidState::new(),
+            request_handler: HostLedsRequestHandler { report_id: None },
+        }
     }
 
-    fn init(builder: &mut Builder<'d, D>, hid_state: &'d mut Self::Params) -> Self {
-        let hid_config = embassy_usb::class::hid::Config {
+    fn init(
+        builder: &mut Builder<'d, D>,
+        params: &'d mut Self::Params,
+        configs: MessageConfigs,
+    ) -> Self {
+        let encoder =
+            KeyboardReportEncoder::new(KeyboardReportConfig::from_message_configs(configs));
+        let hid_state = &mut params.hid_state;
+        #[cfg_attr(not(feature = "nkro"), expect(unused_mut))]
+        let mut hid_config = embassy_usb::class::hid::Config {
             report_descriptor: HidKeyboardReport::desc(),
-            request_handler: None,
+            request_handler: Some(&mut params.request_handler),
             poll_ms: 60,
             max_packet_size: 64,
             hid_subclass: HidSubclass::No,
             hid_boot_protocol: HidBootProtocol::None,
         };
+        // The interface supports the boot protocol, so that the keyboard also works in hosts that
+        // do not parse the report descriptor (e.g. a BIOS).
+        #[cfg(feature = "nkro")]
+        if encoder.is_nkro() {
+            hid_config.report_descriptor = &NKRO_REPORT_DESCRIPTOR;
+            hid_config.hid_subclass = HidSubclass::Boot;
+            hid_config.hid_boot_protocol = HidBootProtocol::Keyboard;
+        }
 
-        let hid_writer = HidWriter::<_, KEYBOARD_REPORT_SIZE>::new(builder, hid_state, hid_config);
+        let hid_writer =
+            HidWriter::<_, MAX_KEYBOARD_REPORT_SIZE>::new(builder, hid_state, hid_config);
         Self {
             hid_writer: Mutex::new(hid_writer),
+            encoder,
         }
     }
 }
@@ -47,21 +136,94 @@ impl<'d, D: Driver<'d>> InitMessageService<'d, D> for KeyboardReportService<'d,
 impl<'d, D: Driver<'d>> TxMessageService<KeyboardReport> for KeyboardReportService<'d, D> {
     async fn send(&self, message: KeyboardReport) {
         let hid_writer = &mut *self.hid_writer.lock().await;
+        let boot_protocol = self.encoder.is_nkro() && BOOT_PROTOCOL.load(Ordering::Acquire);
+        let Some(report) = self.encoder.encode(&message, boot_protocol) else {
+            return;
+        };
+        if let Err(e) = hid_writer.write(&report).await {
+            #[cfg(feature = "defmt")]
+            let e = defmt::Debug2Format(&e);
+            error!("Failed to write HID report: {}", e);
+        }
+    }
+}
 
-        let hid_keyboard_report = message.to_hid_report();
+impl TxMessage for HidReport {
+    type MessageService<'d, D: Driver<'d>> = HidReportService<'d, D>;
+}
 
-        let mut buf = [0; KEYBOARD_REPORT_SIZE];
-        let len = match hid_keyboard_report.serialize(&mut buf) {
-            Ok(v) => v,
-            Err(e) => {
-                #[cfg(feature = "defmt")]
-                let e = defmt::Debug2Format(&e);
-                error!("Failed to serialize keyboard report: {}", e);
-                return;
-            }
+/// Message service that sends the keyboard, consumer control and system control reports through a
+/// single HID interface, using a different report ID for each report.
+pub struct HidReportService<'d, D: Driver<'d>> {
+    hid_writer: Mutex<CriticalSectionRawMutex, HidWriter<'d, D, HID_REPORT_SIZE>>,
+    encoder: KeyboardReportEncoder,
+}
+
+impl<'d, D: Driver<'d>> InitMessageService<'d, D> for HidReportService<'d, D> {
+    type Params = KeyboardReportServiceParams<'d>;
+
+    fn create_params() -> Self::Params {
+        KeyboardReportServiceParams {
+            hid_state: HidState::new(),
+            request_handler: HostLedsRequestHandler {
+                report_id: Some(KEYBOARD_REPORT_ID),
+            },
+        }
+    }
+
+    fn init(
+        builder: &mut Builder<'d, D>,
+        params: &'d mut Self::Params,
+        configs: MessageConfigs,
+    ) -> Self {
+        let encoder =
+            KeyboardReportEncoder::new(KeyboardReportConfig::from_message_configs(configs));
+        #[cfg_attr(not(feature = "nkro"), expect(unused_mut))]
+        let mut report_descriptor: &[u8] = &HID_REPORT_DESCRIPTOR;
+        #[cfg(feature = "nkro")]
+        if encoder.is_nkro() {
+            report_descriptor = &NKRO_HID_REPORT_DESCRIPTOR;
+        }
+        let hid_config = embassy_usb::class::hid::Config {
+            report_descriptor,
+            request_handler: Some(&mut params.request_handler),
+            poll_ms: 60,
+            max_packet_size: 64,
+            hid_subclass: HidSubclass::No,
+            hid_boot_protocol: HidBootProtocol::None,
         };
 
-        if let Err(e) = hid_writer.write(&buf[..len]).await {
+        let hid_writer =
+            HidWriter::<_, HID_REPORT_SIZE>::new(builder, &mut params.hid_state, hid_config);
+        Self {
+            hid_writer: Mutex::new(hid_writer),
+            encoder,
+        }
+    }
+}
+
+impl<'d, D: Driver<'d>> TxMessageService<HidReport> for HidReportService<'d, D> {
+    async fn send(&self, message: HidReport) {
+        let hid_writer = &mut *self.hid_writer.lock().await;
+        let mut report = ArrayVec::<u8, HID_REPORT_SIZE>::new();
+        match message {
+            HidReport::Keyboard(keyboard_report) => {
+                let Some(bytes) = self.encoder.encode(&keyboard_report, false) else {
+                    return;
+                };
+                report.push(KEYBOARD_REPORT_ID);
+                report.extend(bytes);
+            }
+            HidReport::Consumer(consumer_report) => {
+                report.push(CONSUMER_REPORT_ID);
+                report.extend(consumer_report.to_hid_report());
+            }
+            HidReport::SystemControl(system_control_report) => {
+                report.push(SYSTEM_CONTROL_REPORT_ID);
+                report.extend(system_control_report.to_hid_report());
+            }
+        }
+        if let Err(e) = hid_writer.write(&report).await {
             #[cfg(feature = "defmt")]
             let e = defmt::Debug2Format(&e);
             error!("Failed to write HID report: {}", e);
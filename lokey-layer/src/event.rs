@@ -0,0 +1,103 @@
+use crate::{LayerId, LayerManagerQuery};
+use embassy_futures::select::{Either, select};
+use embassy_time::{Duration, Timer};
+use generic_array::GenericArray;
+use lokey::util::error;
+use lokey::{AnyState, Component, Context, Device, Transports, internal};
+
+/// Internal message that is sent by the [`LayerChangeNotifier`] when the active layer of the
+/// [`LayerManager`](crate::LayerManager) changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LayerChanged {
+    /// The ID of the new active layer.
+    pub layer: LayerId,
+}
+
+impl internal::Message for LayerChanged {
+    type Size = typenum::U1;
+
+    const TAG: [u8; 4] = [0x27, 0xe4, 0x5a, 0x93];
+
+    fn from_bytes(bytes: GenericArray<u8, Self::Size>) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        Some(Self {
+            layer: LayerId(bytes[0]),
+        })
+    }
+
+    fn to_bytes(&self) -> GenericArray<u8, Self::Size> {
+        [self.layer.0].into()
+    }
+}
+
+/// Component that sends a [`LayerChanged`] message on the internal channel when the active layer
+/// changes.
+///
+/// The changes are debounced: the message is only sent once the active layer did not change for
+/// the debounce duration, so e.g. a layer that is only active for a moment during fast layer
+/// toggles does not cause a message. No message is sent if the active layer is the same as the one
+/// of the previous message after the debounce duration.
+///
+/// This requires the [`LayerManager`](crate::LayerManager) to be part of the state as a query
+/// (see [`LayerManagerQuery`]). As the message is sent over the internal channel, it is also
+/// received by the other parts of a split keyboard, e.g. to show the layer with an LED on the
+/// peripheral.
+pub struct LayerChangeNotifier {
+    debounce: Duration,
+}
+
+impl Component for LayerChangeNotifier {}
+
+impl Default for LayerChangeNotifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LayerChangeNotifier {
+    pub const fn new() -> Self {
+        Self {
+            debounce: Duration::from_millis(50),
+        }
+    }
+
+    /// Sets how long the active layer must stay the same before the change is sent (defaults to
+    /// 50ms).
+    pub const fn debounce(mut self, value: Duration) -> Self {
+        self.debounce = value;
+        self
+    }
+
+    /// Runs the component.
+    pub async fn run<D, T, S>(self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let Some(layer_manager) = context.state.try_query::<LayerManagerQuery>() else {
+            error!("LayerChangeNotifier requires LayerManager");
+            return;
+        };
+        let mut last_layer = layer_manager.active();
+        loop {
+            // The layer may have changed while the previous message was sent.
+            if layer_manager.active() == last_layer {
+                layer_manager.wait_for_change().await;
+            }
+            // Wait until the active layer did not change for the debounce duration.
+            while let Either::First(_) =
+                select(layer_manager.wait_for_change(), Timer::after(self.debounce)).await
+            {
+            }
+            let layer = layer_manager.active();
+            if layer != last_layer {
+                last_layer = layer;
+                context.internal_channel.send(LayerChanged { layer }).await;
+            }
+        }
+    }
+}
@@ -1,14 +1,22 @@
 use crate::{ConditionalLayer, LayerId, LayerManagerEntry};
 use arrayvec::ArrayVec;
 use core::cell::RefCell;
+use core::task::{Context, Poll};
 use embassy_sync::blocking_mutex::Mutex;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::waitqueue::MultiWakerRegistration;
 use lokey::util::info;
 
 // The maximum number of layers that can be active at the same time. This includes layers that got
 // activated through conditional layers.
 const ACTIVATE_LAYER_SLOTS: usize = 16;
 
+// The maximum number of conditional layers that can be added with `add_conditional`.
+const ADDED_CONDITIONAL_LAYER_SLOTS: usize = 8;
+
+// The maximum number of tasks that can wait for a change of the active layer at the same time.
+const MAX_CHANGE_WAITERS: usize = 4;
+
 #[derive(Clone)]
 struct ActiveEntry {
     entry_id: u64,
@@ -19,31 +27,173 @@ struct ActiveEntry {
 pub trait LayerManagerTrait {
     fn active(&self) -> LayerId;
     fn push(&self, layer: LayerId) -> LayerManagerEntry;
-    fn remove(&self, entry: LayerManagerEntry) -> LayerId;
+    fn remove(&self, entry: LayerManagerEntry) -> Option<LayerId>;
+    fn switch_to(&self, layer: LayerId) -> LayerManagerEntry;
+    fn add_conditional(&self, required: &'static [LayerId], then: LayerId);
+    fn default_layer(&self) -> LayerId;
+    fn set_default(&self, layer: LayerId);
+    fn poll_change(&self, cx: &mut Context<'_>, initial_version: &mut Option<u32>) -> Poll<()>;
+}
+
+struct State {
+    default_layer: LayerId,
+    /// The ID of the next entry. IDs are never reused, so entries that were removed by
+    /// `switch_to` can be detected.
+    next_entry_id: u64,
+    active_layers: ArrayVec<ActiveEntry, ACTIVATE_LAYER_SLOTS>,
+    added_conditional_layers: ArrayVec<ConditionalLayer, ADDED_CONDITIONAL_LAYER_SLOTS>,
+    /// Incremented every time the active layer changes.
+    version: u32,
+    wakers: MultiWakerRegistration<MAX_CHANGE_WAITERS>,
+}
+
+impl State {
+    fn active(&self) -> LayerId {
+        self.active_layers
+            .last()
+            .map(|entry| entry.layer_id)
+            .unwrap_or(self.default_layer)
+    }
 }
 
 pub struct LayerManagerInner<const NUM_CONDITIONAL_LAYERS: usize> {
-    active_layers:
-        Mutex<CriticalSectionRawMutex, RefCell<ArrayVec<ActiveEntry, ACTIVATE_LAYER_SLOTS>>>,
+    state: Mutex<CriticalSectionRawMutex, RefCell<State>>,
     conditional_layers: [ConditionalLayer; NUM_CONDITIONAL_LAYERS],
 }
 
 impl<const NUM_CONDITIONAL_LAYERS: usize> LayerManagerInner<NUM_CONDITIONAL_LAYERS> {
     pub const fn new(conditional_layers: [ConditionalLayer; NUM_CONDITIONAL_LAYERS]) -> Self {
         Self {
-            active_layers: Mutex::new(RefCell::new(ArrayVec::new_const())),
+            state: Mutex::new(RefCell::new(State {
+                default_layer: LayerId(0),
+                next_entry_id: 1,
+                active_layers: ArrayVec::new_const(),
+                added_conditional_layers: ArrayVec::new_const(),
+                version: 0,
+                wakers: MultiWakerRegistration::new(),
+            })),
             conditional_layers,
         }
     }
 
-    fn next_id(map: &ArrayVec<ActiveEntry, ACTIVATE_LAYER_SLOTS>) -> u64 {
-        let next_id = map
-            .iter()
-            .map(|entry| entry.entry_id)
-            .max()
-            .map_or(1, |id| id + 1);
-        assert!(!map.iter().any(|entry| entry.entry_id == next_id));
-        next_id
+    /// Applies the function to the state and wakes the tasks that wait for a change if the active
+    /// layer changed.
+    fn modify<R>(&self, f: impl FnOnce(&mut State) -> R) -> R {
+        self.state.lock(|state| {
+            let state = &mut *state.borrow_mut();
+            let previous_layer = state.active();
+            let result = f(state);
+            if state.active() != previous_layer {
+                state.version = state.version.wrapping_add(1);
+                state.wakers.wake();
+            }
+            result
+        })
+    }
+
+    fn next_id(next_entry_id: &mut u64) -> u64 {
+        let id = *next_entry_id;
+        *next_entry_id += 1;
+        id
+    }
+
+    /// Activates the conditional layers whose required layers are active and deactivates the
+    /// conditional layers whose required layers are not active anymore.
+    ///
+    /// Layers that were activated through conditional layers can satisfy the requirements of other
+    /// conditional layers. The conditional layers are only derived from the layers that were pushed
+    /// explicitly, so conditional layers that require each other can not keep each other active.
+    fn update_conditional_layers(&self, state: &mut State) {
+        let State {
+            active_layers,
+            added_conditional_layers,
+            next_entry_id,
+            ..
+        } = state;
+        let conditional_layers = || {
+            self.conditional_layers
+                .iter()
+                .chain(added_conditional_layers.iter())
+                .enumerate()
+        };
+
+        let mut pushed_layer_ids = LayerSet::new();
+        for entry in active_layers.iter() {
+            if entry.conditional_layer_index.is_none() {
+                pushed_layer_ids.insert(entry.layer_id);
+            }
+        }
+        let mut active_layer_ids = pushed_layer_ids.clone();
+        // Each iteration adds at least one layer to the set, otherwise the loop ends.
+        loop {
+            let mut changed = false;
+            for (_, conditional_layer) in conditional_layers() {
+                if !active_layer_ids.contains(conditional_layer.then)
+                    && active_layer_ids.contains_all(conditional_layer.required)
+                {
+                    active_layer_ids.insert(conditional_layer.then);
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        // A conditional layer is not activated again if the layer was pushed explicitly.
+        let is_satisfied = |conditional_layer: &ConditionalLayer| {
+            active_layer_ids.contains_all(conditional_layer.required)
+                && !pushed_layer_ids.contains(conditional_layer.then)
+        };
+
+        active_layers.retain(|entry| {
+            let Some(index) = entry.conditional_layer_index else {
+                return true;
+            };
+            let (_, conditional_layer) = conditional_layers()
+                .nth(index)
+                .expect("invalid conditional layer index");
+            if is_satisfied(conditional_layer) {
+                return true;
+            }
+            info!("Deactivating conditional layer {}", entry.layer_id.0);
+            false
+        });
+        for (index, conditional_layer) in conditional_layers() {
+            let is_active = active_layers
+                .iter()
+                .any(|entry| entry.conditional_layer_index == Some(index));
+            if is_satisfied(conditional_layer) && !is_active {
+                info!("Activating conditional layer {}", conditional_layer.then.0);
+                let new_id = Self::next_id(next_entry_id);
+                active_layers.push(ActiveEntry {
+                    entry_id: new_id,
+                    layer_id: conditional_layer.then,
+                    conditional_layer_index: Some(index),
+                });
+            }
+        }
+    }
+}
+
+/// A set of layer IDs.
+#[derive(Clone)]
+struct LayerSet([u64; 4]);
+
+impl LayerSet {
+    const fn new() -> Self {
+        Self([0; 4])
+    }
+
+    fn insert(&mut self, layer_id: LayerId) {
+        self.0[layer_id.0 as usize / 64] |= 1 << (layer_id.0 % 64);
+    }
+
+    fn contains(&self, layer_id: LayerId) -> bool {
+        self.0[layer_id.0 as usize / 64] & (1 << (layer_id.0 % 64)) != 0
+    }
+
+    fn contains_all(&self, layer_ids: &[LayerId]) -> bool {
+        layer_ids.iter().all(|layer_id| self.contains(*layer_id))
     }
 }
 
@@ -52,75 +202,100 @@ impl<const NUM_CONDITIONAL_LAYERS: usize> LayerManagerTrait
 {
     /// Sets the active layer to the layer with the specified ID.
     fn push(&self, layer: LayerId) -> LayerManagerEntry {
-        self.active_layers.lock(|active_layers| {
-            let active_layers = &mut *active_layers.borrow_mut();
-
-            let new_id = Self::next_id(active_layers);
-            active_layers.push(ActiveEntry {
+        self.modify(|state| {
+            let new_id = Self::next_id(&mut state.next_entry_id);
+            state.active_layers.push(ActiveEntry {
                 entry_id: new_id,
                 layer_id: layer,
                 conditional_layer_index: None,
             });
+            self.update_conditional_layers(state);
 
-            let entry = LayerManagerEntry(new_id);
-
-            for (index, conditional_layer) in self.conditional_layers.iter().enumerate() {
-                let required_layers_are_active =
-                    conditional_layer.required.iter().all(|required_layer_id| {
-                        active_layers
-                            .iter()
-                            .any(|entry| entry.layer_id == *required_layer_id)
-                    });
-                if required_layers_are_active {
-                    info!("Activating conditional layer {}", conditional_layer.then.0);
-                    let new_id = Self::next_id(active_layers);
-                    active_layers.push(ActiveEntry {
-                        entry_id: new_id,
-                        layer_id: conditional_layer.then,
-                        conditional_layer_index: Some(index),
-                    });
-                }
-            }
-
-            entry
+            LayerManagerEntry(new_id)
         })
     }
 
     /// Deactivates the layer that was pushed to the stack with the specified [`LayerManagerEntry`].
-    fn remove(&self, entry: LayerManagerEntry) -> LayerId {
-        self.active_layers.lock(|active_layers| {
-            let active_layers = &mut *active_layers.borrow_mut();
-
-            let index = active_layers
+    fn remove(&self, entry: LayerManagerEntry) -> Option<LayerId> {
+        self.modify(|state| {
+            let Some(index) = state
+                .active_layers
                 .iter()
                 .position(|active_entry| active_entry.entry_id == entry.0)
-                .expect("invalid LayerManagerEntry");
-            let removed_layer_id = active_layers.remove(index).layer_id;
-
-            for (active_entry_index, active_entry) in active_layers.clone().iter().enumerate().rev()
-            {
-                let Some(conditional_layer_index) = active_entry.conditional_layer_index else {
-                    continue;
-                };
-                let conditional_layer = &self.conditional_layers[conditional_layer_index];
-                if conditional_layer.required.contains(&removed_layer_id) {
-                    info!("Deactivating conditional layer {}", active_entry.layer_id.0);
-                    active_layers.remove(active_entry_index);
-                }
-            }
+            else {
+                // The entry was already removed by `switch_to`
+                assert!(entry.0 < state.next_entry_id, "invalid LayerManagerEntry");
+                return None;
+            };
+            let removed_layer_id = state.active_layers.remove(index).layer_id;
+            self.update_conditional_layers(state);
+
+            Some(removed_layer_id)
+        })
+    }
 
-            removed_layer_id
+    /// Deactivates all layers that were pushed to the stack and activates the specified layer.
+    fn switch_to(&self, layer: LayerId) -> LayerManagerEntry {
+        self.modify(|state| {
+            info!("Switching to layer {}", layer.0);
+            state
+                .active_layers
+                .retain(|entry| entry.conditional_layer_index.is_some());
+            let new_id = Self::next_id(&mut state.next_entry_id);
+            state.active_layers.push(ActiveEntry {
+                entry_id: new_id,
+                layer_id: layer,
+                conditional_layer_index: None,
+            });
+            self.update_conditional_layers(state);
+
+            LayerManagerEntry(new_id)
+        })
+    }
+
+    /// Adds a conditional layer that is activated while all of the required layers are active.
+    fn add_conditional(&self, required: &'static [LayerId], then: LayerId) {
+        self.modify(|state| {
+            state
+                .added_conditional_layers
+                .try_push(ConditionalLayer::new(required, then))
+                .expect("too many conditional layers");
+            self.update_conditional_layers(state);
         })
     }
 
-    /// Returns the ID of the currently active layer (i.e. the layer ID that was last pushed to the stack).
+    /// Returns the ID of the default layer.
+    fn default_layer(&self) -> LayerId {
+        self.state.lock(|state| state.borrow().default_layer)
+    }
+
+    /// Sets the layer that is active when no other layer is active.
+    fn set_default(&self, layer: LayerId) {
+        self.modify(|state| {
+            info!("Setting default layer to {}", layer.0);
+            state.default_layer = layer;
+        })
+    }
+
+    /// Returns the ID of the currently active layer (i.e. the layer ID that was last pushed to the
+    /// stack, or the default layer if the stack is empty).
     fn active(&self) -> LayerId {
-        self.active_layers.lock(|active_layers| {
-            let active_layers = &*active_layers.borrow();
-            active_layers
-                .last()
-                .map(|entry| entry.layer_id)
-                .unwrap_or(LayerId(0))
+        self.state.lock(|state| state.borrow().active())
+    }
+
+    /// Returns `Poll::Ready` if the active layer changed since the first call.
+    ///
+    /// The version of the state at the first call is stored in `initial_version`.
+    fn poll_change(&self, cx: &mut Context<'_>, initial_version: &mut Option<u32>) -> Poll<()> {
+        self.state.lock(|state| {
+            let mut state = state.borrow_mut();
+            let initial_version = *initial_version.get_or_insert(state.version);
+            if state.version != initial_version {
+                Poll::Ready(())
+            } else {
+                state.wakers.register(cx.waker());
+                Poll::Pending
+            }
         })
     }
 }
@@ -6,10 +6,15 @@
 #![cfg_attr(not(test), no_std)]
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+mod event;
 mod layer_manager_inner;
 
+use core::future::poll_fn;
+pub use event::{LayerChangeNotifier, LayerChanged};
+use generic_array::GenericArray;
 use layer_manager_inner::{LayerManagerInner, LayerManagerTrait};
 use lokey::state::ToStateQuery;
+use lokey::storage::{self, Storage};
 
 /// The ID of a layer.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -24,16 +29,18 @@ pub struct LayerManagerEntry(u64);
 /// Conditional layer configuration.
 ///
 /// A conditional layer is a layer that is automatically activated when specific layers are active.
-/// If all layers in the `required` array are active, the layer in the `then` field will be
-/// activated as well.
+/// If all layers in the `required` slice are active, the layer in the `then` field will be
+/// activated as well, and it is deactivated again as soon as one of the required layers is
+/// deactivated. The required layers can also be activated by other conditional layers.
 ///
 /// Conditional layers can be added to a [`LayerManager`] by using the
-/// [`LayerManager::with_conditional_layers`] function.
+/// [`LayerManager::with_conditional_layers`] function or at runtime with
+/// [`LayerManager::add_conditional`].
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct ConditionalLayer {
     /// The layers that need to be active for the conditional layer to be activated.
-    pub required: [LayerId; 2],
+    pub required: &'static [LayerId],
     /// The layer to activate when all layers in `required` are active.
     pub then: LayerId,
 }
@@ -41,7 +48,7 @@ pub struct ConditionalLayer {
 impl ConditionalLayer {
     /// Creates a new [`ConditionalLayer`] with the specified required layers and the layer to
     /// activate.
-    pub const fn new(required: [LayerId; 2], then: LayerId) -> Self {
+    pub const fn new(required: &'static [LayerId], then: LayerId) -> Self {
         Self { required, then }
     }
 }
@@ -80,7 +87,8 @@ impl<const NUM_CONDITIONAL_LAYERS: usize> LayerManager<NUM_CONDITIONAL_LAYERS> {
         }
     }
 
-    /// Returns the ID of the currently active layer (i.e. the layer ID that was last pushed to the stack).
+    /// Returns the ID of the currently active layer (i.e. the layer ID that was last pushed to the
+    /// stack, or the [default layer](Self::set_default) if no layer was pushed).
     pub fn active(&self) -> LayerId {
         self.inner.active()
     }
@@ -90,10 +98,78 @@ impl<const NUM_CONDITIONAL_LAYERS: usize> LayerManager<NUM_CONDITIONAL_LAYERS> {
         self.inner.push(layer)
     }
 
+    /// Returns the ID of the default layer.
+    pub fn default_layer(&self) -> LayerId {
+        self.inner.default_layer()
+    }
+
+    /// Sets the default layer, i.e. the layer that is active while no other layer is pushed to the
+    /// stack.
+    ///
+    /// The default layer is [`LayerId(0)`](LayerId) unless it is changed with this method.
+    pub fn set_default(&self, layer: LayerId) {
+        self.inner.set_default(layer)
+    }
+
+    /// Writes the ID of the default layer to the storage, so that it can be restored with
+    /// [`load_default`](Self::load_default) after a reboot.
+    pub async fn store_default<St: Storage>(
+        &self,
+        storage: &St,
+    ) -> Result<(), storage::Error<St::FlashError>> {
+        store_default(&self.inner, storage).await
+    }
+
+    /// Sets the default layer to the layer that was written to the storage with
+    /// [`store_default`](Self::store_default).
+    ///
+    /// Returns the ID of the restored layer, or `None` if no default layer was stored.
+    pub async fn load_default<St: Storage>(
+        &self,
+        storage: &St,
+    ) -> Result<Option<LayerId>, storage::Error<St::FlashError>> {
+        load_default(&self.inner, storage).await
+    }
+
+    /// Waits until the active layer changes and returns the ID of the new active layer.
+    ///
+    /// Changes that do not affect the active layer (e.g. changing the default layer while another
+    /// layer is pushed) are ignored. See [`LayerChangeNotifier`] for sending the changes as
+    /// internal messages.
+    pub async fn wait_for_change(&self) -> LayerId {
+        wait_for_change(&self.inner).await
+    }
+
     /// Deactivates the layer that was pushed to the stack with the specified [`LayerManagerEntry`].
-    pub fn remove(&self, entry: LayerManagerEntry) -> LayerId {
+    ///
+    /// Returns the ID of the deactivated layer, or `None` if the layer was already deactivated by
+    /// [`switch_to`](Self::switch_to).
+    pub fn remove(&self, entry: LayerManagerEntry) -> Option<LayerId> {
         self.inner.remove(entry)
     }
+
+    /// Deactivates all layers that were pushed to the stack and activates the layer with the
+    /// specified ID instead.
+    ///
+    /// Unlike the [default layer](Self::set_default), the layer is only active until it is removed
+    /// with the returned [`LayerManagerEntry`] or until the stack is replaced again. Removing an
+    /// entry that was deactivated by this method has no effect.
+    pub fn switch_to(&self, layer: LayerId) -> LayerManagerEntry {
+        self.inner.switch_to(layer)
+    }
+
+    /// Adds a conditional layer that is activated while all of the required layers are active (see
+    /// [`ConditionalLayer`]).
+    ///
+    /// A layer that was activated through a conditional layer has no [`LayerManagerEntry`], so it
+    /// can not be deactivated directly while the required layers are active.
+    ///
+    /// # Panics
+    ///
+    /// Panics if more than 8 conditional layers are added with this method.
+    pub fn add_conditional(&self, required: &'static [LayerId], then: LayerId) {
+        self.inner.add_conditional(required, then)
+    }
 }
 
 impl<const NUM_CONDITIONAL_LAYERS: usize> ToStateQuery for LayerManager<NUM_CONDITIONAL_LAYERS> {
@@ -138,7 +214,8 @@ pub struct LayerManagerQuery<'a> {
 }
 
 impl<'a> LayerManagerQuery<'a> {
-    /// Returns the ID of the currently active layer (i.e. the layer ID that was last pushed to the stack).
+    /// Returns the ID of the currently active layer (i.e. the layer ID that was last pushed to the
+    /// stack, or the [default layer](Self::set_default) if no layer was pushed).
     pub fn active(&self) -> LayerId {
         self.inner.active()
     }
@@ -148,10 +225,104 @@ impl<'a> LayerManagerQuery<'a> {
         self.inner.push(layer)
     }
 
-    /// Deactivates the layer that was pushed to the stack with the specified [`LayerManagerEntry`].
-    pub fn remove(&self, entry: LayerManagerEntry) -> LayerId {
+    /// Returns the ID of the default layer.
+    pub fn default_layer(&self) -> LayerId {
+        self.inner.default_layer()
+    }
+
+    /// Sets the default layer (see [`LayerManager::set_default`]).
+    pub fn set_default(&self, layer: LayerId) {
+        self.inner.set_default(layer)
+    }
+
+    /// Writes the ID of the default layer to the storage (see [`LayerManager::store_default`]).
+    pub async fn store_default<St: Storage>(
+        &self,
+        storage: &St,
+    ) -> Result<(), storage::Error<St::FlashError>> {
+        store_default(self.inner, storage).await
+    }
+
+    /// Sets the default layer to the layer that was written to the storage (see
+    /// [`LayerManager::load_default`]).
+    pub async fn load_default<St: Storage>(
+        &self,
+        storage: &St,
+    ) -> Result<Option<LayerId>, storage::Error<St::FlashError>> {
+        load_default(self.inner, storage).await
+    }
+
+    /// Waits until the active layer changes and returns the ID of the new active layer (see
+    /// [`LayerManager::wait_for_change`]).
+    pub async fn wait_for_change(&self) -> LayerId {
+        wait_for_change(self.inner).await
+    }
+
+    /// Deactivates the layer that was pushed to the stack with the specified [`LayerManagerEntry`]
+    /// (see [`LayerManager::remove`]).
+    pub fn remove(&self, entry: LayerManagerEntry) -> Option<LayerId> {
         self.inner.remove(entry)
     }
+
+    /// Deactivates all layers that were pushed to the stack and activates the layer with the
+    /// specified ID instead (see [`LayerManager::switch_to`]).
+    pub fn switch_to(&self, layer: LayerId) -> LayerManagerEntry {
+        self.inner.switch_to(layer)
+    }
+
+    /// Adds a conditional layer that is activated while all of the required layers are active (see
+    /// [`LayerManager::add_conditional`]).
+    pub fn add_conditional(&self, required: &'static [LayerId], then: LayerId) {
+        self.inner.add_conditional(required, then)
+    }
+}
+
+/// Storage entry for the ID of the default layer.
+struct DefaultLayerEntry(LayerId);
+
+impl storage::Entry for DefaultLayerEntry {
+    type Size = typenum::U1;
+    type TagParams = ();
+
+    fn tag(_: Self::TagParams) -> [u8; storage::ENTRY_TAG_SIZE] {
+        [0x3f, 0x91, 0x0c, 0x5e, 0xa7, 0x24, 0xd8, 0x01]
+    }
+
+    fn from_bytes(bytes: &GenericArray<u8, Self::Size>) -> Option<Self> {
+        Some(Self(LayerId(bytes[0])))
+    }
+
+    fn to_bytes(&self) -> GenericArray<u8, Self::Size> {
+        [self.0.0].into()
+    }
+}
+
+async fn wait_for_change(layer_manager: &dyn LayerManagerTrait) -> LayerId {
+    let mut initial_version = None;
+    poll_fn(|cx| layer_manager.poll_change(cx, &mut initial_version)).await;
+    layer_manager.active()
+}
+
+async fn store_default<St: Storage>(
+    layer_manager: &dyn LayerManagerTrait,
+    storage: &St,
+) -> Result<(), storage::Error<St::FlashError>> {
+    let entry = DefaultLayerEntry(layer_manager.default_layer());
+    storage.store((), &entry).await
+}
+
+async fn load_default<St: Storage>(
+    layer_manager: &dyn LayerManagerTrait,
+    storage: &St,
+) -> Result<Option<LayerId>, storage::Error<St::FlashError>> {
+    let layer = storage
+        .fetch::<DefaultLayerEntry>(())
+        .await?
+        .map(|entry| entry.0);
+    if let Some(layer) = layer {
+        layer_manager.set_default(layer);
+    }
+    Ok(layer)
 }
 
 #[cfg(test)]
@@ -221,7 +392,7 @@ mod tests {
     #[test]
     fn conditional_layer1() {
         let manager = LayerManager::with_conditional_layers([ConditionalLayer::new(
-            [LayerId(1), LayerId(2)],
+            &[LayerId(1), LayerId(2)],
             LayerId(42),
         )]);
         assert_eq!(manager.active(), LayerId(0));
@@ -248,8 +419,8 @@ mod tests {
     #[test]
     fn conditional_layer2() {
         let manager = LayerManager::with_conditional_layers([
-            ConditionalLayer::new([LayerId(20), LayerId(30)], LayerId(10)),
-            ConditionalLayer::new([LayerId(30), LayerId(40)], LayerId(50)),
+            ConditionalLayer::new(&[LayerId(20), LayerId(30)], LayerId(10)),
+            ConditionalLayer::new(&[LayerId(30), LayerId(40)], LayerId(50)),
         ]);
         assert_eq!(manager.active(), LayerId(0));
 
@@ -278,6 +449,93 @@ mod tests {
         assert_eq!(manager.active(), LayerId(0));
     }
 
+    #[test]
+    fn conditional_layer_chain() {
+        let manager = LayerManager::new();
+        manager.add_conditional(&[LayerId(1), LayerId(2)], LayerId(3));
+        manager.add_conditional(&[LayerId(3)], LayerId(4));
+        // Conditional layers that require each other are never activated by themselves.
+        manager.add_conditional(&[LayerId(5)], LayerId(6));
+        manager.add_conditional(&[LayerId(6)], LayerId(5));
+
+        let entry1 = manager.push(LayerId(1));
+        let entry2 = manager.push(LayerId(2));
+        assert_eq!(manager.active(), LayerId(4));
+
+        // Pushing another layer does not activate the conditional layers again.
+        let entry3 = manager.push(LayerId(7));
+        manager.remove(entry3);
+        assert_eq!(manager.active(), LayerId(4));
+
+        manager.remove(entry1);
+        assert_eq!(manager.active(), LayerId(2));
+
+        let entry5 = manager.push(LayerId(5));
+        assert_eq!(manager.active(), LayerId(6));
+        manager.remove(entry5);
+        assert_eq!(manager.active(), LayerId(2));
+
+        manager.remove(entry2);
+        assert_eq!(manager.active(), LayerId(0));
+    }
+
+    #[test]
+    fn default_layer() {
+        let manager = LayerManager::new();
+        manager.set_default(LayerId(3));
+        assert_eq!(manager.active(), LayerId(3));
+
+        let entry = manager.push(LayerId(5));
+        assert_eq!(manager.active(), LayerId(5));
+
+        manager.remove(entry);
+        assert_eq!(manager.active(), LayerId(3));
+    }
+
+    #[test]
+    fn switch_to_layer() {
+        let manager = LayerManager::new();
+        let entry1 = manager.push(LayerId(1));
+        let entry2 = manager.push(LayerId(2));
+        assert_eq!(manager.active(), LayerId(2));
+
+        manager.switch_to(LayerId(3));
+        assert_eq!(manager.active(), LayerId(3));
+
+        // Releasing the layers that were active before has no effect.
+        assert_eq!(manager.remove(entry2), None);
+        assert_eq!(manager.remove(entry1), None);
+        assert_eq!(manager.active(), LayerId(3));
+
+        let entry4 = manager.push(LayerId(4));
+        assert_eq!(manager.active(), LayerId(4));
+        assert_eq!(manager.remove(entry4), Some(LayerId(4)));
+        assert_eq!(manager.active(), LayerId(3));
+    }
+
+    #[test]
+    fn wait_for_change() {
+        use core::pin::pin;
+        use core::task::{Context, Poll, Waker};
+
+        let manager = LayerManager::new();
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut future = pin!(manager.wait_for_change());
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        let entry = manager.push(LayerId(1));
+        assert_eq!(future.as_mut().poll(&mut cx), Poll::Ready(LayerId(1)));
+
+        // Changing the default layer while another layer is active does not change the active
+        // layer.
+        let mut future = pin!(manager.wait_for_change());
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        manager.set_default(LayerId(2));
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        manager.remove(entry);
+        assert_eq!(future.as_mut().poll(&mut cx), Poll::Ready(LayerId(2)));
+    }
+
     #[test]
     fn state_query() {
         use lokey::{AnyState, State};
@@ -0,0 +1,169 @@
+use crate::{BrightnessMessage, GammaCorrectionMessage, GammaCurve};
+use lokey::{AnyState, Context, Device, Transports};
+use lokey_keyboard::Action;
+
+/// The default step by which the brightness actions change the brightness.
+const DEFAULT_BRIGHTNESS_STEP: u8 = 25;
+
+/// Action that increases the brightness of the [`LedArray`](crate::LedArray).
+///
+/// If the LED array is run with a storage, the new brightness is written to it once the
+/// brightness has not been changed for a few seconds.
+///
+/// # Example
+///
+/// ```
+/// use lokey_led_array::action::IncreaseBrightness;
+///
+/// let action = IncreaseBrightness::new()
+///     .step(50); // optional, defaults to 25
+/// ```
+pub struct IncreaseBrightness {
+    pub step: u8,
+}
+
+impl Default for IncreaseBrightness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IncreaseBrightness {
+    pub const fn new() -> Self {
+        Self {
+            step: DEFAULT_BRIGHTNESS_STEP,
+        }
+    }
+
+    /// Sets the step by which the brightness is increased, where 255 is the full brightness.
+    pub const fn step(mut self, step: u8) -> Self {
+        self.step = step;
+        self
+    }
+}
+
+impl Action for IncreaseBrightness {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        context
+            .internal_channel
+            .send(BrightnessMessage::Increase(self.step))
+            .await;
+    }
+
+    async fn on_release<D, T, S>(&self, _: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+    }
+}
+
+/// Action that decreases the brightness of the [`LedArray`](crate::LedArray).
+///
+/// If the LED array is run with a storage, the new brightness is written to it once the
+/// brightness has not been changed for a few seconds.
+///
+/// # Example
+///
+/// ```
+/// use lokey_led_array::action::DecreaseBrightness;
+///
+/// let action = DecreaseBrightness::new()
+///     .step(50); // optional, defaults to 25
+/// ```
+pub struct DecreaseBrightness {
+    pub step: u8,
+}
+
+impl Default for DecreaseBrightness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DecreaseBrightness {
+    pub const fn new() -> Self {
+        Self {
+            step: DEFAULT_BRIGHTNESS_STEP,
+        }
+    }
+
+    /// Sets the step by which the brightness is decreased, where 255 is the full brightness.
+    pub const fn step(mut self, step: u8) -> Self {
+        self.step = step;
+        self
+    }
+}
+
+impl Action for DecreaseBrightness {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        context
+            .internal_channel
+            .send(BrightnessMessage::Decrease(self.step))
+            .await;
+    }
+
+    async fn on_release<D, T, S>(&self, _: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+    }
+}
+
+/// Action that switches the gamma correction curve of the [`LedArray`](crate::LedArray).
+///
+/// The curve is not written to the storage, so the configured curve is used again after a
+/// restart.
+///
+/// # Example
+///
+/// ```
+/// use lokey_led_array::GammaCurve;
+/// use lokey_led_array::action::SetGammaCurve;
+///
+/// let action = SetGammaCurve::new(GammaCurve::Srgb);
+/// ```
+pub struct SetGammaCurve {
+    pub curve: GammaCurve,
+}
+
+impl SetGammaCurve {
+    pub const fn new(curve: GammaCurve) -> Self {
+        Self { curve }
+    }
+}
+
+impl Action for SetGammaCurve {
+    async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        context
+            .internal_channel
+            .send(GammaCorrectionMessage::Set(self.curve))
+            .await;
+    }
+
+    async fn on_release<D, T, S>(&self, _: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+    }
+}
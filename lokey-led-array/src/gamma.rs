@@ -0,0 +1,203 @@
+use arrayvec::ArrayVec;
+use embassy_time::{Duration, Instant};
+use generic_array::GenericArray;
+use lokey::internal;
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+/// The duration of the transition from one gamma correction curve to another.
+const TRANSITION_DURATION: Duration = Duration::from_millis(250);
+
+/// The maximum number of curve switches that can be in transition at the same time.
+const MAX_TRANSITIONS: usize = 4;
+
+/// Gamma correction that does not change the brightness.
+pub fn linear(value: f32) -> f32 {
+    value
+}
+
+/// Gamma correction with a gamma of 2 (this is the default).
+pub fn quadratic(value: f32) -> f32 {
+    value * value
+}
+
+/// Gamma correction with a gamma of 3.
+pub fn cubic(value: f32) -> f32 {
+    value * value * value
+}
+
+/// Gamma correction with the transfer function of the sRGB color space.
+pub fn srgb(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        libm::powf((value + 0.055) / 1.055, 2.4)
+    }
+}
+
+/// A gamma correction curve that can be selected at runtime with a [`GammaCorrectionMessage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, MaxSize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum GammaCurve {
+    /// See [`linear`].
+    Linear,
+    /// See [`quadratic`].
+    Quadratic,
+    /// See [`cubic`].
+    Cubic,
+    /// See [`srgb`].
+    Srgb,
+}
+
+impl GammaCurve {
+    /// Returns the function of the curve.
+    pub fn function(self) -> fn(f32) -> f32 {
+        match self {
+            Self::Linear => linear,
+            Self::Quadratic => quadratic,
+            Self::Cubic => cubic,
+            Self::Srgb => srgb,
+        }
+    }
+}
+
+/// Internal message that changes the gamma correction of a [`LedArray`](crate::LedArray).
+///
+/// The LED array fades from the previous curve to the new one, so that switching the curve while
+/// an animation is shown does not cause a visible jump in brightness. The curve is not persisted
+/// in the storage, so the curve that was configured with
+/// [`LedArray::gamma_correction`](crate::LedArray::gamma_correction) is used again after a restart.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, MaxSize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum GammaCorrectionMessage {
+    /// Switches to the specified curve.
+    Set(GammaCurve),
+    /// Switches back to the curve that was configured with
+    /// [`LedArray::gamma_correction`](crate::LedArray::gamma_correction).
+    Reset,
+}
+
+impl internal::Message for GammaCorrectionMessage {
+    type Size = typenum::U2;
+
+    const TAG: [u8; 4] = [0x5b, 0x0e, 0xa7, 0x61];
+
+    fn from_bytes(bytes: GenericArray<u8, Self::Size>) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        postcard::from_bytes(&bytes).ok()
+    }
+
+    fn to_bytes(&self) -> GenericArray<u8, Self::Size> {
+        let mut buf = GenericArray::default();
+        postcard::to_slice(self, &mut buf).expect("buffer is too small");
+        buf
+    }
+}
+
+/// A gamma correction curve.
+type Curve = fn(f32) -> f32;
+
+/// The gamma correction of a running [`LedArray`](crate::LedArray), including the transitions
+/// between curves.
+pub(crate) struct GammaCorrection {
+    initial: Curve,
+    /// The curves that were switched to, together with the start of their transition.
+    ///
+    /// Each curve is faded in from the result of the preceding ones, so that switching the curve
+    /// during a transition continues from the currently shown brightness.
+    transitions: ArrayVec<(Curve, Instant), MAX_TRANSITIONS>,
+}
+
+impl GammaCorrection {
+    pub(crate) fn new(f: Curve) -> Self {
+        Self {
+            initial: f,
+            transitions: ArrayVec::new(),
+        }
+    }
+
+    /// Starts the transition to the specified curve.
+    pub(crate) fn switch_to(&mut self, f: Curve, now: Instant) {
+        self.remove_finished_transitions(now);
+        if self.transitions.is_full() {
+            // Replace the most recent target, the transitions before it are kept intact.
+            self.transitions.pop();
+        }
+        self.transitions.push((f, now));
+    }
+
+    /// Removes the transitions that are no longer needed to calculate the result.
+    pub(crate) fn remove_finished_transitions(&mut self, now: Instant) {
+        let last_finished = self.transitions.iter().rposition(|(_, started)| {
+            now.saturating_duration_since(*started) >= TRANSITION_DURATION
+        });
+        if let Some(index) = last_finished {
+            self.initial = self.transitions[index].0;
+            self.transitions.drain(..=index);
+        }
+    }
+
+    /// Returns `true` if the curve is currently changing.
+    pub(crate) fn is_transitioning(&self, now: Instant) -> bool {
+        self.transitions
+            .iter()
+            .any(|(_, started)| now.saturating_duration_since(*started) < TRANSITION_DURATION)
+    }
+
+    pub(crate) fn apply(&self, value: f32, now: Instant) -> f32 {
+        let mut result = (self.initial)(value);
+        for (f, started) in &self.transitions {
+            let elapsed = now.saturating_duration_since(*started);
+            let progress = if elapsed >= TRANSITION_DURATION {
+                1.0
+            } else {
+                elapsed.as_ticks() as f32 / TRANSITION_DURATION.as_ticks() as f32
+            };
+            result += (f(value) - result) * progress;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets() {
+        for curve in [
+            GammaCurve::Linear,
+            GammaCurve::Quadratic,
+            GammaCurve::Cubic,
+            GammaCurve::Srgb,
+        ] {
+            let f = curve.function();
+            assert_eq!(f(0.0), 0.0);
+            assert!((f(1.0) - 1.0).abs() < 1e-6);
+        }
+        assert!((srgb(0.5) - 0.214).abs() < 1e-3);
+    }
+
+    #[test]
+    fn transition() {
+        let start = Instant::from_millis(1000);
+        let mut gamma_correction = GammaCorrection::new(quadratic);
+        gamma_correction.switch_to(linear, start);
+        assert_eq!(gamma_correction.apply(0.5, start), 0.25);
+        let mid = start + TRANSITION_DURATION / 2;
+        assert_eq!(gamma_correction.apply(0.5, mid), 0.375);
+        assert!(gamma_correction.is_transitioning(mid));
+
+        // Switching during a transition continues from the current value
+        gamma_correction.switch_to(cubic, mid);
+        assert_eq!(gamma_correction.apply(0.5, mid), 0.375);
+
+        let end = mid + TRANSITION_DURATION;
+        assert!(!gamma_correction.is_transitioning(end));
+        gamma_correction.remove_finished_transitions(end);
+        assert!(gamma_correction.transitions.is_empty());
+        assert_eq!(gamma_correction.apply(0.5, end), 0.125);
+    }
+}
@@ -0,0 +1,28 @@
+use core::future::Future;
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+/// The color of a single addressable LED.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, MaxSize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const OFF: Self = Self::new(0, 0, 0);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+/// Driver for a strip of `N` addressable LEDs (e.g. WS2812).
+pub trait LedStrip<const N: usize> {
+    /// Writes the colors of all LEDs to the strip.
+    ///
+    /// The first color is written to the LED that is connected to the data pin.
+    fn write(&mut self, colors: &[Rgb; N]) -> impl Future<Output = ()>;
+}
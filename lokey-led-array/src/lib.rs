@@ -6,27 +6,54 @@
 #![no_std]
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+#[cfg(feature = "keyboard-actions")]
+pub mod action;
+pub mod gamma;
+mod led_strip;
+pub mod mux;
 #[cfg(feature = "nrf52840")]
 pub mod nrf52840;
 pub mod pwm;
+pub mod rgb_matrix;
+#[cfg(feature = "rp2040")]
+pub mod rp2040;
+mod settings;
 
 use arrayvec::ArrayVec;
 use core::sync::atomic::Ordering;
-use embassy_futures::join::join;
-use embassy_futures::select::{Either, select};
+use embassy_futures::join::join3;
+use embassy_futures::select::{Either, Either4, select, select4};
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::signal::Signal;
 use embassy_time::{Duration, Instant, Timer};
+use gamma::GammaCorrection;
+pub use gamma::{GammaCorrectionMessage, GammaCurve};
 use generic_array::GenericArray;
-use lokey::util::{unwrap, warn};
+pub use led_strip::{LedStrip, Rgb};
+use lokey::battery::BatteryLevel;
+use lokey::storage::{EmptyStorage, Storage};
+use lokey::util::{debug, error, unwrap, warn};
 use lokey::{Address, Component, DynContext, internal};
 use portable_atomic::AtomicU32;
 use postcard::experimental::max_size::MaxSize;
 use pwm::PwmChannel;
 use seq_macro::seq;
 use serde::{Deserialize, Serialize};
+pub use settings::{BrightnessMessage, LedArraySettings};
 
 // TODO: Make configurable
 const ACTION_SLOTS: usize = 8;
 
+/// The default number of brightness updates per LED of the slide animations.
+const DEFAULT_SLIDE_STEPS_PER_LED: u16 = 200;
+
+/// The interval in which the LEDs are updated while the gamma correction curve changes.
+const GAMMA_TRANSITION_UPDATE_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The time without further brightness changes after which the settings are written to the
+/// storage, so that rapid changes only cause a single write.
+const PERSIST_DELAY: Duration = Duration::from_secs(5);
+
 static ACTION_ID: AtomicU32 = AtomicU32::new(0);
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, MaxSize)]
@@ -64,6 +91,14 @@ pub enum Action {
         duration_ms: u16,
         count: Option<u16>,
     },
+    /// Turns the LEDs on and off the specified number of times.
+    ///
+    /// Each blink takes `period_ms`, of which the LEDs are on for the first half.
+    Blink {
+        indices_bitmask: u64,
+        times: u16,
+        period_ms: u16,
+    },
     Stop {
         action_id: ActionId,
     },
@@ -92,7 +127,7 @@ impl Message {
 }
 
 impl internal::Message for Message {
-    type Size = typenum::U33;
+    type Size = typenum::U35;
 
     const TAG: [u8; 4] = [0x77, 0xaf, 0xc7, 0x3d];
 
@@ -111,13 +146,13 @@ impl internal::Message for Message {
 }
 
 pub fn default_gamma_correction(value: f32) -> f32 {
-    value * value
+    gamma::quadratic(value)
 }
 
 fn set_brightness(
     pwm_channel: &mut dyn PwmChannel,
     mut brightness: f32,
-    gamma_correction: fn(f32) -> f32,
+    gamma_correction: &GammaCorrection,
 ) {
     if !(0.0..=1.0).contains(&brightness) {
         warn!(
@@ -126,7 +161,7 @@ fn set_brightness(
         );
         brightness = brightness.clamp(0.0, 1.0);
     }
-    let mut corrected_brightness = gamma_correction(brightness);
+    let mut corrected_brightness = gamma_correction.apply(brightness, Instant::now());
     if !(0.0..=1.0).contains(&corrected_brightness) {
         warn!(
             "corrected brightness {} is out of range (expected a value between 0.0 and 1.0)",
@@ -148,19 +183,25 @@ fn deactivate_pwm_channels(pwm_channels: &mut [&mut dyn PwmChannel]) {
 struct ActionHandler<'a, 'b, const N: usize> {
     actions: &'a mut ArrayVec<(ActionId, Action, Option<Instant>), ACTION_SLOTS>,
     pwm_channels: &'a mut [&'b mut dyn PwmChannel; N],
-    gamma_correction: fn(f32) -> f32,
+    gamma_correction: &'a GammaCorrection,
+    brightness: f32,
+    slide_steps_per_led: u16,
 }
 
 impl<'a, 'b, const N: usize> ActionHandler<'a, 'b, N> {
     fn new(
         actions: &'a mut ArrayVec<(ActionId, Action, Option<Instant>), ACTION_SLOTS>,
         pwm_channels: &'a mut [&'b mut dyn PwmChannel; N],
-        gamma_correction: fn(f32) -> f32,
+        gamma_correction: &'a GammaCorrection,
+        brightness: f32,
+        slide_steps_per_led: u16,
     ) -> Self {
         Self {
             actions,
             pwm_channels,
             gamma_correction,
+            brightness,
+            slide_steps_per_led,
         }
     }
 
@@ -205,6 +246,17 @@ impl<'a, 'b, const N: usize> ActionHandler<'a, 'b, N> {
                 let count = *count;
                 self.activate_slide(duration_ms, count, true, started).await;
             }
+            Action::Blink {
+                indices_bitmask,
+                times,
+                period_ms,
+            } => {
+                let indices_bitmask = *indices_bitmask;
+                let times = *times;
+                let period_ms = *period_ms;
+                self.activate_blink(indices_bitmask, times, period_ms, started)
+                    .await;
+            }
             Action::Stop { action_id } => {
                 let action_id = action_id.clone();
                 self.stop(action_id);
@@ -224,17 +276,7 @@ impl<'a, 'b, const N: usize> ActionHandler<'a, 'b, N> {
                 .unwrap_or(Duration::from_ticks(0))
         });
         if remaining.is_none_or(|v| v > Duration::from_ticks(0)) {
-            for i in 0..64 {
-                if indices_bitmask & (1 << i) > 0 {
-                    match self.pwm_channels.get_mut(i) {
-                        Some(pwm_channel) => {
-                            pwm_channel.enable();
-                            pwm_channel.set_duty(0);
-                        }
-                        None => warn!("PWM channel with index {} does not exist", i),
-                    }
-                }
-            }
+            self.activate_indices(indices_bitmask);
         }
         match remaining {
             Some(v) => Timer::after(v).await,
@@ -244,6 +286,49 @@ impl<'a, 'b, const N: usize> ActionHandler<'a, 'b, N> {
         self.actions.pop();
     }
 
+    fn activate_indices(&mut self, indices_bitmask: u64) {
+        for i in 0..64 {
+            if indices_bitmask & (1 << i) > 0 {
+                match self.pwm_channels.get_mut(i) {
+                    Some(pwm_channel) => {
+                        pwm_channel.enable();
+                        set_brightness(*pwm_channel, self.brightness, self.gamma_correction);
+                    }
+                    None => warn!("PWM channel with index {} does not exist", i),
+                }
+            }
+        }
+    }
+
+    async fn activate_blink(
+        &mut self,
+        indices_bitmask: u64,
+        times: u16,
+        period_ms: u16,
+        started: Instant,
+    ) {
+        let period = Duration::from_millis(period_ms.into());
+        let total = period * u32::from(times);
+        // The phase is derived from the start of the action, so that the blinking continues where
+        // it was when the action was preempted by another action.
+        loop {
+            let elapsed = Instant::now().duration_since(started);
+            if elapsed >= total {
+                break;
+            }
+            let offset = Duration::from_ticks(elapsed.as_ticks() % period.as_ticks());
+            if offset < period / 2 {
+                self.activate_indices(indices_bitmask);
+                Timer::after(period / 2 - offset).await;
+            } else {
+                self.deactivate();
+                Timer::after(period - offset).await;
+            }
+        }
+        self.deactivate();
+        self.actions.pop();
+    }
+
     async fn activate_progress(&mut self, value: u16, timeout_ms: Option<u16>, started: Instant) {
         let remaining = timeout_ms.map(|timeout_ms| {
             Duration::from_millis(timeout_ms.into())
@@ -261,7 +346,11 @@ impl<'a, 'b, const N: usize> ActionHandler<'a, 'b, N> {
                     (value - max * i as u16) as f32 / max as f32
                 };
                 pwm_channel.enable();
-                set_brightness(*pwm_channel, brightness, self.gamma_correction);
+                set_brightness(
+                    *pwm_channel,
+                    brightness * self.brightness,
+                    self.gamma_correction,
+                );
             }
         }
         match remaining {
@@ -273,21 +362,20 @@ impl<'a, 'b, const N: usize> ActionHandler<'a, 'b, N> {
     }
 
     async fn activate_single_slide(&mut self, duration_ms: u16, skip_ms: u16, reverse: bool) {
-        // TODO: Don't hardcode RANGE (num_updates_per_led)
-        const RANGE: isize = 200;
-        let num_updates = RANGE as usize * (N + 1);
+        let range = self.slide_steps_per_led.max(1) as isize;
+        let num_updates = range as usize * (N + 1);
 
         let wait_duration = Duration::from_millis(duration_ms.into()) / num_updates as u32;
         for pwm_channel in self.pwm_channels.iter_mut() {
             pwm_channel.enable();
         }
 
-        fn calculate_brightness(update_num: usize, pwm_channel_index: usize) -> f32 {
-            let value = update_num as isize - (pwm_channel_index as isize * RANGE + RANGE);
-            let value = (RANGE - value.abs()).clamp(0, RANGE);
-            let brightness = value as f32 / RANGE as f32;
+        let calculate_brightness = |update_num: usize, pwm_channel_index: usize| -> f32 {
+            let value = update_num as isize - (pwm_channel_index as isize * range + range);
+            let value = (range - value.abs()).clamp(0, range);
+            let brightness = value as f32 / range as f32;
             1.0 - (1.0 - brightness) * (1.0 - brightness)
-        }
+        };
 
         let factor = skip_ms as f32 / duration_ms as f32;
         let start = (factor * num_updates as f32) as usize;
@@ -295,12 +383,12 @@ impl<'a, 'b, const N: usize> ActionHandler<'a, 'b, N> {
             let started = Instant::now();
             if reverse {
                 for (i, pwm_channel) in self.pwm_channels.iter_mut().rev().enumerate() {
-                    let brightness = calculate_brightness(update_num, i);
+                    let brightness = calculate_brightness(update_num, i) * self.brightness;
                     set_brightness(*pwm_channel, brightness, self.gamma_correction);
                 }
             } else {
                 for (i, pwm_channel) in self.pwm_channels.iter_mut().enumerate() {
-                    let brightness = calculate_brightness(update_num, i);
+                    let brightness = calculate_brightness(update_num, i) * self.brightness;
                     set_brightness(*pwm_channel, brightness, self.gamma_correction);
                 }
             }
@@ -364,6 +452,7 @@ impl<'a, 'b, const N: usize> ActionHandler<'a, 'b, N> {
 pub struct LedArray<const NUM_LEDS: usize, Hooks> {
     context: DynContext,
     gamma_correction: fn(f32) -> f32,
+    slide_steps_per_led: u16,
     hook_bundle: Hooks,
 }
 
@@ -374,17 +463,78 @@ impl<const NUM_LEDS: usize, Hooks: HookBundle> LedArray<NUM_LEDS, Hooks> {
         Self {
             context,
             gamma_correction: default_gamma_correction,
+            slide_steps_per_led: DEFAULT_SLIDE_STEPS_PER_LED,
             hook_bundle,
         }
     }
 
+    /// Sets the gamma correction curve (defaults to [`default_gamma_correction`]).
+    ///
+    /// The [`gamma`] module contains a few presets. The curve can be changed at runtime with a
+    /// [`GammaCorrectionMessage`].
     pub const fn gamma_correction(mut self, f: fn(f32) -> f32) -> Self {
         self.gamma_correction = f;
         self
     }
 
-    pub async fn run(self, mut pwm_channels: [&mut dyn PwmChannel; NUM_LEDS]) {
+    /// Sets the number of brightness updates per LED of the slide animations (defaults to 200).
+    ///
+    /// More updates make the animations smoother, but the brightness of all LEDs is set on every
+    /// update, so slower MCUs may not be able to keep up with the duration of the animation. The
+    /// duration of a slide is the same regardless of this value.
+    pub const fn slide_steps_per_led(mut self, value: u16) -> Self {
+        self.slide_steps_per_led = value;
+        self
+    }
+
+    pub async fn run(self, pwm_channels: [&mut dyn PwmChannel; NUM_LEDS]) {
+        self.run_inner(pwm_channels, None::<&EmptyStorage>).await;
+    }
+
+    /// Runs the LED array and persists its [`LedArraySettings`] in the specified storage.
+    ///
+    /// The settings are loaded from the storage at startup and written back after the brightness
+    /// was changed by a [`BrightnessMessage`].
+    pub async fn run_with_storage<St: Storage>(
+        self,
+        pwm_channels: [&mut dyn PwmChannel; NUM_LEDS],
+        storage: &St,
+    ) {
+        self.run_inner(pwm_channels, Some(storage)).await;
+    }
+
+    async fn run_inner<St: Storage>(
+        self,
+        mut pwm_channels: [&mut dyn PwmChannel; NUM_LEDS],
+        storage: Option<&St>,
+    ) {
+        let mut settings = match storage {
+            Some(storage) => match storage.fetch::<LedArraySettings>(()).await {
+                Ok(v) => v.unwrap_or_default(),
+                Err(e) => {
+                    #[cfg(feature = "defmt")]
+                    let e = defmt::Debug2Format(&e);
+                    error!("Failed to load LED array settings: {}", e);
+                    LedArraySettings::default()
+                }
+            },
+            None => LedArraySettings::default(),
+        };
+        let stored_settings = settings;
+        let changed_settings = Signal::<NoopRawMutex, LedArraySettings>::new();
+
         let mut receiver = unwrap!(self.context.internal_channel.receiver::<Message>());
+        let mut brightness_receiver = unwrap!(
+            self.context
+                .internal_channel
+                .receiver::<BrightnessMessage>()
+        );
+        let mut gamma_correction_receiver = unwrap!(
+            self.context
+                .internal_channel
+                .receiver::<GammaCorrectionMessage>()
+        );
+        let mut gamma_correction = GammaCorrection::new(self.gamma_correction);
         let mut actions = ArrayVec::<(ActionId, Action, Option<Instant>), ACTION_SLOTS>::new();
         deactivate_pwm_channels(&mut pwm_channels);
         let handle_messages = async {
@@ -400,21 +550,85 @@ impl<const NUM_LEDS: usize, Hooks: HookBundle> LedArray<NUM_LEDS, Hooks> {
                         break (message.action_id, message.action);
                     }
                 };
+                gamma_correction.remove_finished_transitions(Instant::now());
+                let gamma_correction_transitioning =
+                    gamma_correction.is_transitioning(Instant::now());
                 let handle = async {
-                    ActionHandler::new(&mut actions, &mut pwm_channels, self.gamma_correction)
-                        .run()
-                        .await;
+                    let mut handler = ActionHandler::new(
+                        &mut actions,
+                        &mut pwm_channels,
+                        &gamma_correction,
+                        settings.brightness_factor(),
+                        self.slide_steps_per_led,
+                    );
+                    let run = handler.run();
+                    // The actions resume where they were when they are restarted, so restarting
+                    // them periodically updates LEDs with a constant brightness during the
+                    // transition of the gamma correction curve.
+                    if gamma_correction_transitioning {
+                        select(run, Timer::after(GAMMA_TRANSITION_UPDATE_INTERVAL)).await;
+                    } else {
+                        run.await;
+                    }
                 };
 
-                if let Either::First((action_id, action)) = select(recv, handle).await {
-                    actions.push((action_id, action, None));
+                match select4(
+                    recv,
+                    brightness_receiver.next(),
+                    gamma_correction_receiver.next(),
+                    handle,
+                )
+                .await
+                {
+                    Either4::First((action_id, action)) => actions.push((action_id, action, None)),
+                    Either4::Second(message) => {
+                        let brightness = message.apply(settings.brightness);
+                        if brightness != settings.brightness {
+                            settings.brightness = brightness;
+                            changed_settings.signal(settings);
+                        }
+                    }
+                    Either4::Third(message) => {
+                        let f = match message {
+                            GammaCorrectionMessage::Set(curve) => curve.function(),
+                            GammaCorrectionMessage::Reset => self.gamma_correction,
+                        };
+                        gamma_correction.switch_to(f, Instant::now());
+                    }
+                    Either4::Fourth(()) => {}
                 }
             }
         };
 
+        let persist_settings = async {
+            let Some(storage) = storage else {
+                return;
+            };
+            let mut stored_settings = stored_settings;
+            loop {
+                let mut settings = changed_settings.wait().await;
+                while let Either::Second(v) =
+                    select(Timer::after(PERSIST_DELAY), changed_settings.wait()).await
+                {
+                    settings = v;
+                }
+                if settings == stored_settings {
+                    continue;
+                }
+                debug!("Writing LED array settings to flash");
+                if let Err(e) = storage.store((), &settings).await {
+                    #[cfg(feature = "defmt")]
+                    let e = defmt::Debug2Format(&e);
+                    error!("Failed to write LED array settings to flash: {}", e);
+                    continue;
+                }
+                stored_settings = settings;
+            }
+        };
+
         let run_hooks = self.hook_bundle.run_all::<NUM_LEDS>(self.context);
 
-        join(handle_messages, run_hooks).await;
+        join3(handle_messages, run_hooks, persist_settings).await;
     }
 }
 
@@ -466,6 +680,82 @@ impl Hook for BootHook {
     }
 }
 
+/// Hook that shows the battery level with the [`Action::Progress`] animation.
+///
+/// The battery level is shown whenever a [`BatteryLevel`] message is received (i.e. whenever the
+/// battery monitor of the device measured a different percentage) and, if an interval is set,
+/// periodically with the most recently received level. The LEDs are always turned off again after
+/// the timeout, so that showing the battery level does not drain the battery.
+pub struct BatteryHook {
+    timeout_ms: u16,
+    interval: Option<Duration>,
+}
+
+impl Default for BatteryHook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BatteryHook {
+    pub const fn new() -> Self {
+        Self {
+            timeout_ms: 2000,
+            interval: None,
+        }
+    }
+
+    /// Sets how long the battery level is shown (defaults to 2000ms).
+    pub const fn timeout_ms(mut self, value: u16) -> Self {
+        self.timeout_ms = value;
+        self
+    }
+
+    /// Sets the interval in which the most recently received battery level is shown again
+    /// (disabled by default).
+    pub const fn interval(mut self, value: Duration) -> Self {
+        self.interval = Some(value);
+        self
+    }
+}
+
+impl Hook for BatteryHook {
+    async fn run<const NUM_LEDS: usize>(self, context: DynContext) {
+        let mut receiver = unwrap!(context.internal_channel.receiver::<BatteryLevel>());
+        let mut percentage = None;
+        loop {
+            let interval_elapsed = async {
+                match self.interval {
+                    Some(v) => Timer::after(v).await,
+                    None => core::future::pending().await,
+                }
+            };
+            match select(receiver.next(), interval_elapsed).await {
+                Either::First(message) => percentage = Some(message.percentage),
+                Either::Second(()) => {}
+            }
+            // Nothing is shown until the first battery level was received.
+            let Some(percentage) = percentage else {
+                continue;
+            };
+            let action_id = ActionId::new(context.address);
+            let action = Action::Progress {
+                value: progress_value(percentage),
+                timeout_ms: Some(self.timeout_ms),
+            };
+            context
+                .internal_channel
+                .send(Message::new(action_id, action))
+                .await;
+        }
+    }
+}
+
+/// Converts a battery percentage to the value of an [`Action::Progress`].
+fn progress_value(percentage: u8) -> u16 {
+    (u32::from(percentage.min(100)) * u32::from(u16::MAX) / 100) as u16
+}
+
 #[cfg(feature = "ble")]
 pub use ble::{BleAdvertisementHook, BleProfileHook};
 
@@ -548,6 +838,124 @@ mod ble {
     }
 }
 
+#[cfg(feature = "layer")]
+pub use layer::LayerHook;
+
+#[cfg(feature = "layer")]
+mod layer {
+    use super::*;
+    use lokey_layer::{LayerChanged, LayerId};
+
+    /// Hook that lights up the LED of the active layer when the active layer changes.
+    ///
+    /// The hook receives the [`LayerChanged`] messages that are sent by the
+    /// [`LayerChangeNotifier`](lokey_layer::LayerChangeNotifier) component, which has to be
+    /// enabled on the device that has the [`LayerManager`](lokey_layer::LayerManager) (e.g. the
+    /// central of a split keyboard).
+    pub struct LayerHook {
+        mapping: Option<&'static [(LayerId, usize)]>,
+        timeout_ms: Option<u16>,
+    }
+
+    impl Default for LayerHook {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl LayerHook {
+        /// Creates a new [`LayerHook`] that lights up the LED whose index is the ID of the layer.
+        pub const fn new() -> Self {
+            Self {
+                mapping: None,
+                timeout_ms: Some(1000),
+            }
+        }
+
+        /// Sets the indices of the LEDs that are lit up for the layers.
+        ///
+        /// Nothing is shown for layers that are not contained in the mapping. A layer can be
+        /// contained multiple times to light up multiple LEDs.
+        pub const fn mapping(mut self, value: &'static [(LayerId, usize)]) -> Self {
+            self.mapping = Some(value);
+            self
+        }
+
+        /// Sets how long the LEDs are lit up after the layer changed (defaults to 1000ms).
+        ///
+        /// If set to `None`, the LEDs stay lit up until the layer changes again.
+        pub const fn timeout_ms(mut self, value: Option<u16>) -> Self {
+            self.timeout_ms = value;
+            self
+        }
+
+        fn indices_bitmask<const NUM_LEDS: usize>(&self, layer: LayerId) -> u64 {
+            let mut indices_bitmask = 0;
+            let mut add = |index: usize| {
+                if index < NUM_LEDS.min(64) {
+                    indices_bitmask |= 1 << index;
+                } else {
+                    warn!("No LED with index {} for layer {}", index, layer.0);
+                }
+            };
+            match self.mapping {
+                Some(mapping) => mapping
+                    .iter()
+                    .filter(|(v, _)| *v == layer)
+                    .for_each(|(_, index)| add(*index)),
+                None => add(layer.0.into()),
+            }
+            indices_bitmask
+        }
+    }
+
+    impl Hook for LayerHook {
+        async fn run<const NUM_LEDS: usize>(self, context: DynContext) {
+            let mut receiver = unwrap!(context.internal_channel.receiver::<LayerChanged>());
+            let mut current_action = None;
+            loop {
+                let LayerChanged { layer } = receiver.next().await;
+                let indices_bitmask = self.indices_bitmask::<NUM_LEDS>(layer);
+                if indices_bitmask != 0 {
+                    let action_id = ActionId::new(context.address);
+                    let action = Action::Individual {
+                        indices_bitmask,
+                        timeout_ms: self.timeout_ms,
+                    };
+                    context
+                        .internal_channel
+                        .send(Message::new(action_id.clone(), action))
+                        .await;
+                    let previous_action = current_action.replace((action_id, Instant::now()));
+                    stop_if_active(context, previous_action, self.timeout_ms).await;
+                } else {
+                    stop_if_active(context, current_action.take(), self.timeout_ms).await;
+                }
+            }
+        }
+    }
+
+    /// Stops the action of the previous layer if its timeout has not elapsed yet.
+    async fn stop_if_active(
+        context: DynContext,
+        action: Option<(ActionId, Instant)>,
+        timeout_ms: Option<u16>,
+    ) {
+        let Some((action_id, sent_at)) = action else {
+            return;
+        };
+        if timeout_ms.is_some_and(|v| sent_at.elapsed() >= Duration::from_millis(v.into())) {
+            return;
+        }
+        let new_action_id = ActionId::new(context.address);
+        let action = Action::Stop { action_id };
+        context
+            .internal_channel
+            .send(Message::new(new_action_id, action))
+            .await;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -555,7 +963,16 @@ mod tests {
     #[test]
     fn serialized_max_size() {
         assert_eq!(ActionId::POSTCARD_MAX_SIZE, 11);
-        assert_eq!(Action::POSTCARD_MAX_SIZE, 15);
-        assert_eq!(Message::POSTCARD_MAX_SIZE, 33);
+        assert_eq!(Action::POSTCARD_MAX_SIZE, 17);
+        assert_eq!(Message::POSTCARD_MAX_SIZE, 35);
+        assert_eq!(BrightnessMessage::POSTCARD_MAX_SIZE, 2);
+        assert_eq!(GammaCorrectionMessage::POSTCARD_MAX_SIZE, 2);
+    }
+
+    #[test]
+    fn battery_progress_value() {
+        assert_eq!(progress_value(0), 0);
+        assert_eq!(progress_value(50), u16::MAX / 2);
+        assert_eq!(progress_value(100), u16::MAX);
     }
 }
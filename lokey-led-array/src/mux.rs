@@ -0,0 +1,212 @@
+use core::cell::RefCell;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embedded_hal::digital::{ErrorType, OutputPin};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChannelState {
+    Released,
+    On,
+    Off,
+}
+
+struct Inner<P, const N: usize> {
+    pin: P,
+    states: [ChannelState; N],
+}
+
+impl<P: OutputPin, const N: usize> Inner<P, N> {
+    fn apply(&mut self) -> Result<(), P::Error> {
+        let state = self
+            .states
+            .iter()
+            .find(|v| **v != ChannelState::Released)
+            .copied()
+            .unwrap_or(ChannelState::Off);
+        match state {
+            ChannelState::On => self.pin.set_high(),
+            ChannelState::Released | ChannelState::Off => self.pin.set_low(),
+        }
+    }
+}
+
+/// Shares a single LED pin between multiple components.
+///
+/// The mux provides `N` channels that implement [`OutputPin`], so they can be used in place of the
+/// physical pin (e.g. by a blink component and a connection indicator at the same time). The pin is
+/// driven by the channel with the highest priority that is currently not released, where the
+/// channel with index 0 has the highest priority. If all channels are released, the LED is turned
+/// off.
+///
+/// A channel claims the LED with [`OutputPin::set_high`] and releases it again with
+/// [`OutputPin::set_low`], so that generic [`OutputPin`] users (e.g. a blink component) only
+/// control the LED while they turn it on. To keep the LED turned off regardless of the channels
+/// with a lower priority, a channel can claim it with [`LedMuxChannel::force_off`]. A channel is
+/// released as well when it is dropped.
+///
+/// # Example
+///
+/// ```ignore
+/// use lokey_led_array::mux::LedMux;
+/// use static_cell::StaticCell;
+///
+/// static LED_MUX: StaticCell<LedMux<Output<'static>, 2>> = StaticCell::new();
+/// let led_mux = LED_MUX.init(LedMux::new(Output::new(pin, Level::Low, OutputDrive::Standard)));
+///
+/// // The connection indicator takes precedence over the blink component while it is not released.
+/// let connection_indicator = led_mux.channel(0);
+/// let blink = led_mux.channel(1);
+/// ```
+pub struct LedMux<P, const N: usize> {
+    inner: Mutex<CriticalSectionRawMutex, RefCell<Inner<P, N>>>,
+}
+
+impl<P: OutputPin, const N: usize> LedMux<P, N> {
+    /// Creates a new [`LedMux`] where all channels are released.
+    pub const fn new(pin: P) -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(Inner {
+                pin,
+                states: [ChannelState::Released; N],
+            })),
+        }
+    }
+
+    /// Returns the channel with the specified index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is not smaller than `N`.
+    pub fn channel(&self, index: usize) -> LedMuxChannel<'_, P, N> {
+        assert!(index < N, "LED mux channel index out of bounds");
+        LedMuxChannel { mux: self, index }
+    }
+
+    fn set_state(&self, index: usize, state: ChannelState) -> Result<(), P::Error> {
+        self.inner.lock(|inner| {
+            let mut inner = inner.borrow_mut();
+            inner.states[index] = state;
+            inner.apply()
+        })
+    }
+}
+
+/// A channel of a [`LedMux`].
+pub struct LedMuxChannel<'a, P: OutputPin, const N: usize> {
+    mux: &'a LedMux<P, N>,
+    index: usize,
+}
+
+impl<P: OutputPin, const N: usize> LedMuxChannel<'_, P, N> {
+    /// Claims the LED and turns it off, so that channels with a lower priority can not turn it on.
+    ///
+    /// The LED is claimed until the channel is released with [`LedMuxChannel::release`] or
+    /// [`OutputPin::set_low`], or claimed again with [`OutputPin::set_high`].
+    pub fn force_off(&mut self) -> Result<(), P::Error> {
+        self.mux.set_state(self.index, ChannelState::Off)
+    }
+
+    /// Releases the channel, so that channels with a lower priority control the LED again.
+    ///
+    /// This is the same as [`OutputPin::set_low`].
+    pub fn release(&mut self) -> Result<(), P::Error> {
+        self.mux.set_state(self.index, ChannelState::Released)
+    }
+}
+
+impl<P: OutputPin, const N: usize> Drop for LedMuxChannel<'_, P, N> {
+    fn drop(&mut self) {
+        // The error can not be returned here, but the next state change drives the pin again.
+        let _ = self.release();
+    }
+}
+
+impl<P: OutputPin, const N: usize> ErrorType for LedMuxChannel<'_, P, N> {
+    type Error = P::Error;
+}
+
+impl<P: OutputPin, const N: usize> OutputPin for LedMuxChannel<'_, P, N> {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.release()
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.mux.set_state(self.index, ChannelState::On)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+
+    struct MockPin<'a>(&'a core::cell::Cell<bool>);
+
+    impl ErrorType for MockPin<'_> {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for MockPin<'_> {
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.0.set(false);
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.0.set(true);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn highest_priority_channel_drives_pin() {
+        let is_high = core::cell::Cell::new(false);
+        let mux = LedMux::<_, 3>::new(MockPin(&is_high));
+        let mut high = mux.channel(0);
+        let mut medium = mux.channel(1);
+        let mut low = mux.channel(2);
+
+        low.set_high().unwrap();
+        assert!(is_high.get());
+        high.force_off().unwrap();
+        assert!(!is_high.get());
+        medium.set_high().unwrap();
+        assert!(!is_high.get());
+        high.release().unwrap();
+        assert!(is_high.get());
+        medium.set_low().unwrap();
+        assert!(is_high.get());
+        low.set_low().unwrap();
+        assert!(!is_high.get());
+    }
+
+    #[test]
+    fn set_low_releases_channel() {
+        let is_high = core::cell::Cell::new(false);
+        let mux = LedMux::<_, 2>::new(MockPin(&is_high));
+        let mut indicator = mux.channel(0);
+        let mut blink = mux.channel(1);
+
+        indicator.set_high().unwrap();
+        blink.set_low().unwrap();
+        assert!(is_high.get());
+        indicator.set_low().unwrap();
+        assert!(!is_high.get());
+        blink.set_high().unwrap();
+        assert!(is_high.get());
+    }
+
+    #[test]
+    fn dropped_channel_is_released() {
+        let is_high = core::cell::Cell::new(false);
+        let mux = LedMux::<_, 2>::new(MockPin(&is_high));
+        let mut low = mux.channel(1);
+        low.set_high().unwrap();
+
+        let mut high = mux.channel(0);
+        high.force_off().unwrap();
+        assert!(!is_high.get());
+        drop(high);
+        assert!(is_high.get());
+    }
+}
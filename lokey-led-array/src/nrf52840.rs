@@ -1,3 +1,5 @@
 mod pwm;
+mod ws2812;
 
 pub use pwm::{Pwm, PwmChannel};
+pub use ws2812::Ws2812I2s;
@@ -0,0 +1,117 @@
+use crate::led_strip::{LedStrip, Rgb};
+use embassy_nrf::i2s::OutputStream;
+use lokey::util::error;
+
+/// The number of I2S bits that encode a single WS2812 bit.
+const BITS_PER_LED_BIT: usize = 3;
+
+/// The number of WS2812 bits per LED.
+const LED_BITS_PER_LED: usize = 24;
+
+/// The number of bits per I2S sample.
+const BITS_PER_SAMPLE: usize = 16;
+
+/// The number of zero samples that are sent after the colors to latch them.
+///
+/// With a bit rate of 2.909MHz, 56 samples take about 308µs, which is longer than the reset time of
+/// all WS2812 variants (50µs for the original WS2812, 280µs for newer revisions).
+const RESET_SAMPLES: usize = 56;
+
+/// WS2812 driver that uses the I2S peripheral of the nRF52840.
+///
+/// Every WS2812 bit is encoded as three I2S bits (`100` for a zero bit and `110` for a one bit).
+/// The I2S peripheral must be configured as a master with a bit rate between 2.4MHz and 3MHz (e.g.
+/// 2.909MHz with a 32MHz / 11 master clock), 16 bit samples and stereo output, so that the pulse
+/// widths are within the tolerances of the WS2812 timing. The colors are encoded
+/// while the I2S peripheral transmits the previous buffer (double buffering), so the buffer size
+/// `NS` can be smaller than the encoded frame.
+///
+/// # Example
+///
+/// ```ignore
+/// use embassy_nrf::i2s::{self, I2S, MasterClock, MultiBuffering};
+/// use lokey_led_array::nrf52840::Ws2812I2s;
+///
+/// let mut config = i2s::Config::default();
+/// config.sample_width = i2s::SampleWidth::_16bit;
+/// config.align = i2s::Align::Left;
+/// config.format = i2s::Format::Aligned;
+/// config.channels = i2s::Channels::Stereo;
+/// // 32MHz / 11 = 2.909MHz bit rate
+/// let master_clock = MasterClock::new(i2s::MckFreq::_32MDiv11, i2s::Ratio::_32x);
+/// let stream = I2S::new_master(p.I2S, Irqs, p.P0_25, p.P0_26, p.P0_27, master_clock, config)
+///     .output(p.P0_06, MultiBuffering::<i16, 2, 64>::new());
+/// let led_strip = Ws2812I2s::new(stream);
+/// ```
+pub struct Ws2812I2s<'d, const NS: usize> {
+    stream: OutputStream<'d, i16, 2, NS>,
+}
+
+impl<'d, const NS: usize> Ws2812I2s<'d, NS> {
+    pub fn new(stream: OutputStream<'d, i16, 2, NS>) -> Self {
+        Self { stream }
+    }
+}
+
+impl<const N: usize, const NS: usize> LedStrip<N> for Ws2812I2s<'_, NS> {
+    async fn write(&mut self, colors: &[Rgb; N]) {
+        let data_samples = (N * LED_BITS_PER_LED * BITS_PER_LED_BIT).div_ceil(BITS_PER_SAMPLE);
+        let num_samples = data_samples + RESET_SAMPLES;
+        let mut started = false;
+        for offset in (0..num_samples).step_by(NS) {
+            encode(colors, offset, self.stream.buffer());
+            let result = if started {
+                self.stream.send().await
+            } else {
+                started = true;
+                self.stream.start().await
+            };
+            if let Err(e) = result {
+                #[cfg(feature = "defmt")]
+                let e = defmt::Debug2Format(&e);
+                error!("Failed to send WS2812 data: {:?}", e);
+                self.stream.stop().await;
+                return;
+            }
+        }
+        // Queue another buffer of zeros, which waits until the last buffer of the frame was
+        // transmitted before the stream is stopped.
+        encode(colors, num_samples, self.stream.buffer());
+        let _ = self.stream.send().await;
+        self.stream.stop().await;
+    }
+}
+
+/// Encodes the samples of the frame starting at the sample with the index `offset` into `buf`.
+///
+/// Samples after the end of the encoded colors are set to zero, which keeps the data line low.
+fn encode(colors: &[Rgb], offset: usize, buf: &mut [i16]) {
+    for (i, sample) in buf.iter_mut().enumerate() {
+        let mut value = 0u16;
+        for bit in 0..BITS_PER_SAMPLE {
+            let stream_bit = (offset + i) * BITS_PER_SAMPLE + bit;
+            if stream_bit_is_high(colors, stream_bit) {
+                value |= 1 << (BITS_PER_SAMPLE - 1 - bit);
+            }
+        }
+        *sample = value as i16;
+    }
+}
+
+fn stream_bit_is_high(colors: &[Rgb], stream_bit: usize) -> bool {
+    let led_bit = stream_bit / BITS_PER_LED_BIT;
+    let led_index = led_bit / LED_BITS_PER_LED;
+    let Some(color) = colors.get(led_index) else {
+        return false;
+    };
+    match stream_bit % BITS_PER_LED_BIT {
+        0 => true,
+        1 => {
+            let bit_in_led = led_bit % LED_BITS_PER_LED;
+            // WS2812 LEDs expect the colors in GRB order, most significant bit first.
+            let byte = [color.g, color.r, color.b][bit_in_led / 8];
+            byte & (0x80 >> (bit_in_led % 8)) != 0
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,385 @@
+//! Per-key lighting with addressable RGB LEDs.
+//!
+//! The [`RgbMatrix`] component drives a [`LedStrip`] and uses a [`KeyLedMap`] to associate the
+//! keys of a keyboard with the LEDs of the strip. Keys light up when a [`Message`] is received,
+//! which is sent by the [`Reactive`] action if the `keyboard-actions` feature is enabled.
+
+use crate::led_strip::{LedStrip, Rgb};
+use embassy_futures::select::{Either, select};
+use embassy_time::{Duration, Instant, Timer};
+use generic_array::GenericArray;
+use lokey::util::unwrap;
+use lokey::{Component, DynContext, internal};
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+/// The interval in which the colors are updated while a key is fading out.
+const FRAME_INTERVAL: Duration = Duration::from_millis(20);
+
+/// The order in which the LEDs of a row are connected in [`KeyLedMap::map_rows_and_cols`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedWiring {
+    /// The LEDs of every row are connected from the first to the last column.
+    Linear,
+    /// The LEDs of every odd row are connected from the last to the first column, so that the
+    /// data line zigzags through the rows.
+    Serpentine,
+}
+
+/// Maps the index of a key to the index of its LED in a [`LedStrip`].
+///
+/// Keys without a mapped LED are ignored by the [`RgbMatrix`]. This allows the halves of a split
+/// keyboard to only map the keys that are located on the respective half.
+///
+/// # Example
+///
+/// ```
+/// use lokey_led_array::rgb_matrix::{KeyLedMap, LedWiring};
+///
+/// // A 3x5 key matrix with LEDs that are wired in a zigzag, followed by a single thumb key
+/// const KEY_LED_MAP: KeyLedMap<16> = KeyLedMap::new()
+///     .map_rows_and_cols::<3, 5>(0, 0, LedWiring::Serpentine)
+///     .map_next(15);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct KeyLedMap<const NUM_KEYS: usize> {
+    leds: [Option<usize>; NUM_KEYS],
+}
+
+impl<const NUM_KEYS: usize> Default for KeyLedMap<NUM_KEYS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const NUM_KEYS: usize> KeyLedMap<NUM_KEYS> {
+    /// Creates a new map where no key has an LED.
+    pub const fn new() -> Self {
+        Self {
+            leds: [None; NUM_KEYS],
+        }
+    }
+
+    /// Maps the key with the specified index to the LED with the specified index.
+    pub const fn map(mut self, key_index: usize, led_index: usize) -> Self {
+        self.leds[key_index] = Some(led_index);
+        self
+    }
+
+    /// Maps the first key that does not have an LED yet to the LED with the specified index.
+    pub const fn map_next(mut self, led_index: usize) -> Self {
+        // Use while loops so that the function can be `const`
+        let mut i = 0;
+        while i < self.leds.len() {
+            if self.leds[i].is_none() {
+                self.leds[i] = Some(led_index);
+                break;
+            }
+            i += 1;
+        }
+        self
+    }
+
+    /// Maps a grid of keys, whose indices are in row-major order starting at `start_key_index`, to
+    /// consecutive LEDs starting at `start_led_index`.
+    pub const fn map_rows_and_cols<const NUM_ROWS: usize, const NUM_COLS: usize>(
+        mut self,
+        mut start_key_index: usize,
+        start_led_index: usize,
+        wiring: LedWiring,
+    ) -> Self {
+        // Use while loops so that the function can be `const`
+        let mut i = 0;
+        while i < NUM_ROWS {
+            let mut j = 0;
+            while j < NUM_COLS {
+                let col = match wiring {
+                    LedWiring::Serpentine if i % 2 == 1 => NUM_COLS - 1 - j,
+                    LedWiring::Linear | LedWiring::Serpentine => j,
+                };
+                self.leds[start_key_index] = Some(start_led_index + i * NUM_COLS + col);
+                start_key_index += 1;
+                j += 1;
+            }
+            i += 1;
+        }
+        self
+    }
+
+    /// Returns the index of the LED of the key with the specified index.
+    pub const fn led_index(&self, key_index: usize) -> Option<usize> {
+        if key_index < NUM_KEYS {
+            self.leds[key_index]
+        } else {
+            None
+        }
+    }
+}
+
+/// Internal message that changes the color of a single key.
+#[derive(Debug, Clone, Serialize, Deserialize, MaxSize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Message {
+    /// Lights up the key with the specified color until it is released.
+    Press {
+        key_index: u16,
+        color: Rgb,
+        /// The duration in milliseconds in which the color fades to the background after the key
+        /// is released.
+        fade_out_ms: u16,
+    },
+    /// Starts fading out the color of the key.
+    Release { key_index: u16 },
+}
+
+impl internal::Message for Message {
+    type Size = typenum::U10;
+
+    const TAG: [u8; 4] = [0x5c, 0x0e, 0x93, 0xb1];
+
+    fn from_bytes(bytes: GenericArray<u8, Self::Size>) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        postcard::from_bytes(&bytes).ok()
+    }
+
+    fn to_bytes(&self) -> GenericArray<u8, Self::Size> {
+        let mut buf = GenericArray::default();
+        postcard::to_slice(self, &mut buf).expect("buffer is too small");
+        buf
+    }
+}
+
+#[derive(Clone, Copy)]
+struct LitKey {
+    color: Rgb,
+    fade_out: Duration,
+    released: Option<Instant>,
+}
+
+/// Returns the color that is `progress` (between 0.0 and 1.0) of the way from `from` to `to`.
+fn blend(from: Rgb, to: Rgb, progress: f32) -> Rgb {
+    let channel = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * progress) as u8;
+    Rgb::new(
+        channel(from.r, to.r),
+        channel(from.g, to.g),
+        channel(from.b, to.b),
+    )
+}
+
+/// Component that lights up the LEDs of individual keys.
+///
+/// All LEDs show the background color, unless the key of an LED was lit up by a [`Message`].
+pub struct RgbMatrix<const NUM_KEYS: usize, const NUM_LEDS: usize> {
+    context: DynContext,
+    key_led_map: KeyLedMap<NUM_KEYS>,
+    background: Rgb,
+}
+
+impl<const NUM_KEYS: usize, const NUM_LEDS: usize> Component for RgbMatrix<NUM_KEYS, NUM_LEDS> {}
+
+impl<const NUM_KEYS: usize, const NUM_LEDS: usize> RgbMatrix<NUM_KEYS, NUM_LEDS> {
+    pub const fn new(context: DynContext, key_led_map: KeyLedMap<NUM_KEYS>) -> Self {
+        Self {
+            context,
+            key_led_map,
+            background: Rgb::OFF,
+        }
+    }
+
+    /// Sets the color of the LEDs whose keys are not lit up.
+    ///
+    /// Defaults to [`Rgb::OFF`].
+    pub const fn background(mut self, color: Rgb) -> Self {
+        self.background = color;
+        self
+    }
+
+    pub async fn run(self, mut led_strip: impl LedStrip<NUM_LEDS>) {
+        let mut receiver = unwrap!(self.context.internal_channel.receiver::<Message>());
+        let mut lit_keys = [None::<LitKey>; NUM_LEDS];
+        loop {
+            let now = Instant::now();
+            let colors = core::array::from_fn(|i| match lit_keys[i] {
+                Some(LitKey {
+                    color,
+                    released: None,
+                    ..
+                }) => color,
+                Some(LitKey {
+                    color,
+                    fade_out,
+                    released: Some(released),
+                }) => {
+                    let elapsed = now.duration_since(released);
+                    if elapsed >= fade_out {
+                        lit_keys[i] = None;
+                        self.background
+                    } else {
+                        let progress = elapsed.as_ticks() as f32 / fade_out.as_ticks() as f32;
+                        blend(color, self.background, progress)
+                    }
+                }
+                None => self.background,
+            });
+            led_strip.write(&colors).await;
+
+            let is_fading = lit_keys.iter().flatten().any(|v| v.released.is_some());
+            let message = if is_fading {
+                match select(receiver.next(), Timer::after(FRAME_INTERVAL)).await {
+                    Either::First(message) => message,
+                    Either::Second(()) => continue,
+                }
+            } else {
+                receiver.next().await
+            };
+            match message {
+                Message::Press {
+                    key_index,
+                    color,
+                    fade_out_ms,
+                } => {
+                    if let Some(led_index) = self.led_index(key_index) {
+                        lit_keys[led_index] = Some(LitKey {
+                            color,
+                            fade_out: Duration::from_millis(fade_out_ms.into()),
+                            released: None,
+                        });
+                    }
+                }
+                Message::Release { key_index } => {
+                    if let Some(lit_key) = self
+                        .led_index(key_index)
+                        .and_then(|led_index| lit_keys[led_index].as_mut())
+                    {
+                        lit_key.released = Some(Instant::now());
+                    }
+                }
+            }
+        }
+    }
+
+    fn led_index(&self, key_index: u16) -> Option<usize> {
+        self.key_led_map
+            .led_index(key_index as usize)
+            .filter(|led_index| *led_index < NUM_LEDS)
+    }
+}
+
+#[cfg(feature = "keyboard-actions")]
+pub use action::Reactive;
+
+#[cfg(feature = "keyboard-actions")]
+mod action {
+    use super::*;
+    use embassy_futures::join::join;
+    use lokey::{AnyState, Context, Device, Transports};
+    use lokey_keyboard::Action;
+
+    /// Action that lights up the LED of the key while it is held and then fades it out.
+    ///
+    /// The key press and release are passed on to the wrapped action. The LED is controlled by
+    /// the [`RgbMatrix`] component.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use embassy_time::Duration;
+    /// use lokey_keyboard::Key;
+    /// use lokey_led_array::Rgb;
+    /// use lokey_led_array::rgb_matrix::Reactive;
+    ///
+    /// let action = Reactive::new(Key::A, Rgb::new(0, 128, 255))
+    ///     .fade_out(Duration::from_millis(300)); // optional, defaults to 500ms
+    /// ```
+    pub struct Reactive<A> {
+        pub action: A,
+        pub color: Rgb,
+        pub fade_out: Duration,
+    }
+
+    impl<A> Reactive<A> {
+        pub const fn new(action: A, color: Rgb) -> Self {
+            Self {
+                action,
+                color,
+                fade_out: Duration::from_millis(500),
+            }
+        }
+
+        /// Sets the duration in which the color fades out after the key is released.
+        pub const fn fade_out(mut self, duration: Duration) -> Self {
+            self.fade_out = duration;
+            self
+        }
+    }
+
+    impl<A: Action> Action for Reactive<A> {
+        async fn on_press<D, T, S>(&self, context: Context<D, T, S>)
+        where
+            D: Device,
+            T: Transports<D::Mcu>,
+            S: AnyState,
+        {
+            let message = Message::Press {
+                key_index: lokey_keyboard::current_key_index(),
+                color: self.color,
+                fade_out_ms: self.fade_out.as_millis().try_into().unwrap_or(u16::MAX),
+            };
+            // The wrapped action is polled first, so that it can still read the current key index.
+            join(
+                self.action.on_press(context),
+                context.internal_channel.send(message),
+            )
+            .await;
+        }
+
+        async fn on_release<D, T, S>(&self, context: Context<D, T, S>)
+        where
+            D: Device,
+            T: Transports<D::Mcu>,
+            S: AnyState,
+        {
+            let message = Message::Release {
+                key_index: lokey_keyboard::current_key_index(),
+            };
+            join(
+                self.action.on_release(context),
+                context.internal_channel.send(message),
+            )
+            .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialized_max_size() {
+        assert_eq!(Message::POSTCARD_MAX_SIZE, 10);
+    }
+
+    #[test]
+    fn serpentine_key_led_map() {
+        const MAP: KeyLedMap<7> = KeyLedMap::new()
+            .map_rows_and_cols::<2, 3>(0, 0, LedWiring::Serpentine)
+            .map_next(6);
+        let leds: [Option<usize>; 7] = core::array::from_fn(|i| MAP.led_index(i));
+        assert_eq!(
+            leds,
+            [
+                Some(0),
+                Some(1),
+                Some(2),
+                Some(5),
+                Some(4),
+                Some(3),
+                Some(6)
+            ]
+        );
+        assert_eq!(MAP.led_index(7), None);
+    }
+}
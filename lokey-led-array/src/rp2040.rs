@@ -0,0 +1,3 @@
+mod ws2812;
+
+pub use ws2812::Ws2812Pio;
@@ -0,0 +1,47 @@
+use crate::led_strip::{LedStrip, Rgb};
+use embassy_rp::pio::Instance;
+use embassy_rp::pio_programs::ws2812::{Grb, PioWs2812, RgbColorOrder};
+use smart_leds::RGB8;
+
+/// WS2812 driver that uses a PIO state machine of the RP2040.
+///
+/// The PIO program generates the 800kHz signal and the colors are transferred to the state
+/// machine with DMA, so writing the colors does not block the CPU.
+///
+/// # Example
+///
+/// ```ignore
+/// use embassy_rp::pio::Pio;
+/// use embassy_rp::pio_programs::ws2812::{PioWs2812, PioWs2812Program};
+/// use lokey_led_array::rp2040::Ws2812Pio;
+///
+/// let Pio { mut common, sm0, .. } = Pio::new(p.PIO0, Irqs);
+/// let program = PioWs2812Program::new(&mut common);
+/// let driver = PioWs2812::<_, 0, NUM_LEDS, _>::new(
+///     &mut common,
+///     sm0,
+///     p.DMA_CH2,
+///     lokey_rp::DmaIrqs,
+///     p.PIN_16,
+///     &program,
+/// );
+/// let led_strip = Ws2812Pio::new(driver);
+/// ```
+pub struct Ws2812Pio<'d, P: Instance, const S: usize, const N: usize, O: RgbColorOrder = Grb> {
+    driver: PioWs2812<'d, P, S, N, O>,
+}
+
+impl<'d, P: Instance, const S: usize, const N: usize, O: RgbColorOrder> Ws2812Pio<'d, P, S, N, O> {
+    pub fn new(driver: PioWs2812<'d, P, S, N, O>) -> Self {
+        Self { driver }
+    }
+}
+
+impl<P: Instance, const S: usize, const N: usize, O: RgbColorOrder> LedStrip<N>
+    for Ws2812Pio<'_, P, S, N, O>
+{
+    async fn write(&mut self, colors: &[Rgb; N]) {
+        let colors = colors.map(|color| RGB8::new(color.r, color.g, color.b));
+        self.driver.write(&colors).await;
+    }
+}
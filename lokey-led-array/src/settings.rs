@@ -0,0 +1,81 @@
+use generic_array::GenericArray;
+use lokey::{internal, storage};
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+/// The settings of a [`LedArray`](crate::LedArray) that are persisted in the storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LedArraySettings {
+    /// The brightness of all LEDs, where 255 is the full brightness.
+    pub brightness: u8,
+}
+
+impl Default for LedArraySettings {
+    fn default() -> Self {
+        Self { brightness: 255 }
+    }
+}
+
+impl LedArraySettings {
+    pub(crate) fn brightness_factor(&self) -> f32 {
+        self.brightness as f32 / u8::MAX as f32
+    }
+}
+
+impl storage::Entry for LedArraySettings {
+    type Size = typenum::U1;
+    type TagParams = ();
+
+    fn tag(_: Self::TagParams) -> [u8; storage::ENTRY_TAG_SIZE] {
+        [0xc4, 0x1b, 0x6a, 0x29, 0x8e, 0xf0, 0x53, 0x07]
+    }
+
+    fn from_bytes(bytes: &GenericArray<u8, Self::Size>) -> Option<Self> {
+        Some(Self {
+            brightness: bytes[0],
+        })
+    }
+
+    fn to_bytes(&self) -> GenericArray<u8, Self::Size> {
+        [self.brightness].into()
+    }
+}
+
+/// Internal message that changes the brightness of a [`LedArray`](crate::LedArray).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, MaxSize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BrightnessMessage {
+    Increase(u8),
+    Decrease(u8),
+    Set(u8),
+}
+
+impl BrightnessMessage {
+    pub(crate) fn apply(self, brightness: u8) -> u8 {
+        match self {
+            Self::Increase(step) => brightness.saturating_add(step),
+            Self::Decrease(step) => brightness.saturating_sub(step),
+            Self::Set(brightness) => brightness,
+        }
+    }
+}
+
+impl internal::Message for BrightnessMessage {
+    type Size = typenum::U2;
+
+    const TAG: [u8; 4] = [0x19, 0xd2, 0x4e, 0x85];
+
+    fn from_bytes(bytes: GenericArray<u8, Self::Size>) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        postcard::from_bytes(&bytes).ok()
+    }
+
+    fn to_bytes(&self) -> GenericArray<u8, Self::Size> {
+        let mut buf = GenericArray::default();
+        postcard::to_slice(self, &mut buf).expect("buffer is too small");
+        buf
+    }
+}
@@ -204,6 +204,7 @@ pub fn device(attr: TokenStream, item: TokenStream) -> TokenStream {
                 mcu,
                 external_channel,
                 internal_channel,
+                storage,
                 state,
             };
 
@@ -5,7 +5,7 @@ use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_time::Instant;
 use generic_array::GenericArray;
 use lokey::util::error;
-use lokey_ble::external::{InitMessageService, TxMessage, TxMessageService};
+use lokey_ble::external::{InitMessageService, MessageConfigs, TxMessage, TxMessageService};
 use trouble_host::prelude::*;
 
 // Standard BLE MIDI service UUID (03B80E5A-EDE8-4B33-A751-6CE34EC4C700), encoded in little-endian
@@ -57,6 +57,7 @@ pub struct MidiMessageService {
 impl InitMessageService for MidiMessageService {
     fn init<'a, const ATT_MAX: usize>(
         attribute_table: &mut AttributeTable<'static, NoopRawMutex, ATT_MAX>,
+        _: MessageConfigs,
     ) -> Self {
         let midi_service = MidiService::new(attribute_table);
         Self {
@@ -5,7 +5,7 @@ use embassy_usb::Builder;
 use embassy_usb::class::midi::{MidiClass, Receiver, Sender};
 use embassy_usb::driver::Driver;
 use lokey::util::error;
-use lokey_usb::external::{InitMessageService, TxMessage, TxMessageService};
+use lokey_usb::external::{InitMessageService, MessageConfigs, TxMessage, TxMessageService};
 
 // TODO: A midi message of type SysEx can be larger than 3 bytes. The max message size
 //       should be configurable.
@@ -91,7 +91,7 @@ impl<'d, D: Driver<'d>> InitMessageService<'d, D> for MidiMessageService<'d, D>
 
     fn create_params() -> Self::Params {}
 
-    fn init(builder: &mut Builder<'d, D>, _: &'d mut Self::Params) -> Self {
+    fn init(builder: &mut Builder<'d, D>, _: &'d mut Self::Params, _: MessageConfigs) -> Self {
         // TODO: Make parameters configurable (n_in_jacks, n_out_jacks, max_packet_size)
         let midi_class = MidiClass::new(builder, 1, 1, 64);
         let (midi_sender, midi_receiver) = midi_class.split();
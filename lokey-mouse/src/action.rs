@@ -4,7 +4,7 @@ use embassy_futures::join::join;
 use embassy_futures::select::select;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::signal::Signal;
-use embassy_time::{Duration, Timer};
+use embassy_time::{Duration, Instant, Timer};
 use lokey::util::error;
 use lokey::{AnyState, Context, Device, Transports};
 use lokey_keyboard::Action;
@@ -44,6 +44,29 @@ impl Action for MouseButton {
     }
 }
 
+/// A function that returns the factor by which the step of a movement or scroll action is
+/// multiplied, depending on how long the key has been held.
+///
+/// # Example
+///
+/// ```
+/// use embassy_time::Duration;
+/// use lokey_mouse::action::AccelerationCurve;
+///
+/// // Ramps the speed up linearly from 1x to 8x within one second.
+/// let curve: AccelerationCurve = |held| 1.0 + 7.0 * (held.as_millis().min(1000) as f32 / 1000.0);
+/// ```
+pub type AccelerationCurve = fn(Duration) -> f32;
+
+/// Returns the step for a key that has been held for the specified duration.
+fn accelerated_step(step: i8, acceleration: Option<AccelerationCurve>, held: Duration) -> i8 {
+    match acceleration {
+        // The cast saturates at the bounds of i8.
+        Some(curve) => (step as f32 * curve(held)) as i8,
+        None => step,
+    }
+}
+
 async fn send_mouse_report<D, T, S, F>(
     context: Context<D, T, S>,
     interval: Duration,
@@ -52,15 +75,17 @@ async fn send_mouse_report<D, T, S, F>(
     D: Device,
     T: Transports<D::Mcu>,
     S: AnyState,
-    F: Fn(&mut MouseReport),
+    F: Fn(&mut MouseReport, Duration),
 {
+    let pressed_at = Instant::now();
     loop {
         let fut1 = async {
             let Some(report) = context.state.try_get::<MouseReportState>() else {
                 error!("MoveMouseX action requires MouseReport state");
                 return;
             };
-            let report = report.modify_and_get(|report| update_report(report));
+            let held = pressed_at.elapsed();
+            let report = report.modify_and_get(|report| update_report(report, held));
             let _ = context.external_channel.try_send(report).await;
         };
         let fut2 = Timer::after(interval);
@@ -72,6 +97,7 @@ async fn send_mouse_report<D, T, S, F>(
 pub struct MoveMouseX {
     interval: Duration,
     step: i8,
+    acceleration: Option<AccelerationCurve>,
     stop_signal: Signal<CriticalSectionRawMutex, ()>,
     is_active: AtomicBool,
 }
@@ -81,6 +107,7 @@ impl MoveMouseX {
         Self {
             interval: Duration::from_millis(16),
             step,
+            acceleration: None,
             stop_signal: Signal::new(),
             is_active: AtomicBool::new(false),
         }
@@ -103,6 +130,15 @@ impl MoveMouseX {
         self.step += step;
         self
     }
+
+    /// Sets the curve that ramps up the speed while the key is held.
+    ///
+    /// The step is multiplied by the value that the curve returns for the duration the key has
+    /// been held. By default, the speed is constant.
+    pub fn acceleration(mut self, curve: AccelerationCurve) -> Self {
+        self.acceleration = Some(curve);
+        self
+    }
 }
 
 impl Action for MoveMouseX {
@@ -118,7 +154,9 @@ impl Action for MoveMouseX {
 
         self.is_active.store(true, Ordering::SeqCst);
 
-        let send = send_mouse_report(context, self.interval, |report| report.move_x = self.step);
+        let send = send_mouse_report(context, self.interval, |report, held| {
+            report.move_x = accelerated_step(self.step, self.acceleration, held)
+        });
 
         select(self.stop_signal.wait(), send).await;
 
@@ -139,6 +177,7 @@ impl Action for MoveMouseX {
 pub struct MoveMouseY {
     interval: Duration,
     step: i8,
+    acceleration: Option<AccelerationCurve>,
     stop_signal: Signal<CriticalSectionRawMutex, ()>,
     is_active: AtomicBool,
 }
@@ -148,6 +187,7 @@ impl MoveMouseY {
         Self {
             interval: Duration::from_millis(16),
             step,
+            acceleration: None,
             stop_signal: Signal::new(),
             is_active: AtomicBool::new(false),
         }
@@ -170,6 +210,15 @@ impl MoveMouseY {
         self.step += step;
         self
     }
+
+    /// Sets the curve that ramps up the speed while the key is held.
+    ///
+    /// The step is multiplied by the value that the curve returns for the duration the key has
+    /// been held. By default, the speed is constant.
+    pub fn acceleration(mut self, curve: AccelerationCurve) -> Self {
+        self.acceleration = Some(curve);
+        self
+    }
 }
 
 impl Action for MoveMouseY {
@@ -185,7 +234,9 @@ impl Action for MoveMouseY {
 
         self.is_active.store(true, Ordering::SeqCst);
 
-        let send = send_mouse_report(context, self.interval, |report| report.move_y = self.step);
+        let send = send_mouse_report(context, self.interval, |report, held| {
+            report.move_y = accelerated_step(self.step, self.acceleration, held)
+        });
 
         select(self.stop_signal.wait(), send).await;
 
@@ -206,6 +257,7 @@ impl Action for MoveMouseY {
 pub struct ScrollX {
     interval: Duration,
     step: i8,
+    acceleration: Option<AccelerationCurve>,
     stop_signal: Signal<CriticalSectionRawMutex, ()>,
     is_active: AtomicBool,
 }
@@ -215,6 +267,7 @@ impl ScrollX {
         Self {
             interval: Duration::from_millis(32),
             step,
+            acceleration: None,
             stop_signal: Signal::new(),
             is_active: AtomicBool::new(false),
         }
@@ -237,6 +290,15 @@ impl ScrollX {
         self.step += step;
         self
     }
+
+    /// Sets the curve that ramps up the speed while the key is held.
+    ///
+    /// The step is multiplied by the value that the curve returns for the duration the key has
+    /// been held. By default, the speed is constant.
+    pub fn acceleration(mut self, curve: AccelerationCurve) -> Self {
+        self.acceleration = Some(curve);
+        self
+    }
 }
 
 impl Action for ScrollX {
@@ -252,7 +314,9 @@ impl Action for ScrollX {
 
         self.is_active.store(true, Ordering::SeqCst);
 
-        let send = send_mouse_report(context, self.interval, |report| report.scroll_x = self.step);
+        let send = send_mouse_report(context, self.interval, |report, held| {
+            report.scroll_x = accelerated_step(self.step, self.acceleration, held)
+        });
 
         select(self.stop_signal.wait(), send).await;
 
@@ -273,6 +337,7 @@ impl Action for ScrollX {
 pub struct ScrollY {
     interval: Duration,
     step: i8,
+    acceleration: Option<AccelerationCurve>,
     stop_signal: Signal<CriticalSectionRawMutex, ()>,
     is_active: AtomicBool,
 }
@@ -282,6 +347,7 @@ impl ScrollY {
         Self {
             interval: Duration::from_millis(32),
             step,
+            acceleration: None,
             stop_signal: Signal::new(),
             is_active: AtomicBool::new(false),
         }
@@ -304,6 +370,15 @@ impl ScrollY {
         self.step += step;
         self
     }
+
+    /// Sets the curve that ramps up the speed while the key is held.
+    ///
+    /// The step is multiplied by the value that the curve returns for the duration the key has
+    /// been held. By default, the speed is constant.
+    pub fn acceleration(mut self, curve: AccelerationCurve) -> Self {
+        self.acceleration = Some(curve);
+        self
+    }
 }
 
 impl Action for ScrollY {
@@ -319,7 +394,9 @@ impl Action for ScrollY {
 
         self.is_active.store(true, Ordering::SeqCst);
 
-        let send = send_mouse_report(context, self.interval, |report| report.scroll_y = self.step);
+        let send = send_mouse_report(context, self.interval, |report, held| {
+            report.scroll_y = accelerated_step(self.step, self.acceleration, held)
+        });
 
         select(self.stop_signal.wait(), send).await;
 
@@ -4,7 +4,7 @@ use crate::MouseReport;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use generic_array::GenericArray;
 use lokey::util::error;
-use lokey_ble::external::{InitMessageService, TxMessage, TxMessageService};
+use lokey_ble::external::{InitMessageService, MessageConfigs, TxMessage, TxMessageService};
 use trouble_host::prelude::*;
 use usbd_hid::descriptor::{AsInputReport, MouseReport as HidMouseReport, SerializedDescriptor};
 
@@ -50,6 +50,7 @@ pub struct MouseReportService {
 impl InitMessageService for MouseReportService {
     fn init<'a, const ATT_MAX: usize>(
         attribute_table: &mut AttributeTable<'static, NoopRawMutex, ATT_MAX>,
+        _: MessageConfigs,
     ) -> Self {
         let hid_service = HidService::new(attribute_table);
         Self { hid_service }
@@ -5,7 +5,7 @@ use embassy_usb::Builder;
 use embassy_usb::class::hid::{HidBootProtocol, HidSubclass, HidWriter, State as HidState};
 use embassy_usb::driver::Driver;
 use lokey::util::error;
-use lokey_usb::external::{InitMessageService, TxMessage, TxMessageService};
+use lokey_usb::external::{InitMessageService, MessageConfigs, TxMessage, TxMessageService};
 use usbd_hid::descriptor::{AsInputReport, MouseReport as HidMouseReport, SerializedDescriptor};
 
 impl TxMessage for MouseReport {
@@ -25,7 +25,7 @@ impl<'d, D: Driver<'d>> InitMessageService<'d, D> for MouseReportService<'d, D>
         HidState::new()
     }
 
-    fn init(builder: &mut Builder<'d, D>, params: &'d mut Self::Params) -> Self {
+    fn init(builder: &mut Builder<'d, D>, params: &'d mut Self::Params, _: MessageConfigs) -> Self {
         let hid_config = embassy_usb::class::hid::Config {
             report_descriptor: HidMouseReport::desc(),
             request_handler: None,
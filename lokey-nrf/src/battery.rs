@@ -0,0 +1,249 @@
+use crate::{Irqs, Nrf};
+use embassy_futures::join::join;
+use embassy_nrf::Peri;
+use embassy_nrf::peripherals::SAADC;
+use embassy_nrf::saadc::{self, ChannelConfig, Saadc, VddInput, VddhDiv5Input};
+use embassy_time::{Duration, Timer};
+use lokey::battery::{BatteryLevel, BatteryLevelsState, DeviceBatteryLevel, DischargeCurve};
+use lokey::util::{debug, error};
+use lokey::{AnyState, Component, Context, Device, DynContext, Transports};
+
+/// The voltage that is sampled to measure the battery level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BatteryInput {
+    /// The VDD supply voltage.
+    ///
+    /// Use this if the battery is connected directly to VDD (normal voltage mode).
+    Vdd,
+    /// The VDDH supply voltage divided by 5.
+    ///
+    /// Use this if the battery is connected to VDDH (high voltage mode).
+    VddhDiv5,
+}
+
+impl BatteryInput {
+    fn channel_config(self) -> ChannelConfig<'static> {
+        match self {
+            Self::Vdd => ChannelConfig::single_ended(VddInput),
+            Self::VddhDiv5 => ChannelConfig::single_ended(VddhDiv5Input),
+        }
+    }
+
+    fn to_millivolts(self, sample: i16) -> u16 {
+        // With the default configuration (internal 0.6V reference, gain of 1/6 and a resolution of
+        // 12 bits) the full scale of the SAADC is 3.6V.
+        let millivolts = sample.max(0) as u32 * 3600 / 4096;
+        let millivolts = match self {
+            Self::Vdd => millivolts,
+            Self::VddhDiv5 => millivolts * 5,
+        };
+        millivolts.min(u16::MAX as u32) as u16
+    }
+}
+
+/// Component that periodically measures the battery level with the SAADC peripheral.
+///
+/// The measured voltage is converted to a percentage with the configured [`DischargeCurve`] and
+/// sent as a [`BatteryLevel`] message over the internal channel whenever the percentage changes.
+/// Additionally, a [`DeviceBatteryLevel`] message is sent after every measurement, so that the
+/// central device of a split keyboard receives the battery levels of the peripheral devices.
+///
+/// If [`BatteryLevelsState`] is part of the state, the received [`DeviceBatteryLevel`] messages of
+/// all devices are stored in it.
+///
+/// The [`ComponentSupport`](lokey::ComponentSupport) implementation for a device with the [`Nrf`]
+/// MCU can be added with the [`battery_monitor_support`](crate::battery_monitor_support) macro,
+/// as the orphan rules do not allow `lokey-nrf` to implement it for all such devices.
+///
+/// # Example
+///
+/// ```ignore
+/// lokey_nrf::battery_monitor_support!(MyDevice);
+///
+/// // Enabled like any other component:
+/// context.enable(BatteryMonitor::new().interval(Duration::from_secs(300))).await;
+/// ```
+///
+/// This is the same as implementing the trait manually with [`BatteryMonitor::run_on_mcu`]:
+///
+/// ```ignore
+/// use lokey::{AnyState, ComponentSupport, Context, Transports};
+/// use lokey_nrf::battery::BatteryMonitor;
+///
+/// impl<S: AnyState> ComponentSupport<BatteryMonitor, S> for MyDevice {
+///     async fn enable<T>(component: BatteryMonitor, context: Context<Self, T, S>)
+///     where
+///         T: Transports<Self::Mcu>,
+///     {
+///         component.run_on_mcu(context).await;
+///     }
+/// }
+/// ```
+pub struct BatteryMonitor {
+    input: BatteryInput,
+    interval: Duration,
+    discharge_curve: DischargeCurve,
+}
+
+impl Component for BatteryMonitor {}
+
+impl BatteryMonitor {
+    /// Creates a new [`BatteryMonitor`] that samples VDD every minute and uses the
+    /// [`DischargeCurve::LI_ION`] discharge curve.
+    pub const fn new() -> Self {
+        Self {
+            input: BatteryInput::Vdd,
+            interval: Duration::from_secs(60),
+            discharge_curve: DischargeCurve::LI_ION,
+        }
+    }
+
+    /// Sets the voltage that is sampled.
+    pub const fn input(mut self, input: BatteryInput) -> Self {
+        self.input = input;
+        self
+    }
+
+    /// Sets the interval between two measurements.
+    pub const fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets the discharge curve that is used to convert the measured voltage to a percentage.
+    pub const fn discharge_curve(mut self, discharge_curve: DischargeCurve) -> Self {
+        self.discharge_curve = discharge_curve;
+        self
+    }
+
+    /// Runs the battery monitor with the SAADC peripheral of the MCU (see [`Nrf::take_saadc`]).
+    ///
+    /// Logs an error and returns if the SAADC peripheral was already taken.
+    pub async fn run_on_mcu<D, T, S>(self, context: Context<D, T, S>)
+    where
+        D: Device<Mcu = Nrf>,
+        T: Transports<Nrf>,
+        S: AnyState,
+    {
+        let Some(saadc) = context.mcu.take_saadc() else {
+            error!(
+                "Failed to run the battery monitor because the SAADC peripheral was already taken"
+            );
+            return;
+        };
+        self.run(saadc, context.as_dyn()).await;
+    }
+
+    /// Runs the battery monitor with the specified SAADC peripheral.
+    pub async fn run(self, saadc: Peri<'static, SAADC>, context: DynContext) {
+        let mut saadc = Saadc::new(
+            saadc,
+            Irqs,
+            saadc::Config::default(),
+            [self.input.channel_config()],
+        );
+        saadc.calibrate().await;
+
+        let input = self.input;
+        self.run_with(
+            async || {
+                let mut buf = [0; 1];
+                saadc.sample(&mut buf).await;
+                input.to_millivolts(buf[0])
+            },
+            context,
+        )
+        .await;
+    }
+
+    /// Runs the battery monitor with a custom function for measuring the battery voltage.
+    ///
+    /// The function is called once per interval and must return the battery voltage in
+    /// millivolts. This can be used if the battery is connected to the SAADC through a voltage
+    /// divider or measured by an external chip. The configured [`BatteryInput`] is ignored.
+    pub async fn run_with(
+        self,
+        mut read_millivolts: impl AsyncFnMut() -> u16,
+        context: DynContext,
+    ) {
+        let measure = async {
+            let mut last_percentage = None;
+            loop {
+                let millivolts = read_millivolts().await;
+                let percentage = self.discharge_curve.percentage(millivolts);
+                debug!(
+                    "Measured battery voltage of {}mV ({}%)",
+                    millivolts, percentage
+                );
+                if last_percentage != Some(percentage) {
+                    last_percentage = Some(percentage);
+                    context
+                        .internal_channel
+                        .send(BatteryLevel { percentage })
+                        .await;
+                }
+                context
+                    .internal_channel
+                    .send(DeviceBatteryLevel {
+                        address: context.address,
+                        percentage,
+                    })
+                    .await;
+                Timer::after(self.interval).await;
+            }
+        };
+        let collect = async {
+            let Some(battery_levels) = context.state.try_get::<BatteryLevelsState>() else {
+                return;
+            };
+            let mut receiver = match context.internal_channel.receiver::<DeviceBatteryLevel>() {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("Failed to receive battery levels: {}", e);
+                    return;
+                }
+            };
+            loop {
+                battery_levels.update(receiver.next().await);
+            }
+        };
+        join(measure, collect).await;
+    }
+}
+
+impl Default for BatteryMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Implements [`ComponentSupport`](lokey::ComponentSupport) for the [`BatteryMonitor`] for the
+/// specified device types, which must use the [`Nrf`] MCU.
+///
+/// The battery monitor is run with [`BatteryMonitor::run_on_mcu`].
+///
+/// # Example
+///
+/// ```ignore
+/// lokey_nrf::battery_monitor_support!(KeyboardLeft, KeyboardRight);
+/// ```
+#[macro_export]
+macro_rules! battery_monitor_support {
+    ($($device:ty),+ $(,)?) => {
+        $(
+            impl<S: ::lokey::AnyState>
+                ::lokey::ComponentSupport<$crate::battery::BatteryMonitor, S> for $device
+            {
+                async fn enable<T>(
+                    component: $crate::battery::BatteryMonitor,
+                    context: ::lokey::Context<Self, T, S>,
+                ) where
+                    T: ::lokey::Transports<<Self as ::lokey::Device>::Mcu>,
+                {
+                    component.run_on_mcu(context).await;
+                }
+            }
+        )+
+    };
+}
@@ -8,13 +8,17 @@
 #![no_std]
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+pub mod battery;
+
 use core::ops::Range;
-use embassy_nrf::bind_interrupts;
+use core::sync::atomic::{AtomicBool, Ordering};
+use embassy_nrf::gpio::{Level, Pin, Port, Pull};
 use embassy_nrf::interrupt::Priority;
-use embassy_nrf::peripherals::RNG;
+use embassy_nrf::peripherals::{RNG, SAADC};
+use embassy_nrf::{Peri, bind_interrupts};
 use lokey::storage::{DefaultStorage, StorageDriver};
 use lokey::util::unwrap;
-use lokey::{Address, AnyState, Context, Device, Mcu, Transports};
+use lokey::{Address, AnyState, Context, Device, Mcu, SystemOff, Transports};
 use nrf_mpsl::{Flash, MultiprotocolServiceLayer, SessionMem};
 use static_cell::StaticCell;
 #[cfg(feature = "ble")]
@@ -44,6 +48,7 @@ impl Default for StorageConfig {
 
 bind_interrupts!(struct Irqs {
     RNG => embassy_nrf::rng::InterruptHandler<RNG>;
+    SAADC => embassy_nrf::saadc::InterruptHandler;
     EGU0_SWI0 => nrf_mpsl::LowPrioInterruptHandler;
     CLOCK_POWER => nrf_mpsl::ClockInterruptHandler, embassy_nrf::usb::vbus_detect::InterruptHandler;
     RADIO => nrf_mpsl::HighPrioInterruptHandler;
@@ -54,6 +59,7 @@ bind_interrupts!(struct Irqs {
 
 pub struct Nrf {
     mpsl: &'static MultiprotocolServiceLayer<'static>,
+    saadc_taken: AtomicBool,
     #[cfg(feature = "ble")]
     ble_stack: &'static Stack<'static, SoftdeviceController<'static>, DefaultPacketPool>,
     #[cfg(feature = "ble")]
@@ -73,6 +79,20 @@ pub struct Nrf {
     >,
 }
 
+impl Nrf {
+    /// Takes the SAADC peripheral, e.g. for the [`BatteryMonitor`](battery::BatteryMonitor).
+    ///
+    /// Returns `None` if the peripheral was already taken.
+    pub fn take_saadc(&self) -> Option<Peri<'static, SAADC>> {
+        if self.saadc_taken.swap(true, Ordering::SeqCst) {
+            return None;
+        }
+        // SAFETY: The peripherals returned by `embassy_nrf::init` in `Nrf::create` are not used
+        // for the SAADC, and the peripheral is only returned once.
+        Some(unsafe { SAADC::steal() })
+    }
+}
+
 impl Mcu for Nrf {
     type Config = embassy_nrf::config::Config;
 
@@ -144,6 +164,7 @@ impl Mcu for Nrf {
 
         Self {
             mpsl,
+            saadc_taken: AtomicBool::new(false),
             #[cfg(feature = "ble")]
             ble_stack,
             #[cfg(feature = "ble")]
@@ -163,6 +184,77 @@ impl Mcu for Nrf {
     {
         self.mpsl.run().await
     }
+
+    fn reboot(&'static self) -> ! {
+        cortex_m::peripheral::SCB::sys_reset()
+    }
+
+    /// Resets the MCU into the UF2 mode of the [Adafruit nRF52 bootloader].
+    ///
+    /// [Adafruit nRF52 bootloader]: https://github.com/adafruit/Adafruit_nRF52_Bootloader
+    fn reboot_to_bootloader(&'static self) -> ! {
+        // The bootloader reads the reason for the reset from the GPREGRET register, which is not
+        // cleared by a soft reset
+        unsafe { core::ptr::write_volatile(GPREGRET, DFU_MAGIC_UF2_RESET) };
+        cortex_m::peripheral::SCB::sys_reset()
+    }
+}
+
+/// The address of the GPREGRET register of the POWER peripheral.
+const GPREGRET: *mut u32 = 0x4000_051c as *mut u32;
+
+/// The value of the GPREGRET register that makes the bootloader enter the UF2 mode.
+const DFU_MAGIC_UF2_RESET: u32 = 0x57;
+
+impl SystemOff for Nrf {
+    fn system_off(&'static self) -> ! {
+        embassy_nrf::power::set_system_off();
+        // The MCU only turns off once the CPU is idle. In debug interface mode, the system off
+        // mode is emulated and the CPU continues to run.
+        loop {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// Configures the pin as an input that wakes the MCU up from the system off mode once it is at the
+/// specified level.
+///
+/// Without a wake-up source, the MCU can only be woken up from the system off mode by a reset. This
+/// function is intended to be called in the closure that is passed to
+/// [`IdleSleep::run`](https://docs.rs/lokey-keyboard/latest/lokey_keyboard/struct.IdleSleep.html#method.run),
+/// where the pins are usually owned by the scanner and have to be stolen. The configuration is
+/// written directly to the pin configuration register, so it is kept after the pin is dropped.
+///
+/// # Example
+///
+/// ```ignore
+/// use embassy_nrf::gpio::{Level, Pull};
+/// use embassy_nrf::peripherals::P0_13;
+///
+/// // The key connects the pin to ground, so a key press pulls the pin low
+/// lokey_nrf::enable_wake_up(unsafe { P0_13::steal() }, Pull::Up, Level::Low);
+/// ```
+pub fn enable_wake_up(pin: Peri<'_, impl Pin>, pull: Pull, level: Level) {
+    use embassy_nrf::pac::gpio::vals;
+
+    let port = match pin.port() {
+        Port::Port0 => embassy_nrf::pac::P0,
+        Port::Port1 => embassy_nrf::pac::P1,
+    };
+    port.pin_cnf(pin.pin() as usize).write(|w| {
+        w.set_dir(vals::Dir::INPUT);
+        w.set_input(vals::Input::CONNECT);
+        w.set_pull(match pull {
+            Pull::None => vals::Pull::DISABLED,
+            Pull::Up => vals::Pull::PULLUP,
+            Pull::Down => vals::Pull::PULLDOWN,
+        });
+        w.set_sense(match level {
+            Level::Low => vals::Sense::LOW,
+            Level::High => vals::Sense::HIGH,
+        });
+    });
 }
 
 type WordSize = typenum::U4;
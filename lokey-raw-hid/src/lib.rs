@@ -0,0 +1,459 @@
+//! Command protocol over a raw HID interface.
+//!
+//! The [`Cli`] component receives [`RawHidReport`]s from the host, executes the contained command
+//! and sends a report with the response back to the host. This allows companion apps to query and
+//! configure the device through a stable protocol.
+//!
+//! The raw HID interface is added to the USB transport by using [`RawHidReport`] as its
+//! `RxMessage` type (requires the `usb` feature):
+//!
+//! ```ignore
+//! type ExternalTransport = lokey_usb::external::Transport<Mcu, KeyboardReport, RawHidReport>;
+//! ```
+//!
+//! The reports are passed directly between the interface and the [`Cli`] component, so they are
+//! not received through the external channel.
+//!
+//! # Protocol
+//!
+//! All reports have a size of [`REPORT_SIZE`] bytes, unused bytes are set to zero.
+//!
+//! A request report consists of the command ID in the first byte followed by the payload of the
+//! command. The response report repeats the command ID in the first byte, followed by the
+//! [`Status`] in the second byte and the data of the response.
+//!
+//! The commands are executed one at a time. A request that is received while the previous command
+//! is still being executed is answered with [`Status::Busy`] and is not executed.
+//!
+//! | Command                             | ID     | Payload                     | Response data                                        |
+//! | ----------------------------------- | ------ | --------------------------- | ---------------------------------------------------- |
+//! | [`GetVersion`](Command::GetVersion) | `0x01` | -                           | Protocol version, length and firmware version string |
+//! | [`GetLayer`](Command::GetLayer)     | `0x02` | -                           | ID of the active layer                               |
+//! | [`SetLayer`](Command::SetLayer)     | `0x03` | Layer ID                    | -                                                    |
+//! | [`ResetLayer`](Command::ResetLayer) | `0x04` | -                           | -                                                    |
+//! | [`EnterBootloader`](Command::EnterBootloader) | `0x05` | -                 | -                                                    |
+//! | [`ClearBleBond`](Command::ClearBleBond) | `0x06` | Profile index (`0xff` for all profiles) | -                                |
+//! | [`GetBatteryLevel`](Command::GetBatteryLevel) | `0x07` | -                 | Battery level in percent                             |
+//!
+//! The command IDs `0x80` to `0xff` are reserved for custom commands, which can be handled by
+//! implementing [`CommandHandler`].
+//!
+//! # Feature flags
+//!
+#![doc = document_features::document_features!(feature_label = r#"<span class="stab portability"><code>{feature}</code></span>"#)]
+//!
+
+#![no_std]
+#![cfg_attr(docsrs, feature(doc_cfg))]
+
+#[cfg(feature = "usb")]
+pub mod usb;
+
+use core::future::Future;
+use embassy_futures::select::{Either, select};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use lokey::battery::BatteryLevel;
+use lokey::external::Message;
+use lokey::util::{debug, unwrap};
+use lokey::{AnyState, Component, Context, Device, DynContext, Transports};
+
+/// The size of the raw HID reports in bytes.
+pub const REPORT_SIZE: usize = 32;
+
+/// The version of the command protocol.
+///
+/// The version is incremented whenever a change to the protocol is not backwards compatible.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// Reports received from the host that have not been handled by the [`Cli`] component yet.
+static REQUESTS: Channel<CriticalSectionRawMutex, RawHidReport, 1> = Channel::new();
+/// Reports of the [`Cli`] component that have not been sent to the host yet.
+static RESPONSES: Channel<CriticalSectionRawMutex, RawHidReport, 1> = Channel::new();
+
+/// A report that is sent or received through the raw HID interface.
+#[derive(Debug, Clone, PartialEq, Eq, Message)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RawHidReport(pub [u8; REPORT_SIZE]);
+
+impl RawHidReport {
+    /// Creates the response to the request with the specified status, without any data.
+    pub(crate) fn status_response(request: &RawHidReport, status: Status) -> Self {
+        let mut response = [0; REPORT_SIZE];
+        response[0] = request.0[0];
+        response[1] = status as u8;
+        Self(response)
+    }
+}
+
+/// The built-in commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Command {
+    /// Returns the protocol version and the firmware version.
+    GetVersion = 0x01,
+    /// Returns the ID of the active layer.
+    ///
+    /// This requires the `layer` feature.
+    GetLayer = 0x02,
+    /// Activates the layer with the specified ID.
+    ///
+    /// The layer replaces the layer that was previously activated with this command. This requires
+    /// the `layer` feature and the number of layers to be set with [`Cli::num_layers`]. Layer IDs
+    /// that are not less than the number of layers are rejected with [`Status::InvalidPayload`].
+    SetLayer = 0x03,
+    /// Deactivates the layer that was activated with [`SetLayer`](Self::SetLayer).
+    ///
+    /// This requires the `layer` feature.
+    ResetLayer = 0x04,
+    /// Reboots the device into its bootloader.
+    ///
+    /// This is handled by [`CommandHandler::enter_bootloader`].
+    EnterBootloader = 0x05,
+    /// Removes the bond of the BLE profile with the specified index, or of all profiles if the
+    /// index is `0xff`.
+    ///
+    /// This requires the `ble` feature.
+    ClearBleBond = 0x06,
+    /// Returns the last measured battery level.
+    GetBatteryLevel = 0x07,
+}
+
+impl Command {
+    /// Returns the built-in command with the specified ID.
+    pub const fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0x01 => Some(Self::GetVersion),
+            0x02 => Some(Self::GetLayer),
+            0x03 => Some(Self::SetLayer),
+            0x04 => Some(Self::ResetLayer),
+            0x05 => Some(Self::EnterBootloader),
+            0x06 => Some(Self::ClearBleBond),
+            0x07 => Some(Self::GetBatteryLevel),
+            _ => None,
+        }
+    }
+}
+
+/// The status of a response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Status {
+    /// The command was executed successfully.
+    Ok = 0x00,
+    /// The command ID is unknown.
+    UnknownCommand = 0x01,
+    /// The payload of the command is invalid.
+    InvalidPayload = 0x02,
+    /// The command is not supported by the device (e.g. because a feature is disabled).
+    Unsupported = 0x03,
+    /// The requested value is not available yet (e.g. the battery level was not measured yet).
+    NotAvailable = 0x04,
+    /// The previous command is still being executed, so the command was discarded.
+    ///
+    /// The host should send the command again after it received the response of the previous
+    /// command.
+    Busy = 0x05,
+}
+
+/// Trait for extending the [`Cli`] component with custom commands.
+///
+/// The unit type `()` implements this trait without handling any custom commands.
+pub trait CommandHandler {
+    /// Handles a command that is not a built-in [`Command`].
+    ///
+    /// The data of the response is written to `data`, which is initially filled with zeros. The
+    /// default implementation returns [`Status::UnknownCommand`].
+    fn handle(
+        &mut self,
+        command: u8,
+        payload: &[u8],
+        data: &mut [u8],
+        context: DynContext,
+    ) -> impl Future<Output = Result<(), Status>> {
+        let _ = (command, payload, data, context);
+        async { Err(Status::UnknownCommand) }
+    }
+
+    /// Reboots the device into its bootloader.
+    ///
+    /// The default implementation returns [`Status::Unsupported`].
+    fn enter_bootloader(
+        &mut self,
+        context: DynContext,
+    ) -> impl Future<Output = Result<(), Status>> {
+        let _ = context;
+        async { Err(Status::Unsupported) }
+    }
+}
+
+impl CommandHandler for () {}
+
+/// Component that executes the commands received through the raw HID interface.
+///
+/// The external transport of the device must provide the raw HID interface (see the
+/// [crate-level documentation](crate)).
+///
+/// # Example
+///
+/// ```ignore
+/// use lokey::{AnyState, ComponentSupport, Context, Transports};
+/// use lokey_raw_hid::{Cli, CommandHandler};
+///
+/// impl<S: AnyState, H: CommandHandler> ComponentSupport<Cli<H>, S> for MyDevice {
+///     async fn enable<T>(component: Cli<H>, context: Context<Self, T, S>)
+///     where
+///         T: Transports<Self::Mcu>,
+///     {
+///         component.run(context).await;
+///     }
+/// }
+///
+/// context.enable(Cli::new(env!("CARGO_PKG_VERSION")).num_layers(3)).await;
+/// ```
+pub struct Cli<H> {
+    firmware_version: &'static str,
+    handler: H,
+    #[cfg(feature = "layer")]
+    num_layers: Option<u8>,
+}
+
+impl<H> Component for Cli<H> {}
+
+impl Cli<()> {
+    /// Creates a new [`Cli`] component that only handles the built-in commands.
+    ///
+    /// The firmware version is returned by the [`GetVersion`](Command::GetVersion) command.
+    pub const fn new(firmware_version: &'static str) -> Self {
+        Self::with_handler(firmware_version, ())
+    }
+}
+
+impl<H> Cli<H> {
+    /// Creates a new [`Cli`] component that passes custom commands to the specified handler.
+    pub const fn with_handler(firmware_version: &'static str, handler: H) -> Self {
+        Self {
+            firmware_version,
+            handler,
+            #[cfg(feature = "layer")]
+            num_layers: None,
+        }
+    }
+
+    /// Sets the number of layers of the layout.
+    ///
+    /// The [`SetLayer`](Command::SetLayer) command only activates layers with an ID that is less
+    /// than this value and is unsupported if the number of layers is not set.
+    #[cfg(feature = "layer")]
+    pub const fn num_layers(mut self, value: u8) -> Self {
+        self.num_layers = Some(value);
+        self
+    }
+}
+
+impl<H: CommandHandler> Cli<H> {
+    /// Runs the CLI component.
+    pub async fn run<D, T, S>(mut self, context: Context<D, T, S>)
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let mut battery_receiver = unwrap!(context.internal_channel.receiver::<BatteryLevel>());
+
+        let mut session = Session {
+            battery_level: None,
+            #[cfg(feature = "layer")]
+            layer_entry: None,
+        };
+        loop {
+            let request = match select(REQUESTS.receive(), battery_receiver.next()).await {
+                Either::First(report) => report,
+                Either::Second(battery_level) => {
+                    session.battery_level = Some(battery_level.percentage);
+                    continue;
+                }
+            };
+            let command = request.0[0];
+            debug!("Received raw HID command: {}", command);
+
+            let mut response = RawHidReport::status_response(&request, Status::Ok);
+            let result = self
+                .execute(
+                    command,
+                    &request.0[1..],
+                    &mut response.0[2..],
+                    &mut session,
+                    context,
+                )
+                .await;
+            if let Err(status) = result {
+                response.0[1] = status as u8;
+            }
+
+            RESPONSES.send(response).await;
+        }
+    }
+
+    async fn execute<D, T, S>(
+        &mut self,
+        command: u8,
+        payload: &[u8],
+        data: &mut [u8],
+        session: &mut Session,
+        context: Context<D, T, S>,
+    ) -> Result<(), Status>
+    where
+        D: Device,
+        T: Transports<D::Mcu>,
+        S: AnyState,
+    {
+        let Some(command) = Command::from_id(command) else {
+            return self
+                .handler
+                .handle(command, payload, data, context.as_dyn())
+                .await;
+        };
+        match command {
+            Command::GetVersion => {
+                write_version(self.firmware_version, data);
+                Ok(())
+            }
+            #[cfg(feature = "layer")]
+            Command::GetLayer | Command::SetLayer | Command::ResetLayer => {
+                use lokey::util::error;
+                use lokey_layer::{LayerId, LayerManagerQuery};
+                let Some(layer_manager) = context.state.try_query::<LayerManagerQuery>() else {
+                    error!("The layer commands require LayerManager");
+                    return Err(Status::Unsupported);
+                };
+                if command == Command::GetLayer {
+                    data[0] = layer_manager.active().0;
+                    return Ok(());
+                }
+                let layer = match command {
+                    Command::SetLayer => {
+                        Some(LayerId(validate_layer(payload[0], self.num_layers)?))
+                    }
+                    _ => None,
+                };
+                if let Some(entry) = session.layer_entry.take() {
+                    layer_manager.remove(entry);
+                }
+                if let Some(layer) = layer {
+                    session.layer_entry = Some(layer_manager.push(layer));
+                }
+                Ok(())
+            }
+            #[cfg(not(feature = "layer"))]
+            Command::GetLayer | Command::SetLayer | Command::ResetLayer => Err(Status::Unsupported),
+            Command::EnterBootloader => self.handler.enter_bootloader(context.as_dyn()).await,
+            #[cfg(feature = "ble")]
+            Command::ClearBleBond => {
+                use lokey_ble::external::BleControl;
+                match payload[0] {
+                    0xff => context.as_dyn().ble_clear_all_profiles().await,
+                    profile_index => context.as_dyn().ble_clear_profile(profile_index).await,
+                }
+                Ok(())
+            }
+            #[cfg(not(feature = "ble"))]
+            Command::ClearBleBond => Err(Status::Unsupported),
+            Command::GetBatteryLevel => {
+                data[0] = session.battery_level.ok_or(Status::NotAvailable)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Writes the response data of the [`GetVersion`](Command::GetVersion) command.
+///
+/// The firmware version is truncated if it does not fit into the report.
+fn write_version(firmware_version: &str, data: &mut [u8]) {
+    let version = firmware_version.as_bytes();
+    let len = version.len().min(data.len() - 2);
+    data[0] = PROTOCOL_VERSION;
+    data[1] = len as u8;
+    data[2..2 + len].copy_from_slice(&version[..len]);
+}
+
+/// Returns the layer ID of the payload of the [`SetLayer`](Command::SetLayer) command if it is
+/// less than the number of layers.
+#[cfg(feature = "layer")]
+fn validate_layer(layer: u8, num_layers: Option<u8>) -> Result<u8, Status> {
+    match num_layers {
+        Some(num_layers) if layer < num_layers => Ok(layer),
+        Some(_) => Err(Status::InvalidPayload),
+        None => {
+            lokey::util::error!("The SetLayer command requires the number of layers to be set");
+            Err(Status::Unsupported)
+        }
+    }
+}
+
+/// State that is kept between the commands.
+struct Session {
+    battery_level: Option<u8>,
+    #[cfg(feature = "layer")]
+    layer_entry: Option<lokey_layer::LayerManagerEntry>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_ids() {
+        for id in 0x01..=0x07 {
+            let command = Command::from_id(id).unwrap();
+            assert_eq!(command as u8, id);
+        }
+        assert_eq!(Command::from_id(0x00), None);
+        assert_eq!(Command::from_id(0x08), None);
+        assert_eq!(Command::from_id(0x80), None);
+    }
+
+    #[test]
+    fn version_response() {
+        let mut data = [0; REPORT_SIZE - 2];
+        write_version("1.2.3", &mut data);
+        assert_eq!(
+            data[..7],
+            [PROTOCOL_VERSION, 5, b'1', b'.', b'2', b'.', b'3']
+        );
+        assert!(data[7..].iter().all(|v| *v == 0));
+    }
+
+    #[test]
+    fn version_response_is_truncated() {
+        let mut data = [0; REPORT_SIZE - 2];
+        write_version(core::str::from_utf8(&[b'a'; 64]).unwrap(), &mut data);
+        assert_eq!(data[1] as usize, REPORT_SIZE - 4);
+        assert!(data[2..].iter().all(|v| *v == b'a'));
+    }
+
+    #[cfg(feature = "layer")]
+    #[test]
+    fn set_layer_payload() {
+        assert_eq!(validate_layer(0, Some(3)), Ok(0));
+        assert_eq!(validate_layer(2, Some(3)), Ok(2));
+        assert_eq!(validate_layer(3, Some(3)), Err(Status::InvalidPayload));
+        assert_eq!(validate_layer(0xff, Some(3)), Err(Status::InvalidPayload));
+        assert_eq!(validate_layer(0, None), Err(Status::Unsupported));
+    }
+
+    #[test]
+    fn busy_response() {
+        let mut request = [0xaa; REPORT_SIZE];
+        request[0] = Command::GetLayer as u8;
+        let response = RawHidReport::status_response(&RawHidReport(request), Status::Busy);
+        assert_eq!(
+            response.0[..2],
+            [Command::GetLayer as u8, Status::Busy as u8]
+        );
+        assert!(response.0[2..].iter().all(|v| *v == 0));
+    }
+}
@@ -0,0 +1,112 @@
+use crate::{REPORT_SIZE, REQUESTS, RESPONSES, RawHidReport, Status};
+use embassy_futures::select::{Either, select};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::TrySendError;
+use embassy_sync::mutex::Mutex;
+use embassy_usb::Builder;
+use embassy_usb::class::hid::{
+    HidBootProtocol, HidReader, HidReaderWriter, HidSubclass, HidWriter, State as HidState,
+};
+use embassy_usb::driver::Driver;
+use lokey::util::error;
+use lokey_usb::external::{InitMessageService, MessageConfigs, RxMessage, RxMessageService};
+
+/// Report descriptor of the raw HID interface.
+///
+/// The usage page and usages are the same as the ones of the raw HID interface of QMK, so that
+/// existing host libraries can find the interface.
+#[rustfmt::skip]
+const REPORT_DESCRIPTOR: &[u8] = &[
+    0x06, 0x60, 0xff,         // Usage Page (Vendor Defined 0xFF60)
+    0x09, 0x61,               // Usage (0x61)
+    0xa1, 0x01,               // Collection (Application)
+    0x09, 0x62,               //   Usage (0x62)
+    0x15, 0x00,               //   Logical Minimum (0)
+    0x26, 0xff, 0x00,         //   Logical Maximum (255)
+    0x95, REPORT_SIZE as u8,  //   Report Count (REPORT_SIZE)
+    0x75, 0x08,               //   Report Size (8)
+    0x81, 0x02,               //   Input (Data, Variable, Absolute)
+    0x09, 0x63,               //   Usage (0x63)
+    0x15, 0x00,               //   Logical Minimum (0)
+    0x26, 0xff, 0x00,         //   Logical Maximum (255)
+    0x95, REPORT_SIZE as u8,  //   Report Count (REPORT_SIZE)
+    0x75, 0x08,               //   Report Size (8)
+    0x91, 0x02,               //   Output (Data, Variable, Absolute)
+    0xc0,                     // End Collection
+];
+
+impl RxMessage for RawHidReport {
+    type MessageService<'d, D: Driver<'d>> = RawHidService<'d, D>;
+}
+
+/// Message service that adds the raw HID interface.
+///
+/// The received reports are passed to the [`Cli`](crate::Cli) component and its responses are
+/// written back to the host. No messages are passed to the external channel.
+pub struct RawHidService<'d, D: Driver<'d>> {
+    hid_reader: Mutex<CriticalSectionRawMutex, HidReader<'d, D, REPORT_SIZE>>,
+    hid_writer: Mutex<CriticalSectionRawMutex, HidWriter<'d, D, REPORT_SIZE>>,
+}
+
+impl<'d, D: Driver<'d>> InitMessageService<'d, D> for RawHidService<'d, D> {
+    type Params = HidState<'d>;
+
+    fn create_params() -> Self::Params {
+        HidState::new()
+    }
+
+    fn init(builder: &mut Builder<'d, D>, params: &'d mut Self::Params, _: MessageConfigs) -> Self {
+        let hid_config = embassy_usb::class::hid::Config {
+            report_descriptor: REPORT_DESCRIPTOR,
+            request_handler: None,
+            poll_ms: 1,
+            max_packet_size: 64,
+            hid_subclass: HidSubclass::No,
+            hid_boot_protocol: HidBootProtocol::None,
+        };
+
+        let hid = HidReaderWriter::<_, REPORT_SIZE, REPORT_SIZE>::new(builder, params, hid_config);
+        let (hid_reader, hid_writer) = hid.split();
+        Self {
+            hid_reader: Mutex::new(hid_reader),
+            hid_writer: Mutex::new(hid_writer),
+        }
+    }
+}
+
+impl<'d, D: Driver<'d>> RxMessageService<RawHidReport> for RawHidService<'d, D> {
+    async fn receive(&self) -> RawHidReport {
+        let hid_reader = &mut *self.hid_reader.lock().await;
+        let hid_writer = &mut *self.hid_writer.lock().await;
+        loop {
+            let mut buf = [0; REPORT_SIZE];
+            match select(hid_reader.read(&mut buf), RESPONSES.receive()).await {
+                Either::First(Ok(_)) => {
+                    // Waiting for the Cli component here could deadlock, as it may be waiting for
+                    // the previous response to be written
+                    if let Err(TrySendError::Full(request)) = REQUESTS.try_send(RawHidReport(buf)) {
+                        let RawHidReport(report) =
+                            RawHidReport::status_response(&request, Status::Busy);
+                        if let Err(e) = hid_writer.write(&report).await {
+                            #[cfg(feature = "defmt")]
+                            let e = defmt::Debug2Format(&e);
+                            error!("Failed to write raw HID report: {}", e);
+                        }
+                    }
+                }
+                Either::First(Err(e)) => {
+                    #[cfg(feature = "defmt")]
+                    let e = defmt::Debug2Format(&e);
+                    error!("Failed to read raw HID report: {}", e);
+                }
+                Either::Second(RawHidReport(report)) => {
+                    if let Err(e) = hid_writer.write(&report).await {
+                        #[cfg(feature = "defmt")]
+                        let e = defmt::Debug2Format(&e);
+                        error!("Failed to write raw HID report: {}", e);
+                    }
+                }
+            }
+        }
+    }
+}
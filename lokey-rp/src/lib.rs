@@ -9,10 +9,24 @@
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
 use core::ops::Range;
-use embassy_rp::flash;
+/// Re-exported for aligning the [`Cyw43Firmware`] files.
+#[cfg(feature = "ble")]
+pub use cyw43::{A4, Aligned};
 use embassy_rp::peripherals::{DMA_CH0, FLASH};
+use embassy_rp::{bind_interrupts, dma, flash, peripherals};
 use lokey::storage::{DefaultStorage, StorageDriver};
 use lokey::{Address, AnyState, Context, Device, Mcu, Transports};
+#[cfg(feature = "ble")]
+use {
+    embassy_rp::clocks::RoscRng,
+    embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex,
+    embassy_sync::mutex::Mutex,
+    rand_chacha::ChaCha12Rng,
+    rand_chacha::rand_core::SeedableRng,
+    static_cell::StaticCell,
+    trouble_host::prelude::{Central, DefaultPacketPool, Peripheral, Runner},
+    trouble_host::{HostResources, Stack},
+};
 
 pub struct StorageConfig {
     pub flash_range: Range<u32>,
@@ -26,15 +40,188 @@ impl Default for StorageConfig {
     }
 }
 
+/// The configuration of the [`Rp`] microcontroller.
+///
+/// If the `ble` feature is enabled, the configuration has to be created with
+/// [`Config::new`] in [`Device::mcu_config`], as the firmware of the CYW43439 chip can not be
+/// provided by the default configuration.
+///
+/// # Panics
+///
+/// The [`Default`] implementation only exists because [`Mcu::Config`] requires it. If the `ble`
+/// feature is enabled, creating the [`Rp`] microcontroller with the default configuration (e.g.
+/// by not implementing [`Device::mcu_config`]) panics, as the CYW43439 chip can not be started
+/// without its firmware.
+#[derive(Default)]
+pub struct Config {
+    /// The configuration of the HAL.
+    pub hal: embassy_rp::config::Config,
+    /// The firmware that is loaded onto the CYW43439 chip.
+    #[cfg(feature = "ble")]
+    cyw43_firmware: Option<Cyw43Firmware>,
+}
+
+#[cfg(feature = "ble")]
+impl Config {
+    /// Creates a new configuration with the specified firmware of the CYW43439 chip.
+    ///
+    /// ```ignore
+    /// impl Device for MyDevice {
+    ///     type Mcu = Rp;
+    ///     // ...
+    ///
+    ///     fn mcu_config() -> lokey_rp::Config {
+    ///         lokey_rp::Config::new(CYW43_FIRMWARE)
+    ///     }
+    /// }
+    /// ```
+    pub fn new(cyw43_firmware: Cyw43Firmware) -> Self {
+        Self {
+            hal: embassy_rp::config::Config::default(),
+            cyw43_firmware: Some(cyw43_firmware),
+        }
+    }
+}
+
+/// The firmware files of the CYW43439 chip.
+///
+/// The files are provided by Infineon and can be found in the `cyw43-firmware` directory of the
+/// [embassy repository](https://github.com/embassy-rs/embassy/tree/main/cyw43-firmware). The
+/// firmware files have to be aligned to 4 bytes:
+///
+/// ```ignore
+/// use lokey_rp::{Aligned, Cyw43Firmware};
+///
+/// const CYW43_FIRMWARE: Cyw43Firmware = Cyw43Firmware::new(
+///     &Aligned(*include_bytes!("../cyw43-firmware/43439A0.bin")),
+///     include_bytes!("../cyw43-firmware/43439A0_clm.bin"),
+///     &Aligned(*include_bytes!("../cyw43-firmware/43439A0_btfw.bin")),
+///     &Aligned(*include_bytes!("../cyw43-firmware/nvram_rp2040.bin")),
+/// );
+/// ```
+#[cfg(feature = "ble")]
+pub struct Cyw43Firmware {
+    firmware: &'static Aligned<A4, [u8]>,
+    clm: &'static [u8],
+    bluetooth_firmware: &'static Aligned<A4, [u8]>,
+    nvram: &'static Aligned<A4, [u8]>,
+}
+
+#[cfg(feature = "ble")]
+impl Cyw43Firmware {
+    /// Creates a new [`Cyw43Firmware`] from the firmware files.
+    ///
+    /// - `firmware`: The WLAN firmware (`43439A0.bin`), which is required for the Bluetooth
+    ///   firmware to run.
+    /// - `clm`: The country locale matrix (`43439A0_clm.bin`).
+    /// - `bluetooth_firmware`: The Bluetooth firmware (`43439A0_btfw.bin`).
+    /// - `nvram`: The configuration of the board (`nvram_rp2040.bin` for the Raspberry Pi Pico W
+    ///   and Pico 2 W).
+    pub const fn new(
+        firmware: &'static Aligned<A4, [u8]>,
+        clm: &'static [u8],
+        bluetooth_firmware: &'static Aligned<A4, [u8]>,
+        nvram: &'static Aligned<A4, [u8]>,
+    ) -> Self {
+        Self {
+            firmware,
+            clm,
+            bluetooth_firmware,
+            nvram,
+        }
+    }
+}
+
+/// The Raspberry Pi RP2040 and RP235x microcontrollers.
+///
+/// If the `ble` feature is enabled, the microcontroller is expected to be connected to a CYW43439
+/// chip in the same way as on the Raspberry Pi Pico W and Pico 2 W (power on `PIN_23`, data on
+/// `PIN_24`, chip select on `PIN_25` and clock on `PIN_29`). The chip is driven by `PIO0` and
+/// `DMA_CH1`, so these peripherals can not be used for anything else.
+///
+/// The `DMA_IRQ_0` interrupt is bound by this crate, so drivers for other DMA channels have to be
+/// created with the [`DmaIrqs`] binding.
 #[non_exhaustive]
-pub struct Rp {}
+pub struct Rp {
+    #[cfg(feature = "ble")]
+    cyw43: Mutex<CriticalSectionRawMutex, Option<ble::Cyw43>>,
+    #[cfg(feature = "ble")]
+    ble_stack: &'static Stack<'static, ble::Controller, DefaultPacketPool>,
+    #[cfg(feature = "ble")]
+    ble_host_central:
+        Mutex<CriticalSectionRawMutex, Central<'static, ble::Controller, DefaultPacketPool>>,
+    #[cfg(feature = "ble")]
+    ble_host_peripheral:
+        Mutex<CriticalSectionRawMutex, Peripheral<'static, ble::Controller, DefaultPacketPool>>,
+    #[cfg(feature = "ble")]
+    ble_host_runner:
+        Mutex<CriticalSectionRawMutex, Runner<'static, ble::Controller, DefaultPacketPool>>,
+}
 
 impl Mcu for Rp {
-    type Config = embassy_rp::config::Config;
+    type Config = Config;
+
+    async fn create(config: Self::Config, address: Address) -> Self {
+        #[cfg(not(feature = "ble"))]
+        let _ = address;
 
-    async fn create(config: Self::Config, _address: Address) -> Self {
-        embassy_rp::init(config);
-        Self {}
+        #[cfg_attr(not(feature = "ble"), allow(unused_variables))]
+        let p = embassy_rp::init(config.hal);
+
+        #[cfg(feature = "ble")]
+        let (cyw43, ble_stack) = {
+            let (cyw43, controller) = ble::build_cyw43(
+                ble::Cyw43Peripherals {
+                    pio: p.PIO0,
+                    dma: p.DMA_CH1,
+                    pwr: p.PIN_23,
+                    dio: p.PIN_24,
+                    cs: p.PIN_25,
+                    clk: p.PIN_29,
+                },
+                config.cyw43_firmware.expect(
+                    "the MCU config must be created with `lokey_rp::Config::new` if the `ble` \
+                     feature is enabled",
+                ),
+            )
+            .await;
+
+            let mut rng = ChaCha12Rng::from_rng(RoscRng).unwrap();
+
+            static RESOURCES: StaticCell<HostResources<DefaultPacketPool, 2, 4, 72>> =
+                StaticCell::new();
+            let resources = RESOURCES.init(HostResources::new());
+            let ble_stack = trouble_host::new(controller, resources)
+                .set_random_address(ble::device_address_to_ble_address(&address))
+                .set_random_generator_seed(&mut rng);
+
+            static BLE_STACK: StaticCell<Stack<'static, ble::Controller, DefaultPacketPool>> =
+                StaticCell::new();
+            (cyw43, BLE_STACK.init(ble_stack))
+        };
+
+        #[cfg(feature = "ble")]
+        let (ble_host_central, ble_host_peripheral, ble_host_runner) = {
+            let ble_host = ble_stack.build();
+            (
+                Mutex::new(ble_host.central),
+                Mutex::new(ble_host.peripheral),
+                Mutex::new(ble_host.runner),
+            )
+        };
+
+        Self {
+            #[cfg(feature = "ble")]
+            cyw43: Mutex::new(Some(cyw43)),
+            #[cfg(feature = "ble")]
+            ble_stack,
+            #[cfg(feature = "ble")]
+            ble_host_central,
+            #[cfg(feature = "ble")]
+            ble_host_peripheral,
+            #[cfg(feature = "ble")]
+            ble_host_runner,
+        }
     }
 
     async fn run<D, T, S>(&'static self, _context: Context<D, T, S>)
@@ -43,9 +230,60 @@ impl Mcu for Rp {
         T: Transports<Self>,
         S: AnyState,
     {
+        #[cfg(feature = "ble")]
+        {
+            let cyw43 = self.cyw43.lock().await.take();
+            if let Some(cyw43) = cyw43 {
+                cyw43.run().await;
+            }
+        }
+    }
+
+    fn reboot(&'static self) -> ! {
+        cortex_m::peripheral::SCB::sys_reset()
+    }
+
+    /// Resets the MCU into the USB mass storage (UF2) mode of the boot ROM.
+    fn reboot_to_bootloader(&'static self) -> ! {
+        embassy_rp::rom_data::reset_to_usb_boot(0, 0);
+        // The boot ROM function does not return
+        loop {
+            core::hint::spin_loop();
+        }
     }
 }
 
+bind_interrupts!(
+    /// Binds the `DMA_IRQ_0` interrupt to the handlers of all DMA channels.
+    ///
+    /// All DMA channels share the `DMA_IRQ_0` interrupt, which is bound by this crate for the DMA
+    /// channels of the flash storage and the CYW43439 chip. This binding has to be used when
+    /// creating a driver for another DMA channel.
+    pub struct DmaIrqs {
+        DMA_IRQ_0 =>
+            dma::InterruptHandler<peripherals::DMA_CH0>,
+            dma::InterruptHandler<peripherals::DMA_CH1>,
+            dma::InterruptHandler<peripherals::DMA_CH2>,
+            dma::InterruptHandler<peripherals::DMA_CH3>,
+            dma::InterruptHandler<peripherals::DMA_CH4>,
+            dma::InterruptHandler<peripherals::DMA_CH5>,
+            dma::InterruptHandler<peripherals::DMA_CH6>,
+            dma::InterruptHandler<peripherals::DMA_CH7>,
+            dma::InterruptHandler<peripherals::DMA_CH8>,
+            dma::InterruptHandler<peripherals::DMA_CH9>,
+            dma::InterruptHandler<peripherals::DMA_CH10>,
+            dma::InterruptHandler<peripherals::DMA_CH11>,
+            #[cfg(any(feature = "rp235xa", feature = "rp235xb"))]
+            dma::InterruptHandler<peripherals::DMA_CH12>,
+            #[cfg(any(feature = "rp235xa", feature = "rp235xb"))]
+            dma::InterruptHandler<peripherals::DMA_CH13>,
+            #[cfg(any(feature = "rp235xa", feature = "rp235xb"))]
+            dma::InterruptHandler<peripherals::DMA_CH14>,
+            #[cfg(any(feature = "rp235xa", feature = "rp235xb"))]
+            dma::InterruptHandler<peripherals::DMA_CH15>;
+    }
+);
+
 type Flash = flash::Flash<'static, FLASH, flash::Async, 0x200000>;
 
 type WordSize = typenum::U4;
@@ -59,7 +297,11 @@ impl StorageDriver for DefaultStorageDriver {
     type Config = StorageConfig;
 
     fn create_storage(_: &'static Self::Mcu, config: Self::Config) -> Self::Storage {
-        let flash = Flash::new(unsafe { FLASH::steal() }, unsafe { DMA_CH0::steal() });
+        let flash = Flash::new(
+            unsafe { FLASH::steal() },
+            unsafe { DMA_CH0::steal() },
+            DmaIrqs,
+        );
         DefaultStorage::new(flash, config.flash_range)
     }
 }
@@ -84,3 +326,136 @@ mod usb {
         }
     }
 }
+
+#[cfg(feature = "ble")]
+mod ble {
+    use super::{Cyw43Firmware, DmaIrqs, Rp};
+    use bt_hci::controller::ExternalController;
+    use cyw43::bluetooth::BtDriver;
+    use cyw43_pio::{DEFAULT_CLOCK_DIVIDER, PioSpi};
+    use embassy_futures::join::join;
+    use embassy_rp::gpio::{Level, Output};
+    use embassy_rp::peripherals::{DMA_CH1, PIN_23, PIN_24, PIN_25, PIN_29, PIO0};
+    use embassy_rp::pio::{InterruptHandler, Pio};
+    use embassy_rp::{Peri, bind_interrupts, dma};
+    use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+    use embassy_sync::mutex::Mutex;
+    use lokey::Address;
+    use lokey_ble::BleStack;
+    use static_cell::StaticCell;
+    use trouble_host::Stack;
+    use trouble_host::prelude::{AddrKind, BdAddr, Central, DefaultPacketPool, Peripheral, Runner};
+
+    bind_interrupts!(struct Irqs {
+        PIO0_IRQ_0 => InterruptHandler<PIO0>;
+    });
+
+    /// The number of HCI command slots of the controller.
+    const NUM_COMMAND_SLOTS: usize = 10;
+
+    pub type Controller = ExternalController<BtDriver<'static>, NUM_COMMAND_SLOTS>;
+
+    type Spi = PioSpi<'static, PIO0, 0>;
+
+    pub struct Cyw43Peripherals {
+        pub pio: Peri<'static, PIO0>,
+        pub dma: Peri<'static, DMA_CH1>,
+        pub pwr: Peri<'static, PIN_23>,
+        pub dio: Peri<'static, PIN_24>,
+        pub cs: Peri<'static, PIN_25>,
+        pub clk: Peri<'static, PIN_29>,
+    }
+
+    /// The background work of the CYW43439 chip.
+    pub struct Cyw43 {
+        runner: cyw43::Runner<'static, cyw43::SpiBus<Output<'static>, Spi>>,
+        control: cyw43::Control<'static>,
+        clm: &'static [u8],
+    }
+
+    impl Cyw43 {
+        /// Runs the driver of the chip.
+        ///
+        /// The HCI packets of the Bluetooth controller are only exchanged while this function is
+        /// running.
+        pub async fn run(self) {
+            let Self {
+                runner,
+                mut control,
+                clm,
+            } = self;
+            join(runner.run(), control.init(clm)).await;
+        }
+    }
+
+    pub async fn build_cyw43(p: Cyw43Peripherals, firmware: Cyw43Firmware) -> (Cyw43, Controller) {
+        let pwr = Output::new(p.pwr, Level::Low);
+        let cs = Output::new(p.cs, Level::High);
+        let mut pio = Pio::new(p.pio, Irqs);
+        let spi = PioSpi::new(
+            &mut pio.common,
+            pio.sm0,
+            DEFAULT_CLOCK_DIVIDER,
+            pio.irq0,
+            cs,
+            p.dio,
+            p.clk,
+            dma::Channel::new(p.dma, DmaIrqs),
+        );
+
+        static STATE: StaticCell<cyw43::State> = StaticCell::new();
+        let state = STATE.init(cyw43::State::new());
+        let (_net_device, bt_device, control, runner) = cyw43::new_with_bluetooth(
+            state,
+            pwr,
+            spi,
+            firmware.firmware,
+            firmware.bluetooth_firmware,
+            firmware.nvram,
+        )
+        .await;
+
+        let cyw43 = Cyw43 {
+            runner,
+            control,
+            clm: firmware.clm,
+        };
+        (cyw43, ExternalController::new(bt_device))
+    }
+
+    pub fn device_address_to_ble_address(address: &Address) -> trouble_host::Address {
+        trouble_host::Address {
+            kind: AddrKind::RANDOM,
+            addr: BdAddr::new(address.0),
+        }
+    }
+
+    impl BleStack for Rp {
+        type Controller = Controller;
+
+        fn ble_stack(&self) -> &Stack<'static, Self::Controller, DefaultPacketPool> {
+            self.ble_stack
+        }
+
+        fn ble_host_central(
+            &self,
+        ) -> &Mutex<CriticalSectionRawMutex, Central<'static, Self::Controller, DefaultPacketPool>>
+        {
+            &self.ble_host_central
+        }
+
+        fn ble_host_peripheral(
+            &self,
+        ) -> &Mutex<CriticalSectionRawMutex, Peripheral<'static, Self::Controller, DefaultPacketPool>>
+        {
+            &self.ble_host_peripheral
+        }
+
+        fn ble_host_runner(
+            &self,
+        ) -> &Mutex<CriticalSectionRawMutex, Runner<'static, Self::Controller, DefaultPacketPool>>
+        {
+            &self.ble_host_runner
+        }
+    }
+}
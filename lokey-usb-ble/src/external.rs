@@ -1,24 +1,40 @@
-use embassy_futures::join::join4;
+use embassy_futures::join::join5;
 use embassy_futures::select::{Either, select};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::mutex::Mutex;
 use embassy_sync::signal::Signal;
 use embassy_time::Duration;
 use generic_array::GenericArray;
+use lokey::external::MessageConfigs;
 use lokey::util::{error, info, unwrap};
 use lokey::{Address, external, internal, storage};
 use lokey_ble::BleStack;
+use lokey_ble::external::Event;
 use lokey_usb::CreateDriver;
-use trouble_host::prelude::{BluetoothUuid16, appearance};
+use trouble_host::prelude::{AdStructure, BluetoothUuid16, appearance};
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum TransportSelection {
     Usb,
     Ble,
 }
 
+impl TransportSelection {
+    /// Returns whether the USB and the BLE transport are active if this transport is selected.
+    const fn activation(self) -> (bool, bool) {
+        match self {
+            Self::Usb => (true, false),
+            Self::Ble => (false, true),
+        }
+    }
+}
+
 pub enum Message {
+    /// Selects the transport that is used for sending and receiving messages.
+    ///
+    /// If [`TransportConfig::auto_failover`] is enabled, the selected transport is used even if
+    /// it is not connected, until it is disconnected the next time.
     SetActive(TransportSelection),
 }
 
@@ -58,6 +74,8 @@ pub struct Transport<Mcu: 'static, TxMessage, RxMessage> {
     active: Mutex<CriticalSectionRawMutex, TransportSelection>,
     activation_request: Signal<CriticalSectionRawMutex, ()>,
     deactivate_unused_transport: bool,
+    auto_failover: bool,
+    failover_state: Mutex<CriticalSectionRawMutex, FailoverState>,
     internal_channel: internal::DynChannelRef<'static>,
 }
 
@@ -104,7 +122,11 @@ where
             ble_transport,
             active,
             activation_request,
-            deactivate_unused_transport: config.deactivate_unused_transport,
+            // The unused transport has to stay active to detect when it is connected
+            deactivate_unused_transport: config.deactivate_unused_transport
+                && !config.auto_failover,
+            auto_failover: config.auto_failover,
+            failover_state: Mutex::new(FailoverState::new(TransportSelection::Ble)),
             internal_channel: internal_channel.as_dyn_ref(),
         }
     }
@@ -121,23 +143,8 @@ where
                     Either::First(()) => TransportSelection::Usb,
                     Either::Second(()) => TransportSelection::Ble,
                 };
-                info!("Setting active transport to {}", transport_selection);
-                let previous_transport_selection = {
-                    let mut active = self.active.lock().await;
-                    let v = *active;
-                    *active = transport_selection;
-                    v
-                };
-                if self.deactivate_unused_transport
-                    && previous_transport_selection != transport_selection
-                {
-                    self.usb_transport
-                        .set_active(transport_selection == TransportSelection::Usb)
-                        .await;
-                    self.usb_transport
-                        .set_active(transport_selection == TransportSelection::Ble)
-                        .await;
-                }
+                self.failover_state.lock().await.preferred = transport_selection;
+                self.select(transport_selection).await;
                 self.activation_request.signal(());
             }
         };
@@ -147,17 +154,55 @@ where
             loop {
                 let message = receiver.next().await;
                 match message {
-                    Message::SetActive(channel_selection) => {
-                        let mut active = self.active.lock().await;
-                        *active = channel_selection;
+                    Message::SetActive(transport_selection) => {
+                        self.failover_state
+                            .lock()
+                            .await
+                            .select_explicitly(transport_selection);
+                        self.select(transport_selection).await
                     }
                 }
             }
         };
 
-        join4(
+        let handle_failover = async {
+            if !self.auto_failover {
+                return;
+            }
+            let mut receiver = unwrap!(self.internal_channel.receiver::<Event>());
+            self.failover_state.lock().await.usb_connected = self.usb_transport.is_configured();
+            loop {
+                let (transport_selection, connected) = match select(
+                    self.usb_transport.wait_for_configured_change(),
+                    receiver.next(),
+                )
+                .await
+                {
+                    Either::First(configured) => (TransportSelection::Usb, configured),
+                    Either::Second(Event::Connected { .. }) => (TransportSelection::Ble, true),
+                    Either::Second(Event::Disconnected { .. }) => (TransportSelection::Ble, false),
+                    Either::Second(_) => continue,
+                };
+                let target = {
+                    let mut failover_state = self.failover_state.lock().await;
+                    failover_state.set_connected(transport_selection, connected);
+                    failover_state.target()
+                };
+                let Some(target) = target else {
+                    continue;
+                };
+                let active = *self.active.lock().await;
+                if active != target {
+                    info!("Failing over to {}", target);
+                    self.select(target).await;
+                }
+            }
+        };
+
+        join5(
             handle_activation_request,
             handle_internal_messages,
+            handle_failover,
             self.usb_transport.run(storage),
             self.ble_transport.run(storage),
         )
@@ -182,15 +227,9 @@ where
 
     async fn set_active(&self, value: bool) -> bool {
         if value && self.deactivate_unused_transport {
-            let active = *self.active.lock().await;
-            let usb_supported = self
-                .usb_transport
-                .set_active(active == TransportSelection::Usb)
-                .await;
-            let ble_supported = self
-                .ble_transport
-                .set_active(active == TransportSelection::Ble)
-                .await;
+            let (usb_active, ble_active) = self.active.lock().await.activation();
+            let usb_supported = self.usb_transport.set_active(usb_active).await;
+            let ble_supported = self.ble_transport.set_active(ble_active).await;
             usb_supported || ble_supported
         } else {
             let usb_supported = self.usb_transport.set_active(value).await;
@@ -210,6 +249,108 @@ where
     }
 }
 
+impl<Mcu, TxMessage, RxMessage> Transport<Mcu, TxMessage, RxMessage>
+where
+    Mcu: 'static + CreateDriver + BleStack,
+    TxMessage: external::Message + lokey_usb::external::TxMessage + lokey_ble::external::TxMessage,
+    RxMessage: external::Message + lokey_usb::external::RxMessage + lokey_ble::external::RxMessage,
+{
+    /// Sets the transport that is used for sending and receiving messages.
+    ///
+    /// If `deactivate_unused_transport` is enabled, the selected transport is activated and the
+    /// other transport is deactivated.
+    async fn select(&self, transport_selection: TransportSelection) {
+        select_transport(
+            &self.usb_transport,
+            &self.ble_transport,
+            &self.active,
+            transport_selection,
+            self.deactivate_unused_transport,
+        )
+        .await
+    }
+}
+
+/// Stores the selected transport in `active`.
+///
+/// If `deactivate_unused_transport` is `true` and the selection changed, the selected transport is
+/// activated and the other transport is deactivated.
+async fn select_transport<U, B>(
+    usb_transport: &U,
+    ble_transport: &B,
+    active: &Mutex<CriticalSectionRawMutex, TransportSelection>,
+    transport_selection: TransportSelection,
+    deactivate_unused_transport: bool,
+) where
+    U: external::Transport,
+    B: external::Transport,
+{
+    info!("Setting active transport to {}", transport_selection);
+    let previous_transport_selection =
+        core::mem::replace(&mut *active.lock().await, transport_selection);
+    if deactivate_unused_transport && previous_transport_selection != transport_selection {
+        let (usb_active, ble_active) = transport_selection.activation();
+        usb_transport.set_active(usb_active).await;
+        ble_transport.set_active(ble_active).await;
+    }
+}
+
+/// The connection state of both transports that is used to select the transport if
+/// [`TransportConfig::auto_failover`] is enabled.
+struct FailoverState {
+    /// The transport that is used while it is connected.
+    preferred: TransportSelection,
+    /// Whether the preferred transport was selected with [`Message::SetActive`] and is used even
+    /// if it is not connected.
+    overridden: bool,
+    usb_connected: bool,
+    ble_connected: bool,
+}
+
+impl FailoverState {
+    const fn new(preferred: TransportSelection) -> Self {
+        Self {
+            preferred,
+            overridden: false,
+            usb_connected: false,
+            ble_connected: false,
+        }
+    }
+
+    fn select_explicitly(&mut self, transport_selection: TransportSelection) {
+        self.preferred = transport_selection;
+        self.overridden = true;
+    }
+
+    fn set_connected(&mut self, transport_selection: TransportSelection, connected: bool) {
+        match transport_selection {
+            TransportSelection::Usb => self.usb_connected = connected,
+            TransportSelection::Ble => self.ble_connected = connected,
+        }
+        if !connected && transport_selection == self.preferred {
+            self.overridden = false;
+        }
+    }
+
+    /// Returns the transport that should be used, or `None` if the transport was selected
+    /// explicitly.
+    ///
+    /// The preferred transport is used if it is connected or if the other transport is not
+    /// connected either.
+    fn target(&self) -> Option<TransportSelection> {
+        if self.overridden {
+            return None;
+        }
+        let (usb_connected, ble_connected) = (self.usb_connected, self.ble_connected);
+        let target = match self.preferred {
+            TransportSelection::Usb if !usb_connected && ble_connected => TransportSelection::Ble,
+            TransportSelection::Ble if !ble_connected && usb_connected => TransportSelection::Usb,
+            preferred => preferred,
+        };
+        Some(target)
+    }
+}
+
 pub struct TransportConfig {
     pub name: &'static str,
     pub vendor_id: u16,
@@ -224,7 +365,29 @@ pub struct TransportConfig {
     pub appearance: &'static BluetoothUuid16,
     pub ble_min_connection_interval: Option<Duration>,
     pub ble_max_connection_interval: Option<Duration>,
+    pub ble_profile_connection_params: &'static [lokey_ble::external::ConnectionParams],
+    pub ble_battery_service: bool,
+    pub ble_startup_delay: Duration,
+    pub ble_coalesce_window: Duration,
+    pub ble_custom_ad_structures: &'static [AdStructure<'static>],
+    pub ble_require_pairing_mode: bool,
+    pub ble_pairing_mode_timeout: Duration,
     pub deactivate_unused_transport: bool,
+    /// Automatically use the other transport while the selected transport is not connected.
+    ///
+    /// If this is enabled and the selected transport (e.g. BLE) has no connection, messages are
+    /// sent over the other transport if it is connected (e.g. USB is configured by a host). Once
+    /// the selected transport is connected again, it is used again. A transport that is selected
+    /// with [`Message::SetActive`] is used even if it is not connected, until it is disconnected
+    /// the next time.
+    ///
+    /// Both transports are kept active while this is enabled, so `deactivate_unused_transport`
+    /// has no effect.
+    pub auto_failover: bool,
+    /// Configurations of the sent and received message types (e.g. of the keyboard reports).
+    ///
+    /// The configurations are used by both transports.
+    pub message_configs: MessageConfigs,
 }
 
 impl Default for TransportConfig {
@@ -243,7 +406,16 @@ impl Default for TransportConfig {
             appearance: &appearance::UNKNOWN,
             ble_min_connection_interval: None,
             ble_max_connection_interval: None,
+            ble_profile_connection_params: &[],
+            ble_battery_service: false,
+            ble_startup_delay: Duration::from_ticks(0),
+            ble_coalesce_window: Duration::from_ticks(0),
+            ble_custom_ad_structures: &[],
+            ble_require_pairing_mode: false,
+            ble_pairing_mode_timeout: Duration::from_secs(60),
             deactivate_unused_transport: true,
+            auto_failover: false,
+            message_configs: MessageConfigs::default(),
         }
     }
 }
@@ -257,6 +429,7 @@ impl TransportConfig {
             product: self.product,
             serial_number: self.serial_number,
             self_powered: self.self_powered,
+            message_configs: self.message_configs,
         }
     }
 
@@ -273,6 +446,133 @@ impl TransportConfig {
             appearance: self.appearance,
             min_connection_interval: self.ble_min_connection_interval,
             max_connection_interval: self.ble_max_connection_interval,
+            profile_connection_params: self.ble_profile_connection_params,
+            battery_service: self.ble_battery_service,
+            startup_delay: self.ble_startup_delay,
+            coalesce_window: self.ble_coalesce_window,
+            custom_ad_structures: self.ble_custom_ad_structures,
+            require_pairing_mode: self.ble_require_pairing_mode,
+            pairing_mode_timeout: self.ble_pairing_mode_timeout,
+            message_configs: self.message_configs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use embassy_futures::block_on;
+    use lokey::external::{NoMessage, Transport as _};
+
+    struct MockTransport {
+        active: AtomicBool,
+    }
+
+    impl external::Transport for MockTransport {
+        type Config = ();
+        type Mcu = ();
+        type TxMessage = NoMessage;
+        type RxMessage = NoMessage;
+
+        async fn create<T>(
+            _: Self::Config,
+            _: &'static Self::Mcu,
+            _: Address,
+            _: &'static internal::Channel<T>,
+        ) -> Self
+        where
+            T: internal::Transport<Mcu = Self::Mcu>,
+        {
+            mock_transport()
+        }
+
+        async fn run<Storage>(&self, _: &'static Storage)
+        where
+            Storage: storage::Storage,
+        {
+        }
+
+        async fn send(&self, _: Self::TxMessage) {}
+
+        async fn receive(&self) -> Self::RxMessage {
+            core::future::pending().await
+        }
+
+        async fn set_active(&self, value: bool) -> bool {
+            self.active.store(value, Ordering::SeqCst);
+            true
+        }
+
+        fn is_active(&self) -> bool {
+            self.active.load(Ordering::SeqCst)
         }
     }
+
+    fn mock_transport() -> MockTransport {
+        MockTransport {
+            active: AtomicBool::new(true),
+        }
+    }
+
+    #[test]
+    fn switching_deactivates_the_unused_transport() {
+        let usb_transport = mock_transport();
+        let ble_transport = mock_transport();
+        let active = Mutex::new(TransportSelection::Ble);
+        let select = |transport_selection| {
+            block_on(select_transport(
+                &usb_transport,
+                &ble_transport,
+                &active,
+                transport_selection,
+                true,
+            ))
+        };
+
+        select(TransportSelection::Usb);
+        assert!(usb_transport.is_active());
+        assert!(!ble_transport.is_active());
+
+        select(TransportSelection::Ble);
+        assert!(!usb_transport.is_active());
+        assert!(ble_transport.is_active());
+        assert_eq!(*block_on(active.lock()), TransportSelection::Ble);
+    }
+
+    #[test]
+    fn switching_keeps_both_transports_active() {
+        let usb_transport = mock_transport();
+        let ble_transport = mock_transport();
+        let active = Mutex::new(TransportSelection::Ble);
+        block_on(select_transport(
+            &usb_transport,
+            &ble_transport,
+            &active,
+            TransportSelection::Usb,
+            false,
+        ));
+        assert!(usb_transport.is_active());
+        assert!(ble_transport.is_active());
+        assert_eq!(*block_on(active.lock()), TransportSelection::Usb);
+    }
+
+    #[test]
+    fn failover() {
+        let mut state = FailoverState::new(TransportSelection::Ble);
+        state.set_connected(TransportSelection::Usb, true);
+        assert_eq!(state.target(), Some(TransportSelection::Usb));
+        state.set_connected(TransportSelection::Ble, true);
+        assert_eq!(state.target(), Some(TransportSelection::Ble));
+
+        // An explicit selection overrides the failover until the selected transport disconnects
+        state.select_explicitly(TransportSelection::Usb);
+        assert_eq!(state.target(), None);
+        state.set_connected(TransportSelection::Ble, false);
+        assert_eq!(state.target(), None);
+        state.set_connected(TransportSelection::Usb, false);
+        assert_eq!(state.target(), Some(TransportSelection::Usb));
+        state.set_connected(TransportSelection::Ble, true);
+        assert_eq!(state.target(), Some(TransportSelection::Ble));
+    }
 }
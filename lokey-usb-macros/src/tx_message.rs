@@ -91,10 +91,10 @@ pub fn tx_message_derive(item: TokenStream) -> TokenStream {
                 )
             }
 
-            fn init(builder: &mut ::lokey_usb::embassy_usb::Builder<'d, D>, params: &'d mut Self::Params) -> Self {
+            fn init(builder: &mut ::lokey_usb::embassy_usb::Builder<'d, D>, params: &'d mut Self::Params, configs: ::lokey_usb::external::MessageConfigs) -> Self {
                 Self {
                     services: (
-                        #(<<#variant_types as ::lokey_usb::external::TxMessage>::MessageService<'d, D> as ::lokey_usb::external::InitMessageService<'d, D>>::init(builder, &mut params.#field_indices)),*
+                        #(<<#variant_types as ::lokey_usb::external::TxMessage>::MessageService<'d, D> as ::lokey_usb::external::InitMessageService<'d, D>>::init(builder, &mut params.#field_indices, configs)),*
                     ),
                 }
             }
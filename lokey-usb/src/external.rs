@@ -5,6 +5,7 @@ use core::sync::atomic::Ordering;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::signal::Signal;
 use embassy_usb::driver::Driver;
+pub use lokey::external::MessageConfigs;
 use lokey::external::{self, NoMessage};
 use lokey::util::{debug, info};
 #[cfg(feature = "macros")]
@@ -21,6 +22,8 @@ pub struct TransportConfig {
     pub product: Option<&'static str>,
     pub serial_number: Option<&'static str>,
     pub self_powered: bool,
+    /// Configurations of the sent and received message types (e.g. of the keyboard reports).
+    pub message_configs: MessageConfigs,
 }
 
 impl Default for TransportConfig {
@@ -32,6 +35,7 @@ impl Default for TransportConfig {
             product: None,
             serial_number: None,
             self_powered: false,
+            message_configs: MessageConfigs::default(),
         }
     }
 }
@@ -48,7 +52,8 @@ impl From<TransportConfig> for embassy_usb::Config<'static> {
 }
 
 struct DeviceHandlerContext {
-    configured: bool,
+    configured: AtomicBool,
+    configured_signal: Signal<CriticalSectionRawMutex, bool>,
     suspended: AtomicBool,
     activation_request_signal: Signal<CriticalSectionRawMutex, ()>,
 }
@@ -56,7 +61,8 @@ struct DeviceHandlerContext {
 impl DeviceHandlerContext {
     pub fn new() -> Self {
         Self {
-            configured: false,
+            configured: AtomicBool::new(false),
+            configured_signal: Signal::new(),
             suspended: AtomicBool::new(false),
             activation_request_signal: Signal::new(),
         }
@@ -64,7 +70,8 @@ impl DeviceHandlerContext {
 
     fn create_device_handler(&self) -> DeviceHandler<'_> {
         DeviceHandler {
-            configured: self.configured,
+            configured: &self.configured,
+            configured_signal: &self.configured_signal,
             suspended: &self.suspended,
             activation_request_signal: &self.activation_request_signal,
         }
@@ -72,16 +79,23 @@ impl DeviceHandlerContext {
 }
 
 struct DeviceHandler<'a> {
-    configured: bool,
+    configured: &'a AtomicBool,
+    configured_signal: &'a Signal<CriticalSectionRawMutex, bool>,
     suspended: &'a AtomicBool,
     activation_request_signal: &'a Signal<CriticalSectionRawMutex, ()>,
 }
 
-impl<'a> DeviceHandler<'a> {}
+impl<'a> DeviceHandler<'a> {
+    fn set_configured(&mut self, configured: bool) {
+        if self.configured.swap(configured, Ordering::AcqRel) != configured {
+            self.configured_signal.signal(configured);
+        }
+    }
+}
 
 impl<'a> embassy_usb::Handler for DeviceHandler<'a> {
     fn enabled(&mut self, enabled: bool) {
-        self.configured = false;
+        self.set_configured(false);
         self.suspended.store(false, Ordering::Release);
         #[allow(clippy::if_same_then_else)]
         if enabled {
@@ -92,18 +106,18 @@ impl<'a> embassy_usb::Handler for DeviceHandler<'a> {
     }
 
     fn reset(&mut self) {
-        self.configured = false;
+        self.set_configured(false);
         debug!("Bus reset, the Vbus current limit is 100mA");
     }
 
     fn addressed(&mut self, addr: u8) {
-        self.configured = false;
+        self.set_configured(false);
         info!("USB address set to: {}", addr);
         self.activation_request_signal.signal(());
     }
 
     fn configured(&mut self, configured: bool) {
-        self.configured = configured;
+        self.set_configured(configured);
         #[allow(clippy::if_same_then_else)]
         if configured {
             debug!(
@@ -123,7 +137,7 @@ impl<'a> embassy_usb::Handler for DeviceHandler<'a> {
         } else {
             self.suspended.store(false, Ordering::Release);
             #[allow(clippy::if_same_then_else)]
-            if self.configured {
+            if self.configured.load(Ordering::Acquire) {
                 debug!(
                     "USB device resumed, it may now draw up to the configured current limit from Vbus"
                 );
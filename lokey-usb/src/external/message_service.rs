@@ -1,13 +1,21 @@
 use embassy_usb::Builder;
 use embassy_usb::driver::Driver;
-use lokey::external::{Message, NoMessage};
+use lokey::external::{Message, MessageConfigs, NoMessage};
 
 pub trait InitMessageService<'d, D: Driver<'d>> {
     type Params;
 
     fn create_params() -> Self::Params;
 
-    fn init(builder: &mut Builder<'d, D>, params: &'d mut Self::Params) -> Self;
+    /// Initializes the message service.
+    ///
+    /// `configs` contains the message configurations of the transport config, from which the
+    /// message service can get its configuration.
+    fn init(
+        builder: &mut Builder<'d, D>,
+        params: &'d mut Self::Params,
+        configs: MessageConfigs,
+    ) -> Self;
 }
 
 pub trait TxMessageService<T: Message> {
@@ -23,7 +31,7 @@ impl<'d, D: Driver<'d>> InitMessageService<'d, D> for () {
 
     fn create_params() -> Self::Params {}
 
-    fn init(_: &mut Builder<'d, D>, _: &'d mut Self::Params) -> Self {}
+    fn init(_: &mut Builder<'d, D>, _: &'d mut Self::Params, _: MessageConfigs) -> Self {}
 }
 
 impl TxMessageService<NoMessage> for () {
@@ -87,13 +87,17 @@ where
         builder.handler(&mut device_handler);
 
         #[allow(clippy::missing_transmute_annotations)]
-        let tx_message_service = TxMessage::MessageService::init(&mut builder, unsafe {
-            core::mem::transmute(&mut tx_message_service_params)
-        });
+        let tx_message_service = TxMessage::MessageService::init(
+            &mut builder,
+            unsafe { core::mem::transmute(&mut tx_message_service_params) },
+            self.config.message_configs,
+        );
         #[allow(clippy::missing_transmute_annotations)]
-        let rx_message_service = RxMessage::MessageService::init(&mut builder, unsafe {
-            core::mem::transmute(&mut rx_message_service_params)
-        });
+        let rx_message_service = RxMessage::MessageService::init(
+            &mut builder,
+            unsafe { core::mem::transmute(&mut rx_message_service_params) },
+            self.config.message_configs,
+        );
 
         let mut usb = builder.build();
 
@@ -159,3 +163,20 @@ where
             .await
     }
 }
+
+impl<Mcu, TxMessage, RxMessage> Transport<Mcu, TxMessage, RxMessage> {
+    /// Returns `true` if the device is configured by a USB host.
+    pub fn is_configured(&self) -> bool {
+        self.device_handler_context
+            .configured
+            .load(Ordering::Acquire)
+    }
+
+    /// Waits until the device is configured or no longer configured by a USB host and returns the
+    /// new state.
+    ///
+    /// Only one task should wait for changes at a time.
+    pub async fn wait_for_configured_change(&self) -> bool {
+        self.device_handler_context.configured_signal.wait().await
+    }
+}
@@ -0,0 +1,292 @@
+//! Types for reporting the battery level of a device.
+
+use crate::{Address, internal};
+use arrayvec::ArrayVec;
+use core::cell::RefCell;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use generic_array::GenericArray;
+
+/// Internal message that is sent when the battery level of a device was measured.
+///
+/// Components that measure the battery level (e.g. an ADC based battery monitor) send this
+/// message, and components that report the battery level (e.g. status LEDs) receive it. The BLE
+/// battery service uses the [`DeviceBatteryLevel`] message instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BatteryLevel {
+    /// The charge of the battery in percent (between 0 and 100).
+    pub percentage: u8,
+}
+
+impl internal::Message for BatteryLevel {
+    type Size = typenum::U1;
+
+    const TAG: [u8; 4] = [0x3b, 0x9e, 0x51, 0xd4];
+
+    fn from_bytes(bytes: GenericArray<u8, Self::Size>) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        let percentage = bytes[0];
+        if percentage > 100 {
+            return None;
+        }
+        Some(Self { percentage })
+    }
+
+    fn to_bytes(&self) -> GenericArray<u8, Self::Size> {
+        [self.percentage].into()
+    }
+}
+
+/// Internal message that is sent periodically with the battery level of the device with the
+/// specified address.
+///
+/// In contrast to [`BatteryLevel`], the message identifies the device that measured the battery
+/// level, so that the central device of a split keyboard can keep track of the battery levels of
+/// all parts (see [`BatteryLevels`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeviceBatteryLevel {
+    /// The address of the device that measured the battery level.
+    pub address: Address,
+    /// The charge of the battery in percent (between 0 and 100).
+    pub percentage: u8,
+}
+
+impl internal::Message for DeviceBatteryLevel {
+    type Size = typenum::U7;
+
+    const TAG: [u8; 4] = [0x8d, 0x27, 0xe3, 0x4a];
+
+    fn from_bytes(bytes: GenericArray<u8, Self::Size>) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        let bytes = bytes.into_array::<7>();
+        let percentage = bytes[6];
+        if percentage > 100 {
+            return None;
+        }
+        Some(Self {
+            address: Address(bytes[..6].try_into().unwrap()),
+            percentage,
+        })
+    }
+
+    fn to_bytes(&self) -> GenericArray<u8, Self::Size> {
+        let mut bytes = [0; 7];
+        bytes[..6].copy_from_slice(&self.address.0);
+        bytes[6] = self.percentage;
+        bytes.into()
+    }
+}
+
+/// The maximum number of devices whose battery levels are tracked by [`BatteryLevels`].
+pub const MAX_BATTERY_DEVICES: usize = 4;
+
+/// The most recent battery levels of multiple devices (e.g. both halves of a split keyboard).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatteryLevels {
+    levels: ArrayVec<(Address, u8), MAX_BATTERY_DEVICES>,
+}
+
+impl BatteryLevels {
+    /// Creates an empty [`BatteryLevels`].
+    pub const fn new() -> Self {
+        Self {
+            levels: ArrayVec::new_const(),
+        }
+    }
+
+    /// Stores the battery level of the device in the message.
+    ///
+    /// Returns `true` if the lowest battery level changed. If the levels of more than
+    /// [`MAX_BATTERY_DEVICES`] devices are received, the levels of the additional devices are
+    /// ignored.
+    pub fn update(&mut self, message: DeviceBatteryLevel) -> bool {
+        let previous_lowest = self.lowest();
+        match self
+            .levels
+            .iter_mut()
+            .find(|(address, _)| *address == message.address)
+        {
+            Some((_, percentage)) => *percentage = message.percentage,
+            None => {
+                if self
+                    .levels
+                    .try_push((message.address, message.percentage))
+                    .is_err()
+                {
+                    return false;
+                }
+            }
+        }
+        self.lowest() != previous_lowest
+    }
+
+    /// Returns the battery level of the device with the specified address.
+    pub fn get(&self, address: Address) -> Option<u8> {
+        self.levels
+            .iter()
+            .find(|(v, _)| *v == address)
+            .map(|(_, percentage)| *percentage)
+    }
+
+    /// Returns the lowest battery level of all devices.
+    ///
+    /// This is the level that is reported to the host, as the keyboard stops working once any of
+    /// its parts runs out of battery.
+    pub fn lowest(&self) -> Option<u8> {
+        self.levels.iter().map(|(_, percentage)| *percentage).min()
+    }
+
+    /// Returns an iterator over the addresses and battery levels of all devices.
+    pub fn iter(&self) -> impl Iterator<Item = (Address, u8)> + '_ {
+        self.levels.iter().copied()
+    }
+}
+
+/// State type for the battery levels of all devices.
+///
+/// If this type is part of the state, the battery monitor of the central device stores the
+/// received [`DeviceBatteryLevel`] messages in it.
+pub struct BatteryLevelsState {
+    inner: Mutex<CriticalSectionRawMutex, RefCell<BatteryLevels>>,
+}
+
+impl Default for BatteryLevelsState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BatteryLevelsState {
+    /// Creates a new [`BatteryLevelsState`] without any battery levels.
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(BatteryLevels::new())),
+        }
+    }
+
+    /// Returns a clone of the current battery levels.
+    pub fn get(&self) -> BatteryLevels {
+        self.inner.lock(|v| v.borrow().clone())
+    }
+
+    /// Stores the battery level of the device in the message.
+    pub fn update(&self, message: DeviceBatteryLevel) {
+        self.inner.lock(|v| v.borrow_mut().update(message));
+    }
+}
+
+/// Mapping from battery voltages to charge percentages.
+///
+/// The curve consists of `(millivolts, percentage)` points that must be sorted by descending
+/// voltage. Voltages between two points are linearly interpolated, voltages above the first point
+/// are mapped to the percentage of the first point and voltages below the last point are mapped to
+/// the percentage of the last point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DischargeCurve {
+    points: &'static [(u16, u8)],
+}
+
+impl DischargeCurve {
+    /// Discharge curve of a typical single cell lithium-ion or lithium-polymer battery.
+    pub const LI_ION: Self = Self::new(&[
+        (4200, 100),
+        (4100, 90),
+        (4000, 80),
+        (3900, 65),
+        (3800, 50),
+        (3750, 40),
+        (3700, 30),
+        (3650, 20),
+        (3600, 10),
+        (3500, 5),
+        (3300, 0),
+    ]);
+
+    /// Creates a new discharge curve from the specified `(millivolts, percentage)` points.
+    ///
+    /// The points must be sorted by descending voltage.
+    pub const fn new(points: &'static [(u16, u8)]) -> Self {
+        Self { points }
+    }
+
+    /// Converts the specified battery voltage to a charge percentage.
+    pub fn percentage(&self, millivolts: u16) -> u8 {
+        let Some(&(first_millivolts, first_percentage)) = self.points.first() else {
+            return 0;
+        };
+        if millivolts >= first_millivolts {
+            return first_percentage.min(100);
+        }
+        for window in self.points.windows(2) {
+            let (upper_millivolts, upper_percentage) = window[0];
+            let (lower_millivolts, lower_percentage) = window[1];
+            if millivolts >= lower_millivolts {
+                let range = upper_millivolts.saturating_sub(lower_millivolts) as u32;
+                if range == 0 {
+                    return upper_percentage.min(100);
+                }
+                let offset = (millivolts - lower_millivolts) as u32;
+                let lower = lower_percentage as u32;
+                let upper = upper_percentage as u32;
+                let percentage = if upper >= lower {
+                    lower + (upper - lower) * offset / range
+                } else {
+                    lower - (lower - upper) * offset / range
+                };
+                return (percentage as u8).min(100);
+            }
+        }
+        let (_, last_percentage) = self.points[self.points.len() - 1];
+        last_percentage.min(100)
+    }
+}
+
+impl Default for DischargeCurve {
+    fn default() -> Self {
+        Self::LI_ION
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn li_ion_percentage() {
+        let curve = DischargeCurve::LI_ION;
+        assert_eq!(curve.percentage(4300), 100);
+        assert_eq!(curve.percentage(4200), 100);
+        assert_eq!(curve.percentage(4150), 95);
+        assert_eq!(curve.percentage(3800), 50);
+        assert_eq!(curve.percentage(3775), 45);
+        assert_eq!(curve.percentage(3300), 0);
+        assert_eq!(curve.percentage(3000), 0);
+    }
+
+    #[test]
+    fn lowest_battery_level() {
+        let central = Address([1; 6]);
+        let peripheral = Address([2; 6]);
+        let mut levels = BatteryLevels::new();
+        assert!(levels.update(DeviceBatteryLevel {
+            address: central,
+            percentage: 80,
+        }));
+        assert!(levels.update(DeviceBatteryLevel {
+            address: peripheral,
+            percentage: 60,
+        }));
+        assert!(!levels.update(DeviceBatteryLevel {
+            address: central,
+            percentage: 70,
+        }));
+        assert_eq!(levels.get(central), Some(70));
+        assert_eq!(levels.lowest(), Some(60));
+    }
+}
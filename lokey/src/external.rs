@@ -1,11 +1,12 @@
 //! External communication with a host.
 
+pub mod broadcast;
 mod channel;
 pub mod empty;
 mod r#override;
 pub mod toggle;
 
-use crate::util::declare_const_for_feature_group;
+use crate::util::{declare_const_for_feature_group, warn};
 use crate::{Address, Device, Transports, internal};
 pub use channel::{Channel, DynChannelRef, Observer, Receiver, TryObserver, TryReceiver};
 use core::any::Any;
@@ -198,6 +199,64 @@ impl Message for NoMessage {
     }
 }
 
+/// Trait for the configuration of a message type that is passed to the external transports with
+/// [`MessageConfigs`].
+///
+/// Only types that implement this trait can be added to [`MessageConfigs`], so that passing a
+/// value that is not a message configuration (which would be silently ignored) fails to compile.
+pub trait MessageConfig: Any + Sync {}
+
+/// Configurations of the message types that are sent or received by an external transport.
+///
+/// Transports pass these configurations to the services that handle the individual message types
+/// (e.g. to choose the HID report descriptor of a keyboard report). Each configuration is
+/// identified by its type, so a message type can look up its configuration with [`get`](Self::get)
+/// and use its default configuration if none was provided. Configurations of message types that
+/// are not sent or received by the transport are ignored.
+///
+/// # Example
+///
+/// ```
+/// use lokey::external::{MessageConfig, MessageConfigs};
+///
+/// #[derive(Default)]
+/// pub struct MyMessageConfig {
+///     pub poll_interval_ms: u8,
+/// }
+///
+/// impl MessageConfig for MyMessageConfig {}
+///
+/// let configs = MessageConfigs::new(&[&MyMessageConfig {
+///     poll_interval_ms: 10,
+/// }]);
+/// assert_eq!(configs.get::<MyMessageConfig>().unwrap().poll_interval_ms, 10);
+/// ```
+#[derive(Clone, Copy, Default)]
+pub struct MessageConfigs(&'static [&'static dyn MessageConfig]);
+
+impl MessageConfigs {
+    /// Creates a new [`MessageConfigs`] with the specified configurations.
+    pub const fn new(configs: &'static [&'static dyn MessageConfig]) -> Self {
+        Self(configs)
+    }
+
+    /// Returns the configuration of type `T`, or `None` if no such configuration was provided.
+    ///
+    /// If multiple configurations of type `T` were provided, the first one is returned and a
+    /// warning is logged.
+    pub fn get<T: MessageConfig>(&self) -> Option<&'static T> {
+        let mut configs = self.0.iter().filter_map(|config| {
+            let config: &'static dyn Any = *config;
+            config.downcast_ref()
+        });
+        let config = configs.next();
+        if configs.next().is_some() {
+            warn!("Multiple message configs of the same type were provided, using the first one");
+        }
+        config
+    }
+}
+
 /// Trait for external transports used by devices to communicate with a host.
 pub trait Transport: Any {
     /// The configuration for this transport.
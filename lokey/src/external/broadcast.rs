@@ -0,0 +1,207 @@
+//! An external transport wrapper that uses two transports at the same time.
+//!
+//! Unlike the [toggle transport](super::toggle) or a transport that switches between USB and BLE,
+//! every message is sent through both transports, e.g. to send the keyboard reports to two hosts
+//! at the same time. Multiple broadcast transports can be nested to use more than two transports.
+//!
+//! The broadcast transport is active as long as at least one of its transports is active, since
+//! the messages still reach a host in that case. Accordingly, it only supports deactivating if both
+//! transports do, because a transport that can not be deactivated keeps the broadcast transport
+//! active. This means that after [`set_active(false)`](external::Transport::set_active) returned
+//! `true`, [`is_active`](external::Transport::is_active) returns `false`.
+
+use crate::{Address, external, internal};
+use embassy_futures::join::join;
+use embassy_futures::select::{Either, select};
+
+/// Configuration for the broadcast transport.
+pub struct TransportConfig<T1, T2> {
+    /// The configuration of the first transport.
+    pub first: T1,
+    /// The configuration of the second transport.
+    pub second: T2,
+}
+
+impl<T1, T2> TransportConfig<T1, T2> {
+    /// Creates a new transport configuration with the specified configurations of the underlying
+    /// transports.
+    pub const fn new(first: T1, second: T2) -> Self {
+        Self { first, second }
+    }
+}
+
+/// An external transport wrapper that sends every message through two transports.
+///
+/// Messages received by either transport are passed to the device.
+pub struct Transport<T1, T2> {
+    first: T1,
+    second: T2,
+}
+
+impl<T1, T2, TxMessage, RxMessage> external::Transport for Transport<T1, T2>
+where
+    T1: external::Transport<TxMessage = TxMessage, RxMessage = RxMessage>,
+    T2: external::Transport<Mcu = T1::Mcu, TxMessage = TxMessage, RxMessage = RxMessage>,
+    TxMessage: external::Message,
+    RxMessage: external::Message,
+{
+    type Config = TransportConfig<T1::Config, T2::Config>;
+    type Mcu = T1::Mcu;
+    type TxMessage = TxMessage;
+    type RxMessage = RxMessage;
+
+    async fn create<U>(
+        config: Self::Config,
+        mcu: &'static Self::Mcu,
+        address: Address,
+        internal_channel: &'static internal::Channel<U>,
+    ) -> Self
+    where
+        U: internal::Transport<Mcu = Self::Mcu>,
+    {
+        let first = T1::create(config.first, mcu, address, internal_channel).await;
+        let second = T2::create(config.second, mcu, address, internal_channel).await;
+        Self { first, second }
+    }
+
+    async fn run<Storage>(&self, storage: &'static Storage)
+    where
+        Storage: crate::storage::Storage,
+    {
+        join(self.first.run(storage), self.second.run(storage)).await;
+    }
+
+    async fn send(&self, message: Self::TxMessage) {
+        join(self.first.send(message.clone()), self.second.send(message)).await;
+    }
+
+    async fn receive(&self) -> Self::RxMessage {
+        match select(self.first.receive(), self.second.receive()).await {
+            Either::First(message) | Either::Second(message) => message,
+        }
+    }
+
+    /// Activates or deactivates both transports.
+    ///
+    /// Returns `true` only if both transports support deactivating (see the
+    /// [module-level documentation](self)).
+    async fn set_active(&self, value: bool) -> bool {
+        let (first, second) =
+            join(self.first.set_active(value), self.second.set_active(value)).await;
+        first && second
+    }
+
+    /// Returns whether at least one of the transports is activated (see the
+    /// [module-level documentation](self)).
+    fn is_active(&self) -> bool {
+        self.first.is_active() || self.second.is_active()
+    }
+
+    async fn wait_for_activation_request(&self) {
+        select(
+            self.first.wait_for_activation_request(),
+            self.second.wait_for_activation_request(),
+        )
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external::{NoMessage, Transport as _};
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use embassy_futures::block_on;
+
+    /// A transport that is always active if it does not support deactivating.
+    struct MockTransport {
+        supports_deactivating: bool,
+        active: AtomicBool,
+    }
+
+    impl MockTransport {
+        fn new(supports_deactivating: bool) -> Self {
+            Self {
+                supports_deactivating,
+                active: AtomicBool::new(true),
+            }
+        }
+    }
+
+    impl external::Transport for MockTransport {
+        type Config = ();
+        type Mcu = ();
+        type TxMessage = NoMessage;
+        type RxMessage = NoMessage;
+
+        async fn create<U>(
+            _: Self::Config,
+            _: &'static Self::Mcu,
+            _: Address,
+            _: &'static internal::Channel<U>,
+        ) -> Self
+        where
+            U: internal::Transport<Mcu = Self::Mcu>,
+        {
+            Self::new(true)
+        }
+
+        async fn run<Storage>(&self, _: &'static Storage)
+        where
+            Storage: crate::storage::Storage,
+        {
+        }
+
+        async fn send(&self, _: Self::TxMessage) {}
+
+        async fn receive(&self) -> Self::RxMessage {
+            core::future::pending().await
+        }
+
+        async fn set_active(&self, value: bool) -> bool {
+            if self.supports_deactivating {
+                self.active.store(value, Ordering::SeqCst);
+            }
+            self.supports_deactivating
+        }
+
+        fn is_active(&self) -> bool {
+            self.active.load(Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn deactivate_both() {
+        let transport = Transport {
+            first: MockTransport::new(true),
+            second: MockTransport::new(true),
+        };
+        assert!(block_on(transport.set_active(false)));
+        assert!(!transport.is_active());
+        assert!(block_on(transport.set_active(true)));
+        assert!(transport.is_active());
+    }
+
+    #[test]
+    fn deactivate_unsupported() {
+        let transport = Transport {
+            first: MockTransport::new(true),
+            second: MockTransport::new(false),
+        };
+        assert!(!block_on(transport.set_active(false)));
+        assert!(!transport.first.is_active());
+        assert!(transport.is_active());
+    }
+
+    #[test]
+    fn active_if_any_is_active() {
+        let transport = Transport {
+            first: MockTransport::new(true),
+            second: MockTransport::new(true),
+        };
+        block_on(transport.first.set_active(false));
+        assert!(transport.is_active());
+        block_on(transport.second.set_active(false));
+        assert!(!transport.is_active());
+    }
+}
@@ -105,6 +105,13 @@ where
         }
     }
 
+    /// Activates or deactivates the transport of this channel.
+    ///
+    /// Returns `false` if the transport does not support deactivating, otherwise `true`.
+    pub async fn set_active(&self, value: bool) -> bool {
+        self.transport.set_active(value).await
+    }
+
     /// Sends a message through this channel.
     pub async fn send<M>(&self, message: M)
     where
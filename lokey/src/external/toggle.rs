@@ -1,6 +1,10 @@
 //! An external transport wrapper that can be activated and deactivated.
 //!
 //! The transport can be activated and deactivated by sending [`Message`] through the internal channel.
+//!
+//! Each toggle transport belongs to a group (see [`TransportConfig::group`]). A message only
+//! affects the toggle transports of the addressed device that belong to the group of the message,
+//! which allows multiple toggle transports to be used independently of each other.
 
 use crate::util::{debug, unwrap};
 use crate::{Address, external, internal};
@@ -11,32 +15,40 @@ use embassy_sync::signal::Signal;
 use generic_array::GenericArray;
 use portable_atomic::AtomicBool;
 
-static ACTIVATION_REQUEST: Signal<CriticalSectionRawMutex, ()> = Signal::new();
-static ACTIVE: AtomicBool = AtomicBool::new(false);
-
 /// An internal message used to activate, deactivate, or toggle the transport.
+///
+/// The second field of each variant is the group of the transport (see
+/// [`TransportConfig::group`]).
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Message {
-    /// Activates the transport of the device with the specified address.
-    Activate(Address),
-    /// Deactivates the transport of the device with the specified address.
-    Deactivate(Address),
-    /// Toggles the transport of the device with the specified address.
-    Toggle(Address),
+    /// Activates the transport of the device with the specified address and group.
+    Activate(Address, u8),
+    /// Deactivates the transport of the device with the specified address and group.
+    Deactivate(Address, u8),
+    /// Toggles the transport of the device with the specified address and group.
+    Toggle(Address, u8),
 }
 
 impl Message {
     fn address(&self) -> &Address {
         match self {
-            Self::Activate(address) => address,
-            Self::Deactivate(address) => address,
-            Self::Toggle(address) => address,
+            Self::Activate(address, _) => address,
+            Self::Deactivate(address, _) => address,
+            Self::Toggle(address, _) => address,
+        }
+    }
+
+    fn group(&self) -> u8 {
+        match self {
+            Self::Activate(_, group) => *group,
+            Self::Deactivate(_, group) => *group,
+            Self::Toggle(_, group) => *group,
         }
     }
 }
 
 impl internal::Message for Message {
-    type Size = typenum::U7;
+    type Size = typenum::U8;
 
     const TAG: [u8; 4] = [0x16, 0xb3, 0x17, 0x8e];
 
@@ -44,24 +56,27 @@ impl internal::Message for Message {
     where
         Self: Sized,
     {
-        match bytes.into_array::<7>() {
-            [0, address_bytes @ ..] => Some(Self::Activate(Address(address_bytes))),
-            [1, address_bytes @ ..] => Some(Self::Deactivate(Address(address_bytes))),
-            [2, address_bytes @ ..] => Some(Self::Toggle(Address(address_bytes))),
+        let [first_byte, group, address_bytes @ ..] = bytes.into_array::<8>();
+        let address = Address(address_bytes);
+        match first_byte {
+            0 => Some(Self::Activate(address, group)),
+            1 => Some(Self::Deactivate(address, group)),
+            2 => Some(Self::Toggle(address, group)),
             _ => None,
         }
     }
 
     fn to_bytes(&self) -> GenericArray<u8, Self::Size> {
-        let (first_byte, address) = match self {
-            Self::Activate(address) => (0, address),
-            Self::Deactivate(address) => (1, address),
-            Self::Toggle(address) => (2, address),
+        let first_byte = match self {
+            Self::Activate(..) => 0,
+            Self::Deactivate(..) => 1,
+            Self::Toggle(..) => 2,
         };
-        let mut value = [0; 7];
+        let mut value = [0; 8];
         value[0] = first_byte;
-        for (i, byte) in address.0.iter().enumerate() {
-            value[i + 1] = *byte;
+        value[1] = self.group();
+        for (i, byte) in self.address().0.iter().enumerate() {
+            value[i + 2] = *byte;
         }
         value.into()
     }
@@ -75,6 +90,10 @@ pub struct TransportConfig<T> {
     pub active: bool,
     /// Whether the transport should ignore activation requests from the host.
     pub ignore_activation_request: bool,
+    /// The group of the transport.
+    ///
+    /// Messages only affect the transports that belong to the group of the message.
+    pub group: u8,
 }
 
 impl<T> TransportConfig<T> {
@@ -84,6 +103,7 @@ impl<T> TransportConfig<T> {
             transport,
             active: true,
             ignore_activation_request: true,
+            group: 0,
         }
     }
 
@@ -98,14 +118,23 @@ impl<T> TransportConfig<T> {
         self.ignore_activation_request = value;
         self
     }
+
+    /// Sets the group of the transport.
+    pub const fn group(mut self, value: u8) -> Self {
+        self.group = value;
+        self
+    }
 }
 
 /// An external transport wrapper that can be activated and deactivated.
 pub struct Transport<T> {
     transport: T,
     ignore_activation_request: bool,
+    group: u8,
     address: Address,
     internal_channel: internal::DynChannelRef<'static>,
+    active: AtomicBool,
+    activation_request: Signal<CriticalSectionRawMutex, ()>,
 }
 
 impl<T, TxMessage, RxMessage> external::Transport for Transport<T>
@@ -129,14 +158,16 @@ where
         U: internal::Transport<Mcu = Self::Mcu>,
     {
         let transport = T::create(config.transport, mcu, address, internal_channel).await;
-        ACTIVE.store(config.active, Ordering::Release);
         transport.set_active(config.active).await;
 
         Transport {
             transport,
             ignore_activation_request: config.ignore_activation_request,
+            group: config.group,
             address,
             internal_channel: internal_channel.as_dyn_ref(),
+            active: AtomicBool::new(config.active),
+            activation_request: Signal::new(),
         }
     }
 
@@ -149,19 +180,19 @@ where
             loop {
                 let message = receiver.next().await;
                 debug!("Received toggle message: {:?}", message);
-                if message.address() != &self.address {
+                if message.address() != &self.address || message.group() != self.group {
                     continue;
                 }
                 let is_activated = match message {
-                    Message::Activate(_) => {
-                        ACTIVE.store(true, Ordering::Release);
+                    Message::Activate(..) => {
+                        self.active.store(true, Ordering::Release);
                         true
                     }
-                    Message::Deactivate(_) => {
-                        ACTIVE.store(false, Ordering::Release);
+                    Message::Deactivate(..) => {
+                        self.active.store(false, Ordering::Release);
                         false
                     }
-                    Message::Toggle(_) => !ACTIVE.fetch_not(Ordering::AcqRel),
+                    Message::Toggle(..) => !self.active.fetch_not(Ordering::AcqRel),
                 };
                 self.transport.set_active(is_activated).await;
             }
@@ -173,9 +204,9 @@ where
             let handle_activation_request = async {
                 loop {
                     self.transport.wait_for_activation_request().await;
-                    ACTIVE.store(true, Ordering::Release);
+                    self.active.store(true, Ordering::Release);
                     self.transport.set_active(true).await;
-                    ACTIVATION_REQUEST.signal(());
+                    self.activation_request.signal(());
                 }
             };
             join(handle_internal_messages, handle_activation_request).await;
@@ -183,7 +214,7 @@ where
     }
 
     async fn send(&self, message: Self::TxMessage) {
-        if ACTIVE.load(Ordering::Acquire) {
+        if self.active.load(Ordering::Acquire) {
             self.transport.send(message).await;
         }
     }
@@ -202,8 +233,8 @@ where
 
     async fn wait_for_activation_request(&self) {
         loop {
-            ACTIVATION_REQUEST.wait().await;
-            if ACTIVE.load(Ordering::Acquire) {
+            self.activation_request.wait().await;
+            if self.active.load(Ordering::Acquire) {
                 break;
             }
         }
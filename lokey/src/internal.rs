@@ -2,6 +2,7 @@
 
 mod channel;
 pub mod empty;
+mod request;
 
 use crate::util::declare_const_for_feature_group;
 use crate::{Address, Device, Transports};
@@ -10,6 +11,7 @@ use core::any::Any;
 use core::future::Future;
 use derive_more::{Display, Error};
 use generic_array::{ArrayLength, GenericArray};
+pub use request::{Request, RequestId, Response};
 
 declare_const_for_feature_group!(
     /// The maximum number of receivers for the internal channel.
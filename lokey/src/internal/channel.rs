@@ -1,9 +1,12 @@
+use crate::Address;
 use crate::internal::{
     self, MAX_MESSAGE_SIZE, MAX_MESSAGE_SIZE_WITH_TAG, MaximumReceiversReached, Message,
-    RECEIVER_SLOTS,
+    RECEIVER_SLOTS, Request, RequestId,
 };
 use crate::util::{error, unwrap};
 use arrayvec::ArrayVec;
+use core::convert::Infallible;
+use core::future::Future;
 use core::marker::PhantomData;
 use embassy_futures::join::join3;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
@@ -104,6 +107,32 @@ impl<Transport: internal::Transport> Channel<Transport> {
             _phantom: PhantomData,
         })
     }
+
+    /// Sends a request and waits for the matching response.
+    ///
+    /// See [`DynChannelRef::request`] for details.
+    pub async fn request<R: Request>(
+        &self,
+        address: Address,
+        build_request: impl FnOnce(RequestId) -> R,
+    ) -> Result<R::Response, MaximumReceiversReached> {
+        self.as_dyn_ref().request(address, build_request).await
+    }
+
+    /// Answers all incoming requests of the specified type.
+    ///
+    /// See [`DynChannelRef::serve`] for details.
+    pub async fn serve<R, F, Fut>(
+        &self,
+        handle_request: F,
+    ) -> Result<Infallible, MaximumReceiversReached>
+    where
+        R: Request,
+        F: FnMut(R) -> Fut,
+        Fut: Future<Output = R::Response>,
+    {
+        self.as_dyn_ref().serve(handle_request).await
+    }
 }
 
 /// A dynamic reference to the internal channel.
@@ -129,7 +158,7 @@ pub struct DynChannelRef<'a> {
     >,
 }
 
-impl DynChannelRef<'_> {
+impl<'a> DynChannelRef<'a> {
     /// Sends a message through this channel.
     pub async fn send<M: Message>(&self, message: M) {
         if let Some(bytes) = build_message_bytes(message) {
@@ -138,7 +167,10 @@ impl DynChannelRef<'_> {
     }
 
     /// Creates a new receiver for messages of the specified type.
-    pub fn receiver<M: Message>(&self) -> Result<Receiver<'_, M>, MaximumReceiversReached> {
+    ///
+    /// The receiver borrows the channel instead of this reference, so a receiver of a
+    /// `DynChannelRef<'static>` can be stored for as long as needed.
+    pub fn receiver<M: Message>(&self) -> Result<Receiver<'a, M>, MaximumReceiversReached> {
         let subscriber = self
             .rx_channel
             .subscriber()
@@ -148,6 +180,56 @@ impl DynChannelRef<'_> {
             _phantom: PhantomData,
         })
     }
+
+    /// Sends a request and waits for the matching response.
+    ///
+    /// A new [`RequestId`] for the device part with the specified address (usually the address of
+    /// the [`Context`](crate::Context)) is allocated and passed to `build_request`, which must
+    /// include it in the returned request. Responses with a different request ID (e.g. responses
+    /// to requests of other components or other device parts) are ignored.
+    ///
+    /// This method blocks forever if the request is never answered, e.g. because no device part
+    /// serves requests of this type. Wrap the returned future in a timeout (e.g.
+    /// `embassy_time::with_timeout`) if the request might not be answered.
+    pub async fn request<R: Request>(
+        &self,
+        address: Address,
+        build_request: impl FnOnce(RequestId) -> R,
+    ) -> Result<R::Response, MaximumReceiversReached> {
+        // The receiver must be created before sending the request so that the response can not be
+        // missed.
+        let mut receiver = self.receiver::<R::Response>()?;
+        let request_id = RequestId::new(address);
+        self.send(build_request(request_id)).await;
+        loop {
+            let response = receiver.next().await;
+            if internal::Response::request_id(&response) == request_id {
+                return Ok(response);
+            }
+        }
+    }
+
+    /// Answers all incoming requests of the specified type.
+    ///
+    /// The responses returned by `handle_request` are sent over the channel and must contain the
+    /// request ID of the corresponding request. This method only returns if the receiver for the
+    /// requests could not be created.
+    pub async fn serve<R, F, Fut>(
+        &self,
+        mut handle_request: F,
+    ) -> Result<Infallible, MaximumReceiversReached>
+    where
+        R: Request,
+        F: FnMut(R) -> Fut,
+        Fut: Future<Output = R::Response>,
+    {
+        let mut receiver = self.receiver::<R>()?;
+        loop {
+            let request = receiver.next().await;
+            let response = handle_request(request).await;
+            self.send(response).await;
+        }
+    }
 }
 
 impl<'a, Transport: internal::Transport> From<&'a Channel<Transport>> for DynChannelRef<'a> {
@@ -165,6 +247,12 @@ fn build_message_bytes<M: Message>(message: M) -> Option<ArrayVec<u8, MAX_MESSAG
 }
 
 /// Receiver for messages of a specific type from the internal channel.
+///
+/// Every receiver gets every message that is sent through the channel, so multiple receivers for
+/// the same message type can be used independently of each other. No messages are dropped: if the
+/// buffer of the channel is full, sending waits until all receivers have caught up. For this
+/// reason, a receiver should be polled continuously with [`next`](Self::next) and dropped once it
+/// is no longer needed.
 pub struct Receiver<'a, Message> {
     subscriber: Subscriber<
         'a,
@@ -0,0 +1,73 @@
+use crate::Address;
+use crate::internal::Message;
+use core::sync::atomic::Ordering;
+use portable_atomic::AtomicU16;
+
+static NEXT_REQUEST_COUNTER: AtomicU16 = AtomicU16::new(0);
+
+/// Identifier that is used to match a [`Response`] to its [`Request`].
+///
+/// The ID contains the address of the device part that sent the request, so requests of different
+/// device parts (e.g. the halves of a split keyboard) never share the same ID.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RequestId {
+    /// The address of the device part that sent the request.
+    pub address: Address,
+    /// Counter that distinguishes the requests of the same device part.
+    pub counter: u16,
+}
+
+impl RequestId {
+    /// The size of a serialized request ID in bytes.
+    pub const SIZE: usize = 8;
+
+    /// Allocates a new request ID for the device part with the specified address.
+    pub fn new(address: Address) -> Self {
+        Self {
+            address,
+            counter: NEXT_REQUEST_COUNTER.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// Converts the request ID to bytes.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let [a0, a1, a2, a3, a4, a5] = self.address.0;
+        let [c0, c1] = self.counter.to_be_bytes();
+        [a0, a1, a2, a3, a4, a5, c0, c1]
+    }
+
+    /// Converts bytes created with [`RequestId::to_bytes`] back to a request ID.
+    pub fn from_bytes(bytes: [u8; Self::SIZE]) -> Self {
+        let [a0, a1, a2, a3, a4, a5, c0, c1] = bytes;
+        Self {
+            address: Address([a0, a1, a2, a3, a4, a5]),
+            counter: u16::from_be_bytes([c0, c1]),
+        }
+    }
+}
+
+/// Trait for internal messages that request a [`Response`].
+///
+/// A request is sent with [`DynChannelRef::request`](super::DynChannelRef::request), which
+/// allocates a new [`RequestId`] and waits for the response with the same ID. Requests can be
+/// answered with [`DynChannelRef::serve`](super::DynChannelRef::serve).
+///
+/// Both the request and the response must include the request ID in their serialized bytes, so
+/// that the response can be matched to the request.
+///
+/// There is no built-in timeout: a request that is never answered (e.g. because no device part
+/// serves it) waits forever.
+pub trait Request: Message {
+    /// The response that is sent for this request.
+    type Response: Response;
+
+    /// Returns the ID of this request.
+    fn request_id(&self) -> RequestId;
+}
+
+/// Trait for internal messages that are sent in response to a [`Request`].
+pub trait Response: Message {
+    /// Returns the ID of the request that this response belongs to.
+    fn request_id(&self) -> RequestId;
+}
@@ -82,6 +82,7 @@
 #![cfg_attr(docsrs, feature(doc_cfg))]
 #![deny(missing_docs)]
 
+pub mod battery;
 pub mod external;
 pub mod internal;
 mod mcu;
@@ -282,7 +283,7 @@ pub use lokey_macros::State;
 pub use lokey_macros::device;
 #[doc(hidden)]
 pub use mcu::DummyMcu; // This is only used for doc tests
-pub use mcu::Mcu;
+pub use mcu::{Mcu, SystemOff};
 use postcard::experimental::max_size::MaxSize;
 use seq_macro::seq;
 use serde::{Deserialize, Serialize};
@@ -317,6 +318,8 @@ where
     pub internal_channel: &'static internal::Channel<internal::DeviceTransport<D, T>>,
     /// Channel used to send and receive external messages.
     pub external_channel: &'static external::Channel<external::DeviceTransport<D, T>>,
+    /// The persistent storage of the device.
+    pub storage: &'static <D::StorageDriver as storage::StorageDriver>::Storage,
     /// The application state.
     pub state: &'static S,
 }
@@ -335,6 +338,7 @@ where
             mcu,
             internal_channel: self.internal_channel.as_dyn_ref(),
             external_channel: self.external_channel.as_dyn_ref(),
+            storage: self.storage,
             state: DynState::from_ref(self.state),
         }
     }
@@ -352,6 +356,8 @@ where
 
     /// Enables all passed components concurrently.
     ///
+    /// The components can be passed as a tuple, or as an array if they all have the same type.
+    ///
     /// This requires the device `D` to implement [`ComponentSupport`] for all passed component types.
     pub async fn enable_all<C>(&self, components: C)
     where
@@ -397,10 +403,33 @@ pub struct DynContext {
     pub internal_channel: internal::DynChannelRef<'static>,
     /// Channel used to send and receive external messages.
     pub external_channel: external::DynChannelRef<'static>,
+    /// Reference to the persistent storage of the device.
+    pub storage: &'static dyn Any,
     /// The application state.
     pub state: &'static DynState,
 }
 
+impl DynContext {
+    /// Activates or deactivates the [toggle transport](external::toggle) of the device with the
+    /// specified address that belongs to the specified group.
+    pub async fn set_external_active(&self, address: Address, group: u8, active: bool) {
+        let message = if active {
+            external::toggle::Message::Activate(address, group)
+        } else {
+            external::toggle::Message::Deactivate(address, group)
+        };
+        self.internal_channel.send(message).await;
+    }
+
+    /// Toggles the [toggle transport](external::toggle) of the device with the specified address
+    /// that belongs to the specified group.
+    pub async fn toggle_external_active(&self, address: Address, group: u8) {
+        self.internal_channel
+            .send(external::toggle::Message::Toggle(address, group))
+            .await;
+    }
+}
+
 impl<D, T, S> From<Context<D, T, S>> for DynContext
 where
     D: Device,
@@ -518,6 +547,18 @@ macro_rules! impl_component_collection_for_tuples {
 
 impl_component_collection_for_tuples!(16);
 
+impl<D, T, S, C, const N: usize> ComponentCollection<D, T, S> for [C; N]
+where
+    D: Device + ComponentSupport<C, S>,
+    T: Transports<D::Mcu>,
+    S: AnyState,
+    C: Component,
+{
+    async fn enable_all(self, context: Context<D, T, S>) {
+        embassy_futures::join::join_array(self.map(|component| context.enable(component))).await;
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -27,6 +27,27 @@ pub trait Mcu: Any {
         T: Transports<Self>,
         S: AnyState,
         Self: Sized;
+
+    /// Resets the MCU.
+    ///
+    /// This restarts the firmware as if the device was power cycled, so this function never
+    /// returns.
+    fn reboot(&'static self) -> !;
+
+    /// Resets the MCU into its bootloader, e.g. to flash a new firmware over USB.
+    ///
+    /// Which bootloader is entered depends on the MCU (see the documentation of the
+    /// implementation). This function never returns.
+    fn reboot_to_bootloader(&'static self) -> !;
+}
+
+/// Trait for MCUs that can be turned off to save power.
+pub trait SystemOff: Mcu {
+    /// Turns the MCU off.
+    ///
+    /// The MCU stays off until it is woken up by one of the wake-up sources that were configured
+    /// beforehand (e.g. a GPIO pin). Waking up resets the MCU, so this function never returns.
+    fn system_off(&'static self) -> !;
 }
 
 pub use dummy::DummyMcu;
@@ -51,5 +72,13 @@ mod dummy {
             S: AnyState,
         {
         }
+
+        fn reboot(&'static self) -> ! {
+            panic!("DummyMcu can not be rebooted")
+        }
+
+        fn reboot_to_bootloader(&'static self) -> ! {
+            panic!("DummyMcu can not be rebooted")
+        }
     }
 }
@@ -8,13 +8,16 @@
 //! - [`Storage`]: Async API to store, fetch, and remove typed entries.
 //! - [`Entry`]: Defines how a type is tagged and serialized for storage.
 //! - [`Error`]: Common storage error type used across backends.
+//! - [`ConfigStore`]: Typed key-value store for values implementing [`Config`].
 //!
 //! Entries are identified by an 8-byte tag (see [`ENTRY_TAG_SIZE`]). Tags can be parameterized via
 //! [`Entry::TagParams`] to support multiple stored instances of the same entry type.
 
+mod config;
 mod default;
 mod empty;
 
+pub use config::{Config, ConfigStore};
 use core::any::Any;
 use core::fmt::Debug;
 pub use default::DefaultStorage;
@@ -59,6 +62,9 @@ pub trait Storage: Any {
         &self,
         tag_params: E::TagParams,
     ) -> impl Future<Output = Result<Option<E>, Error<<Self as Storage>::FlashError>>>;
+
+    /// Removes all entries from storage.
+    fn clear(&self) -> impl Future<Output = Result<(), Error<<Self as Storage>::FlashError>>>;
 }
 
 /// The size of the tag used to identify entries in storage.
@@ -102,6 +108,8 @@ pub enum Error<E> {
     Corrupted,
     /// The entry is too big to be stored in the storage.
     EntryTooBig,
+    /// The entry could not be serialized.
+    Serialization,
 }
 
 impl<E> Error<E> {
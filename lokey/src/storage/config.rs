@@ -0,0 +1,296 @@
+use crate::storage::{ENTRY_TAG_SIZE, Entry, Error, Storage};
+use crate::util::error;
+use generic_array::{ArrayLength, GenericArray};
+use postcard::experimental::max_size::MaxSize;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use typenum::Unsigned;
+
+/// Trait for values that can be stored in a [`ConfigStore`].
+///
+/// The values are serialized with [`postcard`] and the tag of the storage entry is derived from
+/// the [`KEY`](Self::KEY), the name of the type, the [`VERSION`](Self::VERSION), the
+/// [`Size`](Self::Size) and the `POSTCARD_MAX_SIZE` of the type, so that no tag has to be chosen
+/// manually.
+///
+/// The tag is not derived from a hash of the schema of the type: postcard does not provide the
+/// schema of a type (and `bitcode` is not used by lokey), so the fields of the type are not known.
+/// The maximum serialized size only changes with some changes of the layout (e.g. if a field is
+/// added or removed), so the version has to be increased whenever the serialized layout of the type
+/// changes (e.g. if a field is added, removed, reordered or changes its type). Otherwise a value
+/// that was stored with the previous layout may be deserialized to a wrong value. A stored value is
+/// also not found anymore if the type is renamed or moved to another module.
+///
+/// # Example
+///
+/// ```
+/// use lokey::storage::Config;
+/// use postcard::experimental::max_size::MaxSize;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Default, Serialize, Deserialize, MaxSize)]
+/// struct MySettings {
+///     enabled: bool,
+///     timeout_secs: u16,
+/// }
+///
+/// impl Config for MySettings {
+///     type Size = typenum::U4;
+///
+///     const KEY: &'static str = "my-crate/my-settings";
+/// }
+/// ```
+pub trait Config: Serialize + DeserializeOwned + MaxSize {
+    /// The maximum length of the serialized value.
+    ///
+    /// This must be at least the `POSTCARD_MAX_SIZE` of the type, which is checked at compile
+    /// time.
+    type Size: ArrayLength;
+
+    /// The key that identifies the value.
+    ///
+    /// The key has to be unique among all config types, so it should e.g. be prefixed with the
+    /// name of the crate.
+    const KEY: &'static str;
+
+    /// The version of the layout of the serialized value.
+    const VERSION: u16 = 0;
+}
+
+/// Returns the tag of the storage entry for a [`Config`] type.
+///
+/// The tag is the 64-bit FNV-1a hash of the key, the type name, the version, the size and the
+/// maximum serialized size.
+const fn tag(
+    key: &str,
+    type_name: &str,
+    version: u16,
+    size: usize,
+    max_size: usize,
+) -> [u8; ENTRY_TAG_SIZE] {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    const fn hash(mut state: u64, bytes: &[u8]) -> u64 {
+        let mut i = 0;
+        while i < bytes.len() {
+            state ^= bytes[i] as u64;
+            state = state.wrapping_mul(PRIME);
+            i += 1;
+        }
+        state
+    }
+
+    let state = hash(OFFSET_BASIS, key.as_bytes());
+    let state = hash(state, type_name.as_bytes());
+    let state = hash(state, &version.to_le_bytes());
+    let state = hash(state, &(size as u32).to_le_bytes());
+    let state = hash(state, &(max_size as u32).to_le_bytes());
+    state.to_le_bytes()
+}
+
+/// Serializes the value into a buffer of the size of the storage entry.
+fn serialize<T: Config>(value: &T) -> Result<GenericArray<u8, T::Size>, postcard::Error> {
+    const {
+        assert!(
+            T::Size::USIZE >= T::POSTCARD_MAX_SIZE,
+            "The size of the config type is smaller than its POSTCARD_MAX_SIZE"
+        );
+    }
+    let mut buf = GenericArray::default();
+    postcard::to_slice(value, &mut buf)?;
+    Ok(buf)
+}
+
+impl<T: Config> Entry for T {
+    type Size = T::Size;
+    type TagParams = ();
+
+    fn tag(_: Self::TagParams) -> [u8; ENTRY_TAG_SIZE] {
+        tag(
+            T::KEY,
+            core::any::type_name::<T>(),
+            T::VERSION,
+            T::Size::USIZE,
+            T::POSTCARD_MAX_SIZE,
+        )
+    }
+
+    fn from_bytes(bytes: &GenericArray<u8, Self::Size>) -> Option<Self> {
+        postcard::from_bytes(bytes).ok()
+    }
+
+    fn to_bytes(&self) -> GenericArray<u8, Self::Size> {
+        // The buffer is large enough for every value of the type, so this can only fail if the
+        // `Serialize` implementation returns an error. `ConfigStore::set` checks this before the
+        // value is stored.
+        serialize(self).unwrap_or_else(|_| {
+            error!("Failed to serialize config value");
+            GenericArray::default()
+        })
+    }
+}
+
+/// Typed key-value store for configuration values.
+///
+/// This is a wrapper around a [`Storage`] that stores values implementing the [`Config`] trait, so
+/// that no [`Entry`] has to be implemented for them.
+///
+/// # Example
+///
+/// ```ignore
+/// use lokey::storage::ConfigStore;
+///
+/// let config_store = ConfigStore::new(context.storage);
+/// let mut settings = config_store.get::<MySettings>().await?.unwrap_or_default();
+/// settings.enabled = true;
+/// config_store.set(&settings).await?;
+/// ```
+pub struct ConfigStore<'a, S> {
+    storage: &'a S,
+}
+
+impl<'a, S: Storage> ConfigStore<'a, S> {
+    /// Creates a new [`ConfigStore`] that stores the values in the specified storage.
+    pub const fn new(storage: &'a S) -> Self {
+        Self { storage }
+    }
+
+    /// Returns the stored value of the config type.
+    ///
+    /// Returns [`None`] if no value is stored or the stored value can not be deserialized.
+    pub async fn get<T: Config>(&self) -> Result<Option<T>, Error<S::FlashError>> {
+        self.storage.fetch::<T>(()).await
+    }
+
+    /// Stores the value, replacing the previously stored value of the config type.
+    ///
+    /// Returns [`Error::Serialization`] if the `Serialize` implementation of the type returns an
+    /// error.
+    pub async fn set<T: Config>(&self, value: &T) -> Result<(), Error<S::FlashError>> {
+        serialize(value).map_err(|_| Error::Serialization)?;
+        self.storage.store((), value).await
+    }
+
+    /// Removes the stored value of the config type.
+    pub async fn remove<T: Config>(&self) -> Result<(), Error<S::FlashError>> {
+        self.storage.remove::<T>(()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::RefCell;
+    use core::convert::Infallible;
+    use serde::Deserialize;
+    use std::collections::HashMap;
+    use std::vec::Vec;
+
+    #[test]
+    fn config_tag() {
+        let base = tag("lokey/test", "Test", 0, 4, 3);
+        assert_eq!(base, tag("lokey/test", "Test", 0, 4, 3));
+        assert_ne!(base, tag("lokey/test2", "Test", 0, 4, 3));
+        assert_ne!(base, tag("lokey/test", "Test2", 0, 4, 3));
+        assert_ne!(base, tag("lokey/test", "Test", 1, 4, 3));
+        assert_ne!(base, tag("lokey/test", "Test", 0, 5, 3));
+        assert_ne!(base, tag("lokey/test", "Test", 0, 4, 4));
+    }
+
+    #[derive(Default)]
+    struct MemoryStorage {
+        entries: RefCell<HashMap<[u8; ENTRY_TAG_SIZE], Vec<u8>>>,
+    }
+
+    impl Storage for MemoryStorage {
+        type FlashError = Infallible;
+
+        async fn remove<E: Entry>(
+            &self,
+            tag_params: E::TagParams,
+        ) -> Result<(), Error<Infallible>> {
+            self.entries.borrow_mut().remove(&E::tag(tag_params));
+            Ok(())
+        }
+
+        async fn store<E: Entry>(
+            &self,
+            tag_params: E::TagParams,
+            entry: &E,
+        ) -> Result<(), Error<Infallible>> {
+            let bytes = entry.to_bytes().to_vec();
+            self.entries.borrow_mut().insert(E::tag(tag_params), bytes);
+            Ok(())
+        }
+
+        async fn fetch<E: Entry>(
+            &self,
+            tag_params: E::TagParams,
+        ) -> Result<Option<E>, Error<Infallible>> {
+            let entries = self.entries.borrow();
+            Ok(entries
+                .get(&E::tag(tag_params))
+                .and_then(|bytes| E::from_bytes(GenericArray::from_slice(bytes))))
+        }
+
+        async fn clear(&self) -> Result<(), Error<Infallible>> {
+            self.entries.borrow_mut().clear();
+            Ok(())
+        }
+    }
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize, MaxSize)]
+    struct TestSettings {
+        enabled: bool,
+        timeout_secs: u16,
+    }
+
+    impl Config for TestSettings {
+        type Size = typenum::U4;
+
+        const KEY: &'static str = "lokey/test-settings";
+    }
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize, MaxSize)]
+    struct OtherSettings {
+        value: u8,
+    }
+
+    impl Config for OtherSettings {
+        type Size = typenum::U4;
+
+        const KEY: &'static str = "lokey/other-settings";
+    }
+
+    #[test]
+    fn config_store_round_trip() {
+        let storage = MemoryStorage::default();
+        let config_store = ConfigStore::new(&storage);
+        embassy_futures::block_on(async {
+            assert_eq!(config_store.get::<TestSettings>().await.unwrap(), None);
+
+            let settings = TestSettings {
+                enabled: true,
+                timeout_secs: 300,
+            };
+            config_store.set(&settings).await.unwrap();
+            config_store.set(&OtherSettings { value: 7 }).await.unwrap();
+            assert_eq!(
+                config_store.get::<TestSettings>().await.unwrap(),
+                Some(settings)
+            );
+            assert_eq!(
+                config_store.get::<OtherSettings>().await.unwrap(),
+                Some(OtherSettings { value: 7 })
+            );
+
+            config_store.remove::<TestSettings>().await.unwrap();
+            assert_eq!(config_store.get::<TestSettings>().await.unwrap(), None);
+            assert_eq!(
+                config_store.get::<OtherSettings>().await.unwrap(),
+                Some(OtherSettings { value: 7 })
+            );
+        });
+    }
+}
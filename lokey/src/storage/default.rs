@@ -1,4 +1,5 @@
 use crate::storage::{ENTRY_TAG_SIZE, Entry, Error, Storage};
+use crate::util::warn;
 use core::marker::PhantomData;
 use core::ops::Range;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
@@ -123,8 +124,26 @@ where
             .map_err(Error::from_sequential_storage)?;
 
         Ok(data.and_then(|data| {
-            let data = GenericArray::try_from_slice(data).unwrap();
+            // Treat entries with an unexpected size (e.g. written by an older version of the entry
+            // type) like entries that can not be deserialized
+            let Ok(data) = GenericArray::try_from_slice(data) else {
+                warn!(
+                    "Ignoring stored entry with invalid size (expected {} bytes, found {} bytes)",
+                    E::Size::USIZE,
+                    data.len()
+                );
+                return None;
+            };
             E::from_bytes(data)
         }))
     }
+
+    async fn clear(&self) -> Result<(), Error<Flash::Error>> {
+        self.inner
+            .lock()
+            .await
+            .erase_all()
+            .await
+            .map_err(Error::from_sequential_storage)
+    }
 }
@@ -46,4 +46,8 @@ impl Storage for EmptyStorage {
     ) -> Result<Option<E>, Error<<Self as Storage>::FlashError>> {
         Ok(None)
     }
+
+    async fn clear(&self) -> Result<(), Error<<Self as Storage>::FlashError>> {
+        Ok(())
+    }
 }